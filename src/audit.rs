@@ -0,0 +1,279 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::graph::Graph;
+use crate::model::normalize_crate_name;
+use crate::store;
+
+/// options of the `audit` subcommand
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    /// the output of `cargo audit --json`
+    pub report: PathBuf,
+    /// graphs saved with `SAVE_GRAPH`, one per advisory, can be repeated; a finding is
+    /// only checked against the graphs of its advisory
+    #[arg(long = "graph", value_name = "PATH", required = true)]
+    pub graphs: Vec<PathBuf>,
+    /// the lockfile that was audited, to find which packages depend on a vulnerable one
+    #[arg(long, default_value = "Cargo.lock")]
+    pub lockfile: PathBuf,
+    /// write the annotated report to this file instead of stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// whether the packages of the lockfile reach the vulnerable function of an advisory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reachability {
+    // a package depending on the vulnerable one calls the function
+    Reachable,
+    // every package depending on the vulnerable one was shown not to call it
+    Unreachable,
+    // no graph is about the advisory, or it has no verdict for some of the packages
+    Unknown,
+}
+
+impl Reachability {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Reachability::Reachable => "reachable",
+            Reachability::Unreachable => "unreachable",
+            Reachability::Unknown => "unknown",
+        }
+    }
+}
+
+/// annotate every vulnerability of a `cargo audit --json` report with whether the
+/// saved graphs of its advisory show that its function is called by the packages
+/// depending on the vulnerable one; unreachable findings are moved to
+/// `warnings.unreachable`
+///
+/// returns the number of findings left as vulnerabilities
+pub async fn run(args: &AuditArgs) -> Result<usize> {
+    let content = std::fs::read_to_string(&args.report).context(format!(
+        "读取 cargo audit 报告失败: {}",
+        args.report.display()
+    ))?;
+    let mut report: Value = serde_json::from_str(&content).context(format!(
+        "cargo audit 报告不是 JSON，请使用 --json 输出: {}",
+        args.report.display()
+    ))?;
+    let lock = std::fs::read_to_string(&args.lockfile)
+        .context(format!("读取 {} 失败", args.lockfile.display()))?
+        .parse::<toml_edit::DocumentMut>()
+        .context(format!("解析 {} 失败", args.lockfile.display()))?;
+    let packages = locked_packages(&lock);
+    // 不同公告的图分开保存，结论只能和同一公告的图比对
+    let mut graphs = Vec::new();
+    for path in &args.graphs {
+        graphs.push((path, Graph::load(path).await?));
+    }
+
+    let findings = report["vulnerabilities"]["list"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let (mut kept, mut downgraded) = (Vec::new(), Vec::new());
+    for mut finding in findings {
+        let name = finding["package"]["name"].as_str().unwrap_or_default();
+        let version = finding["package"]["version"].as_str().unwrap_or_default();
+        let mut graph = Graph::new();
+        let mut matched = Vec::new();
+        for (path, candidate) in &graphs {
+            if is_about(candidate, &finding) {
+                graph.merge(candidate.clone());
+                matched.push(path.display().to_string());
+            }
+        }
+        let (reachability, evidence) = if matched.is_empty() {
+            (Reachability::Unknown, Vec::new())
+        } else {
+            reachability(&graph, &packages, name, version)
+        };
+        info!(
+            "{} {} {}: {}",
+            finding["advisory"]["id"].as_str().unwrap_or_default(),
+            name,
+            version,
+            reachability.as_str()
+        );
+        finding["reachability"] = json!({
+            "verdict": reachability.as_str(),
+            "graphs": matched,
+            "dependents": evidence,
+        });
+        match reachability {
+            Reachability::Unreachable => downgraded.push(finding),
+            _ => kept.push(finding),
+        }
+    }
+
+    let remaining = kept.len();
+    report["vulnerabilities"] = json!({
+        "found": remaining > 0,
+        "count": remaining,
+        "list": kept,
+    });
+    if !downgraded.is_empty() {
+        if !report["warnings"].is_object() {
+            report["warnings"] = json!({});
+        }
+        report["warnings"]["unreachable"] = json!(downgraded);
+    }
+    let content = serde_json::to_vec_pretty(&report).context("序列化 cargo audit 报告失败")?;
+    match &args.output {
+        Some(output) => {
+            store::write_atomic(output, &content).await?;
+            info!("标注后的报告已写入 {}", output.display());
+        }
+        None => println!("{}", String::from_utf8_lossy(&content)),
+    }
+    Ok(remaining)
+}
+
+/// whether a saved graph was produced for the advisory of a finding: by the advisory
+/// id or an alias recorded with `ADVISORY_ID`, else by the affected functions the
+/// advisory lists
+fn is_about(graph: &Graph, finding: &Value) -> bool {
+    let advisory = &finding["advisory"];
+    if !graph.advisories().is_empty() {
+        return advisory["id"]
+            .as_str()
+            .into_iter()
+            .chain(
+                advisory["aliases"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str),
+            )
+            .any(|id| graph.advisories().contains(id));
+    }
+    finding["affected"]["functions"]
+        .as_object()
+        .is_some_and(|functions| functions.keys().any(|path| graph.targets().contains(path)))
+}
+
+/// (name, version, dependencies) of every package in a lockfile, dependencies as
+/// written there: `name`, `name version` or `name version (source)`
+fn locked_packages(lock: &toml_edit::DocumentMut) -> Vec<(String, String, Vec<String>)> {
+    lock.get("package")
+        .and_then(|packages| packages.as_array_of_tables())
+        .into_iter()
+        .flatten()
+        .filter_map(|package| {
+            let name = package.get("name")?.as_str()?.to_string();
+            let version = package.get("version")?.as_str()?.to_string();
+            let dependencies = package
+                .get("dependencies")
+                .and_then(|dependencies| dependencies.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|dependency| dependency.as_str().map(str::to_owned))
+                .collect();
+            Some((name, version, dependencies))
+        })
+        .collect()
+}
+
+/// the verdict for a vulnerable package and the one of each locked package depending
+/// on it
+fn reachability(
+    graph: &Graph,
+    packages: &[(String, String, Vec<String>)],
+    name: &str,
+    version: &str,
+) -> (Reachability, Vec<Value>) {
+    let key = normalize_crate_name(name);
+    let analyzed = graph.crates().any(|node| {
+        node.depth == 0 && normalize_crate_name(&node.name) == key && node.version == version
+    });
+    if !analyzed {
+        return (Reachability::Unknown, Vec::new());
+    }
+
+    // 只有一个版本时 Cargo.lock 省略依赖的版本号
+    let single_version = packages
+        .iter()
+        .filter(|(package, _, _)| normalize_crate_name(package) == key)
+        .count()
+        == 1;
+    let dependents = packages.iter().filter(|(_, _, dependencies)| {
+        dependencies.iter().any(|dependency| {
+            let mut parts = dependency.split_whitespace();
+            parts
+                .next()
+                .is_some_and(|dependency| normalize_crate_name(dependency) == key)
+                && parts
+                    .next()
+                    .map_or(single_version, |dependency| dependency == version)
+        })
+    });
+
+    let mut verdict = Reachability::Unreachable;
+    let mut evidence = Vec::new();
+    for (dependent, dependent_version, _) in dependents {
+        let dependent_key = normalize_crate_name(dependent);
+        let matches = |parent: &str, parent_version: &str, name: &str, node_version: &str| {
+            normalize_crate_name(parent) == key
+                && parent_version == version
+                && normalize_crate_name(name) == dependent_key
+                && node_version == dependent_version
+        };
+        let functions = graph
+            .dependencies()
+            .filter(|(parent, node, _)| {
+                matches(&parent.name, &parent.version, &node.name, &node.version)
+            })
+            .flat_map(|(_, _, edge)| edge.functions.keys().cloned())
+            .collect::<Vec<_>>();
+        let cleared = graph
+            .cleared()
+            .filter(|entry| {
+                matches(
+                    &entry.parent_name,
+                    &entry.parent_version,
+                    &entry.name,
+                    &entry.version,
+                )
+            })
+            .map(|entry| entry.clearance.reason)
+            .collect::<Vec<_>>();
+        let dependent_verdict = if !functions.is_empty() {
+            Reachability::Reachable
+        } else if !cleared.is_empty() && cleared.iter().all(|reason| reason.proves_no_call()) {
+            Reachability::Unreachable
+        } else {
+            Reachability::Unknown
+        };
+        verdict = match (verdict, dependent_verdict) {
+            (Reachability::Reachable, _) | (_, Reachability::Reachable) => Reachability::Reachable,
+            (Reachability::Unknown, _) | (_, Reachability::Unknown) => Reachability::Unknown,
+            _ => Reachability::Unreachable,
+        };
+        let mut entry = json!({
+            "name": dependent,
+            "version": dependent_version,
+            "verdict": dependent_verdict.as_str(),
+        });
+        if !functions.is_empty() {
+            entry["functions"] = json!(functions);
+        }
+        if !cleared.is_empty() {
+            entry["reasons"] = json!(cleared
+                .iter()
+                .map(|reason| reason.as_str())
+                .collect::<Vec<_>>());
+        }
+        evidence.push(entry);
+    }
+    // 锁文件中没有依赖它的包时无从判断
+    if evidence.is_empty() {
+        verdict = Reachability::Unknown;
+    }
+    (verdict, evidence)
+}
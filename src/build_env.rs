@@ -0,0 +1,285 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use tokio::process::Command;
+
+use crate::diagnostics::RecordedOutput;
+
+/// environment of the processes that compile dependents (call-cg4rs, cargo expand)
+#[derive(Debug, Clone, Default)]
+pub struct BuildEnv {
+    // one `CARGO_TARGET_DIR` for every dependent, from `SHARED_TARGET_DIR`, so common
+    // dependencies are compiled once; cargo locks the directory during a build, so
+    // concurrent analyses take turns compiling
+    pub target_dir: Option<PathBuf>,
+    // `RUSTC_WRAPPER` of the builds, from `BUILD_RUSTC_WRAPPER`, e.g. `sccache`, which
+    // shares compiled dependencies without serializing the builds
+    pub rustc_wrapper: Option<String>,
+    // parallel rustc jobs of one build, from `BUILD_JOBS` (cargo's default: all cpus)
+    pub jobs: Option<u32>,
+    // niceness of the build processes, from `BUILD_NICE`, e.g. 10
+    pub nice: Option<i32>,
+    // cgroup limits of one build (linux, through `systemd-run --user --scope`), from
+    // `BUILD_MEMORY_MAX` (e.g. `8G`) and `BUILD_CPU_QUOTA` (e.g. `400%`, four cpus)
+    // a build exceeding the memory limit is killed on its own instead of the analyzer
+    pub memory_max: Option<String>,
+    pub cpu_quota: Option<String>,
+    // where build scripts and proc macros of untrusted dependents run, from
+    // `BUILD_SANDBOX` or `--sandbox`
+    pub sandbox: Sandbox,
+    // rustup toolchains to build dependents with, from `TOOLCHAINS`, comma separated in
+    // order of preference; each dependent uses the first one satisfying its
+    // `rust-version` and edition, call-cg4rs must be installed for every one of them
+    pub toolchains: Vec<String>,
+}
+
+/// isolation of the dependent builds: no network, only the crate directory (and the
+/// shared target dir) writable
+///
+/// dependencies are fetched with `cargo fetch` outside the sandbox before the build,
+/// which then runs offline; the container image must provide cargo and call-cg4rs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Sandbox {
+    #[default]
+    None,
+    // bubblewrap, the host file system read-only
+    Bubblewrap,
+    // `docker` or `podman` with the given image
+    Container {
+        runtime: String,
+        image: String,
+    },
+}
+
+impl FromStr for Sandbox {
+    type Err = anyhow::Error;
+
+    /// `none`, `bwrap`, `docker:IMAGE` or `podman:IMAGE`
+    fn from_str(spec: &str) -> Result<Self> {
+        match spec.trim().split_once(':') {
+            None if spec.trim() == "none" => Ok(Sandbox::None),
+            None if matches!(spec.trim(), "bwrap" | "bubblewrap") => Ok(Sandbox::Bubblewrap),
+            Some((runtime @ ("docker" | "podman"), image)) if !image.trim().is_empty() => {
+                Ok(Sandbox::Container {
+                    runtime: runtime.to_owned(),
+                    image: image.trim().to_owned(),
+                })
+            }
+            _ => bail!(
+                "未知的沙箱配置 {}，可选 none、bwrap、docker:IMAGE、podman:IMAGE",
+                spec
+            ),
+        }
+    }
+}
+
+impl BuildEnv {
+    pub fn from_env() -> Self {
+        let target_dir = env_string("SHARED_TARGET_DIR")
+            // 子进程在各个依赖者的目录下运行，相对路径要先转成绝对路径
+            .map(|dir| std::path::absolute(&dir).unwrap_or_else(|_| PathBuf::from(dir)));
+        let mut memory_max = env_string("BUILD_MEMORY_MAX");
+        let mut cpu_quota = env_string("BUILD_CPU_QUOTA");
+        if !cfg!(target_os = "linux") && (memory_max.is_some() || cpu_quota.is_some()) {
+            tracing::warn!("BUILD_MEMORY_MAX 和 BUILD_CPU_QUOTA 只在 Linux 上生效，已忽略");
+            memory_max = None;
+            cpu_quota = None;
+        }
+        Self {
+            target_dir,
+            rustc_wrapper: env_string("BUILD_RUSTC_WRAPPER"),
+            jobs: env_string("BUILD_JOBS").and_then(|jobs| jobs.parse().ok()),
+            nice: env_string("BUILD_NICE").and_then(|nice| nice.parse().ok()),
+            memory_max,
+            cpu_quota,
+            sandbox: env_string("BUILD_SANDBOX")
+                .and_then(|spec| match spec.parse() {
+                    Ok(sandbox) => Some(sandbox),
+                    Err(e) => {
+                        tracing::warn!("{:#}，不使用沙箱", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            toolchains: env_string("TOOLCHAINS")
+                .map(|names| {
+                    names
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// download the dependencies of a crate so a sandboxed build can run offline
+    ///
+    /// fetching resolves and downloads sources only, no code of the crate runs
+    pub async fn prefetch(&self, crate_dir: &Path) -> Result<()> {
+        if self.sandbox == Sandbox::None {
+            return Ok(());
+        }
+        let output = Command::new("cargo")
+            .args([
+                "fetch",
+                "--manifest-path",
+                &crate_dir.join("Cargo.toml").to_string_lossy(),
+            ])
+            .current_dir(crate_dir)
+            .recorded_output()
+            .await
+            .context("执行 cargo fetch 失败")?;
+        if !output.status.success() {
+            bail!(
+                "cargo fetch 执行失败: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+
+    /// a command that compiles the dependent in `crate_dir`: `program` with the limits,
+    /// sandbox and environment of this configuration, run in `crate_dir` with the given
+    /// rustup toolchain (None for the default one)
+    ///
+    /// ```text
+    /// systemd-run --user --scope -p MemoryMax=.. -p CPUQuota=.. -- nice -n N \
+    ///     bwrap .. -- rustup run TOOLCHAIN program
+    /// ```
+    pub fn command(&self, program: &str, crate_dir: &Path, toolchain: Option<&str>) -> Command {
+        let mut env = Vec::new();
+        if let Some(target_dir) = &self.target_dir {
+            env.push((
+                "CARGO_TARGET_DIR",
+                target_dir.to_string_lossy().into_owned(),
+            ));
+        }
+        if let Some(wrapper) = &self.rustc_wrapper {
+            env.push(("RUSTC_WRAPPER", wrapper.clone()));
+        }
+        if let Some(jobs) = self.jobs {
+            env.push(("CARGO_BUILD_JOBS", jobs.to_string()));
+        }
+        if self.sandbox != Sandbox::None {
+            env.push(("CARGO_NET_OFFLINE", "true".to_string()));
+        }
+
+        let mut argv: Vec<String> = Vec::new();
+        if self.memory_max.is_some() || self.cpu_quota.is_some() {
+            argv.extend(["systemd-run", "--user", "--scope", "--quiet"].map(String::from));
+            if let Some(memory_max) = &self.memory_max {
+                argv.extend(["-p".to_string(), format!("MemoryMax={}", memory_max)]);
+                // 不允许换出到 swap，否则超限的构建只会变慢而不会被终止
+                argv.extend(["-p".to_string(), "MemorySwapMax=0".to_string()]);
+            }
+            if let Some(cpu_quota) = &self.cpu_quota {
+                argv.extend(["-p".to_string(), format!("CPUQuota={}", cpu_quota)]);
+            }
+            argv.push("--".to_string());
+        }
+        if let Some(nice) = self.nice {
+            argv.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+        // 依赖者目录通常是指向存储的链接，两个路径都要能访问
+        let real_dir = crate_dir
+            .canonicalize()
+            .unwrap_or_else(|_| crate_dir.to_path_buf());
+        let mut writable = vec![real_dir.clone()];
+        writable.extend(self.target_dir.clone());
+        let work_dir = crate_dir.to_string_lossy().into_owned();
+        match &self.sandbox {
+            Sandbox::None => {}
+            Sandbox::Bubblewrap => {
+                argv.extend(
+                    [
+                        "bwrap",
+                        "--ro-bind",
+                        "/",
+                        "/",
+                        "--dev",
+                        "/dev",
+                        "--proc",
+                        "/proc",
+                        "--tmpfs",
+                        "/tmp",
+                        "--unshare-all",
+                        "--die-with-parent",
+                    ]
+                    .map(String::from),
+                );
+                // cargo 要在 CARGO_HOME 下写锁文件，用可写的 tmpfs 覆盖，源码保持只读
+                let cargo_home = cargo_home();
+                argv.extend(["--tmpfs".to_string(), path_arg(&cargo_home)]);
+                for dir in ["registry", "git", "bin", "config.toml", "config"] {
+                    let path = cargo_home.join(dir);
+                    if path.exists() {
+                        argv.extend(["--ro-bind".to_string(), path_arg(&path), path_arg(&path)]);
+                    }
+                }
+                for dir in &writable {
+                    argv.extend(["--bind".to_string(), path_arg(dir), path_arg(dir)]);
+                }
+                argv.extend(["--chdir".to_string(), work_dir, "--".to_string()]);
+            }
+            Sandbox::Container { runtime, image } => {
+                argv.extend([runtime.clone(), "run".to_string(), "--rm".to_string()]);
+                argv.extend(["--network".to_string(), "none".to_string()]);
+                let cargo_home = cargo_home();
+                for dir in ["registry", "git"] {
+                    let path = cargo_home.join(dir);
+                    if path.exists() {
+                        argv.extend([
+                            "-v".to_string(),
+                            format!("{}:/cargo/{}:ro", path.display(), dir),
+                        ]);
+                    }
+                }
+                argv.extend(["-e".to_string(), "CARGO_HOME=/cargo".to_string()]);
+                for dir in &writable {
+                    argv.extend(["-v".to_string(), format!("{0}:{0}", dir.display())]);
+                }
+                if real_dir != crate_dir {
+                    argv.extend([
+                        "-v".to_string(),
+                        format!("{}:{}", real_dir.display(), crate_dir.display()),
+                    ]);
+                }
+                // 容器不继承宿主的环境变量
+                for (key, value) in &env {
+                    argv.extend(["-e".to_string(), format!("{}={}", key, value)]);
+                }
+                argv.extend(["-w".to_string(), work_dir, image.clone()]);
+            }
+        }
+        if let Some(toolchain) = toolchain {
+            argv.extend(["rustup", "run", toolchain].map(String::from));
+        }
+        argv.push(program.to_string());
+
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]).current_dir(crate_dir).envs(env);
+        command
+    }
+}
+
+/// `$CARGO_HOME`, default `~/.cargo`
+fn cargo_home() -> PathBuf {
+    std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")))
+        .unwrap_or_else(|| PathBuf::from(".cargo"))
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn env_string(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
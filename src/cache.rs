@@ -0,0 +1,120 @@
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+/// hit/miss counters of a cache
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub len: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+/// size-bounded LRU cache shared across the pipeline, e.g. for manifests and index metadata
+#[derive(Debug)]
+pub struct MetadataCache<K: Hash + Eq, V> {
+    name: &'static str,
+    // entries with the time they were stored
+    inner: Mutex<LruCache<K, (Instant, V)>>,
+    // entries older than this count as missing
+    ttl: Option<Duration>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K: Hash + Eq, V: Clone> MetadataCache<K, V> {
+    /// the capacity is read from `METADATA_CACHE_CAPACITY`, default 1024 entries
+    pub fn new(name: &'static str) -> Self {
+        let capacity = std::env::var("METADATA_CACHE_CAPACITY")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_CACHE_CAPACITY);
+        Self::with_capacity(name, capacity)
+    }
+
+    pub fn with_capacity(name: &'static str, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            name,
+            inner: Mutex::new(LruCache::new(capacity)),
+            ttl: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// expire entries `ttl` after they were stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        let expired = match (inner.peek(key), self.ttl) {
+            (Some((stored, _)), Some(ttl)) => stored.elapsed() > ttl,
+            _ => false,
+        };
+        if expired {
+            inner.pop(key);
+        }
+        let value = inner.get(key).map(|(_, value)| value.clone());
+        drop(inner);
+        if value.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    /// whether `key` has an unexpired entry, without counting a hit or a miss
+    pub fn contains(&self, key: &K) -> bool {
+        match self.inner.lock().unwrap().peek(key) {
+            Some((stored, _)) => self.ttl.is_none_or(|ttl| stored.elapsed() <= ttl),
+            None => false,
+        }
+    }
+
+    pub fn put(&self, key: K, value: V) {
+        self.inner.lock().unwrap().put(key, (Instant::now(), value));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            len: self.inner.lock().unwrap().len(),
+        }
+    }
+
+    /// log the hit rate of this cache
+    pub fn log_stats(&self) {
+        let stats = self.stats();
+        tracing::info!(
+            "缓存 {}: 命中 {} 次，未命中 {} 次，命中率 {:.1}%，当前 {} 项",
+            self.name,
+            stats.hits,
+            stats.misses,
+            stats.hit_rate() * 100.0,
+            stats.len
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// keys call-cg4rs may use for the path of a caller function
+const PATH_KEYS: &[&str] = &["caller", "path", "def_path", "function", "name"];
+
+/// one caller function found in callers.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallerInfo {
+    // full path of the calling function, e.g. `my_crate::worker::run`
+    pub path: String,
+    // whether the caller is `pub`, None when callers.json doesn't say
+    pub is_public: Option<bool>,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+}
+
+impl CallerInfo {
+    /// callers that can be called from other crates
+    ///
+    /// when visibility is unknown the caller is kept, missing a propagation path is
+    /// worse than analyzing a private helper once more
+    pub fn is_exported(&self) -> bool {
+        self.is_public.unwrap_or(true)
+    }
+}
+
+/// where a caller sits relative to the public API of its crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiExposure {
+    // `pub` and reachable from the crate root, downstream crates can call it
+    Public,
+    // `pub` but only inside modules that aren't reachable from outside
+    Internal,
+    // not `pub`
+    Private,
+    // callers.json doesn't say or the module couldn't be located
+    Unknown,
+}
+
+impl ApiExposure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiExposure::Public => "public",
+            ApiExposure::Internal => "internal",
+            ApiExposure::Private => "private",
+            ApiExposure::Unknown => "unknown",
+        }
+    }
+}
+
+/// where the calling code lives, only `Lib` and `BuildScript` callers ship to users
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallSite {
+    Lib,
+    // `#[cfg(test)]` modules and the `tests/` directory
+    Test,
+    Example,
+    Bench,
+    BuildScript,
+}
+
+impl CallSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CallSite::Lib => "lib",
+            CallSite::Test => "test",
+            CallSite::Example => "example",
+            CallSite::Bench => "bench",
+            CallSite::BuildScript => "build_script",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "lib" => Some(CallSite::Lib),
+            "test" | "tests" => Some(CallSite::Test),
+            "example" | "examples" => Some(CallSite::Example),
+            "bench" | "benches" => Some(CallSite::Bench),
+            "build_script" | "build" => Some(CallSite::BuildScript),
+            _ => None,
+        }
+    }
+
+    /// classify by the source file alone, None for files under `src/`
+    ///
+    /// only the part of an absolute path inside `crate_dir` counts, so a download
+    /// directory below e.g. `/data/tests/` doesn't turn every caller into a test;
+    /// absolute paths outside of the crate aren't classified
+    pub fn from_file(file: &str, crate_dir: &Path) -> Option<Self> {
+        let path = Path::new(file);
+        let file = if path.is_absolute() {
+            let relative = path
+                .strip_prefix(crate_dir)
+                .map(Path::to_path_buf)
+                .ok()
+                .or_else(|| {
+                    // call-cg4rs 可能报告解析过链接的路径
+                    let canonical = crate_dir.canonicalize().ok()?;
+                    path.strip_prefix(canonical).ok().map(Path::to_path_buf)
+                })?;
+            relative.to_string_lossy().replace('\\', "/")
+        } else {
+            file.replace('\\', "/")
+        };
+        let in_dir = |dir: &str| {
+            file.starts_with(&format!("{}/", dir)) || file.contains(&format!("/{}/", dir))
+        };
+        if in_dir("tests") {
+            Some(CallSite::Test)
+        } else if in_dir("examples") {
+            Some(CallSite::Example)
+        } else if in_dir("benches") {
+            Some(CallSite::Bench)
+        } else if file == "build.rs" || file.ends_with("/build.rs") {
+            Some(CallSite::BuildScript)
+        } else {
+            None
+        }
+    }
+}
+
+/// parse the callers out of a callers.json document
+///
+/// accepted shapes: a list of caller objects or strings, or an object holding such a
+/// list under `callers`
+pub fn parse_callers(value: &Value) -> Vec<CallerInfo> {
+    let entries = match value {
+        Value::Array(entries) => entries,
+        Value::Object(map) => match map.get("callers") {
+            Some(Value::Array(entries)) => entries,
+            _ => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    entries.iter().filter_map(parse_caller).collect()
+}
+
+pub fn parse_caller(entry: &Value) -> Option<CallerInfo> {
+    match entry {
+        Value::String(path) => Some(CallerInfo {
+            path: path.clone(),
+            is_public: None,
+            file: None,
+            line: None,
+        }),
+        Value::Object(map) => {
+            let path = PATH_KEYS
+                .iter()
+                .find_map(|key| map.get(*key).and_then(Value::as_str))?;
+            let is_public = map
+                .get("is_public")
+                .or_else(|| map.get("is_pub"))
+                .and_then(Value::as_bool)
+                .or_else(|| {
+                    map.get("visibility")
+                        .and_then(Value::as_str)
+                        .map(|visibility| visibility.starts_with("pub"))
+                });
+            Some(CallerInfo {
+                path: path.to_owned(),
+                is_public,
+                file: map.get("file").and_then(Value::as_str).map(str::to_owned),
+                line: map.get("line").and_then(Value::as_u64),
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_sites_are_classified_inside_the_crate() {
+        let crate_dir = Path::new("/data/tests/downloads/dl/se/rd/serde/serde-1.0.0");
+        let cases = [
+            ("src/lib.rs", None),
+            ("src/tests.rs", None),
+            ("tests/it.rs", Some(CallSite::Test)),
+            ("examples/demo.rs", Some(CallSite::Example)),
+            ("benches/bench.rs", Some(CallSite::Bench)),
+            ("build.rs", Some(CallSite::BuildScript)),
+            ("member/tests/it.rs", Some(CallSite::Test)),
+            ("examples\\demo.rs", Some(CallSite::Example)),
+            // 下载目录本身含 `tests/`，只看 crate 内的部分
+            (
+                "/data/tests/downloads/dl/se/rd/serde/serde-1.0.0/src/lib.rs",
+                None,
+            ),
+            (
+                "/data/tests/downloads/dl/se/rd/serde/serde-1.0.0/benches/b.rs",
+                Some(CallSite::Bench),
+            ),
+            // crate 之外的绝对路径无法判断
+            ("/home/user/examples/other/src/lib.rs", None),
+        ];
+        for (file, expected) in cases {
+            assert_eq!(CallSite::from_file(file, crate_dir), expected, "{}", file);
+        }
+    }
+
+    #[test]
+    fn canonical_paths_of_a_linked_crate_are_classified() {
+        let dir = std::env::temp_dir().join(format!("cross_pro_cg-callers-{}", std::process::id()));
+        let real = dir.join("tests").join("store").join("abc");
+        let link = dir.join("serde-1.0.0");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&real).unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let real = real.canonicalize().unwrap();
+
+        let lib = real.join("src/lib.rs");
+        let example = real.join("examples/demo.rs");
+        assert_eq!(CallSite::from_file(lib.to_str().unwrap(), &link), None);
+        assert_eq!(
+            CallSite::from_file(example.to_str().unwrap(), &link),
+            Some(CallSite::Example)
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
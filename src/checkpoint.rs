@@ -0,0 +1,125 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs as tokio_fs;
+
+use crate::model::{Clearance, DependencyKind, DependentSource, FunctionCalls};
+use crate::resolution::ResolutionCheck;
+use crate::target::FunctionTarget;
+
+/// a dependent of the checkpointed node found to reach a target function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AffectedEntry {
+    pub name: String,
+    pub version: String,
+    pub req: String,
+    #[serde(default)]
+    pub source: DependentSource,
+    #[serde(default)]
+    pub resolved_version: Option<String>,
+    #[serde(default)]
+    pub kinds: Vec<DependencyKind>,
+    #[serde(default)]
+    pub required_features: Vec<String>,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default)]
+    pub resolution: Option<ResolutionCheck>,
+    pub calls: FunctionCalls,
+}
+
+/// a dependent of the checkpointed node analyzed and found not to reach any target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClearedEntry {
+    pub name: String,
+    pub version: String,
+    pub req: String,
+    #[serde(default)]
+    pub source: DependentSource,
+    pub clearance: Clearance,
+}
+
+/// where the call of an affected dependent first appears, found by bisecting its older
+/// versions that admit the checkpointed node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntroducedEntry {
+    pub name: String,
+    // the oldest version found reaching a target
+    pub first_affected: String,
+    // the newest older version found not calling any target, None when no older version
+    // admitting the node could be shown clear
+    pub last_clear: Option<String>,
+    // versions analyzed during the bisection
+    pub checked: usize,
+}
+
+/// progress of one BFS node, persisted after every batch of dependents so that a
+/// crash only loses the batch in flight
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NodeCheckpoint {
+    // `name@version` of every dependent with a verdict, failed ones are retried on resume
+    pub processed: BTreeSet<String>,
+    pub affected: Vec<AffectedEntry>,
+    #[serde(default)]
+    pub cleared: Vec<ClearedEntry>,
+    #[serde(default)]
+    pub introduced: Vec<IntroducedEntry>,
+}
+
+impl NodeCheckpoint {
+    /// checkpoint file of a node, the target set is part of the name so that runs with
+    /// different targets never share progress
+    pub fn path(
+        checkpoint_dir: &Path,
+        name: &str,
+        version: &str,
+        targets: &[FunctionTarget],
+    ) -> PathBuf {
+        let mut hasher = Sha256::new();
+        for target in targets {
+            hasher.update(target.name.as_bytes());
+            hasher.update(b"\n");
+        }
+        let digest = hex::encode(hasher.finalize());
+        checkpoint_dir.join(format!("{}-{}-{}.json", name, version, &digest[..12]))
+    }
+
+    /// load the checkpoint, a missing or unreadable file means starting from scratch
+    pub async fn load(path: &Path) -> Self {
+        match tokio_fs::read_to_string(path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                tracing::warn!("检查点文件 {} 无法解析，重新开始: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// write the checkpoint atomically (write to a temp file, then rename)
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio_fs::create_dir_all(parent)
+                .await
+                .context(format!("创建检查点目录失败: {}", parent.display()))?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        tokio_fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .await
+            .context(format!("写入检查点失败: {}", tmp_path.display()))?;
+        tokio_fs::rename(&tmp_path, path)
+            .await
+            .context(format!("写入检查点失败: {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn key(name: &str, version: &str) -> String {
+        format!("{}@{}", name, version)
+    }
+
+    pub fn is_processed(&self, name: &str, version: &str) -> bool {
+        self.processed.contains(&Self::key(name, version))
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+
+use crate::audit::AuditArgs;
+use crate::build_env::Sandbox;
+use crate::gc::GcArgs;
+use crate::import::ImportArgs;
+use crate::merge::MergeArgs;
+use crate::model::{DependentSource, ReverseDependency};
+use crate::report::ReportArgs;
+use crate::sbom::SbomArgs;
+
+/// command line options
+#[derive(Debug, Parser)]
+#[command(version, about = "跨 crate 漏洞函数调用影响分析")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// only analyze the dependents listed in this file instead of querying the
+    /// reverse dependencies from the database, one `crate@version` per line
+    #[arg(long)]
+    pub dependents_file: Option<PathBuf>,
+    /// repeat the call graph extraction for these target triples, e.g.
+    /// `x86_64-pc-windows-msvc,aarch64-apple-darwin`, the rust standard library must be
+    /// installed for each of them
+    #[arg(long, value_delimiter = ',')]
+    pub targets: Vec<String>,
+    /// also analyze a dependent hosted in a git repository, `URL` or `URL#REV` with a
+    /// branch, tag or commit, can be repeated
+    #[arg(long = "git-dependent", value_name = "URL[#REV]")]
+    pub git_dependents: Vec<String>,
+    /// also analyze a local (unpublished) project, the workspace root or package
+    /// directory, can be repeated
+    #[arg(long = "local-project", value_name = "PATH")]
+    pub local_projects: Vec<PathBuf>,
+    /// run the builds of dependents in a sandbox without network: `none`, `bwrap`,
+    /// `docker:IMAGE` or `podman:IMAGE`, overrides `BUILD_SANDBOX`
+    #[arg(long, value_name = "SANDBOX")]
+    pub sandbox: Option<Sandbox>,
+}
+
+/// maintenance commands, without one the analysis runs
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// annotate the findings of `cargo audit --json` with their reachability from saved
+    /// graphs, exits with 1 while reachable or unknown findings remain
+    Audit(AuditArgs),
+    /// report the disk usage of the download directory and delete cached crates
+    #[command(alias = "clean")]
+    Gc(GcArgs),
+    /// build an SQLite metadata database from the crates.io database dump, to be used
+    /// as `DATABASE_URL` instead of a Postgres mirror
+    ImportDump(ImportArgs),
+    /// combine graphs saved by several runs (`SAVE_GRAPH`) into one, then print and
+    /// export it like the result of an analysis
+    MergeGraphs(MergeArgs),
+    /// render a report from a saved graph without analyzing again
+    Report(ReportArgs),
+    /// match the crate components of a CycloneDX or SPDX SBOM against saved graphs
+    Sbom(SbomArgs),
+}
+
+/// read an explicit dependent list, blank lines and `#` comments are ignored
+pub fn read_dependents_file(path: &Path) -> Result<Vec<ReverseDependency>> {
+    let content =
+        std::fs::read_to_string(path).context(format!("读取依赖者列表失败: {}", path.display()))?;
+    let mut dependents = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((name, version)) = line.split_once('@') else {
+            bail!(
+                "{}:{} 不是 crate@version 格式: {}",
+                path.display(),
+                index + 1,
+                line
+            );
+        };
+        semver::Version::parse(version.trim()).context(format!(
+            "{}:{} 版本号无效: {}",
+            path.display(),
+            index + 1,
+            version
+        ))?;
+        // 依赖者的版本要求未知，由 patch 阶段的 cargo update 验证是否兼容
+        dependents.push(
+            ReverseDependency::new(
+                name.trim().to_string(),
+                version.trim().to_string(),
+                "*".to_string(),
+            )
+            .with_source(DependentSource::File),
+        );
+    }
+    Ok(dependents)
+}
@@ -0,0 +1,410 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::build_env::BuildEnv;
+use crate::callers::CallSite;
+use crate::graph::ChainFilter;
+use crate::index::MetadataSource;
+use crate::model::{DependencyKind, FeatureSet, PinStrategy, PrereleasePolicy, YankedPolicy};
+use crate::precheck::PrecheckKind;
+use crate::registry::REGISTRY;
+use crate::target::PathAlias;
+
+/// analyzer settings, read from the environment (and `.env` via dotenv)
+#[derive(Debug, Clone)]
+pub struct AnalyzerConfig {
+    // crates whose sources must not leave the machine, from `SENSITIVE_CRATES`
+    // comma separated, a trailing `*` matches by prefix, e.g. `acme-*,internal-tool`
+    pub sensitive_crates: Vec<String>,
+    // export the graph to this database after the run, from `EXPORT_SQL_URL`
+    // either a database url or a file path for a standalone SQLite database
+    pub export_sql_url: Option<String>,
+    // write the crate-level graph as Graphviz DOT to this file, from `EXPORT_CRATE_DOT`
+    pub export_crate_dot: Option<PathBuf>,
+    // write the function-level graph as Graphviz DOT to this file, from `EXPORT_DOT`
+    pub export_dot: Option<PathBuf>,
+    // write the crate-level graph as GraphML to this file, from `EXPORT_CRATE_GRAPHML`
+    pub export_crate_graphml: Option<PathBuf>,
+    // write the function-level graph as GraphML to this file, from `EXPORT_GRAPHML`
+    pub export_graphml: Option<PathBuf>,
+    // write the crate-level graph as GEXF for Gephi to this file, from `EXPORT_GEXF`
+    pub export_gexf: Option<PathBuf>,
+    // write the graph as a Cypher script for Neo4j to this file, from `EXPORT_CYPHER`
+    pub export_cypher: Option<PathBuf>,
+    // write one row per analyzed dependent as Parquet to this file, from `EXPORT_PARQUET`
+    pub export_parquet: Option<PathBuf>,
+    // write a self-contained HTML report to this file, from `EXPORT_HTML`
+    pub export_html: Option<PathBuf>,
+    // write a Markdown summary to this file, from `EXPORT_MARKDOWN`
+    pub export_markdown: Option<PathBuf>,
+    // write the affected dependents as SARIF to this file, from `EXPORT_SARIF`
+    pub export_sarif: Option<PathBuf>,
+    // write a draft OSV record per affected dependent into this directory, from
+    // `EXPORT_OSV_DIR`
+    pub export_osv_dir: Option<PathBuf>,
+    // write a CycloneDX VEX document to this file, from `EXPORT_VEX`
+    pub export_vex: Option<PathBuf>,
+    // the vulnerability the VEX statements are about, e.g. `RUSTSEC-2025-0024`, from
+    // `VEX_VULNERABILITY_ID`, else `ADVISORY_ID`; derived from the roots when unset
+    pub vex_vulnerability_id: Option<String>,
+    // the advisory analyzed, e.g. `RUSTSEC-2025-0024`, recorded in the saved graph so that
+    // `audit` and `sbom` match findings only against its graph, from `ADVISORY_ID`
+    pub advisory_id: Option<String>,
+    // save the whole graph as JSON to this file, for `merge-graphs`, from `SAVE_GRAPH`
+    pub save_graph: Option<PathBuf>,
+    // where per-node progress is persisted between batches, from `CHECKPOINT_DIR`
+    pub checkpoint_dir: PathBuf,
+    // desired wall time between two checkpoints, from `CHECKPOINT_INTERVAL_SECS`
+    // the batch size is adapted to the average dependent duration to hit it
+    pub checkpoint_interval: Duration,
+    // rounds of retrying the dependents whose analysis failed, run once the last batch
+    // of a node is done, from `DEPENDENT_RETRIES` (0 by default: transient failures are
+    // retried by the commands themselves and the rest are left to a resume)
+    pub dependent_retries: usize,
+    // paths of target functions in other version ranges, from `TARGET_PATH_ALIASES`
+    // `;` separated entries of `target|version_req|path`
+    pub path_aliases: Vec<PathAlias>,
+    // seed the BFS with every public function of the vulnerable crate that reaches a
+    // target, from `INTRA_CRATE_CLOSURE` (default true)
+    pub intra_crate_closure: bool,
+    // crates someone is waiting on, from `PRIORITY_CRATES`, same syntax as
+    // `SENSITIVE_CRATES`; they are analyzed before the rest of their level and their
+    // verdicts are reported as soon as they are known
+    pub priority_crates: Vec<String>,
+    // fraction of dependents whose call-cg4rs run is repeated to detect nondeterministic
+    // output, from `VERIFY_SAMPLE_RATE` (0.0 - 1.0, default 0 = off)
+    pub verify_sample_rate: f64,
+    // limits of the exported propagation chains, from `CHAIN_TOP_K` (chains kept per
+    // dependent, 3 by default) and `CHAIN_MAX_LENGTH` (crates per chain, unlimited when
+    // unset)
+    pub chain_filter: ChainFilter,
+    // callers in these categories don't count as impact, from `EXCLUDE_CALL_SITES`
+    // comma separated `lib,test,example,bench,build_script`, default `test,example,bench`
+    pub excluded_call_sites: Vec<CallSite>,
+    // dependency tables a dependent may declare the parent in for it to count as
+    // affected, from `DEPENDENCY_KINDS`, comma separated `normal,build,dev` (default all)
+    // target-specific tables count as their kind
+    pub dependency_kinds: Vec<DependencyKind>,
+    // how the parent version is pinned in a dependent, from `PIN_STRATEGY`: `lockfile`
+    // (default, `cargo update --precise`) or `manifest` (rewrite the requirement to `=x.y.z`)
+    pub pin_strategy: PinStrategy,
+    // annotate dependents with whether a resolver picks the parent version on its own,
+    // from `SIMULATE_RESOLUTION` (default true)
+    pub simulate_resolution: bool,
+    // bisect the older versions of affected dependents for the first one calling a
+    // target, from `BISECT_INTRODUCTION` (default false)
+    pub bisect_introduction: bool,
+    // check whether upgrading the affected direct dependents of a root removes their
+    // exposure, from `FIX_DETECTION` (default false)
+    pub fix_detection: bool,
+    // attribute the call sites of affected dependents to the commit that introduced them
+    // with `git blame` on a clone of their repository, from `GIT_BLAME` (default false)
+    pub git_blame: bool,
+    // pre-release versions, from `PRERELEASE_VERSIONS`: `include` (default, whenever they
+    // are inside a range), `exclude` or `only` (only pre-release roots)
+    pub prerelease_policy: PrereleasePolicy,
+    // yanked versions of dependents and roots, from `YANKED_VERSIONS`: `skip` (default)
+    // or `flag` (analyze them and mark them in the report)
+    pub yanked_policy: YankedPolicy,
+    // feature configurations of a dependent call-cg4rs runs under, from `FEATURE_MATRIX`,
+    // comma separated `default`, `no-default`, `all` or features joined by `+`
+    // (default: only the default features)
+    pub feature_matrix: Vec<FeatureSet>,
+    // target triples call-cg4rs builds dependents for, from `--targets` (default: the host)
+    pub platforms: Vec<String>,
+    // run `cargo expand` when the target isn't in the raw sources, so that calls
+    // generated by macros pass the precheck, from `EXPAND_MACROS` (default false)
+    pub expand_macros: bool,
+    // how dependents are checked for references to the targets before call-cg4rs runs,
+    // from `PRECHECK`: `none`, `identifier`, `import` (default) or `ast`
+    // `none` for correctness-critical runs that can't afford a false negative
+    pub precheck: PrecheckKind,
+    // live registry API, from `REGISTRY_API` or `CRATES_IO_API_URL`, e.g.
+    // `https://crates.io/api/v1`, for crates.io only consulted when the database dump is
+    // older than `dump_max_age`, always for a private registry
+    pub crates_io_api: Option<String>,
+    // where root version lists and the requirements of listed dependents come from,
+    // from `METADATA_SOURCE`: `database` (default) or `index` (the registry's sparse index)
+    pub metadata_source: MetadataSource,
+    // from `DUMP_MAX_AGE_HOURS` (default 168, one week)
+    pub dump_max_age: Duration,
+    // how long the dependents of a crate queried from the database are reused, from
+    // `DEPENDENTS_CACHE_TTL_SECS` (default 3600)
+    pub dependents_cache_ttl: Duration,
+    // larger results are streamed again on every query instead of kept in memory, from
+    // `DEPENDENTS_CACHE_MAX_ROWS` (default 50000)
+    pub dependents_cache_max_rows: usize,
+    // shared target dir and compiler wrapper of the dependent builds
+    pub build_env: BuildEnv,
+    // never build a dependent whose build runs build scripts or proc macros outside
+    // `trusted_build_crates`, from `SAFE_BUILD` (default false); such dependents are
+    // reported as unverified when the precheck finds a target referenced
+    pub safe_build: bool,
+    // crates whose build scripts and proc macros may run in safe mode, from
+    // `TRUSTED_BUILD_CRATES`, same syntax as `SENSITIVE_CRATES`, e.g. `serde_derive,libc`
+    pub trusted_build_crates: Vec<String>,
+    pub timeouts: PhaseTimeouts,
+}
+
+/// time limits of the phases of analyzing one crate, each from `<PHASE>_TIMEOUT_SECS`,
+/// e.g. `BUILD_TIMEOUT_SECS`, 0 for no limit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseTimeouts {
+    // downloading the .crate file, retries and mirrors included (default 600)
+    pub download: Option<Duration>,
+    // unpacking it (default 120)
+    pub extract: Option<Duration>,
+    // pinning the parent version in the dependent (default 60)
+    pub patch: Option<Duration>,
+    // one call-cg4rs or cargo expand run, compiling the dependent included (default 1800)
+    pub build: Option<Duration>,
+    // all call-cg4rs runs on one dependent (default 3600)
+    pub analysis: Option<Duration>,
+}
+
+impl PhaseTimeouts {
+    pub fn from_env() -> Self {
+        let limit = |phase: &str, default: u64| {
+            let secs = env_parse(&format!("{}_TIMEOUT_SECS", phase), default);
+            (secs > 0).then(|| Duration::from_secs(secs))
+        };
+        Self {
+            download: limit("DOWNLOAD", 600),
+            extract: limit("EXTRACT", 120),
+            patch: limit("PATCH", 60),
+            build: limit("BUILD", 1800),
+            analysis: limit("ANALYSIS", 3600),
+        }
+    }
+}
+
+impl AnalyzerConfig {
+    pub fn from_env() -> Self {
+        Self {
+            sensitive_crates: env_list("SENSITIVE_CRATES"),
+            export_sql_url: env::var("EXPORT_SQL_URL").ok(),
+            export_crate_dot: env::var("EXPORT_CRATE_DOT").ok().map(PathBuf::from),
+            export_dot: env::var("EXPORT_DOT").ok().map(PathBuf::from),
+            export_crate_graphml: env::var("EXPORT_CRATE_GRAPHML").ok().map(PathBuf::from),
+            export_graphml: env::var("EXPORT_GRAPHML").ok().map(PathBuf::from),
+            export_gexf: env::var("EXPORT_GEXF").ok().map(PathBuf::from),
+            export_cypher: env::var("EXPORT_CYPHER").ok().map(PathBuf::from),
+            export_parquet: env::var("EXPORT_PARQUET").ok().map(PathBuf::from),
+            export_html: env::var("EXPORT_HTML").ok().map(PathBuf::from),
+            export_markdown: env::var("EXPORT_MARKDOWN").ok().map(PathBuf::from),
+            export_sarif: env::var("EXPORT_SARIF").ok().map(PathBuf::from),
+            export_osv_dir: env::var("EXPORT_OSV_DIR").ok().map(PathBuf::from),
+            export_vex: env::var("EXPORT_VEX").ok().map(PathBuf::from),
+            vex_vulnerability_id: env::var("VEX_VULNERABILITY_ID")
+                .or_else(|_| env::var("ADVISORY_ID"))
+                .ok(),
+            advisory_id: env::var("ADVISORY_ID").ok(),
+            save_graph: env::var("SAVE_GRAPH").ok().map(PathBuf::from),
+            checkpoint_dir: env::var("CHECKPOINT_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from("./checkpoints")),
+            checkpoint_interval: Duration::from_secs(env_parse("CHECKPOINT_INTERVAL_SECS", 600)),
+            dependent_retries: env_parse("DEPENDENT_RETRIES", 0),
+            path_aliases: env::var("TARGET_PATH_ALIASES")
+                .unwrap_or_default()
+                .split(';')
+                .filter(|spec| !spec.trim().is_empty())
+                .filter_map(|spec| match PathAlias::parse(spec) {
+                    Ok(alias) => Some(alias),
+                    Err(e) => {
+                        tracing::warn!("忽略无效的路径别名: {:#}", e);
+                        None
+                    }
+                })
+                .collect(),
+            intra_crate_closure: env_flag("INTRA_CRATE_CLOSURE", true),
+            priority_crates: env_list("PRIORITY_CRATES"),
+            verify_sample_rate: match env_parse("VERIFY_SAMPLE_RATE", 0.0) {
+                rate if (0.0..=1.0).contains(&rate) => rate,
+                rate => {
+                    tracing::warn!(
+                        "VERIFY_SAMPLE_RATE 应在 0.0 到 1.0 之间: {}，不做重复验证",
+                        rate
+                    );
+                    0.0
+                }
+            },
+            chain_filter: ChainFilter {
+                top_k: env_parse("CHAIN_TOP_K", 3),
+                max_length: env::var("CHAIN_MAX_LENGTH")
+                    .ok()
+                    .and_then(|value| value.parse().ok()),
+            },
+            excluded_call_sites: env::var("EXCLUDE_CALL_SITES")
+                .unwrap_or_else(|_| "test,example,bench".to_string())
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .filter_map(|name| {
+                    let site = CallSite::parse(name);
+                    if site.is_none() {
+                        tracing::warn!("忽略未知的调用位置类别: {}", name);
+                    }
+                    site
+                })
+                .collect(),
+            dependency_kinds: env::var("DEPENDENCY_KINDS")
+                .unwrap_or_else(|_| "normal,build,dev".to_string())
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .filter_map(|name| {
+                    let kind = DependencyKind::parse(name);
+                    if kind.is_none() {
+                        tracing::warn!("忽略未知的依赖类别: {}", name);
+                    }
+                    kind
+                })
+                .collect(),
+            pin_strategy: env::var("PIN_STRATEGY")
+                .ok()
+                .and_then(|name| match name.parse() {
+                    Ok(strategy) => Some(strategy),
+                    Err(e) => {
+                        tracing::warn!("{:#}，使用默认方式", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            simulate_resolution: env_flag("SIMULATE_RESOLUTION", true),
+            bisect_introduction: env_flag("BISECT_INTRODUCTION", false),
+            fix_detection: env_flag("FIX_DETECTION", false),
+            git_blame: env_flag("GIT_BLAME", false),
+            prerelease_policy: env::var("PRERELEASE_VERSIONS")
+                .ok()
+                .and_then(|name| match name.parse() {
+                    Ok(policy) => Some(policy),
+                    Err(e) => {
+                        tracing::warn!("{:#}，包含预发布版本", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            yanked_policy: env::var("YANKED_VERSIONS")
+                .ok()
+                .and_then(|name| match name.parse() {
+                    Ok(policy) => Some(policy),
+                    Err(e) => {
+                        tracing::warn!("{:#}，跳过撤回的版本", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            feature_matrix: env::var("FEATURE_MATRIX")
+                .unwrap_or_default()
+                .split(',')
+                .filter(|name| !name.trim().is_empty())
+                .filter_map(|name| match name.parse() {
+                    Ok(feature_set) => Some(feature_set),
+                    Err(e) => {
+                        tracing::warn!("忽略 feature 配置 {}: {:#}", name, e);
+                        None
+                    }
+                })
+                .collect(),
+            platforms: Vec::new(),
+            expand_macros: env_flag("EXPAND_MACROS", false),
+            precheck: env::var("PRECHECK")
+                .ok()
+                .and_then(|name| match name.parse() {
+                    Ok(kind) => Some(kind),
+                    Err(e) => {
+                        tracing::warn!("{:#}，使用默认策略", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            crates_io_api: REGISTRY.api.clone(),
+            metadata_source: env::var("METADATA_SOURCE")
+                .ok()
+                .and_then(|name| match name.parse() {
+                    Ok(source) => Some(source),
+                    Err(e) => {
+                        tracing::warn!("{:#}，使用数据库", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            dump_max_age: Duration::from_secs(
+                env_parse::<u64>("DUMP_MAX_AGE_HOURS", 168).saturating_mul(3600),
+            ),
+            dependents_cache_ttl: Duration::from_secs(env_parse("DEPENDENTS_CACHE_TTL_SECS", 3600)),
+            dependents_cache_max_rows: env_parse("DEPENDENTS_CACHE_MAX_ROWS", 50_000),
+            build_env: BuildEnv::from_env(),
+            safe_build: env_flag("SAFE_BUILD", false),
+            trusted_build_crates: env_list("TRUSTED_BUILD_CRATES"),
+            timeouts: PhaseTimeouts::from_env(),
+        }
+    }
+
+    /// whether the evidence of this crate has to be minimized
+    pub fn is_sensitive(&self, crate_name: &str) -> bool {
+        matches_any(&self.sensitive_crates, crate_name)
+    }
+
+    /// whether the build scripts and proc macros of this crate may run in safe mode
+    pub fn is_trusted_build(&self, crate_name: &str) -> bool {
+        matches_any(&self.trusted_build_crates, crate_name)
+    }
+
+    /// whether this crate jumps ahead of the normal frontier ordering
+    pub fn is_priority(&self, crate_name: &str) -> bool {
+        matches_any(&self.priority_crates, crate_name)
+    }
+}
+
+/// match a crate name against `name` / `prefix*` patterns
+fn matches_any(patterns: &[String], crate_name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => crate_name.starts_with(prefix),
+            None => crate_name == pattern,
+        })
+}
+
+/// read a value from an environment variable, falling back to the default when unset or invalid
+pub(crate) fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// read an on/off switch from an environment variable: `1`/`true` or `0`/`false`,
+/// anything else is reported and the default is used
+pub(crate) fn env_flag(key: &str, default: bool) -> bool {
+    let Ok(value) = env::var(key) else {
+        return default;
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" => true,
+        "0" | "false" => false,
+        _ => {
+            tracing::warn!(
+                "{} 的值无效: {}，应为 1/true 或 0/false，使用默认值 {}",
+                key,
+                value,
+                default
+            );
+            default
+        }
+    }
+}
+
+/// read a comma separated list from an environment variable
+fn env_list(key: &str) -> Vec<String> {
+    env::var(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::download::client_builder;
+use crate::limits::LIMITS;
+use crate::model::{DependentSource, ReverseDependency};
+
+const PER_PAGE: usize = 100;
+// 被限流后最多重试的次数
+const RATE_LIMIT_RETRIES: u32 = 5;
+// 响应没有 `Retry-After` 时的等待时间
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// sent with every registry request; the crates.io crawler policy asks for a way to
+/// contact the operator, given with `CRAWLER_CONTACT` (an email or URL), or replace the
+/// whole header with `HTTP_USER_AGENT`
+pub static USER_AGENT: Lazy<String> = Lazy::new(|| {
+    if let Ok(agent) = env::var("HTTP_USER_AGENT") {
+        return agent;
+    }
+    let base = concat!("cross_pro_cg/", env!("CARGO_PKG_VERSION"));
+    match env::var("CRAWLER_CONTACT") {
+        Ok(contact) => format!("{} (reverse dependency impact analysis; {})", base, contact),
+        Err(_) => {
+            warn!("未设置 CRAWLER_CONTACT，crates.io 要求爬虫的 User-Agent 带有联系方式");
+            format!("{} (reverse dependency impact analysis)", base)
+        }
+    }
+});
+
+/// client of the live crates.io web API, or of a private registry serving the same API
+#[derive(Debug, Clone)]
+pub struct CratesIoApi {
+    client: reqwest::Client,
+    // e.g. `https://crates.io/api/v1`
+    base_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReverseDependenciesPage {
+    dependencies: Vec<ApiDependency>,
+    versions: Vec<ApiVersion>,
+    meta: ApiMeta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiDependency {
+    version_id: u64,
+    req: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiVersion {
+    id: u64,
+    #[serde(rename = "crate")]
+    crate_name: String,
+    num: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMeta {
+    total: usize,
+}
+
+impl CratesIoApi {
+    pub fn new(base_url: &str, token: Option<&str>) -> Result<Self> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value = reqwest::header::HeaderValue::from_str(token)
+                .context("认证令牌不是合法的请求头")?;
+            value.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        let client = client_builder()
+            .default_headers(headers)
+            .build()
+            .context("创建HTTP客户端失败")?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// reverse dependencies of a crate, the API only lists the newest version of each
+    /// dependent
+    pub async fn reverse_dependencies(&self, crate_name: &str) -> Result<Vec<ReverseDependency>> {
+        info!("通过crates.io API查询依赖 {} 的crates", crate_name);
+        let mut dependents = Vec::new();
+        let mut page = 1;
+        loop {
+            let url = format!(
+                "{}/crates/{}/reverse_dependencies?page={}&per_page={}",
+                self.base_url, crate_name, page, PER_PAGE
+            );
+            let response: ReverseDependenciesPage = self.get_json(&url).await?;
+
+            let versions = response
+                .versions
+                .into_iter()
+                .map(|version| (version.id, version))
+                .collect::<HashMap<_, _>>();
+            let count = response.dependencies.len();
+            for dependency in response.dependencies {
+                if let Some(version) = versions.get(&dependency.version_id) {
+                    dependents.push(
+                        ReverseDependency::new(
+                            version.crate_name.clone(),
+                            version.num.clone(),
+                            dependency.req,
+                        )
+                        .with_source(DependentSource::LiveApi)
+                        .with_yanked(version.yanked),
+                    );
+                }
+            }
+            if count < PER_PAGE || page * PER_PAGE >= response.meta.total {
+                break;
+            }
+            page += 1;
+        }
+        info!("crates.io API返回 {} 个依赖者", dependents.len());
+        Ok(dependents)
+    }
+
+    /// GET a JSON document, within the request rate and waiting out `429 Too Many Requests`
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            LIMITS.throttle(url).await;
+            let response = self
+                .client
+                .get(url)
+                .send()
+                .await
+                .context(format!("请求失败: {}", url))?;
+            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                if attempts > RATE_LIMIT_RETRIES {
+                    bail!("请求被限流: {}", url);
+                }
+                let wait = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok())
+                    .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs);
+                warn!("请求被限流，{} 秒后重试: {}", wait.as_secs(), url);
+                tokio::time::sleep(wait).await;
+                continue;
+            }
+            return response
+                .error_for_status()
+                .context(format!("请求失败: {}", url))?
+                .json()
+                .await
+                .context(format!("解析响应失败: {}", url));
+        }
+    }
+}
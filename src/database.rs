@@ -1,97 +1,740 @@
+use std::collections::HashMap;
 use std::env;
-use std::time::Duration;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use sqlx::{postgres::PgPoolOptions, PgPool, Row};
-use tracing::info;
+use futures::{Stream, StreamExt};
+use sqlx::any::AnyPoolOptions;
+use sqlx::{AnyPool, Row};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{info, warn};
 
+use crate::cache::MetadataCache;
+use crate::config::{env_flag, env_parse};
+use crate::diagnostics::Invocation;
+use crate::limits::LIMITS;
 use crate::model::ReverseDependency;
+use crate::resolution::Release;
+
+/// the kind of database behind the connection string, queries differing between the
+/// two are written once per backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    // a crates.io database dump restored into Postgres
+    Postgres,
+    // a local metadata file with the same tables and columns
+    Sqlite,
+}
+
+impl Backend {
+    fn of(url: &str) -> Result<Self> {
+        match url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("postgres" | "postgresql") => Ok(Backend::Postgres),
+            Some("sqlite") => Ok(Backend::Sqlite),
+            _ => anyhow::bail!("不支持的数据库地址: {}", url),
+        }
+    }
+
+    /// unix time of a timestamp expression as a float
+    fn epoch(self, column: &str) -> String {
+        match self {
+            Backend::Postgres => format!("EXTRACT(EPOCH FROM {})::float8", column),
+            Backend::Sqlite => format!("CAST(strftime('%s', {}) AS REAL)", column),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Database {
-    pool: PgPool,
+    pool: AnyPool,
+    backend: Backend,
+    // crate name -> all published versions
+    versions_cache: Arc<MetadataCache<String, Vec<String>>>,
+    // the dependents query, built once because the publish time differs per backend and
+    // the row stream borrows the statement
+    dependents_query: String,
+    // unix time of the newest version in the dump, i.e. when the dump was taken
+    dump_timestamp: Arc<OnceCell<f64>>,
+    // verdicts waiting to be written, None unless `PERSIST_RESULTS` is set
+    results: Option<Arc<Mutex<ResultBuffer>>>,
+    // times a query failing with a transient error is repeated, from `PG_QUERY_RETRIES`
+    query_retries: u32,
+}
+
+// wait before the n-th retry of a query or of connecting: n times this
+const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+
+// rows per multi-row INSERT, well below the 65535 bind parameters of a statement
+const RESULT_INSERT_CHUNK: usize = 1000;
+
+// bound bytes per INSERT, captured build output makes some rows tens of kilobytes
+pub const INSERT_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+// crate names per `IN (...)` list
+const NAME_LIST_CHUNK: usize = 500;
+
+const RESULTS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS cg_dependent_results (
+        run_id TEXT NOT NULL,
+        parent_name TEXT NOT NULL,
+        parent_version TEXT NOT NULL,
+        crate_name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        verdict TEXT NOT NULL,
+        detail TEXT NOT NULL,
+        recorded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    )";
+
+const INVOCATIONS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS cg_dependent_invocations (
+        run_id TEXT NOT NULL,
+        parent_name TEXT NOT NULL,
+        parent_version TEXT NOT NULL,
+        crate_name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        command TEXT NOT NULL,
+        exit_code INTEGER,
+        duration_ms BIGINT NOT NULL,
+        failure TEXT,
+        stdout TEXT NOT NULL,
+        stderr TEXT NOT NULL,
+        recorded_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    )";
+
+/// verdict on one dependent, persisted while the run is still going
+#[derive(Debug, Clone)]
+pub struct ResultRow {
+    pub parent_name: String,
+    pub parent_version: String,
+    pub crate_name: String,
+    pub version: String,
+    // `affected` or `cleared`
+    pub verdict: &'static str,
+    // the called target functions, or the clearance reason
+    pub detail: String,
+}
+
+/// results are written in multi-row INSERTs once `flush_rows` are buffered or
+/// `flush_interval` has passed since the last write; the cargo / call-cg4rs invocations
+/// of each dependent go to `cg_dependent_invocations` the same way
+#[derive(Debug)]
+struct ResultBuffer {
+    run_id: String,
+    rows: Vec<ResultRow>,
+    invocations: Vec<Invocation>,
+    last_flush: Instant,
+    flush_rows: usize,
+    flush_interval: Duration,
 }
 
 impl Database {
+    /// whether a metadata database is configured at all: `DATABASE_URL` or any of the
+    /// `PG_*` connection variables is set
+    pub fn configured() -> bool {
+        [
+            "DATABASE_URL",
+            "PG_HOST",
+            "PG_USER",
+            "PG_PASSWORD",
+            "PG_DATABASE",
+        ]
+        .iter()
+        .any(|name| env::var_os(name).is_some())
+    }
+
+    /// connect to `DATABASE_URL`, either a `postgres://` / `sqlite://` url or the path
+    /// of an SQLite file, or when unset to the Postgres server described by `PG_HOST`,
+    /// `PG_USER`, `PG_PASSWORD` and `PG_DATABASE`
     pub async fn new() -> Result<Self> {
         // 从环境变量获取数据库连接信息
-        let db_host = env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string());
-        let db_user = env::var("PG_USER").unwrap_or_else(|_| "mega".to_string());
-        let db_pass = env::var("PG_PASSWORD").unwrap_or_else(|_| "mega".to_string());
-        let db_name = env::var("PG_DATABASE").unwrap_or_else(|_| "crates_io_db".to_string());
+        let connection_string = match env::var("DATABASE_URL") {
+            Ok(url) if url.contains("://") => url,
+            Ok(path) => format!("sqlite://{}", path),
+            Err(_) => {
+                let db_host = env::var("PG_HOST").unwrap_or_else(|_| "localhost".to_string());
+                let db_user = env::var("PG_USER").unwrap_or_else(|_| "mega".to_string());
+                let db_pass = env::var("PG_PASSWORD").unwrap_or_else(|_| "mega".to_string());
+                let db_name =
+                    env::var("PG_DATABASE").unwrap_or_else(|_| "crates_io_db".to_string());
+                info!("连接到数据库 {}@{}/{}", db_user, db_host, db_name);
+                format!("postgres://{}:{}@{}/{}", db_user, db_pass, db_host, db_name)
+            }
+        };
+        let backend = Backend::of(&connection_string)?;
+        if backend == Backend::Sqlite {
+            info!("使用 SQLite 元数据库 {}", connection_string);
+        }
+        sqlx::any::install_default_drivers();
 
-        let connection_string =
-            format!("postgres://{}:{}@{}/{}", db_user, db_pass, db_host, db_name);
+        // 创建连接池：借出前检查连接是否可用，断开的连接被丢弃并自动重连，
+        // 长时间空闲或存活过久的连接主动关闭，避免被服务器或中间的代理悄悄断开
+        let query_retries = env_parse("PG_QUERY_RETRIES", 3);
+        let options = AnyPoolOptions::new()
+            .max_connections(env_parse("PG_POOL_SIZE", LIMITS.db_connections()))
+            .acquire_timeout(Duration::from_secs(env_parse(
+                "PG_ACQUIRE_TIMEOUT_SECS",
+                10,
+            )))
+            .idle_timeout(Some(Duration::from_secs(env_parse(
+                "PG_IDLE_TIMEOUT_SECS",
+                300,
+            ))))
+            .max_lifetime(Some(Duration::from_secs(1800)))
+            .test_before_acquire(true);
+        let mut attempt = 0;
+        let pool = loop {
+            attempt += 1;
+            match options.clone().connect(&connection_string).await {
+                Ok(pool) => break pool,
+                Err(e) if attempt <= query_retries && is_transient(&e) => {
+                    warn!("连接数据库失败，第{}次重试: {}", attempt, e);
+                    tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+                }
+                Err(e) => return Err(e).context("无法连接到数据库"),
+            }
+        };
 
-        info!("连接到数据库 {}@{}/{}", db_user, db_host, db_name);
+        info!("数据库连接成功");
 
-        // 创建连接池
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .acquire_timeout(Duration::from_secs(3))
-            .connect(&connection_string)
-            .await
-            .context("无法连接到数据库")?;
+        // 结果表写在同一个数据库里，只读的快照库不要打开
+        let persist = env_flag("PERSIST_RESULTS", false);
+        let results = if persist {
+            for schema in [RESULTS_SCHEMA, INVOCATIONS_SCHEMA] {
+                sqlx::query(schema)
+                    .execute(&pool)
+                    .await
+                    .context("创建结果表失败")?;
+            }
+            let run_id = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_millis().to_string())
+                .unwrap_or_default();
+            info!(
+                "分析结果将写入 cg_dependent_results 和 cg_dependent_invocations, run_id={}",
+                run_id
+            );
+            Some(Arc::new(Mutex::new(ResultBuffer {
+                run_id,
+                rows: Vec::new(),
+                invocations: Vec::new(),
+                last_flush: Instant::now(),
+                flush_rows: env_parse("RESULTS_FLUSH_ROWS", 500),
+                flush_interval: Duration::from_secs(env_parse("RESULTS_FLUSH_SECS", 30)),
+            })))
+        } else {
+            None
+        };
 
-        info!("数据库连接成功");
+        Ok(Self {
+            pool,
+            backend,
+            versions_cache: Arc::new(MetadataCache::new("crate_versions")),
+            dependents_query: format!(
+                "WITH target_crate AS (
+                    SELECT id FROM crates WHERE name = $1
+                )
+                SELECT DISTINCT c.name, v.num, d.req,
+                    CAST(CASE WHEN v.yanked THEN 1 ELSE 0 END AS BIGINT) AS yanked,
+                    {} AS published_at
+                FROM dependencies d
+                JOIN versions v ON d.version_id = v.id
+                JOIN crates c ON v.crate_id = c.id
+                WHERE d.crate_id = (SELECT id FROM target_crate)
+                AND d.req IS NOT NULL
+                ORDER BY c.name, v.num",
+                backend.epoch("v.created_at")
+            ),
+            dump_timestamp: Arc::new(OnceCell::new()),
+            results,
+            query_retries,
+        })
+    }
+
+    /// run a query, repeating it when it fails with a transient error such as a dropped
+    /// connection; `what` describes it in the logs and the error
+    async fn retry<T, F, Fut>(&self, what: &str, mut query: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match query().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt <= self.query_retries && is_transient(&e) => {
+                    warn!("{}遇到临时错误，第{}次重试: {}", what, attempt, e);
+                    tokio::time::sleep(RETRY_BACKOFF * attempt).await;
+                }
+                Err(e) => return Err(e).context(format!("{}失败", what)),
+            }
+        }
+    }
+
+    /// how often a caller consuming `stream_dependents` should restart it after a
+    /// transient error, see `is_transient_error`
+    pub fn query_retries(&self) -> u32 {
+        self.query_retries
+    }
 
-        Ok(Self { pool })
+    /// buffer a verdict, writing the buffer when it is full or old enough
+    pub async fn record_result(&self, row: ResultRow) {
+        let Some(results) = &self.results else {
+            return;
+        };
+        let mut buffer = results.lock().await;
+        buffer.rows.push(row);
+        self.flush_if_due(&mut buffer).await;
+    }
+
+    /// buffer an invocation on a dependent, see `record_result`
+    pub async fn record_invocation(&self, invocation: Invocation) {
+        let Some(results) = &self.results else {
+            return;
+        };
+        let mut buffer = results.lock().await;
+        buffer.invocations.push(invocation);
+        self.flush_if_due(&mut buffer).await;
+    }
+
+    /// whether verdicts and invocations are written to the database at all
+    pub fn persists_results(&self) -> bool {
+        self.results.is_some()
+    }
+
+    async fn flush_if_due(&self, buffer: &mut ResultBuffer) {
+        if buffer.rows.len() + buffer.invocations.len() >= buffer.flush_rows
+            || buffer.last_flush.elapsed() >= buffer.flush_interval
+        {
+            self.write_results(buffer).await;
+        }
+    }
+
+    /// write all buffered verdicts, called when the run ends or is interrupted
+    pub async fn flush_results(&self) {
+        if let Some(results) = &self.results {
+            self.write_results(&mut *results.lock().await).await;
+        }
+    }
+
+    async fn write_results(&self, buffer: &mut ResultBuffer) {
+        buffer.last_flush = Instant::now();
+        let rows = std::mem::take(&mut buffer.rows);
+        let invocations = std::mem::take(&mut buffer.invocations);
+        if rows.is_empty() && invocations.is_empty() {
+            return;
+        }
+        let _permit = LIMITS.db_queries.acquire().await.unwrap();
+        let run_id = &buffer.run_id;
+        for chunk in rows.chunks(RESULT_INSERT_CHUNK) {
+            let values = (0..chunk.len())
+                .map(|i| format!("({})", placeholders(i * 7 + 1, 7)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let statement = format!(
+                "INSERT INTO cg_dependent_results \
+                 (run_id, parent_name, parent_version, crate_name, version, verdict, detail) \
+                 VALUES {}",
+                values
+            );
+            let insert = || {
+                let mut query = sqlx::query(&statement);
+                for row in chunk {
+                    query = query
+                        .bind(run_id.as_str())
+                        .bind(row.parent_name.as_str())
+                        .bind(row.parent_version.as_str())
+                        .bind(row.crate_name.as_str())
+                        .bind(row.version.as_str())
+                        .bind(row.verdict)
+                        .bind(row.detail.as_str());
+                }
+                query.execute(&self.pool)
+            };
+            if let Err(e) = self.retry("写入分析结果", insert).await {
+                warn!("{} 条分析结果未能写入: {:#}", chunk.len(), e);
+            }
+        }
+        let chunks = byte_chunks(
+            &invocations,
+            RESULT_INSERT_CHUNK,
+            INSERT_CHUNK_BYTES,
+            |row| row.command.len() + row.stdout.len() + row.stderr.len(),
+        );
+        for chunk in chunks {
+            let values = (0..chunk.len())
+                .map(|i| format!("({})", placeholders(i * 11 + 1, 11)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let statement = format!(
+                "INSERT INTO cg_dependent_invocations \
+                 (run_id, parent_name, parent_version, crate_name, version, command, \
+                 exit_code, duration_ms, failure, stdout, stderr) \
+                 VALUES {}",
+                values
+            );
+            let insert = || {
+                let mut query = sqlx::query(&statement);
+                for invocation in chunk {
+                    query = query
+                        .bind(run_id.as_str())
+                        .bind(invocation.parent_name.as_str())
+                        .bind(invocation.parent_version.as_str())
+                        .bind(invocation.crate_name.as_str())
+                        .bind(invocation.version.as_str())
+                        .bind(invocation.command.as_str())
+                        .bind(invocation.exit_code)
+                        .bind(invocation.duration.as_millis() as i64)
+                        .bind(invocation.failure.map(|kind| kind.as_str()))
+                        .bind(invocation.stdout.as_str())
+                        .bind(invocation.stderr.as_str());
+                }
+                query.execute(&self.pool)
+            };
+            if let Err(e) = self.retry("写入调用记录", insert).await {
+                warn!("{} 条调用记录未能写入: {:#}", chunk.len(), e);
+            }
+        }
+        info!(
+            "已写入 {} 条分析结果和 {} 条调用记录",
+            rows.len(),
+            invocations.len()
+        );
     }
 
     // 查询crate的所有版本
     pub async fn query_crate_versions(&self, crate_name: &str) -> Result<Vec<String>> {
+        if let Some(versions) = self.versions_cache.get(&crate_name.to_string()) {
+            return Ok(versions);
+        }
+
         info!("查询crate {} 的所有版本", crate_name);
 
-        let rows = sqlx::query(
-            "SELECT num FROM versions
-             JOIN crates ON versions.crate_id = crates.id
-             WHERE crates.name = $1
-             ORDER BY versions.id DESC",
-        )
-        .bind(crate_name)
-        .fetch_all(&self.pool)
-        .await
-        .context("查询crate版本失败")?;
+        let _permit = LIMITS.db_queries.acquire().await.unwrap();
+        let rows = self
+            .retry("查询crate版本", || {
+                sqlx::query(
+                    "SELECT num FROM versions
+                     JOIN crates ON versions.crate_id = crates.id
+                     WHERE crates.name = $1
+                     ORDER BY versions.id DESC",
+                )
+                .bind(crate_name)
+                .fetch_all(&self.pool)
+            })
+            .await?;
 
-        let versions = rows.iter().map(|row| row.get::<String, _>("num")).collect();
+        let versions: Vec<String> = rows.iter().map(|row| row.get::<String, _>("num")).collect();
 
         info!("找到 {} 个版本", rows.len());
+        self.versions_cache
+            .put(crate_name.to_string(), versions.clone());
         Ok(versions)
     }
 
-    // 查询依赖某个crate的所有crates
-    pub async fn query_dependents(&self, crate_name: &str) -> Result<Vec<ReverseDependency>> {
+    /// 查询crate的所有版本及撤回状态和发布时间，用于模拟依赖解析
+    pub async fn query_releases(&self, crate_name: &str) -> Result<Vec<Release>> {
+        let _permit = LIMITS.db_queries.acquire().await.unwrap();
+        let statement = format!(
+            "SELECT num,
+                CAST(CASE WHEN versions.yanked THEN 1 ELSE 0 END AS BIGINT) AS yanked,
+                {} AS published_at
+             FROM versions
+             JOIN crates ON versions.crate_id = crates.id
+             WHERE crates.name = $1",
+            self.backend.epoch("versions.created_at")
+        );
+        let rows = self
+            .retry("查询crate发布记录", || {
+                sqlx::query(&statement)
+                    .bind(crate_name)
+                    .fetch_all(&self.pool)
+            })
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| Release {
+                num: row.get::<String, _>("num"),
+                yanked: row.get::<i64, _>("yanked") != 0,
+                published_at: row.get::<Option<f64>, _>("published_at"),
+            })
+            .collect())
+    }
+
+    /// 逐行返回依赖某个crate的所有crates，按名称排序，同名的各版本相邻
+    ///
+    /// 版本要求是 semver 语法，数据库里无法判断是否匹配，由调用方边读边筛选，
+    /// 热门 crate 的几十万行依赖关系不会一次性读进内存；流结束前一直占用一个查询名额
+    pub async fn stream_dependents<'a>(
+        &'a self,
+        crate_name: &'a str,
+    ) -> impl Stream<Item = Result<ReverseDependency>> + 'a {
         info!("查询依赖 {} 的所有crates", crate_name);
 
-        let query = "WITH target_crate AS (
-                SELECT id FROM crates WHERE name = $1
-            )
-            SELECT DISTINCT c.name, v.num, d.req
-            FROM dependencies d
-            JOIN versions v ON d.version_id = v.id
-            JOIN crates c ON v.crate_id = c.id
-            WHERE d.crate_id = (SELECT id FROM target_crate)
-            AND d.req IS NOT NULL
-            ORDER BY c.name, v.num";
-
-        let rows = sqlx::query(query)
+        let permit = LIMITS.db_queries.acquire().await.unwrap();
+        sqlx::query(&self.dependents_query)
             .bind(crate_name)
-            .fetch_all(&self.pool)
-            .await
-            .context("查询依赖者失败")?;
-
-        let dependents = rows
-            .iter()
-            .map(|row| {
-                ReverseDependency::new(
+            .fetch(&self.pool)
+            .map(move |row| {
+                let _permit = &permit;
+                let row = row.context("查询依赖者失败")?;
+                Ok(ReverseDependency::new(
                     row.get::<String, _>("name"),
                     row.get::<String, _>("num"),
                     row.get::<String, _>("req"),
                 )
+                .with_yanked(row.get::<i64, _>("yanked") != 0)
+                .with_published_at(row.get::<Option<f64>, _>("published_at")))
             })
-            .collect();
+    }
+
+    /// 一次查询多个crate的依赖者，按crate名称分组，每组内的顺序与 `stream_dependents` 相同
+    ///
+    /// 远程数据库上每个节点单独查询的往返时间比查询本身还长。结果逐行读取，依赖者超过
+    /// `max_rows` 的crate不出现在结果中，由节点自己流式读取
+    pub async fn query_dependents_many(
+        &self,
+        crate_names: &[String],
+        max_rows: usize,
+    ) -> Result<HashMap<String, Vec<ReverseDependency>>> {
+        info!("批量查询 {} 个crate的依赖者", crate_names.len());
 
-        info!("找到 {} 个依赖者", rows.len());
+        let _permit = LIMITS.db_queries.acquire().await.unwrap();
+        let statement = format!(
+            "SELECT DISTINCT t.name AS target, c.name, v.num, d.req,
+                CAST(CASE WHEN v.yanked THEN 1 ELSE 0 END AS BIGINT) AS yanked,
+                {} AS published_at
+             FROM dependencies d
+             JOIN crates t ON d.crate_id = t.id
+             JOIN versions v ON d.version_id = v.id
+             JOIN crates c ON v.crate_id = c.id
+             WHERE t.name IN ({})
+             AND d.req IS NOT NULL
+             ORDER BY t.name, c.name, v.num",
+            self.backend.epoch("v.created_at"),
+            placeholders(1, crate_names.len())
+        );
+        let (dependents, oversized) = self
+            .retry("批量查询依赖者", || async {
+                // 没有依赖者的crate也返回空列表，调用方据此知道不必再查
+                let mut dependents: HashMap<String, Vec<ReverseDependency>> = crate_names
+                    .iter()
+                    .map(|name| (name.clone(), Vec::new()))
+                    .collect();
+                let mut oversized = 0;
+                let mut rows = bind_all(sqlx::query(&statement), crate_names).fetch(&self.pool);
+                while let Some(row) = rows.next().await.transpose()? {
+                    let target = row.get::<String, _>("target");
+                    // 已经因为过大被丢弃的crate跳过剩下的行
+                    let Some(group) = dependents.get_mut(&target) else {
+                        continue;
+                    };
+                    if group.len() >= max_rows {
+                        dependents.remove(&target);
+                        oversized += 1;
+                        continue;
+                    }
+                    group.push(
+                        ReverseDependency::new(
+                            row.get::<String, _>("name"),
+                            row.get::<String, _>("num"),
+                            row.get::<String, _>("req"),
+                        )
+                        .with_yanked(row.get::<i64, _>("yanked") != 0)
+                        .with_published_at(row.get::<Option<f64>, _>("published_at")),
+                    );
+                }
+                Ok((dependents, oversized))
+            })
+            .await?;
+        info!(
+            "找到 {} 条依赖关系，{} 个crate的依赖者过多未读取",
+            dependents.values().map(Vec::len).sum::<usize>(),
+            oversized
+        );
         Ok(dependents)
     }
+
+    // 查询crates的仓库地址，没有填写仓库的crate不出现在结果中
+    pub async fn query_repositories(
+        &self,
+        crate_names: &[String],
+    ) -> Result<HashMap<String, String>> {
+        info!("查询 {} 个crate的仓库地址", crate_names.len());
+
+        let _permit = LIMITS.db_queries.acquire().await.unwrap();
+        let mut repositories = HashMap::new();
+        for chunk in crate_names.chunks(NAME_LIST_CHUNK) {
+            let statement = format!(
+                "SELECT name, repository FROM crates
+                 WHERE name IN ({}) AND repository IS NOT NULL AND repository <> ''",
+                placeholders(1, chunk.len())
+            );
+            let rows = self
+                .retry("查询仓库地址", || {
+                    bind_all(sqlx::query(&statement), chunk).fetch_all(&self.pool)
+                })
+                .await?;
+            repositories.extend(rows.iter().map(|row| {
+                (
+                    row.get::<String, _>("name"),
+                    row.get::<String, _>("repository"),
+                )
+            }));
+        }
+        Ok(repositories)
+    }
+
+    /// 各 crate 所有版本的下载次数：(crate 名, 版本, 下载次数)
+    pub async fn query_version_downloads(
+        &self,
+        crate_names: &[String],
+    ) -> Result<Vec<(String, String, i64)>> {
+        let _permit = LIMITS.db_queries.acquire().await.unwrap();
+        let mut downloads = Vec::new();
+        for chunk in crate_names.chunks(NAME_LIST_CHUNK) {
+            let statement = format!(
+                "SELECT c.name, v.num, CAST(v.downloads AS BIGINT) AS downloads
+                 FROM versions v JOIN crates c ON c.id = v.crate_id
+                 WHERE c.name IN ({})",
+                placeholders(1, chunk.len())
+            );
+            let rows = self
+                .retry("查询下载次数", || {
+                    bind_all(sqlx::query(&statement), chunk).fetch_all(&self.pool)
+                })
+                .await?;
+            downloads.extend(rows.iter().map(|row| {
+                (
+                    row.get::<String, _>("name"),
+                    row.get::<String, _>("num"),
+                    row.get::<i64, _>("downloads"),
+                )
+            }));
+        }
+        Ok(downloads)
+    }
+
+    // 数据库快照的时间：最新发布的版本的创建时间
+    pub async fn dump_age(&self) -> Result<Duration> {
+        let timestamp = self
+            .dump_timestamp
+            .get_or_try_init(|| async {
+                let _permit = LIMITS.db_queries.acquire().await.unwrap();
+                let statement = format!(
+                    "SELECT {} AS ts FROM versions",
+                    self.backend.epoch("MAX(created_at)")
+                );
+                let row = self
+                    .retry("查询数据库快照时间", || {
+                        sqlx::query(&statement).fetch_one(&self.pool)
+                    })
+                    .await?;
+                Ok::<_, anyhow::Error>(row.get::<Option<f64>, _>("ts").unwrap_or_default())
+            })
+            .await?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        Ok(Duration::from_secs_f64((now - timestamp).max(0.0)))
+    }
+
+    /// log the hit rates of the metadata caches
+    pub fn log_cache_stats(&self) {
+        self.versions_cache.log_stats();
+    }
+}
+
+/// `$start, $start+1, ...`, `count` numbered placeholders understood by both backends
+pub fn placeholders(start: usize, count: usize) -> String {
+    (start..start + count)
+        .map(|n| format!("${}", n))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// consecutive slices of `rows` with at most `max_rows` rows and at most `max_bytes`
+/// bytes as measured by `size`, a single larger row gets a slice of its own
+pub fn byte_chunks<T>(
+    rows: &[T],
+    max_rows: usize,
+    max_bytes: usize,
+    size: impl Fn(&T) -> usize,
+) -> Vec<&[T]> {
+    let mut chunks = Vec::new();
+    let (mut start, mut bytes) = (0, 0);
+    for (i, row) in rows.iter().enumerate() {
+        let row_bytes = size(row);
+        if i > start && (i - start >= max_rows || bytes + row_bytes > max_bytes) {
+            chunks.push(&rows[start..i]);
+            (start, bytes) = (i, 0);
+        }
+        bytes += row_bytes;
+    }
+    if start < rows.len() {
+        chunks.push(&rows[start..]);
+    }
+    chunks
+}
+
+fn bind_all<'q>(
+    mut query: sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>>,
+    values: &'q [String],
+) -> sqlx::query::Query<'q, sqlx::Any, sqlx::any::AnyArguments<'q>> {
+    for value in values {
+        query = query.bind(value.as_str());
+    }
+    query
+}
+
+/// errors worth repeating the query for: lost or refused connections, an exhausted
+/// pool, a server shutting down or too busy, and serialization failures
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::Protocol(_)
+        | sqlx::Error::PoolTimedOut => true,
+        // SQLSTATE class 08 connection exception, 57P admin shutdown / crash,
+        // 53300 too many connections, 40001 / 40P01 serialization failure / deadlock;
+        // SQLite result codes are numbers, 5 busy and 6 locked (extended codes keep them
+        // in the low byte)
+        sqlx::Error::Database(e) => e.code().is_some_and(|code| {
+            code.starts_with("08")
+                || code.starts_with("57P")
+                || matches!(code.as_ref(), "53300" | "40001" | "40P01")
+                || (code.len() < 5 && code.parse::<u32>().is_ok_and(|n| matches!(n & 0xff, 5 | 6)))
+        }),
+        _ => false,
+    }
+}
+
+/// whether an error returned by a `Database` method has a transient cause
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<sqlx::Error>()
+            .is_some_and(is_transient)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_are_bounded_by_rows_and_bytes() {
+        let sizes = [10, 10, 10, 50, 10, 10, 10, 10];
+        let lengths = |max_rows, max_bytes| {
+            byte_chunks(&sizes, max_rows, max_bytes, |size| *size)
+                .iter()
+                .map(|chunk| chunk.len())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lengths(3, 1000), [3, 3, 2]);
+        // 单独超过上限的行自成一块
+        assert_eq!(lengths(100, 30), [3, 1, 3, 1]);
+        assert!(byte_chunks(&[] as &[usize], 3, 30, |size| *size).is_empty());
+    }
 }
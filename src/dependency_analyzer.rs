@@ -1,23 +1,45 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Semaphore;
 
 use anyhow::{Context, Result};
 use futures::{stream, StreamExt};
 use semver::{Version, VersionReq};
 use tokio::fs as tokio_fs;
-use tokio::process::Command;
-use tokio::time::{timeout, Duration};
+use tokio::task::{JoinHandle, JoinSet};
 use tracing::{info, warn};
 
-use crate::database::Database;
-use crate::model::{Krate, ReverseDependency};
-
-// 在文件顶部添加常量定义
-const MAX_CONCURRENT_TASKS: usize = 6;
-const BATCH_SIZE: usize = 100;
+use crate::cache::MetadataCache;
+use crate::callers::{self, ApiExposure};
+use crate::checkpoint::{AffectedEntry, ClearedEntry, IntroducedEntry, NodeCheckpoint};
+use crate::config::AnalyzerConfig;
+use crate::crates_io::CratesIoApi;
+use crate::database::{is_transient_error, Database, ResultRow};
+use crate::determinism::DeterminismStats;
+use crate::diagnostics::{self, DependentContext, RecordedOutput, DIAGNOSTICS};
+use crate::failure::{with_timeout, BuildFailure, FailureKind, PhaseTimeout, Retry};
+use crate::git::{self, VcsInfo};
+use crate::graph::{
+    format_kinds, Blame, ClearedDependent, FixAdvice, Graph, Introduction, Remedy,
+};
+use crate::index::{MetadataSource, INDEX};
+use crate::limits::LIMITS;
+use crate::osv;
+use crate::precheck::{Confidence, SourceIndex};
+use crate::reexport;
+use crate::resolution::{ParentReleases, Release};
+use crate::registry::REGISTRY;
+use crate::scheduler::BatchScheduler;
+use crate::store::STORE;
+use crate::surface::PublicSurface;
+use crate::target::{self, FunctionTarget, TargetSpec};
+use crate::toolchain::{self, Toolchain};
+use crate::model::{
+    hashed_path, normalize_crate_name, AnalysisOutcome, AnalysisRoot, Clearance, ClearanceReason,
+    DeclaredDependency, DependencyKind, DependentSource, FeatureSet, FunctionCalls, Krate,
+    ManifestGuard, ReverseDependency, YankedPolicy, MANIFEST_CACHE,
+};
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct VisitedCrateVersion {
@@ -25,21 +47,218 @@ pub struct VisitedCrateVersion {
     pub version: String,
 }
 
+impl VisitedCrateVersion {
+    /// `foo-bar` and `foo_bar` are the same crate
+    pub fn new(name: &str, version: &str) -> Self {
+        Self {
+            name: normalize_crate_name(name),
+            version: version.to_owned(),
+        }
+    }
+}
+
+// 一次批量查询依赖者的 crate 数
+const DEPENDENTS_BATCH_SIZE: usize = 32;
+// 选择预取名称时查看的队列长度
+const PREFETCH_WINDOW: usize = 4 * DEPENDENTS_BATCH_SIZE;
+
+/// 查询依赖者时的计数，只用于日志
+#[derive(Debug, Default)]
+struct DependentStats {
+    rows: usize,
+    crates: usize,
+    versions: usize,
+    // matching versions left out because they were yanked
+    yanked: usize,
+}
+
+/// 一个节点的分析结果：受影响的依赖者、确认未受影响的依赖者和二分定位到的引入版本
+type NodeResult = (
+    Vec<(Krate, ReverseDependency, FunctionCalls)>,
+    Vec<ClearedEntry>,
+    Vec<IntroducedEntry>,
+);
+
+/// (父节点 `name@version`, 依赖者) -> 依赖者的版本
+type DependentHistory = HashMap<(String, String), Vec<ReverseDependency>>;
+
+/// BFS 队列中的节点：crate 版本，以及要在它的依赖者中查找调用者的目标函数
+///
+/// 根节点的目标是用户给出的漏洞函数，更深层节点的目标是上一层找到的公开调用者函数，
+/// 这样只调用中间 crate 包装 API 的依赖者也能被发现
+#[derive(Debug, Clone)]
+pub struct BfsNode {
+    pub krate: Krate,
+    pub targets: Vec<FunctionTarget>,
+    // 根节点为 0，依赖者为父节点加 1
+    pub depth: usize,
+}
+
+/// 遍历中各节点的状态，只在 `bfs_from_queue` 的循环里修改
+///
+/// 同一 crate 版本最多排队一次、最多同时处理一次：还在排队时新目标直接合并，正在处理时
+/// 暂存起来，处理完后所有父节点带来的新目标合并成一次追加分析
+#[derive(Debug, Default)]
+struct BfsState {
+    // 排队顺序，节点本身在 `queued` 中，按键查找和合并不必扫描队列
+    order: VecDeque<VisitedCrateVersion>,
+    queued: HashMap<VisitedCrateVersion, BfsNode>,
+    // 正在处理的节点，任务异常退出时也能知道是哪个节点
+    tasks: HashMap<tokio::task::Id, BfsNode>,
+    running: HashSet<VisitedCrateVersion>,
+    // 正在处理的节点又得到的新目标，以及它是否优先
+    held: HashMap<VisitedCrateVersion, (BfsNode, bool)>,
+}
+
+impl BfsState {
+    fn key(node: &BfsNode) -> VisitedCrateVersion {
+        VisitedCrateVersion::new(&node.krate.name(), &node.krate.version())
+    }
+
+    fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// 排队的节点，按出队顺序
+    fn iter(&self) -> impl Iterator<Item = &BfsNode> {
+        self.order.iter().filter_map(|key| self.queued.get(key))
+    }
+
+    /// 加入只含新目标的节点：已在排队时合并，正在处理时暂存，否则排到队尾（优先节点排到队首）
+    fn add(&mut self, node: BfsNode, priority: bool) {
+        let key = Self::key(&node);
+        if let Some(pending) = self.queued.get_mut(&key) {
+            Self::merge(pending, node);
+        } else if self.running.contains(&key) {
+            match self.held.get_mut(&key) {
+                Some((held, _)) => Self::merge(held, node),
+                None => {
+                    self.held.insert(key, (node, priority));
+                }
+            }
+        } else {
+            if priority {
+                self.order.push_front(key.clone());
+            } else {
+                self.order.push_back(key.clone());
+            }
+            self.queued.insert(key, node);
+        }
+    }
+
+    fn merge(into: &mut BfsNode, node: BfsNode) {
+        for target in node.targets {
+            if !into.targets.contains(&target) {
+                into.targets.push(target);
+            }
+        }
+        into.depth = into.depth.min(node.depth);
+    }
+
+    fn pop(&mut self) -> Option<BfsNode> {
+        let key = self.order.pop_front()?;
+        self.queued.remove(&key)
+    }
+
+    fn start(&mut self, id: tokio::task::Id, node: BfsNode) {
+        self.running.insert(Self::key(&node));
+        self.tasks.insert(id, node);
+    }
+
+    /// 节点处理结束（包括任务异常退出），暂存的新目标作为一个节点重新排队
+    fn finish(&mut self, id: tokio::task::Id) -> Option<BfsNode> {
+        let node = self.tasks.remove(&id)?;
+        let key = Self::key(&node);
+        self.running.remove(&key);
+        if let Some((held, priority)) = self.held.remove(&key) {
+            tracing::info!(
+                "{} {} 处理期间得到 {} 个新目标函数，合并追加分析",
+                key.name,
+                key.version,
+                held.targets.len()
+            );
+            self.add(held, priority);
+        }
+        Some(node)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DependencyAnalyzer {
-    database: Arc<Database>,
-    semaphore: Arc<Semaphore>,
+    // None when no database is configured, dependents then come from the registry API
+    database: Option<Arc<Database>>,
+    config: Arc<AnalyzerConfig>,
+    // 由公告报告者提供的依赖者列表，设置后不再从数据库枚举反向依赖，只分析一层
+    explicit_dependents: Option<Arc<Vec<ReverseDependency>>>,
+    // 不在 crates.io 上的依赖者（例如 git 仓库中的内部项目），每个节点都会额外分析
+    extra_dependents: Arc<Vec<ReverseDependency>>,
+    determinism: Arc<DeterminismStats>,
+    // 数据库快照过旧时用来补充最新依赖者
+    crates_io: Option<Arc<CratesIoApi>>,
+    // 配置的工具链中实际可用的，按偏好顺序
+    toolchains: Arc<Vec<Toolchain>>,
+    // 按原因统计无法构建的依赖者
+    build_failures: Arc<std::sync::Mutex<BTreeMap<FailureKind, usize>>>,
+    // 数据库中各 crate 的依赖者，同一 crate 在不同深度或经不同父节点出现时不再重复查询
+    dependents_cache: Arc<MetadataCache<String, Arc<Vec<ReverseDependency>>>>,
+    // (父节点 `name@version`, 依赖者) -> 版本要求匹配父节点的所有版本，从旧到新，
+    // 只在二分定位调用引入版本时记录，用完即删
+    dependent_history: Arc<std::sync::Mutex<DependentHistory>>,
 }
 
 impl DependencyAnalyzer {
-    pub async fn new() -> Result<Self> {
-        let database = Database::new().await?;
+    pub async fn new(config: AnalyzerConfig) -> Result<Self> {
+        let database = if Database::configured() {
+            let database = Arc::new(Database::new().await?);
+            DIAGNOSTICS.persist_to(Arc::clone(&database));
+            Some(database)
+        } else {
+            info!("未配置数据库，通过 API 查询依赖者，版本列表从索引读取");
+            None
+        };
+        // 没有数据库时 API 是唯一的依赖者来源，未配置时使用 crates.io 的公开 API
+        let api_url = match &database {
+            Some(_) => config.crates_io_api.clone(),
+            None => REGISTRY.api_or_default(),
+        };
+        let crates_io = match &api_url {
+            Some(url) => Some(Arc::new(CratesIoApi::new(url, REGISTRY.token.as_deref())?)),
+            None if database.is_none() => {
+                anyhow::bail!("未配置数据库，私有仓库需要设置 REGISTRY_API 才能查询依赖者")
+            }
+            None => None,
+        };
+        let toolchains = Toolchain::detect_all(&config.build_env.toolchains).await;
+        let dependents_cache =
+            MetadataCache::new("dependents").with_ttl(config.dependents_cache_ttl);
+        let config = Arc::new(config);
+        DIAGNOSTICS.redact_with(Arc::clone(&config));
         Ok(Self {
-            database: Arc::new(database),
-            semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_TASKS)),
+            database,
+            config,
+            explicit_dependents: None,
+            extra_dependents: Arc::new(Vec::new()),
+            determinism: Arc::new(DeterminismStats::default()),
+            crates_io,
+            toolchains: Arc::new(toolchains),
+            build_failures: Arc::default(),
+            dependents_cache: Arc::new(dependents_cache),
+            dependent_history: Arc::default(),
         })
     }
 
+    /// 只分析给定的依赖者（例如厂商提供的 crate@version 列表），跳过数据库中的反向依赖枚举
+    pub fn with_dependents(mut self, dependents: Vec<ReverseDependency>) -> Self {
+        self.explicit_dependents = Some(Arc::new(dependents));
+        self
+    }
+
+    /// 除了从数据库查到的依赖者，每个节点还分析这些依赖者，不依赖该节点的会在 patch 阶段被跳过
+    pub fn with_extra_dependents(mut self, dependents: Vec<ReverseDependency>) -> Self {
+        self.extra_dependents = Arc::new(dependents);
+        self
+    }
+
     /// 从给定的版本列表中选择最老和最新的版本
     fn select_oldest_and_newest_versions<T>(
         &self,
@@ -74,329 +293,2049 @@ impl DependencyAnalyzer {
         result
     }
 
-    pub async fn analyze(
-        &self,
-        crate_name: &str,
-        version_range: &str,
-        function_path: &str,
-    ) -> Result<()> {
-        let version_req = self.parse_version_requirement(version_range).unwrap();
-        let versions = self.database.query_crate_versions(crate_name).await?;
+    /// analyze all roots in one run
+    ///
+    /// the roots share the work queue and the scheduled nodes, so the result is a single
+    /// merged impact graph
+    pub async fn analyze(&self, roots: &[AnalysisRoot], function_paths: &[String]) -> Result<Graph> {
+        let mut graph = Graph::new();
+        let mut root_krates = Vec::new();
+
+        for root in roots {
+            let yanked = self.yanked_versions(&root.crate_name).await;
+            let (selected, in_range) = self.select_root_versions(root, &yanked).await?;
+            graph.set_range_versions(&root.crate_name, in_range);
+            for (version, range) in selected {
+                graph.add_crate(&root.crate_name, &version, 0);
+                graph.set_root_range(&root.crate_name, &version, &range);
+                if yanked.contains(&version) {
+                    graph.mark_yanked(&root.crate_name, &version);
+                }
+                root_krates.push(Krate::new(&root.crate_name, &version));
+            }
+        }
 
-        tracing::info!(
-            "Start analyzing crate: {}, version range: {}, {} versions",
-            crate_name,
-            version_req,
-            versions.len()
-        );
+        if root_krates.is_empty() {
+            tracing::info!("所有根节点都没有找到符合版本要求的版本");
+            return Ok(graph);
+        }
 
-        // 筛选符合版本要求的版本
-        let matching_versions = versions
-            .into_iter()
-            .filter_map(|version| {
-                let parsed_version = Version::parse(&version).ok()?;
-                version_req.matches(&parsed_version).then_some((parsed_version, version))
-            })
-            .collect::<Vec<_>>();
-        
-        tracing::info!("找到符合版本要求的版本数: {}", matching_versions.len());
-        
-        // 只取最老和最新的版本（排序已在方法内部完成）
-        let selected_version_strings = self.select_oldest_and_newest_versions(matching_versions);
-        
-        if selected_version_strings.is_empty() {
-            tracing::info!("没有找到符合版本要求的版本");
-            return Ok(());
+        let mut targets = self
+            .expand_function_targets(&root_krates, function_paths)
+            .await;
+        // 同一函数在不同版本中可能位于不同路径
+        for target in targets.iter_mut() {
+            target.aliases = self
+                .config
+                .path_aliases
+                .iter()
+                .filter(|alias| alias.target == target.name)
+                .map(|alias| (alias.version_req.clone(), alias.path.clone()))
+                .collect();
         }
-        
-        for (i, version) in selected_version_strings.iter().enumerate() {
-            let is_oldest = i == 0;
-            let is_newest = i == selected_version_strings.len() - 1;
-            let version_type = if is_oldest && is_newest {
-                "唯一版本"
-            } else if is_oldest {
-                "最老版本"
-            } else {
-                "最新版本"
-            };
-            tracing::info!("选择{}: {}", version_type, version);
+        if self.config.intra_crate_closure {
+            targets = self
+                .expand_intra_crate_callers(&root_krates, targets, &mut graph)
+                .await;
         }
-        
-        let bfs_queue = selected_version_strings
+        tracing::info!("共 {} 个目标函数", targets.len());
+        if let Some(id) = &self.config.advisory_id {
+            graph.add_advisory(id);
+        }
+        for target in &targets {
+            graph.add_target(&target.name);
+            if let Some(group) = &target.group {
+                graph.add_target_group(group, &target.name);
+            }
+        }
+
+        let mut scheduled = HashMap::new();
+        let bfs_queue = root_krates
             .into_iter()
-            .map(|version| Krate::new(crate_name, &version))
+            .map(|krate| {
+                scheduled.insert(
+                    VisitedCrateVersion::new(&krate.name(), &krate.version()),
+                    targets.clone(),
+                );
+                BfsNode {
+                    krate,
+                    targets: targets.clone(),
+                    depth: 0,
+                }
+            })
             .collect::<VecDeque<_>>();
+        // Ctrl-C 时先写出缓冲的分析结果再退出
+        tokio::select! {
+            result = self.bfs_from_queue(bfs_queue, &mut scheduled, &mut graph) => {
+                self.flush_results().await;
+                result?;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                self.flush_results().await;
+                anyhow::bail!("分析被中断");
+            }
+        }
 
-        self.bfs_from_queue(bfs_queue, function_path).await?;
+        if self.config.fix_detection {
+            self.detect_fixes(roots, &targets, &mut graph).await;
+        }
 
-        Ok(())
+        // 同一仓库（workspace）发布的多个 crate 在报告中汇总，修复和通知都以仓库为单位
+        let crate_names = graph
+            .crates()
+            .map(|node| node.name.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        if let Some(database) = &self.database {
+            match database.query_repositories(&crate_names).await {
+                Ok(repositories) => {
+                    for (name, repository) in repositories {
+                        graph.set_repository(&name, &repository);
+                    }
+                }
+                Err(e) => warn!("查询仓库地址失败: {:#}", e),
+            }
+            // GEXF 按下载次数决定节点大小，Markdown 报告按它排序；保存的图之后可能用来生成报告
+            let needs_downloads = self.config.export_gexf.is_some()
+                || self.config.export_markdown.is_some()
+                || self.config.save_graph.is_some();
+            if needs_downloads {
+                match database.query_version_downloads(&crate_names).await {
+                    Ok(downloads) => {
+                        for (name, version, downloads) in downloads {
+                            graph.set_downloads(&name, &version, downloads.max(0) as u64);
+                        }
+                    }
+                    Err(e) => warn!("查询下载次数失败: {:#}", e),
+                }
+            }
+            database.log_cache_stats();
+        }
+        if self.config.git_blame {
+            self.blame_call_sites(&mut graph).await;
+        }
+        self.dependents_cache.log_stats();
+        MANIFEST_CACHE.log_stats();
+        INDEX.log_stats();
+        self.determinism.log_stats();
+        STORE.log_stats();
+        // 修复检测等之后的调用也要写入数据库
+        self.flush_results().await;
+        graph.set_invocations(DIAGNOSTICS.take());
+        for (kind, count) in self.build_failures.lock().unwrap().iter() {
+            warn!("{} 个依赖者无法构建: {}", count, kind.as_str());
+        }
+
+        Ok(graph)
     }
 
-    async fn bfs_from_queue(
+    /// 展开用户给出的目标：
+    /// - 通配符目标（如 `crossbeam_channel::flavors::list::*`）在每个根版本的源码中查找该模块下的所有函数
+    /// - trait 方法目标（如 `<Channel<T> as Drop>::drop`）生成 call-cg4rs 可能使用的各种路径写法
+    /// - 类型目标（如 `smallvec::SmallVec::*`）展开为该类型的所有固有方法和 trait 方法
+    async fn expand_function_targets(
         &self,
-        mut queue: VecDeque<Krate>,
-        target_function_path: &str,
-    ) -> Result<()> {
-        tracing::info!("bfs queue size: {}", queue.len());
+        roots: &[Krate],
+        function_paths: &[String],
+    ) -> Vec<FunctionTarget> {
+        let mut expanded = BTreeSet::new();
+        for spec in function_paths {
+            let target_spec = TargetSpec::parse(spec);
+            let lib_name = target_spec.lib_name().to_string();
+            let mut crate_dirs = Vec::new();
+            if !matches!(target_spec, TargetSpec::Function(_)) {
+                for krate in roots {
+                    // 目标路径以 lib 名开头，lib 名可能在 Cargo.toml 的 [lib] name 中被改写
+                    match krate.get_crate_dir_path(&self.config.timeouts).await {
+                        Ok(dir) if Krate::lib_name(&dir, &krate.name()).await == lib_name => {
+                            crate_dirs.push((krate, dir))
+                        }
+                        Ok(_) => {}
+                        Err(e) => warn!(
+                            "展开目标 {} 时无法获取 {} {}: {}",
+                            spec,
+                            krate.name(),
+                            krate.version(),
+                            e
+                        ),
+                    }
+                }
+            }
 
-        let mut visited = HashSet::new();
-        let mut level = 0;
+            match &target_spec {
+                TargetSpec::Function(path) => {
+                    expanded.insert(FunctionTarget::exact(path));
+                    continue;
+                }
+                TargetSpec::TraitMethod(trait_method) => {
+                    // 不同版本中 impl 块的位置可能不同，把每个版本的写法都加入候选
+                    let mut resolved = target::resolve_trait_method(trait_method, None).await;
+                    for (_, crate_dir) in &crate_dirs {
+                        let with_impl =
+                            target::resolve_trait_method(trait_method, Some(crate_dir)).await;
+                        for candidate in with_impl.candidates {
+                            if !resolved.candidates.contains(&candidate) {
+                                resolved.candidates.push(candidate);
+                            }
+                        }
+                    }
+                    tracing::info!("目标 {} 的候选路径: {:?}", spec, resolved.candidates);
+                    expanded.insert(resolved);
+                    continue;
+                }
+                TargetSpec::Module(_) | TargetSpec::Type(_) => {}
+            }
 
-        // pop current level
-        let pop_bfs_level = |queue: &mut VecDeque<Krate>| -> Vec<Krate> {
-            let mut current_level = Vec::new();
-            while let Some(node) = queue.pop_front() {
-                current_level.push(node);
+            // 模块或类型目标：展开为其中的所有函数/方法，结果按原始目标分组
+            for (krate, crate_dir) in &crate_dirs {
+                let functions = match &target_spec {
+                    TargetSpec::Type(type_path) => target::expand_type(crate_dir, type_path).await,
+                    TargetSpec::Module(module_path) => {
+                        target::expand_module(crate_dir, module_path).await
+                    }
+                    _ => continue,
+                };
+                match functions {
+                    Ok(functions) => {
+                        tracing::info!(
+                            "目标 {} 在 {} {} 中展开为 {} 个函数",
+                            spec,
+                            krate.name(),
+                            krate.version(),
+                            functions.len()
+                        );
+                        expanded.extend(
+                            functions
+                                .iter()
+                                .map(|path| FunctionTarget::in_group(path, spec)),
+                        );
+                    }
+                    Err(e) => warn!("展开目标 {} 失败: {:#}", spec, e),
+                }
             }
-            tracing::info!("BFS弹出一层，共 {} 个节点", current_level.len());
-            current_level
-        };
+        }
+        expanded.into_iter().collect()
+    }
+
+    /// 在漏洞 crate 自身内部求调用闭包：所有（间接）调用了目标函数的公开函数都加入目标集合
+    ///
+    /// 依赖者可能只调用了包装目标函数的公开 API，不先求闭包就会漏掉这些调用者。
+    /// 私有函数只用于继续向上追溯，不作为目标
+    async fn expand_intra_crate_callers(
+        &self,
+        roots: &[Krate],
+        targets: Vec<FunctionTarget>,
+        graph: &mut Graph,
+    ) -> Vec<FunctionTarget> {
+        let mut expanded: BTreeSet<FunctionTarget> = targets.iter().cloned().collect();
+        for krate in roots {
+            let crate_dir = match krate.get_crate_dir_path(&self.config.timeouts).await {
+                Ok(dir) => dir,
+                Err(e) => {
+                    warn!(
+                        "求 crate 内调用闭包时无法获取 {} {}: {}",
+                        krate.name(),
+                        krate.version(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let lib_name = Krate::lib_name(&crate_dir, &krate.name()).await;
 
-        // push next level
-        let push_next_level = |queue: &mut VecDeque<Krate>, next_nodes: Vec<Krate>| {
-            let count = next_nodes.len();
-            for node in next_nodes {
-                queue.push_back(node);
+            // (函数路径, 分组, 候选写法)，分组沿用原始目标，便于在报告中归并
+            let mut worklist = targets
+                .iter()
+                .filter(|target| {
+                    normalize_crate_name(target.lib_name()) == normalize_crate_name(&lib_name)
+                })
+                .map(|target| {
+                    let group = target.group.clone().unwrap_or_else(|| target.name.clone());
+                    let candidates = target.candidates_for(&krate.name(), &krate.version());
+                    (target.name.clone(), group, candidates)
+                })
+                .collect::<VecDeque<_>>();
+            let mut seen = worklist
+                .iter()
+                .map(|(name, _, _)| name.clone())
+                .collect::<HashSet<_>>();
+
+            while let Some((callee, group, candidates)) = worklist.pop_front() {
+                let mut callers_content = None;
+                for candidate in &candidates {
+                    match self.run_call_cg(&crate_dir, candidate, None).await {
+                        Ok(Some(content)) => {
+                            callers_content = Some(content);
+                            break;
+                        }
+                        Ok(None) => {}
+                        Err(e) => {
+                            warn!("分析 {} 内部调用 {} 失败: {}", krate.name(), candidate, e)
+                        }
+                    }
+                }
+                let Some(content) = callers_content else {
+                    continue;
+                };
+                let callers = serde_json::from_str(&content)
+                    .map(|value| callers::parse_callers(&value))
+                    .unwrap_or_default();
+                for caller in callers {
+                    // 只追溯同一 crate 内的调用者
+                    if !caller.path.starts_with(&format!("{}::", lib_name)) {
+                        continue;
+                    }
+                    graph.add_call(
+                        (&krate.name(), &krate.version(), &caller.path),
+                        (&krate.name(), &krate.version(), &callee),
+                        0,
+                    );
+                    if !seen.insert(caller.path.clone()) {
+                        continue;
+                    }
+                    if caller.is_exported() {
+                        expanded.insert(FunctionTarget::in_group(&caller.path, &group));
+                    }
+                    worklist.push_back((caller.path.clone(), group.clone(), vec![caller.path]));
+                }
             }
-            tracing::info!("BFS推入下一层，共 {} 个节点", count);
-        };
+            let _ = krate.cargo_clean().await;
+        }
+
+        tracing::info!(
+            "crate 内调用闭包把目标函数从 {} 个扩展到 {} 个",
+            targets.len(),
+            expanded.len()
+        );
+        expanded.into_iter().collect()
+    }
 
-        // main loop of BFS Algorithm
-        while !queue.is_empty() {
-            level += 1;
-            tracing::info!("BFS第{}层，队列长度:{}", level, queue.len());
-            let current_level = pop_bfs_level(&mut queue);
-            let results = self
-                .process_bfs_level(current_level, target_function_path, &mut visited)
-                .await?;
-            push_next_level(&mut queue, results);
+    /// 写出缓冲的分析结果，没有数据库时什么也不做
+    async fn flush_results(&self) {
+        if let Some(database) = &self.database {
+            database.flush_results().await;
         }
-        Ok(())
     }
 
-    async fn process_bfs_level(
+    /// 根节点中已被撤回的版本，从索引读取，查询失败时当作没有
+    async fn yanked_versions(&self, crate_name: &str) -> HashSet<String> {
+        match INDEX.versions(crate_name).await {
+            Ok(versions) => versions
+                .iter()
+                .filter(|version| version.yanked)
+                .map(|version| version.vers.clone())
+                .collect(),
+            Err(e) => {
+                warn!("无法从索引查询 {} 的撤回版本: {:#}", crate_name, e);
+                HashSet::new()
+            }
+        }
+    }
+
+    /// 根节点的各个版本区间及判断版本是否在区间内的函数
+    ///
+    /// 公告格式的事件（`[{ introduced = "0.5.0", fixed = "0.5.15" }]`）按 OSV 的规则
+    /// 直接在已发布的版本上求值，每个受影响的区间单独选择版本
+    #[allow(clippy::type_complexity)]
+    fn root_ranges(
         &self,
-        current_level: Vec<Krate>,
-        target_function_path: &str,
-        visited: &mut HashSet<VisitedCrateVersion>,
-    ) -> Result<Vec<Krate>> {
-        let analyzer = Arc::new(self.clone());
-        let results = stream::iter(current_level)
-            .map(|krate| {
-                let analyzer = Arc::clone(&analyzer);
-                let target_function_path = target_function_path.to_string();
-                async move {
-                    let _permit = analyzer.semaphore.acquire().await.unwrap();
-                    analyzer
-                        .process_single_bfs_node(krate, &target_function_path)
-                        .await
+        root: &AnalysisRoot,
+    ) -> Result<Vec<(String, Box<dyn Fn(&Version) -> bool + '_>)>> {
+        let policy = self.config.prerelease_policy;
+        let mut ranges = Vec::<(String, Box<dyn Fn(&Version) -> bool>)>::new();
+        for range in &root.version_ranges {
+            if osv::is_events(range) {
+                let intervals = osv::parse_events(range)
+                    .context(format!("解析 {} 的版本事件失败", root.crate_name))?;
+                for interval in intervals {
+                    let label = interval.label();
+                    ranges.push((
+                        label,
+                        Box::new(move |version| {
+                            policy.keeps_root(version) && interval.contains(version)
+                        }),
+                    ));
+                }
+            } else {
+                let version_req = self.parse_version_requirement(range)?;
+                ranges.push((
+                    range.clone(),
+                    Box::new(move |version| policy.root_matches(&version_req, version)),
+                ));
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// 用 `git blame` 找出受影响的依赖者中调用者所在行是哪个提交引入的
+    ///
+    /// 发布的版本通过 `.cargo_vcs_info.json` 对应到仓库中的提交和目录，没有该文件或
+    /// 仓库地址未知的依赖者跳过；同一仓库的行依次处理，共用一个克隆
+    async fn blame_call_sites(&self, graph: &mut Graph) {
+        // (依赖者, 版本) -> [(调用者, 文件, 行号)]
+        let mut sites = BTreeMap::<(String, String), Vec<(String, String, u64)>>::new();
+        for (_, function) in graph.functions_with_id() {
+            if let (Some(file), Some(line)) = (&function.file, function.line) {
+                sites
+                    .entry((function.crate_name.clone(), function.version.clone()))
+                    .or_default()
+                    .push((function.path.clone(), file.clone(), line));
+            }
+        }
+
+        // 仓库 -> [(依赖者, 版本, 提交, 调用者, 仓库中的文件, 行号)]
+        let mut by_repository = BTreeMap::<String, Vec<_>>::new();
+        for ((name, version), functions) in sites {
+            let krate = Krate::new(&name, &version);
+            let crate_dir = match krate.get_crate_dir_path(&self.config.timeouts).await {
+                Ok(dir) => dir,
+                Err(e) => {
+                    warn!("[{}-{}] 无法获取源码，不做 git blame: {:#}", name, version, e);
+                    continue;
+                }
+            };
+            let Some(vcs) = VcsInfo::read(&crate_dir).await else {
+                info!("[{}-{}] 没有 .cargo_vcs_info.json，无法对应到提交", name, version);
+                continue;
+            };
+            let repository = match graph
+                .crate_node(&name, &version)
+                .and_then(|node| node.repository.clone())
+            {
+                Some(repository) => Some(repository),
+                None => Krate::read_manifest(&crate_dir)
+                    .await
+                    .ok()
+                    .and_then(|content| content.parse::<toml_edit::DocumentMut>().ok())
+                    .and_then(|manifest| {
+                        manifest["package"]["repository"].as_str().map(str::to_owned)
+                    }),
+            };
+            let Some(repository) = repository else {
+                info!("[{}-{}] 仓库地址未知，不做 git blame", name, version);
+                continue;
+            };
+            let canonical_dir = crate_dir.canonicalize().unwrap_or_else(|_| crate_dir.clone());
+            for (function, file, line) in functions {
+                // callers.json 中的路径可能是绝对路径，转为包内的相对路径
+                let file = Path::new(&file);
+                let relative = if file.is_absolute() {
+                    match file
+                        .strip_prefix(&crate_dir)
+                        .or_else(|_| file.strip_prefix(&canonical_dir))
+                    {
+                        Ok(relative) => relative.to_path_buf(),
+                        Err(_) => continue,
+                    }
+                } else {
+                    file.to_path_buf()
+                };
+                let path = Path::new(&vcs.path_in_vcs).join(relative);
+                by_repository.entry(repository.clone()).or_default().push((
+                    name.clone(),
+                    version.clone(),
+                    vcs.commit.clone(),
+                    function,
+                    path.to_string_lossy().into_owned(),
+                    line,
+                ));
+            }
+        }
+
+        let blames = stream::iter(by_repository)
+            .map(|(repository, lines)| async move {
+                let mut blames = Vec::new();
+                for (name, version, commit, function, file, line) in lines {
+                    let blamed = match git::history_checkout(&repository, &commit).await {
+                        Ok(clone_dir) => git::blame_line(&clone_dir, &commit, &file, line).await,
+                        Err(e) => Err(e),
+                    };
+                    match blamed {
+                        Ok(blamed) => blames.push(Blame {
+                            name,
+                            version,
+                            function,
+                            file,
+                            line,
+                            blamed,
+                        }),
+                        Err(e) => warn!(
+                            "[{}-{}] git blame {}:{} 失败: {:#}",
+                            name, version, file, line, e
+                        ),
+                    }
                 }
+                blames
             })
-            .buffer_unordered(MAX_CONCURRENT_TASKS) // 使用常量
+            .buffer_unordered(LIMITS.tasks)
             .collect::<Vec<_>>()
             .await;
+        for blame in blames.into_iter().flatten() {
+            graph.add_blame(blame);
+        }
+    }
 
-        let mut next_nodes = Vec::new();
-        let mut total_new = 0;
-        for result in results {
-            if let Ok(nodes) = result {
-                total_new += nodes.len();
-                for node in nodes {
-                    let cv = VisitedCrateVersion {
-                        name: node.name().to_string(),
-                        version: node.version().to_string(),
-                    };
-                    if visited.insert(cv) {
-                        next_nodes.push(node);
-                    }
+    /// 一个 crate 的所有版本，来自配置的元数据来源，没有数据库时来自索引
+    async fn crate_versions(&self, crate_name: &str) -> Result<Vec<String>> {
+        match (&self.database, self.config.metadata_source) {
+            (Some(database), MetadataSource::Database) => {
+                database.query_crate_versions(crate_name).await
+            }
+            _ => INDEX.version_list(crate_name).await,
+        }
+    }
+
+    /// 对直接依赖根节点的受影响依赖者，检查升级到新版本能否消除影响
+    ///
+    /// 先从索引查找第一个允许使用修复版本（不在任何漏洞区间内）或不再依赖根节点的新版本；
+    /// 都没有时分析最新版本是否还调用目标函数。更深层的依赖者的“修复版本”取决于中间
+    /// crate，不在这里判断
+    async fn detect_fixes(
+        &self,
+        roots: &[AnalysisRoot],
+        targets: &[FunctionTarget],
+        graph: &mut Graph,
+    ) {
+        // (根节点, 依赖者) -> 受影响的最新版本
+        let mut affected = BTreeMap::<(String, String), Version>::new();
+        for (parent, dependent, _) in graph.dependencies() {
+            let Ok(version) = Version::parse(&dependent.version) else {
+                continue;
+            };
+            if parent.depth != 0 {
+                continue;
+            }
+            let newest = affected
+                .entry((normalize_crate_name(&parent.name), dependent.name.clone()))
+                .or_insert_with(|| version.clone());
+            if version > *newest {
+                *newest = version;
+            }
+        }
+
+        for root in roots {
+            let root_name = normalize_crate_name(&root.crate_name);
+            let dependents = affected
+                .iter()
+                .filter(|((parent, _), _)| *parent == root_name)
+                .collect::<Vec<_>>();
+            if dependents.is_empty() {
+                continue;
+            }
+            let (versions, ranges) = match (
+                self.crate_versions(&root.crate_name).await,
+                self.root_ranges(root),
+            ) {
+                (Ok(versions), Ok(ranges)) => (versions, ranges),
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!("无法确定 {} 的修复版本: {:#}", root.crate_name, e);
+                    continue;
                 }
+            };
+            let yanked = self.yanked_versions(&root.crate_name).await;
+            let (vulnerable, fixed): (Vec<_>, Vec<_>) = versions
+                .iter()
+                .filter(|version| !yanked.contains(*version))
+                .filter_map(|version| Version::parse(version).ok())
+                .partition(|version| ranges.iter().any(|(_, matches)| matches(version)));
+            tracing::info!(
+                "{} 有 {} 个修复版本，检查 {} 个受影响的直接依赖者能否通过升级修复",
+                root.crate_name,
+                fixed.len(),
+                dependents.len()
+            );
+
+            let fixes = stream::iter(dependents)
+                .map(|((_, name), version)| async {
+                    let remedy = self
+                        .find_fix(&root.crate_name, name, version, &fixed, &vulnerable, targets)
+                        .await;
+                    FixAdvice {
+                        parent_name: root.crate_name.clone(),
+                        name: name.clone(),
+                        affected_version: version.to_string(),
+                        remedy,
+                    }
+                })
+                .buffer_unordered(LIMITS.tasks)
+                .collect::<Vec<_>>()
+                .await;
+            for fix in fixes {
+                graph.add_fix(fix);
             }
         }
-        tracing::info!("process_bfs_level: 本层发现新节点:{}", total_new);
-        Ok(next_nodes)
     }
 
-    async fn process_single_bfs_node(
+    /// 依赖者 `name` 从受影响的版本 `affected` 升级后能否摆脱 `parent` 的漏洞版本
+    async fn find_fix(
         &self,
-        krate: Krate,
-        target_function_path: &str,
-    ) -> Result<Vec<Krate>> {
-        let node_start_time = std::time::Instant::now();
-        tracing::info!("准备查询依赖者: {} {}", krate.name(), krate.version());
+        parent: &str,
+        name: &str,
+        affected: &Version,
+        fixed: &[Version],
+        vulnerable: &[Version],
+        targets: &[FunctionTarget],
+    ) -> Remedy {
+        let index_versions = match INDEX.versions(name).await {
+            Ok(versions) => versions,
+            Err(e) => {
+                return Remedy::Unknown {
+                    reason: format!("index lookup failed: {:#}", e),
+                }
+            }
+        };
+        let parent_name = normalize_crate_name(parent);
+        let mut newer = index_versions
+            .iter()
+            .filter(|entry| !entry.yanked)
+            .filter_map(|entry| Some((Version::parse(&entry.vers).ok()?, entry)))
+            .filter(|(version, _)| version > affected)
+            .filter(|(version, _)| self.config.prerelease_policy.keeps_dependent(version))
+            .collect::<Vec<_>>();
+        newer.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut newest_req = None;
+        for (version, entry) in &newer {
+            // 只在 dev-dependencies 中的父节点不会随依赖者发布，普通依赖排在前面
+            let mut deps = entry
+                .deps
+                .iter()
+                .filter(|dep| normalize_crate_name(dep.crate_name()) == parent_name)
+                .filter(|dep| dep.kind() != DependencyKind::Dev)
+                .collect::<Vec<_>>();
+            if deps.is_empty() {
+                return Remedy::DropsDependency {
+                    version: version.to_string(),
+                };
+            }
+            deps.sort_by_key(|dep| dep.kind());
+            let Ok(mut reqs) = deps
+                .iter()
+                .map(|dep| VersionReq::parse(&dep.req))
+                .collect::<Result<Vec<_>, _>>()
+            else {
+                continue;
+            };
+            // 按目标平台多次声明时，每一处都允许修复版本才算修复
+            let admitted = reqs
+                .iter()
+                .map(|req| {
+                    fixed
+                        .iter()
+                        .filter(|fixed| self.config.prerelease_policy.accepts(req, fixed))
+                        .max()
+                })
+                .collect::<Option<Vec<_>>>();
+            if let Some(parent_version) = admitted.and_then(|versions| versions.into_iter().min()) {
+                return Remedy::AdmitsFixedParent {
+                    version: version.to_string(),
+                    parent_version: parent_version.to_string(),
+                };
+            }
+            newest_req = Some((version, deps[0].req.clone(), reqs.swap_remove(0)));
+        }
 
-        // 查询前日志
+        // 新版本仍只允许漏洞版本，看最新版本是否还调用目标函数
+        let Some((newest, req, version_req)) = newest_req else {
+            return Remedy::NoNewerVersion;
+        };
+        let Some(parent_version) = vulnerable
+            .iter()
+            .filter(|version| self.config.prerelease_policy.accepts(&version_req, version))
+            .max()
+        else {
+            return Remedy::Unknown {
+                reason: format!("{} {} admits no published {}", name, newest, parent),
+            };
+        };
         tracing::info!(
-            "即将调用 query_dependents for {} {}",
-            krate.name(),
-            krate.version()
+            "{} 的最新版本 {} 仍要求 {} 的漏洞版本，针对 {} 重新分析",
+            name,
+            newest,
+            parent,
+            parent_version
         );
-        let precise_version = &krate.version();
-        let reverse_dependencies = self.database.query_dependents(&krate.name()).await?;
-        let reverse_dependencies_for_certain_version =
-            Self::filter_dependents_by_version_req(reverse_dependencies, precise_version);
+        let parent_krate = Krate::new(parent, &parent_version.to_string());
+        let dependency = ReverseDependency::new(name.to_owned(), newest.to_string(), req);
+        match self
+            .analyze_dependent(&parent_krate, dependency, targets)
+            .await
+        {
+            Some((_, _, AnalysisOutcome::Affected(_))) => Remedy::StillAffected {
+                version: newest.to_string(),
+            },
+            Some((_, _, AnalysisOutcome::Cleared(clearance)))
+                if clearance.reason.proves_no_call() =>
+            {
+                Remedy::CallRemoved {
+                    version: newest.to_string(),
+                    parent_version: parent_version.to_string(),
+                }
+            }
+            Some((_, _, AnalysisOutcome::Cleared(clearance))) => Remedy::Unknown {
+                reason: format!("{} {} {}", name, newest, clearance.reason.as_str()),
+            },
+            None => Remedy::Unknown {
+                reason: format!("analysis of {} {} failed", name, newest),
+            },
+        }
+    }
+
+    /// 选择一个根节点中需要分析的版本：每个版本区间的最老和最新版本，
+    /// 返回版本和它所在的区间，以及所有区间内的版本（用于版本矩阵）
+    async fn select_root_versions(
+        &self,
+        root: &AnalysisRoot,
+        yanked: &HashSet<String>,
+    ) -> Result<(Vec<(String, String)>, Vec<String>)> {
+        let crate_name = root.crate_name.as_str();
+        let versions = self.crate_versions(crate_name).await?;
 
-        let krate = Arc::new(krate); // 用 Arc 包裹
         tracing::info!(
-            "query_dependents 返回 for {} {}",
-            krate.name(),
-            krate.version()
+            "Start analyzing crate: {}, version range: {}, {} versions",
+            crate_name,
+            root.version_ranges.join(" || "),
+            versions.len()
         );
 
-        // 对每个crate名称的不同版本只选择最老和最新的
-        let mut dependents_by_name: std::collections::HashMap<String, Vec<(Version, ReverseDependency)>> = 
-            std::collections::HashMap::new();
-        
-        // 按crate名称分组并解析版本
-        for dep in reverse_dependencies_for_certain_version {
-            if let Ok(version) = Version::parse(&dep.version) {
-                dependents_by_name
-                    .entry(dep.name.clone())
-                    .or_insert_with(Vec::new)
-                    .push((version, dep));
-            }
-        }
-        
-        // 对每个crate名称，按版本排序并只选最老和最新版本
-        let mut selected_dependents = Vec::new();
-        let mut total_crates = 0;
-        let mut total_versions = 0;
-        
-        for (name, versions) in dependents_by_name {
-            total_crates += 1;
-            let versions_count = versions.len();
-            total_versions += versions_count;
-            
-            // 选择最老和最新版本（排序已在方法内部完成）
-            let selected = self.select_oldest_and_newest_versions(versions);
-            let selected_count = selected.len();
-            
+        let mut selected = Vec::<(String, String)>::new();
+        let mut in_range = Vec::<String>::new();
+        for (range, matches) in self.root_ranges(root)? {
+            // 筛选符合版本要求的版本
+            let matching_versions = versions
+                .iter()
+                .filter_map(|version| {
+                    let parsed_version = Version::parse(version).ok()?;
+                    matches(&parsed_version).then(|| (parsed_version, version.clone()))
+                })
+                .collect::<Vec<_>>();
+
             tracing::info!(
-                "依赖者 {} 有{}个版本，选择了{}个版本进行分析", 
-                name, 
-                versions_count, 
-                selected_count
+                "区间 {} 中找到符合版本要求的版本数: {}",
+                range,
+                matching_versions.len()
             );
-            
-            selected_dependents.extend(selected);
-        }
-        
-        let selected_dependents_count = selected_dependents.len();
-        tracing::info!(
-            "共有{}个crate依赖，筛选后剩余{}个版本进行分析",
-            total_crates,
-            selected_dependents_count
-        );
 
-        let mut next_nodes = Vec::new();
-        let mut total_progress_idx = 0;
+            // 撤回的版本在新的依赖解析中不会被选中，影响范围可能比版本区间小
+            let yanked_in_range = matching_versions
+                .iter()
+                .filter(|(_, version)| yanked.contains(version))
+                .map(|(_, version)| version.as_str())
+                .collect::<Vec<_>>();
+            if !yanked_in_range.is_empty() {
+                warn!(
+                    "{} 的版本区间 {} 内有 {} 个版本已被撤回: {}",
+                    crate_name,
+                    range,
+                    yanked_in_range.len(),
+                    yanked_in_range.join(", ")
+                );
+            }
+            let matching_versions = match self.config.yanked_policy {
+                YankedPolicy::Skip => matching_versions
+                    .into_iter()
+                    .filter(|(_, version)| !yanked.contains(version))
+                    .collect(),
+                YankedPolicy::Flag => matching_versions,
+            };
+            for (_, version) in &matching_versions {
+                if !in_range.contains(version) {
+                    in_range.push(version.clone());
+                }
+            }
 
-        for (batch_idx, batch) in selected_dependents.chunks(BATCH_SIZE).enumerate() {
-            tracing::info!("开始处理第{}批, 本批{}个依赖者", batch_idx + 1, batch.len());
-            let batch_vec = batch.to_vec();
-            let selected_dependents_len = selected_dependents.len();
-            let batch_results = stream::iter(batch_vec.into_iter().enumerate())
-                .map(|(idx, reverse_dependency)| {
-                    let reverse_name = reverse_dependency.name.clone();
-                    let reverse_version = reverse_dependency.version.clone();
-                    let req_for_dep = reverse_dependency.req.clone();
-
-                    let analyzer = self.clone();
-                    let target_function_path = target_function_path.to_string();
-                    let krate = Arc::clone(&krate);
-                    
-                    total_progress_idx += 1;
-                    tracing::info!("[依赖者进度 {}/{}] 正在分析依赖者: {} {}", total_progress_idx, selected_dependents_len, reverse_name, reverse_version);
-                    async move {
-                        let _permit = analyzer.semaphore.acquire().await.unwrap();
-                        let dep_krate = Krate::new(&reverse_name, &reverse_version);
-                        let dep_dir = match dep_krate.get_crate_dir_path().await {
-                            Ok(dir) => dir,
-                            Err(e) => {
-                                tracing::warn!("[{}-{}] get_crate_dir_path失败: {}，跳过", reverse_name, reverse_version, e);
-                                return None;
-                            }
-                        };
-
-                        tracing::info!("[{}-{}] 开始 patch_cargo_toml_with_parent", reverse_name, reverse_version);
-                        
-                        let patch_result = timeout(
-                            Duration::from_secs(60),
-                            Krate::patch_cargo_toml_with_parent(&dep_dir, &krate.name(), &krate.version())
-                        ).await;
-
-                        if let Ok(Ok(_)) = patch_result {
-                            tracing::info!("[{}-{}] 完成 patch_cargo_toml_with_parent", reverse_name, reverse_version);
-                        } else {
-                            tracing::warn!("[{}-{}] patch_cargo_toml_with_parent失败，跳过该crate后续分析", reverse_name, reverse_version);
-                            return None;
-                        }
-                        
-                        tracing::info!("[{}-{}] 开始 is_valid_dependent", reverse_name, reverse_version);
-                        let is_valid = analyzer
-                            .is_valid_dependent(
-                                &krate.version(),
-                                &req_for_dep,
-                                &reverse_name,
-                                &reverse_version,
-                                target_function_path.as_str(),
-                            )
-                            .await
-                            .unwrap_or(false);
-                        tracing::info!("[{}-{}] is_valid_dependent结果: {}", reverse_name, reverse_version, is_valid);
-
-                        // 分析结束后删除 Cargo.lock
-                        let cargo_lock_path = dep_dir.join("Cargo.lock");
-                        let _ = tokio_fs::remove_file(&cargo_lock_path).await;
-
-                        if is_valid {
-                            tracing::info!("依赖者 {} {} 满足条件，加入下一层", reverse_name, reverse_version);
-                            Some(dep_krate)
-                        } else {
-                            tracing::info!("依赖者 {} {} 不满足条件，跳过", reverse_name, reverse_version);
-                            None
+            // 只取最老和最新的版本（排序已在方法内部完成）
+            let selected_version_strings =
+                self.select_oldest_and_newest_versions(matching_versions);
+            if selected_version_strings.is_empty() {
+                tracing::info!("{} 在区间 {} 中没有找到符合版本要求的版本", crate_name, range);
+                continue;
+            }
+
+            for (i, version) in selected_version_strings.iter().enumerate() {
+                let is_oldest = i == 0;
+                let is_newest = i == selected_version_strings.len() - 1;
+                let version_type = if is_oldest && is_newest {
+                    "唯一版本"
+                } else if is_oldest {
+                    "最老版本"
+                } else {
+                    "最新版本"
+                };
+                tracing::info!("{} 选择{}: {} ({})", crate_name, version_type, version, range);
+            }
+            // 区间重叠时同一版本只分析一次，归入先出现的区间
+            for version in selected_version_strings {
+                if !selected.iter().any(|(known, _)| *known == version) {
+                    selected.push((version, range.clone()));
+                }
+            }
+        }
+
+        Ok((selected, in_range))
+    }
+
+    /// 流水线式遍历：节点一处理完，它发现的依赖者就进入共享队列，空出的名额立即
+    /// 取下一个节点，不必等同一层最慢的节点
+    ///
+    /// 节点和依赖者预取都作为独立任务运行，循环只等下一个完成的节点；队列、
+    /// `scheduled` 和图都只在这个循环里修改，工作节点之间不共享可变状态
+    async fn bfs_from_queue(
+        &self,
+        mut queue: VecDeque<BfsNode>,
+        scheduled: &mut HashMap<VisitedCrateVersion, Vec<FunctionTarget>>,
+        graph: &mut Graph,
+    ) -> Result<()> {
+        tracing::info!("bfs queue size: {}", queue.len());
+        // 优先节点排在队列最前面，最先拿到并发名额
+        queue
+            .make_contiguous()
+            .sort_by_key(|node| !self.config.is_priority(&node.krate.name()));
+        let mut state = BfsState::default();
+        for node in queue {
+            state.add(node, false);
+        }
+
+        let analyzer = Arc::new(self.clone());
+        let mut in_flight = JoinSet::new();
+        let mut finished = 0;
+        let mut failed = Vec::new();
+        let mut prefetched = HashSet::new();
+        let mut prefetch: Option<JoinHandle<()>> = None;
+        loop {
+            // 上一次预取还没结束时不再发起，也不等它
+            if prefetch.as_ref().is_none_or(JoinHandle::is_finished) {
+                let names = self.prefetch_names(&state, &mut prefetched);
+                if !names.is_empty() {
+                    let analyzer = Arc::clone(&analyzer);
+                    prefetch = Some(tokio::spawn(async move {
+                        analyzer.prefetch_dependents(names).await;
+                    }));
+                }
+            }
+            while in_flight.len() < LIMITS.tasks {
+                let Some(node) = state.pop() else {
+                    break;
+                };
+                let analyzer = Arc::clone(&analyzer);
+                let (krate, targets) = (node.krate.clone(), node.targets.clone());
+                let handle = in_flight.spawn(async move {
+                    analyzer.process_single_bfs_node(krate, &targets).await
+                });
+                state.start(handle.id(), node);
+            }
+            let Some(joined) = in_flight.join_next_with_id().await else {
+                break;
+            };
+            finished += 1;
+            let (id, result) = match joined {
+                Ok((id, result)) => (id, Ok(result)),
+                Err(e) => (e.id(), Err(e)),
+            };
+            let Some(node) = state.finish(id) else {
+                continue;
+            };
+            let next_nodes = match result {
+                Ok(Ok(result)) => Self::merge_node_result(graph, &node, result, &self.config),
+                Ok(Err(e)) => {
+                    warn!(
+                        "处理节点 {} {} 失败: {}",
+                        node.krate.name(),
+                        node.krate.version(),
+                        e
+                    );
+                    failed.push(node);
+                    Vec::new()
+                }
+                Err(e) => {
+                    warn!(
+                        "节点 {} {} 的任务异常退出: {}",
+                        node.krate.name(),
+                        node.krate.version(),
+                        e
+                    );
+                    failed.push(node);
+                    Vec::new()
+                }
+            };
+            // 指定了依赖者列表时只分析一层
+            if self.explicit_dependents.is_none() {
+                for next in next_nodes {
+                    self.schedule(&mut state, scheduled, next);
+                }
+            }
+            tracing::info!(
+                "BFS已完成{}个节点，正在处理{}个，队列中{}个",
+                finished,
+                in_flight.len(),
+                state.len()
+            );
+        }
+        if !failed.is_empty() {
+            warn!(
+                "{} 个节点处理失败，它们的依赖者没有分析: {}",
+                failed.len(),
+                failed
+                    .iter()
+                    .map(|node| format!("{} {}", node.krate.name(), node.krate.version()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// 队列前面还没查询过依赖者的节点名称，不足两个时返回空，不值得合并查询
+    ///
+    /// `prefetched` 记录已经选出的名称，被缓存淘汰或依赖者过多没有缓存的热门crate
+    /// 不再重复批量查询。只看队列前面一段，不必每次扫描整个队列
+    fn prefetch_names(
+        &self,
+        queue: &BfsState,
+        prefetched: &mut HashSet<String>,
+    ) -> Vec<String> {
+        if self.database.is_none() || self.explicit_dependents.is_some() {
+            return Vec::new();
+        }
+        let mut names = Vec::new();
+        for node in queue.iter().take(PREFETCH_WINDOW) {
+            let name = node.krate.name();
+            if !prefetched.contains(&name)
+                && !names.contains(&name)
+                && !self.dependents_cache.contains(&name)
+            {
+                names.push(name);
+            }
+            if names.len() >= DEPENDENTS_BATCH_SIZE {
+                break;
+            }
+        }
+        if names.len() < 2 {
+            return Vec::new();
+        }
+        prefetched.extend(names.iter().cloned());
+        names
+    }
+
+    /// 把多个节点的依赖者合并成一次数据库查询，结果放入缓存
+    ///
+    /// 失败时什么也不做，各节点再单独查询
+    async fn prefetch_dependents(&self, names: Vec<String>) {
+        let Some(database) = &self.database else {
+            return;
+        };
+        let max_rows = self.config.dependents_cache_max_rows;
+        match database.query_dependents_many(&names, max_rows).await {
+            Ok(dependents) => {
+                // 过大的结果不在其中，查询节点时再流式读取
+                for (name, rows) in dependents {
+                    self.dependents_cache.put(name, Arc::new(rows));
+                }
+            }
+            Err(e) => warn!("批量查询依赖者失败，逐个查询: {:#}", e),
+        }
+    }
+
+    /// 把节点放入队列，只保留它还没被调度过的目标函数
+    ///
+    /// 同一依赖者可能从多个父节点到达，它的目标取所有调用者的并集：还在排队或正在处理时
+    /// 合并，已经完成时只用新增的目标再分析一次，见 `BfsState`
+    fn schedule(
+        &self,
+        state: &mut BfsState,
+        scheduled: &mut HashMap<VisitedCrateVersion, Vec<FunctionTarget>>,
+        node: BfsNode,
+    ) {
+        let key = BfsState::key(&node);
+        let known = scheduled.entry(key).or_default();
+        let mut new_targets = Vec::new();
+        for target in node.targets {
+            if !known.contains(&target) && !new_targets.contains(&target) {
+                new_targets.push(target);
+            }
+        }
+        if new_targets.is_empty() {
+            return;
+        }
+        known.extend(new_targets.iter().cloned());
+        let priority = self.config.is_priority(&node.krate.name());
+        state.add(
+            BfsNode {
+                krate: node.krate,
+                targets: new_targets,
+                depth: node.depth,
+            },
+            priority,
+        );
+    }
+
+    /// 把一个节点的分析结果加入图，返回受影响的依赖者作为下一层节点
+    fn merge_node_result(
+        graph: &mut Graph,
+        parent: &BfsNode,
+        (nodes, cleared, introduced): NodeResult,
+        config: &AnalyzerConfig,
+    ) -> Vec<BfsNode> {
+        let parent_name = parent.krate.name();
+        let parent_version = parent.krate.version();
+        let depth = parent.depth + 1;
+        tracing::info!(
+            "节点 {} {} 发现新节点:{}",
+            parent_name,
+            parent_version,
+            nodes.len()
+        );
+        for entry in cleared {
+            graph.add_cleared(ClearedDependent {
+                parent_name: parent_name.clone(),
+                parent_version: parent_version.clone(),
+                name: entry.name,
+                version: entry.version,
+                req: entry.req,
+                source: entry.source,
+                clearance: entry.clearance,
+            });
+        }
+        for entry in introduced {
+            graph.add_introduction(Introduction {
+                parent_name: parent_name.clone(),
+                parent_version: parent_version.clone(),
+                name: entry.name,
+                first_affected: entry.first_affected,
+                last_clear: entry.last_clear,
+                checked: entry.checked,
+            });
+        }
+        let mut next_nodes = Vec::new();
+        for (node, dependency, calls) in nodes {
+            // 敏感依赖者的调用者只以哈希进入图，下一层的目标仍用真实路径；
+            // 父节点敏感时它的函数路径作为被调用者出现，同样只记录哈希
+            let sensitive = config.is_sensitive(&node.name());
+            let parent_sensitive = config.is_sensitive(&parent_name);
+            let mut recorded = if sensitive { calls.minimized() } else { calls.clone() };
+            if parent_sensitive {
+                recorded = recorded.with_hashed_targets();
+            }
+            graph.add_dependency(
+                (&parent_name, &parent_version),
+                (&node.name(), &node.version()),
+                depth,
+                &dependency,
+                &recorded,
+            );
+            let targets = Self::record_calls(
+                graph,
+                &parent_name,
+                &parent_version,
+                &node,
+                &calls,
+                depth,
+                (sensitive, parent_sensitive),
+            )
+            // 解析不出调用者时沿用父节点的目标，保持原来的行为
+            .unwrap_or_else(|| parent.targets.clone());
+            next_nodes.push(BfsNode {
+                krate: node,
+                targets,
+                depth,
+            });
+        }
+        next_nodes
+    }
+
+    /// 检查点中保存的调用关系，`sensitive` 的两项和 `record_calls` 相同
+    ///
+    /// 父节点敏感时目标函数路径只保留哈希，此时调用者也最小化，恢复时这类依赖者会重新分析，
+    /// 不会把哈希过的目标再次加入图
+    fn checkpointed_calls(
+        calls: &FunctionCalls,
+        (sensitive, parent_sensitive): (bool, bool),
+    ) -> FunctionCalls {
+        match (sensitive, parent_sensitive) {
+            (false, false) => calls.clone(),
+            (true, false) => calls.minimized(),
+            (_, true) => calls.minimized().with_hashed_targets(),
+        }
+    }
+
+    /// 写入 callers.json 的证据，敏感依赖者只保留调用者数量和哈希，父节点敏感时目标函数
+    /// 路径只保留哈希
+    fn evidence_json(
+        calls: &FunctionCalls,
+        (sensitive, parent_sensitive): (bool, bool),
+    ) -> serde_json::Value {
+        let calls = if parent_sensitive {
+            &calls.with_hashed_targets()
+        } else {
+            calls
+        };
+        if sensitive {
+            calls.to_minimized_json()
+        } else {
+            calls.to_json()
+        }
+    }
+
+    /// 把依赖者中找到的调用关系加入函数级图，并返回它的公开调用者作为下一层的目标函数
+    ///
+    /// `sensitive` 的第一项表示依赖者敏感，此时图中只记录调用者路径的哈希，不记录文件和
+    /// 行号，因此也不会被 git blame；第二项表示父节点敏感，此时被调用者路径只记录哈希。
+    /// callers.json 中解析不出任何调用者时返回 None
+    fn record_calls(
+        graph: &mut Graph,
+        parent_name: &str,
+        parent_version: &str,
+        dependent: &Krate,
+        calls: &FunctionCalls,
+        level: usize,
+        (sensitive, parent_sensitive): (bool, bool),
+    ) -> Option<Vec<FunctionTarget>> {
+        let recorded = |path: &str, hashed: bool| {
+            if hashed {
+                hashed_path(path)
+            } else {
+                path.to_owned()
+            }
+        };
+        let mut next_targets = BTreeSet::new();
+        let mut parsed_any = false;
+        for (callee, callers) in calls.callers() {
+            for caller in callers {
+                parsed_any = true;
+                let recorded_path = recorded(&caller.path, sensitive);
+                graph.add_call(
+                    (&dependent.name(), &dependent.version(), &recorded_path),
+                    (parent_name, parent_version, &recorded(&callee, parent_sensitive)),
+                    level,
+                );
+                let exposure = calls.exposure(&caller.path);
+                graph.annotate_function(
+                    (&dependent.name(), &dependent.version(), &recorded_path),
+                    exposure,
+                    calls.call_site(&caller.path),
+                );
+                if let (Some(file), Some(line), false) = (&caller.file, caller.line, sensitive) {
+                    graph.locate_function(
+                        (&dependent.name(), &dependent.version(), &caller.path),
+                        file,
+                        line,
+                    );
+                }
+                // 无法判断是否可从外部访问时只看 `pub`
+                let reachable = match exposure {
+                    ApiExposure::Public => true,
+                    ApiExposure::Unknown => caller.is_exported(),
+                    ApiExposure::Internal | ApiExposure::Private => false,
+                };
+                if reachable {
+                    next_targets.insert(FunctionTarget::exact(&caller.path));
+                }
+            }
+        }
+        // 重导出的路径对依赖者的用户可见，同样作为下一层的目标
+        for (target, exposed_paths) in calls.reexports() {
+            for exposed_path in exposed_paths {
+                parsed_any = true;
+                let recorded_path = recorded(exposed_path, sensitive);
+                graph.add_reexport(
+                    (&dependent.name(), &dependent.version(), &recorded_path),
+                    (parent_name, parent_version, &recorded(target, parent_sensitive)),
+                    level,
+                );
+                next_targets.insert(FunctionTarget::exact(exposed_path));
+            }
+        }
+        tracing::info!(
+            "依赖者 {} {} 的 {} 个公开调用者将作为下一层的目标函数",
+            dependent.name(),
+            dependent.version(),
+            next_targets.len()
+        );
+        parsed_any.then(|| next_targets.into_iter().collect())
+    }
+
+    async fn process_single_bfs_node(
+        &self,
+        krate: Krate,
+        targets: &[FunctionTarget],
+    ) -> Result<NodeResult> {
+        let node_start_time = std::time::Instant::now();
+        // 指定了依赖者列表时不再查询数据库
+        let mut selected_dependents = match &self.explicit_dependents {
+            Some(dependents) => self.with_index_reqs(&krate, dependents).await,
+            None => self.select_dependents(&krate).await?,
+        };
+        selected_dependents.extend(self.extra_dependents.iter().cloned());
+        if self.config.simulate_resolution {
+            self.simulate_resolution(&krate, &mut selected_dependents)
+                .await;
+        }
+        let krate = Arc::new(krate); // 用 Arc 包裹
+
+        // 从检查点恢复：已分析过的依赖者直接跳过
+        let checkpoint_path = NodeCheckpoint::path(
+            &self.config.checkpoint_dir,
+            &krate.name(),
+            &krate.version(),
+            targets,
+        );
+        let mut checkpoint = NodeCheckpoint::load(&checkpoint_path).await;
+        // 敏感依赖者和敏感父节点的依赖者在检查点中只有哈希，得不到下一层的目标，恢复时重新分析
+        checkpoint.affected.retain(|entry| {
+            if entry.calls.is_minimized() {
+                checkpoint
+                    .processed
+                    .remove(&NodeCheckpoint::key(&entry.name, &entry.version));
+            }
+            !entry.calls.is_minimized()
+        });
+        let mut next_nodes = checkpoint
+            .affected
+            .iter()
+            .map(|entry| {
+                (
+                    Krate::new(&entry.name, &entry.version),
+                    ReverseDependency::new(
+                        entry.name.clone(),
+                        entry.version.clone(),
+                        entry.req.clone(),
+                    )
+                    .with_source(entry.source)
+                    .with_resolved_version(entry.resolved_version.clone())
+                    .with_kinds(entry.kinds.clone())
+                    .with_required_features(entry.required_features.clone())
+                    .with_yanked(entry.yanked)
+                    .with_resolution(entry.resolution),
+                    entry.calls.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut pending_dependents = selected_dependents
+            .into_iter()
+            .filter(|dep| !checkpoint.is_processed(&dep.name, &dep.version))
+            .collect::<Vec<_>>();
+        // 优先依赖者单独组成最前面的批次，不必等普通依赖者分析完
+        pending_dependents.sort_by_key(|dep| !self.config.is_priority(&dep.name));
+        let priority_count = pending_dependents
+            .iter()
+            .filter(|dep| self.config.is_priority(&dep.name))
+            .count();
+        if !checkpoint.processed.is_empty() {
+            tracing::info!(
+                "从检查点恢复 {} {}: 已分析{}个，剩余{}个",
+                krate.name(),
+                krate.version(),
+                checkpoint.processed.len(),
+                pending_dependents.len()
+            );
+        }
+
+        let pending_len = pending_dependents.len();
+        let scheduler = BatchScheduler::new(
+            pending_len,
+            priority_count,
+            self.config.checkpoint_interval,
+        )
+        .with_retries(self.config.dependent_retries);
+        let pending_dependents = pending_dependents
+            .into_iter()
+            .map(|dep| (NodeCheckpoint::key(&dep.name, &dep.version), dep))
+            .collect();
+        let mut total_progress_idx = 0;
+        let analyze = |reverse_dependency: ReverseDependency| {
+            let analyzer = self.clone();
+            let targets = targets.to_vec();
+            let krate = Arc::clone(&krate);
+
+            total_progress_idx += 1;
+            tracing::info!(
+                "[依赖者进度 {}/{}] 正在分析依赖者: {} {}",
+                total_progress_idx,
+                pending_len,
+                reverse_dependency.name,
+                reverse_dependency.version
+            );
+            async move {
+                let name = reverse_dependency.name.clone();
+                let version = reverse_dependency.version.clone();
+                let result = analyzer
+                    .analyze_dependent(&krate, reverse_dependency, &targets)
+                    .await;
+                if analyzer.config.is_priority(&name) {
+                    info!(
+                        "优先依赖者 {} {} 分析完成: {}",
+                        name,
+                        version,
+                        match &result {
+                            Some((_, _, AnalysisOutcome::Affected(_))) => "受影响",
+                            Some((_, _, AnalysisOutcome::Cleared(_))) => "未受影响",
+                            None => "分析失败",
+                        }
+                    );
+                }
+                if let (Some(database), Some((dep_krate, _, outcome))) =
+                    (&analyzer.database, &result)
+                {
+                    let (verdict, detail) = match outcome {
+                        AnalysisOutcome::Affected(calls) => {
+                            // 父节点敏感时目标函数是它的调用者路径，和图中一样只记录哈希
+                            let parent_sensitive = analyzer.config.is_sensitive(&krate.name());
+                            let functions = calls
+                                .breakdown()
+                                .into_keys()
+                                .map(|function_path| {
+                                    if parent_sensitive {
+                                        hashed_path(&function_path)
+                                    } else {
+                                        function_path
+                                    }
+                                })
+                                .collect::<Vec<_>>();
+                            ("affected", functions.join(","))
+                        }
+                        AnalysisOutcome::Cleared(clearance) => {
+                            ("cleared", clearance.reason.as_str().to_string())
+                        }
+                    };
+                    database
+                        .record_result(ResultRow {
+                            parent_name: krate.name(),
+                            parent_version: krate.version(),
+                            crate_name: dep_krate.name(),
+                            version: dep_krate.version(),
+                            verdict,
+                            detail,
+                        })
+                        .await;
+                }
+                result
+            }
+        };
+        let record = |checkpoint: &mut NodeCheckpoint,
+                      (dep_krate, dependency, outcome): (
+            Krate,
+            ReverseDependency,
+            AnalysisOutcome,
+        )| {
+            let calls = match outcome {
+                AnalysisOutcome::Affected(calls) => calls,
+                AnalysisOutcome::Cleared(clearance) => {
+                    checkpoint.cleared.push(ClearedEntry {
+                        name: dep_krate.name(),
+                        version: dep_krate.version(),
+                        req: dependency.req,
+                        source: dependency.source,
+                        clearance,
+                    });
+                    return;
+                }
+            };
+            checkpoint.affected.push(AffectedEntry {
+                name: dep_krate.name(),
+                version: dep_krate.version(),
+                req: dependency.req.clone(),
+                source: dependency.source,
+                resolved_version: dependency.resolved_version.clone(),
+                kinds: dependency.kinds.clone(),
+                required_features: dependency.required_features.clone(),
+                yanked: dependency.yanked,
+                resolution: dependency.resolution,
+                // 检查点文件同样不能带出敏感依赖者的源码细节和敏感父节点的函数路径
+                calls: Self::checkpointed_calls(
+                    &calls,
+                    (
+                        self.config.is_sensitive(&dep_krate.name()),
+                        self.config.is_sensitive(&krate.name()),
+                    ),
+                ),
+            });
+            next_nodes.push((dep_krate, dependency, calls));
+        };
+        scheduler
+            .run(
+                pending_dependents,
+                LIMITS.tasks,
+                &mut checkpoint,
+                &checkpoint_path,
+                analyze,
+                record,
+            )
+            .await;
+
+        if self.config.bisect_introduction {
+            let introduced = self.bisect_introductions(&krate, targets, &checkpoint).await;
+            if !introduced.is_empty() {
+                checkpoint.introduced.extend(introduced);
+                if let Err(e) = checkpoint.save(&checkpoint_path).await {
+                    warn!("保存检查点失败: {:#}", e);
+                }
+            }
+        }
+
+        tracing::info!(
+            "节点 {} {} 处理完成，耗时 {:?}",
+            krate.name(),
+            krate.version(),
+            node_start_time.elapsed()
+        );
+        Ok((next_nodes, checkpoint.cleared, checkpoint.introduced))
+    }
+
+    /// 对每个受影响的依赖者，在它更早的、版本要求同样匹配父节点的版本中二分查找，
+    /// 定位第一个调用目标函数的版本
+    ///
+    /// 假设调用一旦引入就不会消失；分析失败或无法判断有无调用的版本像 `git bisect skip`
+    /// 一样跳过。只有从数据库或 API 枚举出的依赖者有版本历史
+    async fn bisect_introductions(
+        &self,
+        krate: &Krate,
+        targets: &[FunctionTarget],
+        checkpoint: &NodeCheckpoint,
+    ) -> Vec<IntroducedEntry> {
+        let parent_version = Version::parse(&krate.version())
+            .map(|version| version.to_string())
+            .unwrap_or_default();
+        let parent_key = NodeCheckpoint::key(&normalize_crate_name(&krate.name()), &parent_version);
+        // 每个依赖者最老的受影响版本
+        let mut oldest_affected = BTreeMap::<String, Version>::new();
+        for entry in &checkpoint.affected {
+            let Ok(version) = Version::parse(&entry.version) else {
+                continue;
+            };
+            let oldest = oldest_affected
+                .entry(normalize_crate_name(&entry.name))
+                .or_insert_with(|| version.clone());
+            if version < *oldest {
+                *oldest = version;
+            }
+        }
+        let bisections = oldest_affected
+            .into_iter()
+            .filter(|(name, _)| {
+                !checkpoint
+                    .introduced
+                    .iter()
+                    .any(|entry| normalize_crate_name(&entry.name) == *name)
+            })
+            .filter_map(|(name, affected)| {
+                let history = self
+                    .dependent_history
+                    .lock()
+                    .unwrap()
+                    .remove(&(parent_key.clone(), name.clone()));
+                if history.is_none() {
+                    tracing::info!("{} 没有版本历史，不能二分定位引入版本", name);
+                }
+                Some((name, affected, history?))
+            })
+            .collect::<Vec<_>>();
+
+        stream::iter(bisections)
+            .map(|(name, affected, history)| async move {
+                // 已确认没有调用的最新旧版本是二分的下界
+                let last_clear = checkpoint
+                    .cleared
+                    .iter()
+                    .filter(|entry| normalize_crate_name(&entry.name) == name)
+                    .filter(|entry| entry.clearance.reason.proves_no_call())
+                    .filter_map(|entry| Version::parse(&entry.version).ok())
+                    .filter(|version| *version < affected)
+                    .max();
+                let mut candidates = history
+                    .into_iter()
+                    .filter(|dep| {
+                        Version::parse(&dep.version).is_ok_and(|version| {
+                            version < affected
+                                && last_clear.as_ref().is_none_or(|clear| version > *clear)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                tracing::info!(
+                    "二分定位 {} 引入调用的版本: {} 之前还有 {} 个候选版本",
+                    name,
+                    affected,
+                    candidates.len()
+                );
+                let mut first_affected = affected.to_string();
+                let mut last_clear = last_clear.map(|version| version.to_string());
+                let mut checked = 0;
+                while !candidates.is_empty() {
+                    let mid = candidates.len() / 2;
+                    let dep = candidates[mid].clone();
+                    let version = dep.version.clone();
+                    checked += 1;
+                    match self.analyze_dependent(krate, dep, targets).await {
+                        Some((_, _, AnalysisOutcome::Affected(_))) => {
+                            first_affected = version;
+                            candidates.truncate(mid);
+                        }
+                        Some((_, _, AnalysisOutcome::Cleared(clearance)))
+                            if clearance.reason.proves_no_call() =>
+                        {
+                            last_clear = Some(version);
+                            candidates.drain(..=mid);
+                        }
+                        _ => {
+                            tracing::info!("{} {} 无法判断是否调用，跳过", name, version);
+                            candidates.remove(mid);
                         }
                     }
-                })
-                .buffer_unordered(MAX_CONCURRENT_TASKS)
-                .collect::<Vec<_>>()
+                }
+                tracing::info!(
+                    "{} 从 {} 开始调用目标函数（上一个无调用的版本: {}，二分分析了 {} 个版本）",
+                    name,
+                    first_affected,
+                    last_clear.as_deref().unwrap_or("无"),
+                    checked
+                );
+                IntroducedEntry {
+                    name,
+                    first_affected,
+                    last_clear,
+                    checked,
+                }
+            })
+            .buffer_unordered(LIMITS.tasks)
+            .collect()
+            .await
+    }
+
+    /// 查询依赖该节点的 crate，筛选出版本要求匹配的依赖者，每个依赖者只保留最老和最新版本
+    ///
+    /// 数据库的结果按名称排序逐行读取，一个依赖者的所有版本读完就立即筛选，
+    /// 内存里只保留当前依赖者的版本和已选中的结果
+    ///
+    /// 不超过 `dependents_cache_max_rows` 行的结果在本次运行中缓存，更大的每次重新流式读取，
+    /// 不常驻内存
+    async fn select_dependents(&self, krate: &Krate) -> Result<Vec<ReverseDependency>> {
+        tracing::info!("准备查询依赖者: {} {}", krate.name(), krate.version());
+
+        let crate_name = krate.name();
+        let parent_version = Version::parse(&krate.version()).ok();
+        let Some(database) = &self.database else {
+            let rows = self.api_dependents(&crate_name).await?;
+            return self
+                .select_from_rows(&crate_name, parent_version.as_ref(), Some(rows), HashMap::new())
                 .await;
+        };
+        // API 的结果按依赖者名称分组，在处理数据库中的同名依赖者时合并
+        let mut live = HashMap::<String, Vec<ReverseDependency>>::new();
+        if let Some(crates_io) = &self.crates_io {
+            for dep in self.live_dependents(crates_io, &crate_name).await {
+                live.entry(normalize_crate_name(&dep.name))
+                    .or_default()
+                    .push(dep);
+            }
+        }
+
+        // 数据库连接中途断开时从头重新读取，已选出的结果作废
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let cached = self.dependents_cache.get(&crate_name);
+            match self
+                .select_from_rows(&crate_name, parent_version.as_ref(), cached, live.clone())
+                .await
+            {
+                Err(e) if attempt <= database.query_retries() && is_transient_error(&e) => {
+                    warn!("读取 {} 的依赖者中断，第{}次重试: {:#}", crate_name, attempt, e);
+                    tokio::time::sleep(std::time::Duration::from_secs(2 * attempt as u64)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// 逐行读取数据库（或缓存）中的依赖者，按名称分组筛选，并合并 API 的结果
+    ///
+    /// `cached` 为 None 时从数据库流式读取，结果不太大时放入缓存
+    async fn select_from_rows(
+        &self,
+        crate_name: &str,
+        parent_version: Option<&Version>,
+        cached: Option<Arc<Vec<ReverseDependency>>>,
+        mut live: HashMap<String, Vec<ReverseDependency>>,
+    ) -> Result<Vec<ReverseDependency>> {
+        let mut stats = DependentStats::default();
+        let parent_key = NodeCheckpoint::key(
+            &normalize_crate_name(crate_name),
+            &parent_version.map(Version::to_string).unwrap_or_default(),
+        );
+        let mut selected_dependents = Vec::new();
+        let mut current: Option<String> = None;
+        let mut versions = Vec::new();
+        let rows = match (&cached, &self.database) {
+            (Some(cached), _) => stream::iter(cached.iter().cloned().map(Ok)).boxed(),
+            (None, Some(database)) => database.stream_dependents(crate_name).await.boxed(),
+            // 没有数据库时依赖者总是先从 API 完整读入
+            (None, None) => stream::empty().boxed(),
+        };
+        futures::pin_mut!(rows);
+        let max_cached_rows = self.config.dependents_cache_max_rows;
+        let mut to_cache = cached.is_none().then(Vec::new);
+        while let Some(dep) = rows.next().await {
+            let dep = dep?;
+            stats.rows += 1;
+            if let Some(rows) = &mut to_cache {
+                if rows.len() < max_cached_rows {
+                    rows.push(dep.clone());
+                } else {
+                    to_cache = None;
+                }
+            }
+            let name = normalize_crate_name(&dep.name);
+            if current.as_deref() != Some(name.as_str()) {
+                if let Some(done) = current.replace(name) {
+                    let versions = std::mem::take(&mut versions);
+                    let live_versions = live.remove(&done);
+                    let merged = self.merge_live_versions(
+                        versions,
+                        live_versions,
+                        parent_version,
+                        &mut stats,
+                    );
+                    let selected = self.select_group(&parent_key, &done, merged, &mut stats);
+                    selected_dependents.extend(selected);
+                }
+            }
+            if let Some(version) = self.matching_version(&dep, parent_version, &mut stats) {
+                versions.push((version, dep));
+            }
+        }
+        if let Some(done) = current {
+            let merged =
+                self.merge_live_versions(versions, live.remove(&done), parent_version, &mut stats);
+            selected_dependents.extend(self.select_group(&parent_key, &done, merged, &mut stats));
+        }
+        if let Some(rows) = to_cache {
+            self.dependents_cache.put(crate_name.to_string(), Arc::new(rows));
+        }
+        // 只在 API 结果中出现的依赖者
+        for (name, deps) in live {
+            let merged =
+                self.merge_live_versions(Vec::new(), Some(deps), parent_version, &mut stats);
+            selected_dependents.extend(self.select_group(&parent_key, &name, merged, &mut stats));
+        }
+
+        tracing::info!(
+            "读取了{}条依赖关系，共有{}个crate依赖（{}个版本）符合版本要求，筛选后剩余{}个版本进行分析",
+            stats.rows,
+            stats.crates,
+            stats.versions,
+            selected_dependents.len()
+        );
+        if stats.yanked > 0 {
+            tracing::info!("跳过了{}个已撤回的依赖者版本", stats.yanked);
+        }
+
+        Ok(selected_dependents)
+    }
+
+    /// 一个依赖者的所有匹配版本中选出要分析的最老和最新版本
+    fn select_group(
+        &self,
+        parent_key: &str,
+        name: &str,
+        versions: Vec<(Version, ReverseDependency)>,
+        stats: &mut DependentStats,
+    ) -> Vec<ReverseDependency> {
+        if versions.is_empty() {
+            return Vec::new();
+        }
+        let versions_count = versions.len();
+        stats.crates += 1;
+        stats.versions += versions_count;
+        if self.config.bisect_introduction {
+            let mut history = versions.clone();
+            history.sort_by(|(a, _), (b, _)| a.cmp(b));
+            self.dependent_history.lock().unwrap().insert(
+                (parent_key.to_owned(), normalize_crate_name(name)),
+                history.into_iter().map(|(_, dep)| dep).collect(),
+            );
+        }
+        let selected = self.select_oldest_and_newest_versions(versions);
+        tracing::info!(
+            "依赖者 {} 有{}个版本，选择了{}个版本进行分析",
+            name,
+            versions_count,
+            selected.len()
+        );
+        selected
+    }
+
+    /// 把 API 查到的版本中数据库没有且版本要求匹配的加入 `versions`
+    fn merge_live_versions(
+        &self,
+        mut versions: Vec<(Version, ReverseDependency)>,
+        live: Option<Vec<ReverseDependency>>,
+        parent_version: Option<&Version>,
+        stats: &mut DependentStats,
+    ) -> Vec<(Version, ReverseDependency)> {
+        for dep in live.unwrap_or_default() {
+            if versions.iter().any(|(_, known)| known.version == dep.version) {
+                continue;
+            }
+            if let Some(version) = self.matching_version(&dep, parent_version, stats) {
+                versions.push((version, dep));
+            }
+        }
+        versions
+    }
+
+    /// 依赖者的版本要求接受父节点版本时返回依赖者自己的版本，按配置跳过已撤回的版本
+    fn matching_version(
+        &self,
+        dep: &ReverseDependency,
+        parent_version: Option<&Version>,
+        stats: &mut DependentStats,
+    ) -> Option<Version> {
+        let req = VersionReq::parse(&dep.req).ok()?;
+        let policy = self.config.prerelease_policy;
+        if !policy.accepts(&req, parent_version?) {
+            return None;
+        }
+        if dep.yanked && self.config.yanked_policy == YankedPolicy::Skip {
+            stats.yanked += 1;
+            return None;
+        }
+        Version::parse(&dep.version)
+            .ok()
+            .filter(|version| policy.keeps_dependent(version))
+    }
+
+    /// 只看依赖者对父节点的版本要求，模拟解析器在各策略下选中的父节点版本，
+    /// 标注父节点版本是否会被自然选中，而不是只能靠锁定
+    ///
+    /// 父节点的发布记录来自数据库（含发布时间），没有数据库时来自索引
+    async fn simulate_resolution(&self, parent: &Krate, dependents: &mut [ReverseDependency]) {
+        let releases = match &self.database {
+            Some(database) => database.query_releases(&parent.name()).await,
+            None => INDEX.versions(&parent.name()).await.map(|versions| {
+                versions
+                    .iter()
+                    .map(|version| Release {
+                        num: version.vers.clone(),
+                        yanked: version.yanked,
+                        published_at: None,
+                    })
+                    .collect()
+            }),
+        };
+        let releases = match releases {
+            Ok(releases) => ParentReleases::new(releases),
+            Err(e) => {
+                warn!("查询 {} 的发布记录失败，跳过解析模拟: {:#}", parent.name(), e);
+                return;
+            }
+        };
+        let parent_version = parent.version();
+        let mut picked = 0;
+        for dep in dependents.iter_mut() {
+            dep.resolution = releases.simulate(&dep.req, &parent_version, dep.published_at);
+            if dep
+                .resolution
+                .is_some_and(|resolution| !resolution.policies().is_empty())
+            {
+                picked += 1;
+            }
+        }
+        tracing::info!(
+            "{} {}: {}个依赖者中有{}个在某种解析策略下会自然选中该版本",
+            parent.name(),
+            parent_version,
+            dependents.len(),
+            picked
+        );
+    }
+
+    /// 列表文件中的依赖者没有版本要求，从索引中读出它对父节点的实际要求，
+    /// 不兼容的版本在下载前就被排除；索引中查不到的保持 `*`
+    async fn with_index_reqs(
+        &self,
+        parent: &Krate,
+        dependents: &[ReverseDependency],
+    ) -> Vec<ReverseDependency> {
+        let mut dependents = dependents.to_vec();
+        if self.config.metadata_source != MetadataSource::Index {
+            return dependents;
+        }
+        for dep in dependents.iter_mut() {
+            if dep.source != DependentSource::File {
+                continue;
+            }
+            match INDEX
+                .dependency_req(&dep.name, &dep.version, &parent.name())
+                .await
+            {
+                Ok(Some(req)) => dep.req = req,
+                Ok(None) => {}
+                Err(e) => warn!("查询 {} {} 的索引失败: {:#}", dep.name, dep.version, e),
+            }
+        }
+        dependents
+    }
+
+    /// 没有数据库时从 API 查询依赖者，按名称排序后缓存，同一 crate 在本次运行中只查询一次
+    ///
+    /// API 对每个依赖者只列出最新版本，分析的版本比数据库模式少，请求也更慢
+    async fn api_dependents(&self, crate_name: &String) -> Result<Arc<Vec<ReverseDependency>>> {
+        if let Some(cached) = self.dependents_cache.get(crate_name) {
+            return Ok(cached);
+        }
+        let crates_io = self
+            .crates_io
+            .as_ref()
+            .context("未配置数据库，也没有可用的 API")?;
+        let mut rows = crates_io.reverse_dependencies(crate_name).await?;
+        // 与数据库的结果一样按名称排列，同一依赖者的版本相邻
+        rows.sort_by_cached_key(|dep| normalize_crate_name(&dep.name));
+        let rows = Arc::new(rows);
+        self.dependents_cache
+            .put(crate_name.clone(), Arc::clone(&rows));
+        Ok(rows)
+    }
+
+    /// 数据库快照超过配置的时限时，用 crates.io API 查询最新的依赖者，与数据库的结果合并
+    async fn live_dependents(
+        &self,
+        crates_io: &CratesIoApi,
+        crate_name: &str,
+    ) -> Vec<ReverseDependency> {
+        let Some(database) = &self.database else {
+            return Vec::new();
+        };
+        // 数据库快照只包含 crates.io，私有仓库的依赖者总是通过 API 查询
+        match database.dump_age().await {
+            _ if !REGISTRY.is_crates_io() => info!("查询私有仓库 API 获取依赖者"),
+            Ok(age) if age <= self.config.dump_max_age => return Vec::new(),
+            Ok(age) => info!(
+                "数据库快照已有 {} 小时，查询crates.io API补充依赖者",
+                age.as_secs() / 3600
+            ),
+            Err(e) => {
+                warn!("无法确定数据库快照时间，跳过API查询: {:#}", e);
+                return Vec::new();
+            }
+        }
+        match crates_io.reverse_dependencies(crate_name).await {
+            Ok(live) => {
+                info!("crates.io API返回 {} 个依赖者", live.len());
+                live
+            }
+            Err(e) => {
+                warn!("crates.io API查询失败，只使用数据库结果: {:#}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// 下载依赖者、锁定父节点版本并分析是否调用了目标函数，期间的 cargo / call-cg4rs
+    /// 调用都记在这个依赖者和父节点版本名下
+    async fn analyze_dependent(
+        &self,
+        krate: &Krate,
+        reverse_dependency: ReverseDependency,
+        targets: &[FunctionTarget],
+    ) -> Option<(Krate, ReverseDependency, AnalysisOutcome)> {
+        let context = DependentContext {
+            crate_name: reverse_dependency.name.clone(),
+            version: reverse_dependency.version.clone(),
+            parent_name: krate.name(),
+            parent_version: krate.version(),
+        };
+        diagnostics::for_dependent(
+            context,
+            self.run_dependent_analysis(krate, reverse_dependency, targets),
+        )
+        .await
+    }
+
+    async fn run_dependent_analysis(
+        &self,
+        krate: &Krate,
+        reverse_dependency: ReverseDependency,
+        targets: &[FunctionTarget],
+    ) -> Option<(Krate, ReverseDependency, AnalysisOutcome)> {
+        let mut dependency = reverse_dependency.clone();
+        let reverse_name = reverse_dependency.name;
+        let reverse_version = reverse_dependency.version;
+        let mut req_for_dep = reverse_dependency.req;
+
+        let dep_krate = Krate::new(&reverse_name, &reverse_version);
+        let dep_dir = match dep_krate.get_crate_dir_path(&self.config.timeouts).await {
+            Ok(dir) => dir,
+            Err(e) => {
+                tracing::warn!("[{}-{}] get_crate_dir_path失败: {}，跳过", reverse_name, reverse_version, e);
+                return None;
+            }
+        };
+
+        // 解压目录在并发的分析和其他运行之间共享，从 patch 到恢复原样都独占
+        let _source_lock = match STORE.lock_source(&dep_dir).await {
+            Ok(lock) => lock,
+            Err(e) => {
+                tracing::warn!("[{}-{}] 锁定解压目录失败: {:#}，跳过", reverse_name, reverse_version, e);
+                return None;
+            }
+        };
+        tracing::info!("[{}-{}] 开始 patch_cargo_toml_with_parent", reverse_name, reverse_version);
+        // 解压目录会被之后的运行复用，无论分析如何结束（包括出错和超时）都恢复原样
+        let package_dir = Krate::package_dir(&dep_dir, &reverse_name)
+            .await
+            .unwrap_or_else(|_| dep_dir.clone());
+        let _restore = ManifestGuard::new(&[&dep_dir, &package_dir]);
+
+        let patch_result = {
+            let _build_permit = LIMITS.builds.acquire().await.unwrap();
+            with_timeout(
+                "patch",
+                self.config.timeouts.patch,
+                Krate::patch_cargo_toml_with_parent(
+                    &dep_dir,
+                    &reverse_name,
+                    &krate.name(),
+                    &krate.version(),
+                    self.config.pin_strategy,
+                ),
+            )
+            .await
+        };
 
+        let declared = match patch_result {
+            Ok(declared) => {
+                tracing::info!("[{}-{}] 完成 patch_cargo_toml_with_parent", reverse_name, reverse_version);
+                declared
+            }
+            Err(e) if e.is::<PhaseTimeout>() => {
+                tracing::warn!("[{}-{}] patch_cargo_toml_with_parent超时，跳过该crate后续分析", reverse_name, reverse_version);
+                self.count_failure(FailureKind::TimedOut);
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "[{}-{}] patch_cargo_toml_with_parent失败，跳过该crate后续分析: {:#}",
+                    reverse_name,
+                    reverse_version,
+                    e
+                );
+                return None;
+            }
+        };
+        // 只在不计入影响的依赖表（例如 dev-dependencies）中声明父节点时不再分析
+        let kinds = declared
+            .iter()
+            .map(DeclaredDependency::dependency_kind)
+            .collect::<BTreeSet<_>>();
+        let counted = declared
+            .iter()
+            .filter(|dep| self.config.dependency_kinds.contains(&dep.dependency_kind()))
+            .collect::<Vec<_>>();
+        if !declared.is_empty() && counted.is_empty() {
             tracing::info!(
-                "第{}批处理完成，成功节点数: {}",
-                batch_idx + 1,
-                batch_results.iter().filter(|x| x.is_some()).count()
+                "[{}-{}] 只在 {} 中依赖 {}，不计入影响",
+                reverse_name,
+                reverse_version,
+                format_kinds(&kinds.iter().copied().collect::<Vec<_>>()),
+                krate.name()
             );
-            next_nodes.extend(batch_results.into_iter().filter_map(|x| x));
+            let clearance = Clearance::excluded_dependency_kind(kinds.into_iter().collect());
+            return Some((dep_krate, dependency, AnalysisOutcome::Cleared(clearance)));
+        }
+        dependency.kinds = kinds.into_iter().collect();
+        // 以依赖者实际声明的版本要求为准，数据库中的记录可能与 Cargo.toml 不一致
+        if let Some(dep) = counted.iter().find(|dep| dep.kind.is_none()).or(counted.first()) {
+            req_for_dep = dep.req.clone();
+        }
+        // 父节点是可选依赖且默认不启用时，记录需要的 feature，并在分析时启用它让调用代码参与编译
+        if !counted.is_empty() && counted.iter().all(|dep| dep.optional) {
+            let dep_key = counted[0].rename.as_deref().unwrap_or(&counted[0].name);
+            match Krate::optional_dependency_features(&dep_dir, &reverse_name, dep_key).await {
+                Ok(features) if !features.default && !features.enabling.is_empty() => {
+                    tracing::info!(
+                        "[{}-{}] 只有启用 {} 之一时才依赖 {}",
+                        reverse_name,
+                        reverse_version,
+                        features.enabling.join(", "),
+                        krate.name()
+                    );
+                    let enable = &features.enabling[..1];
+                    if let Err(e) = Krate::enable_default_features(&package_dir, enable).await {
+                        tracing::warn!(
+                            "[{}-{}] 启用 feature 失败: {:#}",
+                            reverse_name,
+                            reverse_version,
+                            e
+                        );
+                    }
+                    dependency.required_features = features.enabling;
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(
+                    "[{}-{}] 无法确定启用可选依赖的 feature: {:#}",
+                    reverse_name,
+                    reverse_version,
+                    e
+                ),
+            }
         }
+        // 依赖者用 `package = "..."` 重命名父节点时，源码中使用的是重命名后的名字
+        let crate_aliases = declared
+            .iter()
+            .filter_map(|dep| dep.rename.as_deref())
+            .map(normalize_crate_name)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
 
-        Ok(next_nodes)
-    }
+        // 传递依赖的约束可能让依赖图解析到其他版本，这时锁定的版本并未被使用
+        match Krate::resolved_parent_version(&dep_dir, &krate.name(), &krate.version()).await {
+            Ok(resolved) if resolved.as_deref() == Some(krate.version().as_str()) => {
+                dependency.resolved_version = resolved;
+            }
+            Ok(resolved) => {
+                tracing::warn!(
+                    "[{}-{}] 依赖图没有解析到 {} {}，实际为 {}",
+                    reverse_name,
+                    reverse_version,
+                    krate.name(),
+                    krate.version(),
+                    resolved.as_deref().unwrap_or("无")
+                );
+                let clearance = Clearance::not_resolved(resolved);
+                return Some((dep_krate, dependency, AnalysisOutcome::Cleared(clearance)));
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "[{}-{}] 无法验证解析到的版本: {:#}",
+                    reverse_name,
+                    reverse_version,
+                    e
+                );
+            }
+        }
 
-    /// 根据依赖表达式筛选能匹配precise_version的依赖者
-    fn filter_dependents_by_version_req(
-        dependents: Vec<ReverseDependency>,
-        precise_version: &str,
-    ) -> Vec<ReverseDependency> {
-        let precise_version_parsed = semver::Version::parse(precise_version).ok();
-        let filtered_dependents: Vec<ReverseDependency> = dependents
-            .into_iter()
-            .filter(|dep| {
-                if let (Some(ver), Ok(dep_req)) = (
-                    precise_version_parsed.as_ref(),
-                    semver::VersionReq::parse(&dep.req),
-                ) {
-                    dep_req.matches(ver)
-                } else {
-                    false
-                }
-            })
-            .collect();
+        tracing::info!("[{}-{}] 开始 is_valid_dependent", reverse_name, reverse_version);
+        let outcome = self
+            .is_valid_dependent(
+                krate,
+                &req_for_dep,
+                &reverse_name,
+                &reverse_version,
+                targets,
+                &crate_aliases,
+            )
+            .await
+            .unwrap_or(None);
+        let affected = matches!(outcome, Some(AnalysisOutcome::Affected(_)));
+        tracing::info!("[{}-{}] is_valid_dependent结果: {}", reverse_name, reverse_version, affected);
 
-        tracing::info!(
-            "filtered_dependents共得到符合版本要求的crate共 {} 个",
-            filtered_dependents.len()
-        );
-        filtered_dependents
+        if affected {
+            tracing::info!("依赖者 {} {} 满足条件，加入下一层", reverse_name, reverse_version);
+        } else {
+            tracing::info!("依赖者 {} {} 不满足条件，跳过", reverse_name, reverse_version);
+        }
+        outcome.map(|outcome| (dep_krate, dependency, outcome))
     }
 
     fn get_original_dir(&self) -> PathBuf {
@@ -406,14 +2345,12 @@ impl DependencyAnalyzer {
     // 主函数改名为更具体的名字
     async fn analyze_function_calls(
         &self,
+        parent: &Krate,
         crate_name: &str,
         crate_version: &str,
-        function_path: &str,
-    ) -> Option<String> {
-        // info!(
-        //     "开始分析 crate {} {} 的函数调用: {}",
-        //     crate_name, crate_version, function_path
-        // );
+        targets: &[FunctionTarget],
+        crate_aliases: &[String],
+    ) -> Option<AnalysisOutcome> {
 
         let krate = Krate::new(crate_name, crate_version);
         let original_dir = self.get_original_dir();
@@ -425,27 +2362,50 @@ impl DependencyAnalyzer {
         {
             Ok(dir) => dir,
             Err(e) => {
-                // warn!("准备分析环境失败: {}", e);
+                warn!("准备分析环境失败: {}", e);
                 return None;
             }
         };
 
-        // 运行函数调用分析工具
-        let analysis_result = self.run_function_analysis(&crate_dir, function_path).await;
+        // 运行函数调用分析工具，抽样的依赖者运行两次以检测结果是否稳定
+        let verify =
+            DeterminismStats::sampled(self.config.verify_sample_rate, crate_name, crate_version);
+        let analysis = self.run_function_analysis(
+            parent,
+            &crate_dir,
+            crate_name,
+            targets,
+            crate_aliases,
+            verify,
+        );
+        let analysis_result = with_timeout("analysis", self.config.timeouts.analysis, analysis).await;
 
         // 清理环境并返回结果
-        let result = self
+        let mut result = self
             .cleanup_and_return_result(&krate, &crate_dir, &original_dir, analysis_result)
             .await;
 
-        // 如果分析成功且有结果，保存到项目目录
-        if let Some(_) = &result {
-            if let Err(e) = self
-                .save_analysis_result(crate_name, crate_version, &crate_dir)
-                .await
-            {
-                warn!("保存分析结果失败: {}", e);
+        // 分析成功时保存到项目目录，未受影响的依赖者也保存证据
+        match &mut result {
+            Some(AnalysisOutcome::Affected(calls)) => {
+                if let Err(e) = self
+                    .save_analysis_result(&parent.name(), crate_name, crate_version, calls)
+                    .await
+                {
+                    warn!("保存分析结果失败: {}", e);
+                }
+            }
+            Some(AnalysisOutcome::Cleared(clearance)) => {
+                // 父节点敏感时目标函数是它的调用者路径，证据、检查点和图中都只记录哈希
+                if self.config.is_sensitive(&parent.name()) {
+                    *clearance = clearance.with_hashed_targets();
+                }
+                match self.save_clearance(crate_name, crate_version, clearance).await {
+                    Ok(path) => clearance.evidence_path = Some(path.display().to_string()),
+                    Err(e) => warn!("保存未受影响证据失败: {}", e),
+                }
             }
+            None => {}
         }
 
         result
@@ -455,35 +2415,339 @@ impl DependencyAnalyzer {
     async fn prepare_analysis_environment(
         &self,
         krate: &Krate,
-        _original_dir: &PathBuf,
+        _original_dir: &Path,
     ) -> Result<PathBuf> {
         // info!("准备分析环境: {} {}", krate.name(), krate.version());
 
         // 下载并解压crate（已自动判断是否已存在）
-        let crate_dir = krate.get_crate_dir_path().await.context(format!(
+        let crate_dir = krate.get_crate_dir_path(&self.config.timeouts).await.context(format!(
             "无法下载或解压 crate: {} {}",
             krate.name(),
             krate.version()
         ))?;
 
+        // 解压目录是工作区根目录（或虚拟清单）时，分析依赖者所在的成员包
+        let crate_dir = match Krate::package_dir(&crate_dir, &krate.name()).await {
+            Ok(package_dir) => {
+                if crate_dir.canonicalize().ok().as_ref() != Some(&package_dir) {
+                    info!("{} 是工作区，分析成员 {}", crate_dir.display(), package_dir.display());
+                }
+                package_dir
+            }
+            Err(e) => {
+                warn!("无法确定 {} 的包目录，使用解压目录: {:#}", krate.name(), e);
+                crate_dir
+            }
+        };
+
         info!("crate目录已就绪: {}", crate_dir.display());
         Ok(crate_dir)
     }
 
-    // 运行函数调用分析工具
+    // 对每个目标函数运行函数调用分析工具，只要调用了其中任意一个即认为受影响
+    // parent 是被锁定版本的父节点，用于选择该版本下目标函数的实际路径（别名）
     async fn run_function_analysis(
         &self,
-        crate_dir: &PathBuf,
-        function_path: &str,
-    ) -> Result<Option<String>> {
+        parent: &Krate,
+        crate_dir: &Path,
+        crate_name: &str,
+        targets: &[FunctionTarget],
+        crate_aliases: &[String],
+        verify: bool,
+    ) -> Result<AnalysisOutcome> {
         let src_dir = crate_dir.join("src");
-        if !self
-            .check_src_contain_target_function(&src_dir.to_string_lossy(), function_path)
-            .await?
+        let mut calls = FunctionCalls::default();
+        // 沙箱中没有网络，先在外面下载依赖
         {
-            return Ok(None);
+            let _build_permit = LIMITS.builds.acquire().await.unwrap();
+            self.config.build_env.prefetch(crate_dir).await?;
+        }
+        // 安全模式下不执行不受信任的构建脚本和过程宏，cargo expand 和 call-cg4rs 都会执行它们
+        let untrusted_build = if self.config.safe_build {
+            Krate::build_time_code(crate_dir)
+                .await?
+                .into_iter()
+                .filter(|(name, _)| !self.config.is_trusted_build(name))
+                .map(|(_, code)| code)
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+
+        // 依赖者可能没有调用目标函数，而是通过 `pub use` 把它暴露给自己的用户
+        // 源码中的写法还包括用重命名后的 crate 名开头的路径，call-cg4rs 只用规范路径
+        let candidates_by_target = targets
+            .iter()
+            .map(|target| {
+                let canonical = target.candidates_for(&parent.name(), &parent.version());
+                let renamed = crate_aliases.iter().flat_map(|alias| {
+                    canonical
+                        .iter()
+                        .map(|candidate| target::with_crate_alias(candidate, alias))
+                });
+                let spellings = canonical.iter().cloned().chain(renamed).collect::<Vec<_>>();
+                (target.name.clone(), spellings)
+            })
+            .collect::<BTreeMap<_, _>>();
+        let lib_name = Krate::lib_name(crate_dir, crate_name).await;
+        let surface = PublicSurface::load(crate_dir, &lib_name)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("解析 {} 的公开接口失败: {:#}", lib_name, e);
+                PublicSurface::default()
+            });
+        for reexport in reexport::find_reexports(&surface, &candidates_by_target) {
+            info!("{} 重导出了 {}", reexport.exposed_path, reexport.target);
+            calls.insert_reexport(&reexport.target, &reexport.exposed_path);
+        }
+
+        // 按配置的预检查策略判断依赖者引用各目标函数的可能性，未受影响时作为证据保存
+        let strategy = self.config.precheck.strategy();
+        let precheck = strategy.kind();
+        let search_dir = src_dir.clone();
+        let spellings = candidates_by_target.clone();
+        let mut confidence_by_target =
+            tokio::task::spawn_blocking(move || strategy.check(&search_dir, &spellings))
+                .await??;
+        // 宏展开后的源码，只在原始源码中找不到目标函数时才生成，每个依赖者最多一次
+        let mut expanded_index: Option<Option<SourceIndex>> = None;
+        let mut referenced = Vec::new();
+        for target in targets {
+            let candidates = target.candidates_for(&parent.name(), &parent.version());
+            if candidates.is_empty() {
+                continue;
+            }
+            let mut confidence = confidence_by_target
+                .get(&target.name)
+                .copied()
+                .unwrap_or(Confidence::None);
+            // 宏（包括 derive 宏）生成的调用在原始源码中看不到
+            if confidence == Confidence::None
+                && self.config.expand_macros
+                && untrusted_build.is_empty()
+            {
+                if expanded_index.is_none() {
+                    expanded_index = Some(
+                        self.expand_macros(crate_dir)
+                            .await
+                            .map(|src| SourceIndex::from_source(&src)),
+                    );
+                }
+                if let Some(Some(index)) = &expanded_index {
+                    let spellings = candidates_by_target.get(&target.name).unwrap_or(&candidates);
+                    confidence = index.confidence(spellings);
+                    if confidence != Confidence::None {
+                        info!("目标函数 {} 只出现在宏展开后的源码中", target.name);
+                    }
+                }
+            }
+            info!(
+                "预检查({}) {}: 置信度 {}",
+                precheck.as_str(),
+                target.name,
+                confidence.as_str()
+            );
+            confidence_by_target.insert(target.name.clone(), confidence);
+            if confidence != Confidence::None {
+                referenced.push((target, candidates));
+            }
+        }
+
+        if !referenced.is_empty() && !untrusted_build.is_empty() {
+            warn!(
+                "{} 引用了目标函数，但编译会执行不受信任的代码，安全模式下不做调用分析: {}",
+                crate_name,
+                untrusted_build.join(", ")
+            );
+            return Ok(AnalysisOutcome::Cleared(Clearance::untrusted_build(
+                precheck,
+                confidence_by_target,
+                untrusted_build,
+            )));
+        }
+
+        // 调用可能在 `#[cfg(feature = ...)]` 之后，按配置的每个 feature 组合分别运行 call-cg4rs
+        let matrix = self.feature_manifests(crate_dir, crate_name).await;
+        let runs = match &matrix {
+            Some((_, manifests)) => manifests
+                .iter()
+                .map(|(feature_set, manifest)| (feature_set, Some(manifest)))
+                .collect(),
+            None => vec![(&FeatureSet::Default, None)],
+        };
+        // `#[cfg(windows)]` 之后的调用和平台相关的依赖只在对应的目标平台上可见
+        let platforms = if self.config.platforms.is_empty() {
+            vec![None]
+        } else {
+            self.config.platforms.iter().map(|p| Some(p.as_str())).collect()
+        };
+        let manifest_path = crate_dir.join("Cargo.toml");
+        let mut found_by_run = Vec::new();
+        // 有多个配置时，一个配置构建失败（例如缺少目标平台的标准库）不影响其他配置
+        let run_count = runs.len() * platforms.len();
+        let mut failed_runs = Vec::new();
+        for (feature_set, manifest) in runs {
+            if let Some(manifest) = manifest {
+                info!("按 feature 配置 {} 运行 call-cg4rs", feature_set.label());
+                tokio_fs::write(&manifest_path, manifest)
+                    .await
+                    .context(format!("写入 {} 失败", manifest_path.display()))?;
+            }
+            'platform: for &platform in &platforms {
+                let mut found = FunctionCalls::default();
+                for (target, candidates) in &referenced {
+                    // 依次尝试同一函数的不同写法，直到 call-cg4rs 找到调用者
+                    for candidate in candidates {
+                        let callers_content =
+                            match self.run_call_cg(crate_dir, candidate, platform).await {
+                                Ok(Some(callers_content)) => callers_content,
+                                Ok(None) => continue,
+                                Err(e) if run_count > 1 => {
+                                    warn!(
+                                        "feature 配置 {}，目标平台 {} 分析失败: {:#}",
+                                        feature_set.label(),
+                                        platform.unwrap_or("本机"),
+                                        e
+                                    );
+                                    failed_runs.push(e);
+                                    continue 'platform;
+                                }
+                                Err(e) => return Err(e),
+                            };
+                        if verify {
+                            self.verify_call_cg(crate_dir, candidate, platform, &callers_content)
+                                .await?;
+                        }
+                        found.insert(&target.name, &callers_content);
+                        break;
+                    }
+                }
+                // 同一目标函数在多个配置下都有调用者时，保留第一个配置的结果
+                calls.merge_callers(&found);
+                found_by_run.push((feature_set, platform, found));
+            }
+        }
+        if let Some((original, _)) = &matrix {
+            tokio_fs::write(&manifest_path, original)
+                .await
+                .context(format!("恢复 {} 失败", manifest_path.display()))?;
+        }
+        if run_count > 1 && failed_runs.len() == run_count {
+            return Err(failed_runs.remove(0));
+        }
+
+        // 只出现在测试、示例、基准中的调用不算实际影响
+        let classify = |caller: &callers::CallerInfo| surface.call_site(caller, crate_dir);
+        calls.classify_call_sites(classify, &self.config.excluded_call_sites);
+        if matrix.is_some() || platforms != [None] {
+            let mut by_feature_set = BTreeMap::new();
+            let mut by_platform = BTreeMap::new();
+            for (feature_set, platform, mut found) in found_by_run {
+                found.classify_call_sites(classify, &self.config.excluded_call_sites);
+                let affected = !found.is_empty() || !calls.reexports().is_empty();
+                info!(
+                    "feature 配置 {}，目标平台 {}: {}",
+                    feature_set.label(),
+                    platform.unwrap_or("本机"),
+                    if affected { "受影响" } else { "未受影响" }
+                );
+                *by_feature_set.entry(feature_set.label()).or_insert(false) |= affected;
+                if let Some(platform) = platform {
+                    *by_platform.entry(platform).or_insert(false) |= affected;
+                }
+            }
+            if matrix.is_some() {
+                for (feature_set, affected) in by_feature_set {
+                    calls.set_feature_verdict(&feature_set, affected);
+                }
+            }
+            for (platform, affected) in by_platform {
+                calls.set_platform_verdict(platform, affected);
+            }
+        }
+        for (site, count) in calls.excluded() {
+            info!("排除 {} 个位于 {} 中的调用者", count, site.as_str());
+        }
+        if calls.is_empty() {
+            let clearance = Clearance::new(precheck, confidence_by_target, &calls);
+            if clearance.reason == ClearanceReason::NotReferenced {
+                info!("依赖者被预检查策略 {} 排除", precheck.as_str());
+            }
+            return Ok(AnalysisOutcome::Cleared(clearance));
+        }
+        // 标注每个调用者是否属于依赖者的公开接口，私有辅助函数中的调用不会继续向下传播
+        for caller in calls.callers().into_values().flatten() {
+            calls.set_exposure(&caller.path, surface.classify(&caller));
+        }
+        Ok(AnalysisOutcome::Affected(calls))
+    }
+
+    // 配置了 feature 矩阵时返回原始 Cargo.toml 和每个 feature 配置对应的 Cargo.toml
+    // 无法读取 feature 表时只分析默认 feature
+    async fn feature_manifests(
+        &self,
+        crate_dir: &Path,
+        crate_name: &str,
+    ) -> Option<(String, Vec<(FeatureSet, String)>)> {
+        let matrix = &self.config.feature_matrix;
+        if matrix.is_empty() || matrix == &[FeatureSet::Default] {
+            return None;
+        }
+        let manifest_path = crate_dir.join("Cargo.toml");
+        let prepared = async {
+            let original = tokio_fs::read_to_string(&manifest_path).await?;
+            let features = Krate::features(crate_dir, crate_name).await?;
+            let manifests = matrix
+                .iter()
+                .map(|feature_set| {
+                    let manifest = feature_set
+                        .apply(&original, &features)
+                        .context(format!("无法应用 feature 配置 {}", feature_set.label()))?;
+                    Ok((feature_set.clone(), manifest))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            anyhow::Ok((original, manifests))
+        };
+        match prepared.await {
+            Ok(prepared) => Some(prepared),
+            Err(e) => {
+                warn!("{} 无法按 feature 矩阵分析，只分析默认 feature: {:#}", crate_name, e);
+                None
+            }
+        }
+    }
+
+    // 再运行一次 call-cg4rs，比较两次输出的哈希
+    async fn verify_call_cg(
+        &self,
+        crate_dir: &Path,
+        function_path: &str,
+        platform: Option<&str>,
+        first_content: &str,
+    ) -> Result<()> {
+        let second_content = self
+            .run_call_cg(crate_dir, function_path, platform)
+            .await?
+            .unwrap_or_default();
+        if !self.determinism.record(first_content, &second_content) {
+            warn!(
+                "call-cg4rs 对 {} 中的 {} 两次输出不一致: {} vs {}",
+                crate_dir.display(),
+                function_path,
+                DeterminismStats::content_hash(first_content),
+                DeterminismStats::content_hash(&second_content)
+            );
         }
+        Ok(())
+    }
 
+    // 针对单个目标函数运行 call-cg4rs，`platform` 是要编译到的目标平台，None 为本机
+    async fn run_call_cg(
+        &self,
+        crate_dir: &Path,
+        function_path: &str,
+        platform: Option<&str>,
+    ) -> Result<Option<String>> {
         info!(
             "!!! 检查到目标函数{}，开始运行函数调用分析工具",
             function_path
@@ -492,22 +2756,82 @@ impl DependencyAnalyzer {
         let manifest_path = crate_dir.join("Cargo.toml");
         let output_dir = crate_dir.join("target"); // 工具生成在 crate 目录下
 
-        let mut cmd = Command::new("call-cg4rs");
-        cmd.args(&[
-            "--find-callers",
-            function_path,
-            "--json-output",
-            "--manifest-path",
-            &manifest_path.to_string_lossy(),
-            "--output-dir",
-            &output_dir.to_string_lossy(),
-        ]);
+        // 删除上一个目标函数留下的 callers.json，避免结果串用
+        let _ = tokio_fs::remove_file(output_dir.join("callers.json")).await;
+
+        let mut toolchain = self.toolchain_for(crate_dir).await;
+        let mut build_env = self.config.build_env.clone();
+        let mut attempts = 0;
+        let mut network_retries = 0;
+        loop {
+            attempts += 1;
+            let mut cmd = build_env.command("call-cg4rs", crate_dir, toolchain);
+            cmd.args([
+                "--find-callers",
+                function_path,
+                "--json-output",
+                "--manifest-path",
+                &manifest_path.to_string_lossy(),
+                "--output-dir",
+                &output_dir.to_string_lossy(),
+            ]);
+            // call-cg4rs 通过 cargo 编译依赖者，cargo 从环境变量读取目标平台
+            if let Some(platform) = platform {
+                cmd.env("CARGO_BUILD_TARGET", platform);
+            }
 
-        let call_cg_result = cmd.output().await.context("运行call-cg4rs工具失败")?;
+            let call_cg_permit = LIMITS.call_cg.acquire().await.unwrap();
+            let build_timeout = self.config.timeouts.build;
+            let call_cg_result = match with_timeout("build", build_timeout, async {
+                Ok(cmd.recorded_output().await)
+            })
+            .await?
+            {
+                Ok(output) => output,
+                // 卡死的依赖者记为超时，不再重试
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {
+                    let failure = FailureKind::Stalled;
+                    return Err(BuildFailure::new(failure, "call-cg4rs", attempts, &e.to_string())
+                        .into());
+                }
+                Err(e) => return Err(e).context("运行call-cg4rs工具失败"),
+            };
+            // 重试前的等待不占用名额
+            drop(call_cg_permit);
+            if call_cg_result.status.success() {
+                break;
+            }
 
-        if !call_cg_result.status.success() {
             let stderr = String::from_utf8_lossy(&call_cg_result.stderr);
-            warn!("call-cg4rs工具执行失败: {}", stderr);
+            let kind = FailureKind::classify(&stderr, &call_cg_result.status);
+            warn!("call-cg4rs工具执行失败 ({}): {}", kind.as_str(), stderr);
+            // 按失败原因决定是否重试，以及重试前要改变什么
+            let retry = match kind.retry() {
+                Retry::After(delay, times) if network_retries < times => {
+                    network_retries += 1;
+                    tokio::time::sleep(delay * network_retries as u32).await;
+                    true
+                }
+                Retry::SingleJob if build_env.jobs != Some(1) => {
+                    build_env.jobs = Some(1);
+                    true
+                }
+                Retry::OtherToolchain => match self.newest_toolchain() {
+                    Some(newest) if toolchain != Some(newest) => {
+                        toolchain = Some(newest);
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            };
+            if retry {
+                info!("重试 call-cg4rs ({})，第 {} 次", kind.as_str(), attempts + 1);
+                continue;
+            }
+            if kind.is_build_failure() {
+                return Err(BuildFailure::new(kind, "call-cg4rs", attempts, &stderr).into());
+            }
             return Ok(None);
         }
 
@@ -530,36 +2854,76 @@ impl DependencyAnalyzer {
         Ok(Some(callers_content))
     }
 
-    async fn check_src_contain_target_function(
-        &self,
-        src: &str,
-        target_function_path: &str,
-    ) -> Result<bool> {
-        let function_name = target_function_path.split("::").last().unwrap();
-
-        // 获取参数并添加到命令字符串
-        let args: Vec<String> = vec![
-            "-r".to_string(),
-            "-n".to_string(),
-            "--color=always".to_string(),
-            function_name.to_string(),
-            src.to_owned(),
-        ];
-        let mut grep_cmd = Command::new("grep");
-        grep_cmd.args(args);
-        tracing::info!("执行命令: {:?}", grep_cmd);
-        // 调用grep命令执行
-        let output = grep_cmd.output().await?;
-        // 返回grep的退出状态码
-        let status = output.status;
-        if status.success() {
-            return Ok(true);
-        } else {
-            // grep没有找到匹配内容时会返回非零状态码，这里特殊处理
-            if output.stdout.is_empty() && status.code() == Some(1) {
-                return Ok(false);
-            } else {
-                return Err(anyhow::anyhow!("搜索过程出错，退出码: {:?}", status.code()));
+    fn count_failure(&self, kind: FailureKind) {
+        *self.build_failures.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    // 版本最新的可用工具链，工具链不匹配时用它重试
+    fn newest_toolchain(&self) -> Option<&str> {
+        self.toolchains
+            .iter()
+            .max_by(|a, b| a.version.cmp(&b.version))
+            .map(|toolchain| toolchain.name.as_str())
+    }
+
+    // 按依赖者的 rust-version 和 edition 选择工具链，没有配置工具链时使用默认的
+    async fn toolchain_for(&self, crate_dir: &Path) -> Option<&str> {
+        if self.toolchains.is_empty() {
+            return None;
+        }
+        let minimum = toolchain::minimum_rust_version(crate_dir)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("无法读取 {} 要求的 Rust 版本: {:#}", crate_dir.display(), e);
+                None
+            });
+        match toolchain::select(&self.toolchains, minimum.as_ref()) {
+            Some(toolchain) => Some(toolchain.name.as_str()),
+            None => {
+                warn!(
+                    "{} 要求 Rust {}，没有满足的工具链，使用默认工具链",
+                    crate_dir.display(),
+                    minimum.map(|version| version.to_string()).unwrap_or_default()
+                );
+                None
+            }
+        }
+    }
+
+    // 运行 cargo expand 得到宏展开后的 lib 源码，失败时返回 None
+    async fn expand_macros(&self, crate_dir: &Path) -> Option<String> {
+        let toolchain = self.toolchain_for(crate_dir).await;
+        let manifest_path = crate_dir.join("Cargo.toml");
+        let mut cmd = self.config.build_env.command("cargo", crate_dir, toolchain);
+        cmd.args([
+            "expand",
+            "--lib",
+            "--manifest-path",
+            &manifest_path.to_string_lossy(),
+        ]);
+        let _build_permit = LIMITS.builds.acquire().await.unwrap();
+        let expand = async { Ok(cmd.recorded_output().await) };
+        let output = match with_timeout("build", self.config.timeouts.build, expand).await {
+            Ok(output) => output,
+            Err(e) => {
+                warn!("cargo expand {:#}", e);
+                return None;
+            }
+        };
+        match output {
+            Ok(output) if output.status.success() => {
+                Some(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            Ok(output) => {
+                warn!(
+                    "cargo expand 执行失败: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                None
+            }
+            Err(e) => {
+                warn!("运行 cargo expand 失败: {}", e);
+                None
             }
         }
     }
@@ -567,11 +2931,11 @@ impl DependencyAnalyzer {
     // 保存分析结果到项目目录
     async fn save_analysis_result(
         &self,
+        parent_name: &str,
         crate_name: &str,
         crate_version: &str,
-        crate_dir: &PathBuf,
+        calls: &FunctionCalls,
     ) -> Result<()> {
-        let src_path = crate_dir.join("target").join("callers.json");
         let result_filename = format!("{}-{}-callers.json", crate_name, crate_version);
         let dst_path = Path::new("target").join(&result_filename);
 
@@ -582,10 +2946,16 @@ impl DependencyAnalyzer {
                 .context("创建target目录失败")?;
         }
 
-        // 复制文件
-        tokio_fs::copy(&src_path, &dst_path).await.context(format!(
-            "复制callers.json到目标目录失败: {} -> {}",
-            src_path.display(),
+        // 按目标函数分组写入所有 callers.json，敏感 crate 只保留哈希和结论
+        let sensitive = self.config.is_sensitive(crate_name);
+        if sensitive {
+            info!("{} 被标记为敏感来源，结果中省略源码细节", crate_name);
+        }
+        let evidence =
+            Self::evidence_json(calls, (sensitive, self.config.is_sensitive(parent_name)));
+        let content = serde_json::to_string_pretty(&evidence)?;
+        tokio_fs::write(&dst_path, content).await.context(format!(
+            "写入分析结果失败: {}",
             dst_path.display()
         ))?;
 
@@ -593,33 +2963,67 @@ impl DependencyAnalyzer {
         Ok(())
     }
 
+    // 保存未受影响的证据到项目目录，返回文件路径
+    async fn save_clearance(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        clearance: &Clearance,
+    ) -> Result<PathBuf> {
+        let dst_path =
+            Path::new("target").join(format!("{}-{}-cleared.json", crate_name, crate_version));
+        tokio_fs::create_dir_all("target")
+            .await
+            .context("创建target目录失败")?;
+        let content = serde_json::to_string_pretty(&clearance.to_json())?;
+        tokio_fs::write(&dst_path, content).await.context(format!(
+            "写入未受影响证据失败: {}",
+            dst_path.display()
+        ))?;
+        Ok(dst_path)
+    }
+
     // 清理环境并返回结果
     async fn cleanup_and_return_result(
         &self,
         krate: &Krate,
-        _crate_dir: &PathBuf,
-        _original_dir: &PathBuf,
-        analysis_result: Result<Option<String>>,
-    ) -> Option<String> {
+        _crate_dir: &Path,
+        _original_dir: &Path,
+        analysis_result: Result<AnalysisOutcome>,
+    ) -> Option<AnalysisOutcome> {
         // 只清理下载的 .crate 压缩包，不删除解压后的项目文件夹
         let _ = krate.cleanup_crate_file().await;
         // 分析后自动 cargo clean，释放 target 空间
         let _ = krate.cargo_clean().await;
 
         match analysis_result {
-            Ok(Some(result)) => {
-                info!("crate {} {} 调用了目标函数", krate.name(), krate.version());
-                Some(result)
+            Ok(AnalysisOutcome::Affected(result)) => {
+                for (function_path, count) in result.breakdown() {
+                    info!(
+                        "crate {} {} 调用了目标函数 {}，调用者 {} 个",
+                        krate.name(),
+                        krate.version(),
+                        function_path,
+                        count
+                    );
+                }
+                Some(AnalysisOutcome::Affected(result))
             }
-            Ok(None) => {
+            Ok(AnalysisOutcome::Cleared(clearance)) => {
                 info!(
-                    "crate {} {} 没有调用目标函数",
+                    "crate {} {} 没有调用目标函数: {}",
                     krate.name(),
-                    krate.version()
+                    krate.version(),
+                    clearance.reason.as_str()
                 );
-                None
+                Some(AnalysisOutcome::Cleared(clearance))
             }
             Err(e) => {
+                if let Some(failure) = e.downcast_ref::<BuildFailure>() {
+                    self.count_failure(failure.kind);
+                } else if e.is::<PhaseTimeout>() {
+                    self.count_failure(FailureKind::TimedOut);
+                }
                 warn!(
                     "分析 crate {} {} 时发生错误: {}",
                     krate.name(),
@@ -639,32 +3043,300 @@ impl DependencyAnalyzer {
     // 检查依赖者是否有效（版本匹配且调用了目标函数）
     async fn is_valid_dependent(
         &self,
-        current_version: &str,
+        parent: &Krate,
         req: &str,
         dep_name: &str,
         dep_version: &str,
-        target_function_path: &str,
-    ) -> Result<bool> {
-        if let (Ok(ver), Ok(dep_req)) = (Version::parse(current_version), VersionReq::parse(req)) {
-            if dep_req.matches(&ver) {
-                let has_function_call = self
-                    .analyze_function_calls(dep_name, dep_version, target_function_path)
-                    .await
-                    .is_some();
-                if has_function_call {
+        targets: &[FunctionTarget],
+        crate_aliases: &[String],
+    ) -> Result<Option<AnalysisOutcome>> {
+        if let (Ok(ver), Ok(dep_req)) = (Version::parse(&parent.version()), VersionReq::parse(req)) {
+            if self.config.prerelease_policy.accepts(&dep_req, &ver) {
+                let calls = self
+                    .analyze_function_calls(parent, dep_name, dep_version, targets, crate_aliases)
+                    .await;
+                if let Some(AnalysisOutcome::Affected(_)) = calls {
                     info!(
                         "依赖者 {} {} 版本匹配且调用了目标函数",
                         dep_name, dep_version
                     );
-                } else {
+                } else if calls.is_some() {
                     info!(
                         "依赖者 {} {} 版本匹配但未调用目标函数",
                         dep_name, dep_version
                     );
                 }
-                return Ok(has_function_call);
+                return Ok(calls);
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{dot, graphml, markdown, sarif};
+    use crate::graph::ChainFilter;
+
+    // everything callers.json says about the caller that must not leave the machine
+    const SECRETS: [&str; 4] = ["parse_token", "token.rs", "SIGNING_KEY", "secret_app::decode"];
+
+    /// a sensitive dependent calling the target from a private helper, with its file,
+    /// line and snippet, and re-exporting the target
+    fn sensitive_calls() -> FunctionCalls {
+        let mut calls = FunctionCalls::default();
+        calls.insert(
+            "vuln::decode",
+            r#"{"callers": [{
+                "path": "secret_app::auth::parse_token",
+                "is_public": true,
+                "file": "/home/build/secret-app/src/auth/token.rs",
+                "line": 42,
+                "snippet": "vuln::decode(SIGNING_KEY)"
+            }]}"#,
+        );
+        calls.set_exposure("secret_app::auth::parse_token", ApiExposure::Public);
+        calls.insert_reexport("vuln::decode", "secret_app::decode");
+        calls
+    }
+
+    #[test]
+    fn sensitive_parents_leave_no_clear_paths_in_checkpoints_or_evidence() {
+        // 敏感父节点的依赖者：目标函数是父节点的调用者路径
+        let mut calls = FunctionCalls::default();
+        calls.insert(
+            "secret_app::auth::parse_token",
+            r#"[{"path": "downstream::login", "is_public": true}]"#,
+        );
+        calls.insert_reexport("secret_app::decode", "downstream::decode");
+
+        let checkpointed = DependencyAnalyzer::checkpointed_calls(&calls, (false, true));
+        let saved = serde_json::to_string(&checkpointed).unwrap();
+        for secret in SECRETS {
+            assert!(
+                !saved.contains(secret),
+                "{} leaked into the checkpoint",
+                secret
+            );
+        }
+        // 恢复时重新分析，而不是把哈希过的目标再加入图
+        assert!(checkpointed.is_minimized());
+
+        let evidence = DependencyAnalyzer::evidence_json(&calls, (false, true)).to_string();
+        for secret in SECRETS {
+            assert!(
+                !evidence.contains(secret),
+                "{} leaked into callers.json",
+                secret
+            );
+        }
+        assert!(evidence.contains(&hashed_path("secret_app::auth::parse_token")));
+        assert!(evidence.contains("downstream::login"));
+
+        let evidence = DependencyAnalyzer::evidence_json(&calls, (true, true)).to_string();
+        assert!(!evidence.contains("parse_token"));
+        assert!(!evidence.contains("downstream::login"));
+    }
+
+    #[test]
+    fn sensitive_crates_are_exported_without_source_detail() {
+        let mut config = AnalyzerConfig::from_env();
+        config.sensitive_crates = vec!["secret-*".to_owned()];
+        let mut graph = Graph::new();
+        graph.add_crate("vuln", "1.0.0", 0);
+        let parent = BfsNode {
+            krate: Krate::new("vuln", "1.0.0"),
+            targets: vec![FunctionTarget::exact("vuln::decode")],
+            depth: 0,
+        };
+        let dependent = (
+            Krate::new("secret-app", "0.1.0"),
+            ReverseDependency::new("secret-app".into(), "0.1.0".into(), "^1".into()),
+            sensitive_calls(),
+        );
+        let next = DependencyAnalyzer::merge_node_result(
+            &mut graph,
+            &parent,
+            (vec![dependent], Vec::new(), Vec::new()),
+            &config,
+        );
+
+        // 传播仍然使用真实路径
+        assert!(next[0]
+            .targets
+            .contains(&FunctionTarget::exact("secret_app::auth::parse_token")));
+        assert_eq!(graph.dependency_count(), 1);
+        assert_eq!(graph.call_count(), 2);
+
+        let filter = ChainFilter {
+            top_k: 3,
+            max_length: None,
+        };
+        let outputs = [
+            sarif::impact_sarif(&graph).to_string(),
+            dot::function_dot(&graph),
+            graphml::function_graphml(&graph),
+            markdown::impact_markdown(&graph, &filter),
+            serde_json::to_string(&sensitive_calls().minimized()).unwrap(),
+        ];
+        for output in &outputs {
+            for secret in SECRETS {
+                assert!(!output.contains(secret), "{} leaked into {}", secret, output);
             }
         }
-        Ok(false)
+        assert!(outputs[1].contains(&hashed_path("secret_app::auth::parse_token")));
+    }
+
+    fn node(version: &str, targets: &[&str], depth: usize) -> BfsNode {
+        BfsNode {
+            krate: Krate::new("mid", version),
+            targets: targets.iter().map(|path| FunctionTarget::exact(path)).collect(),
+            depth,
+        }
+    }
+
+    #[tokio::test]
+    async fn targets_reaching_a_running_node_are_batched_into_one_run() {
+        let mut state = BfsState::default();
+        state.add(node("1.0.0", &["vuln::a"], 2), false);
+        state.add(node("2.0.0", &["vuln::a"], 1), false);
+        // 还在排队时直接合并
+        state.add(node("1.0.0", &["vuln::b"], 1), false);
+        assert_eq!(state.len(), 2);
+
+        let first = state.pop().unwrap();
+        assert_eq!(first.targets.len(), 2);
+        assert_eq!(first.depth, 1);
+        let id = tokio::spawn(async {}).id();
+        state.start(id, first);
+
+        // 正在处理时两个父节点带来的新目标暂存，不再排队
+        state.add(node("1.0.0", &["vuln::c"], 3), false);
+        state.add(node("1.0.0", &["vuln::d"], 2), true);
+        assert_eq!(state.len(), 1);
+
+        let finished = state.finish(id).unwrap();
+        assert_eq!(finished.krate.version(), "1.0.0");
+        assert_eq!(state.len(), 2);
+        // 暂存时第一次加入决定是否优先
+        assert_eq!(state.pop().unwrap().krate.version(), "2.0.0");
+        let batched = state.pop().unwrap();
+        assert_eq!(
+            batched.targets,
+            [FunctionTarget::exact("vuln::c"), FunctionTarget::exact("vuln::d")]
+        );
+        assert_eq!(batched.depth, 2);
+        assert!(state.finish(id).is_none());
+    }
+
+    #[test]
+    fn follow_up_runs_of_a_node_are_united_on_the_edge() {
+        let config = AnalyzerConfig::from_env();
+        let mut graph = Graph::new();
+        graph.add_crate("vuln", "1.0.0", 0);
+        let run = |target: &str, feature_set: &str, platform: &str| {
+            let mut calls = FunctionCalls::default();
+            calls.insert(target, r#"[{"path": "app::run", "is_public": true}]"#);
+            calls.insert_reexport(target, &target.replace("vuln", "app"));
+            calls.set_feature_verdict(feature_set, true);
+            calls.set_platform_verdict(platform, true);
+            let dependent = (
+                Krate::new("app", "0.1.0"),
+                ReverseDependency::new("app".into(), "0.1.0".into(), "^1".into()),
+                calls,
+            );
+            let root = BfsNode {
+                krate: Krate::new("vuln", "1.0.0"),
+                targets: vec![FunctionTarget::exact(target)],
+                depth: 0,
+            };
+            (root, (vec![dependent], Vec::new(), Vec::new()))
+        };
+        // 第二次运行只带着正在运行时到达的目标
+        for (target, feature_set, platform) in [
+            ("vuln::a", "default", "x86_64-unknown-linux-gnu"),
+            ("vuln::b", "std", "aarch64-apple-darwin"),
+        ] {
+            let (root, result) = run(target, feature_set, platform);
+            DependencyAnalyzer::merge_node_result(&mut graph, &root, result, &config);
+        }
+
+        assert_eq!(graph.dependency_count(), 1);
+        let (_, _, edge) = graph.dependencies().next().unwrap();
+        assert_eq!(
+            edge.functions.keys().collect::<Vec<_>>(),
+            ["vuln::a", "vuln::b"]
+        );
+        assert_eq!(edge.reexports.len(), 2);
+        assert_eq!(
+            edge.feature_verdicts,
+            BTreeMap::from([("default".to_owned(), true), ("std".to_owned(), true)])
+        );
+        assert_eq!(
+            edge.platforms,
+            ["aarch64-apple-darwin", "x86_64-unknown-linux-gnu"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sensitive_parents_are_saved_without_clear_paths() {
+        let mut config = AnalyzerConfig::from_env();
+        config.sensitive_crates = vec!["secret-*".to_owned()];
+        let mut graph = Graph::new();
+        graph.add_crate("vuln", "1.0.0", 0);
+        let root = BfsNode {
+            krate: Krate::new("vuln", "1.0.0"),
+            targets: vec![FunctionTarget::exact("vuln::decode")],
+            depth: 0,
+        };
+        let dependent = (
+            Krate::new("secret-app", "0.1.0"),
+            ReverseDependency::new("secret-app".into(), "0.1.0".into(), "^1".into()),
+            sensitive_calls(),
+        );
+        let next = DependencyAnalyzer::merge_node_result(
+            &mut graph,
+            &root,
+            (vec![dependent], Vec::new(), Vec::new()),
+            &config,
+        );
+
+        // 下一层依赖者调用敏感 crate 的公开函数和重导出路径
+        let mut calls = FunctionCalls::default();
+        calls.insert(
+            "secret_app::auth::parse_token",
+            r#"[{"path": "downstream::login", "is_public": true}]"#,
+        );
+        calls.insert_reexport("secret_app::decode", "downstream::decode");
+        let downstream = (
+            Krate::new("downstream", "2.0.0"),
+            ReverseDependency::new("downstream".into(), "2.0.0".into(), "^0.1".into()),
+            calls,
+        );
+        let next = DependencyAnalyzer::merge_node_result(
+            &mut graph,
+            &next[0],
+            (vec![downstream], Vec::new(), Vec::new()),
+            &config,
+        );
+        assert!(next[0]
+            .targets
+            .contains(&FunctionTarget::exact("downstream::login")));
+
+        let path = std::env::temp_dir()
+            .join(format!("cross_pro_cg-sensitive-{}", std::process::id()))
+            .join("graph.json");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        graph.save(&path).await.unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        for secret in SECRETS {
+            assert!(!saved.contains(secret), "{} leaked into the saved graph", secret);
+        }
+        assert!(saved.contains("downstream::login"));
+        // 两层中敏感 crate 的同一个函数是同一个节点
+        assert_eq!(graph.call_count(), 4);
+        assert_eq!(graph.function_count(), 5);
     }
 }
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use sha2::{Digest, Sha256};
+
+/// counters of the double-run verification of call-cg4rs output
+#[derive(Debug, Default)]
+pub struct DeterminismStats {
+    checked: AtomicU64,
+    mismatched: AtomicU64,
+}
+
+impl DeterminismStats {
+    /// whether this dependent belongs to the verified sample
+    ///
+    /// the sample is picked from a hash of `name@version`, so a rerun verifies the same
+    /// dependents
+    pub fn sampled(rate: f64, name: &str, version: &str) -> bool {
+        if rate <= 0.0 {
+            return false;
+        }
+        let digest = Sha256::digest(format!("{}@{}", name, version).as_bytes());
+        let bucket = u16::from_be_bytes([digest[0], digest[1]]) as f64 / u16::MAX as f64;
+        bucket < rate
+    }
+
+    pub fn content_hash(content: &str) -> String {
+        hex::encode(Sha256::digest(content.as_bytes()))
+    }
+
+    /// record one comparison, returns whether both runs agreed
+    pub fn record(&self, first: &str, second: &str) -> bool {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        let same = Self::content_hash(first) == Self::content_hash(second);
+        if !same {
+            self.mismatched.fetch_add(1, Ordering::Relaxed);
+        }
+        same
+    }
+
+    pub fn log_stats(&self) {
+        let checked = self.checked.load(Ordering::Relaxed);
+        if checked == 0 {
+            return;
+        }
+        let mismatched = self.mismatched.load(Ordering::Relaxed);
+        tracing::info!(
+            "call-cg4rs 双跑校验: 共 {} 次，结果不一致 {} 次，不确定率 {:.2}%",
+            checked,
+            mismatched,
+            mismatched as f64 / checked as f64 * 100.0
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use std::future::Future;
+use std::path::Path;
+use std::process::Output;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::config::AnalyzerConfig;
+use crate::database::Database;
+use crate::failure::FailureKind;
+use crate::model::hashed_path;
+use crate::watchdog;
+
+// 本次运行中所有 cargo / call-cg4rs 调用的记录，导出时写入 cg_invocations
+pub static DIAGNOSTICS: Lazy<Diagnostics> = Lazy::new(Diagnostics::from_env);
+
+tokio::task_local! {
+    // 正在分析的依赖者和它的父节点，由 `for_dependent` 设置
+    static DEPENDENT: DependentContext;
+}
+
+/// the dependent a command works on and the parent version it is analyzed against
+#[derive(Debug, Clone, Default)]
+pub struct DependentContext {
+    pub crate_name: String,
+    pub version: String,
+    pub parent_name: String,
+    pub parent_version: String,
+}
+
+/// run `future` with the invocations it records attributed to `context` instead of
+/// the directory they run in, which for workspace members doesn't name the dependent
+pub async fn for_dependent<F: Future>(context: DependentContext, future: F) -> F::Output {
+    DEPENDENT.scope(context, future).await
+}
+
+/// one cargo / call-cg4rs invocation on a dependent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invocation {
+    pub crate_name: String,
+    pub version: String,
+    // the parent the dependent was analyzed against, empty outside of that analysis
+    #[serde(default)]
+    pub parent_name: String,
+    #[serde(default)]
+    pub parent_version: String,
+    // program and arguments
+    pub command: String,
+    // None when killed by a signal or when it couldn't be started
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+    pub stdout: String,
+    pub stderr: String,
+    pub failure: Option<FailureKind>,
+    // the dependent or the parent is in `SENSITIVE_CRATES`: the arguments are hashed
+    // and the output is dropped
+    #[serde(default)]
+    pub sensitive: bool,
+}
+
+/// collects the invocations of a run, output beyond `DIAGNOSTICS_MAX_OUTPUT` bytes per
+/// stream (default 16384) keeps only its end, where the errors are
+///
+/// with `PERSIST_RESULTS` every invocation is written to the database as it finishes,
+/// and only failed ones keep their output in memory for the exports
+#[derive(Debug)]
+pub struct Diagnostics {
+    max_output: usize,
+    invocations: Mutex<Vec<Invocation>>,
+    database: OnceLock<Arc<Database>>,
+    config: OnceLock<Arc<AnalyzerConfig>>,
+}
+
+impl Diagnostics {
+    pub fn from_env() -> Self {
+        Self {
+            max_output: std::env::var("DIAGNOSTICS_MAX_OUTPUT")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(16 * 1024),
+            invocations: Mutex::new(Vec::new()),
+            database: OnceLock::new(),
+            config: OnceLock::new(),
+        }
+    }
+
+    /// redact the invocations on crates that `config` marks as sensitive
+    pub fn redact_with(&self, config: Arc<AnalyzerConfig>) {
+        let _ = self.config.set(config);
+    }
+
+    /// write the invocations recorded from now on to `database` as well, when it
+    /// persists results
+    pub fn persist_to(&self, database: Arc<Database>) {
+        if database.persists_results() {
+            let _ = self.database.set(database);
+        }
+    }
+
+    async fn record(
+        &self,
+        command: &Command,
+        duration: Duration,
+        output: &std::io::Result<Output>,
+    ) {
+        let std_command = command.as_std();
+        let DependentContext {
+            crate_name,
+            version,
+            parent_name,
+            parent_version,
+        } = DEPENDENT.try_with(Clone::clone).unwrap_or_else(|_| {
+            // 不在依赖者的分析中（例如扩展目标函数时），只能从目录名推断
+            let (crate_name, version) = std_command
+                .get_current_dir()
+                .and_then(crate_of)
+                .unwrap_or_default();
+            DependentContext {
+                crate_name,
+                version,
+                ..DependentContext::default()
+            }
+        });
+        let sensitive = self.config.get().is_some_and(|config| {
+            config.is_sensitive(&crate_name) || config.is_sensitive(&parent_name)
+        });
+        // 敏感 crate 的参数中有目标函数路径和源码目录，只保留哈希
+        let command_line = std::iter::once(std_command.get_program().to_string_lossy())
+            .chain(std_command.get_args().map(|arg| {
+                let arg = arg.to_string_lossy();
+                if sensitive {
+                    hashed_path(&arg).into()
+                } else {
+                    arg
+                }
+            }))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut invocation = match output {
+            Ok(output) => Invocation {
+                crate_name,
+                version,
+                parent_name,
+                parent_version,
+                command: command_line,
+                exit_code: output.status.code(),
+                duration,
+                stdout: self.truncate(&output.stdout),
+                stderr: self.truncate(&output.stderr),
+                failure: (!output.status.success()).then(|| {
+                    FailureKind::classify(&String::from_utf8_lossy(&output.stderr), &output.status)
+                }),
+                sensitive: false,
+            },
+            Err(e) => Invocation {
+                crate_name,
+                version,
+                parent_name,
+                parent_version,
+                command: command_line,
+                exit_code: None,
+                duration,
+                stdout: String::new(),
+                stderr: e.to_string(),
+                failure: Some(match e.kind() {
+                    std::io::ErrorKind::TimedOut => FailureKind::Stalled,
+                    _ => FailureKind::Unknown,
+                }),
+                sensitive: false,
+            },
+        };
+        invocation.sensitive = sensitive;
+        if sensitive {
+            invocation.stdout.clear();
+            invocation.stderr.clear();
+        }
+        if let Some(database) = self.database.get() {
+            database.record_invocation(invocation.clone()).await;
+            // 完整的输出已经在数据库中，内存里只为导出保留失败调用的输出
+            if invocation.failure.is_none() {
+                invocation.stdout.clear();
+                invocation.stderr.clear();
+            }
+        }
+        self.invocations.lock().unwrap().push(invocation);
+    }
+
+    fn truncate(&self, output: &[u8]) -> String {
+        let output = String::from_utf8_lossy(output);
+        if output.len() <= self.max_output {
+            return output.into_owned();
+        }
+        let mut start = output.len() - self.max_output;
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+        format!("[省略前 {} 字节]\n{}", start, &output[start..])
+    }
+
+    /// the invocations recorded so far, leaving none behind
+    pub fn take(&self) -> Vec<Invocation> {
+        std::mem::take(&mut *self.invocations.lock().unwrap())
+    }
+}
+
+/// crate name and version of an extract directory `name-version`
+fn crate_of(dir: &Path) -> Option<(String, String)> {
+    let dir_name = dir.file_name()?.to_str()?;
+    // 包名本身可以含 `-`，版本从第一个能解析为 semver 的位置开始
+    dir_name
+        .match_indices('-')
+        .map(|(index, _)| (&dir_name[..index], &dir_name[index + 1..]))
+        .find(|(_, version)| semver::Version::parse(version).is_ok())
+        .map(|(name, version)| (name.to_owned(), version.to_owned()))
+}
+
+/// run a command that works on a dependent (its current dir) under the stall watchdog
+/// and record the invocation
+pub trait RecordedOutput {
+    async fn recorded_output(&mut self) -> std::io::Result<Output>;
+}
+
+impl RecordedOutput for Command {
+    async fn recorded_output(&mut self) -> std::io::Result<Output> {
+        let started = Instant::now();
+        let output = watchdog::output(self).await;
+        DIAGNOSTICS.record(self, started.elapsed(), &output).await;
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sensitive_invocations_carry_no_paths_or_output() {
+        let mut config = AnalyzerConfig::from_env();
+        config.sensitive_crates = vec!["secret-*".to_owned()];
+        let diagnostics = Diagnostics::from_env();
+        diagnostics.redact_with(Arc::new(config));
+
+        let mut command = Command::new("call-cg4rs");
+        command.args(["--find-callers", "secret_app::auth::parse_token"]);
+        let output = Ok(Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: b"secret_app::auth::parse_token".to_vec(),
+            stderr: b"error: /home/secret-app/src/auth.rs".to_vec(),
+        });
+        for (crate_name, parent_name) in [("secret-app", "vuln"), ("downstream", "secret-app")] {
+            let context = DependentContext {
+                crate_name: crate_name.to_owned(),
+                version: "1.0.0".to_owned(),
+                parent_name: parent_name.to_owned(),
+                parent_version: "0.1.0".to_owned(),
+            };
+            for_dependent(
+                context,
+                diagnostics.record(&command, Duration::from_secs(1), &output),
+            )
+            .await;
+        }
+        for_dependent(
+            DependentContext::default(),
+            diagnostics.record(&command, Duration::from_secs(1), &output),
+        )
+        .await;
+
+        let invocations = diagnostics.take();
+        for invocation in &invocations[..2] {
+            assert!(invocation.sensitive);
+            assert!(!invocation.command.contains("secret"));
+            assert!(invocation.command.contains(&hashed_path("--find-callers")));
+            assert!(invocation.stdout.is_empty() && invocation.stderr.is_empty());
+            assert!(invocation.failure.is_some());
+        }
+        assert!(!invocations[2].sensitive);
+        assert!(invocations[2]
+            .command
+            .contains("secret_app::auth::parse_token"));
+    }
+}
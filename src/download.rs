@@ -0,0 +1,257 @@
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use tokio::fs as tokio_fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+use crate::crates_io::USER_AGENT;
+use crate::index::INDEX;
+use crate::limits::LIMITS;
+use crate::registry::REGISTRY;
+
+// 下载进度的日志间隔
+const PROGRESS_STEP: u64 = 4 * 1024 * 1024;
+// 退避时间的上限
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// 失败后的重试次数，来自 `DOWNLOAD_RETRIES`（默认 3）
+static RETRIES: Lazy<u32> = Lazy::new(|| env_parse("DOWNLOAD_RETRIES", 3));
+// 第一次重试前的等待时间，之后每次翻倍，来自 `DOWNLOAD_BACKOFF_MS`（默认 500）
+static BACKOFF: Lazy<Duration> =
+    Lazy::new(|| Duration::from_millis(env_parse("DOWNLOAD_BACKOFF_MS", 500)));
+
+// 索引请求共用一个客户端（连接池），`HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY` 由 reqwest
+// 自动读取
+pub(crate) static CLIENT: Lazy<reqwest::Client> =
+    Lazy::new(|| client_builder().build().expect("创建索引客户端失败"));
+
+// 所有 crate 下载共用一个客户端，`DOWNLOAD_PROXY` 只用于下载
+static DOWNLOAD_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    let mut builder = client_builder();
+    if let Ok(proxy) = env::var("DOWNLOAD_PROXY") {
+        match reqwest::Proxy::all(&proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy.no_proxy(reqwest::NoProxy::from_env())),
+            Err(e) => warn!("忽略无效的 DOWNLOAD_PROXY {}: {}", proxy, e),
+        }
+    }
+    builder.build().expect("创建下载客户端失败")
+});
+
+// 镜像要求的认证头，例如 `X-JFrog-Art-Api: <key>`，只发给 `DOWNLOAD_MIRRORS` 中的镜像
+static MIRROR_AUTH: Lazy<Option<(reqwest::header::HeaderName, reqwest::header::HeaderValue)>> =
+    Lazy::new(|| {
+        let header = env::var("DOWNLOAD_AUTH_HEADER").ok()?;
+        parse_header(&header)
+            .map_err(|e| warn!("忽略无效的 DOWNLOAD_AUTH_HEADER: {:#}", e))
+            .ok()
+    });
+
+/// user agent and timeouts shared by every registry client, the whole request is
+/// bounded by `DOWNLOAD_TIMEOUT_SECS` (300 by default)
+pub(crate) fn client_builder() -> reqwest::ClientBuilder {
+    let timeout = env_parse("DOWNLOAD_TIMEOUT_SECS", 300);
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT.as_str())
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(Duration::from_secs(timeout))
+}
+
+/// `Name: value`
+fn parse_header(
+    header: &str,
+) -> Result<(reqwest::header::HeaderName, reqwest::header::HeaderValue)> {
+    let (name, value) = header.split_once(':').context("缺少 `:`")?;
+    let name = reqwest::header::HeaderName::from_bytes(name.trim().as_bytes())?;
+    let mut value = reqwest::header::HeaderValue::from_str(value.trim())?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// a download that still failed after every retry
+#[derive(Debug)]
+pub struct DownloadError {
+    pub url: String,
+    pub attempts: u32,
+    // HTTP status of the last attempt, None for network or I/O errors
+    pub status: Option<u16>,
+    pub last_error: anyhow::Error,
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "下载 {} 失败（尝试 {} 次", self.url, self.attempts)?;
+        if let Some(status) = self.status {
+            write!(f, "，HTTP {}", status)?;
+        }
+        write!(f, "）: {:#}", self.last_error)
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+/// download `url` to `path`, retrying failed attempts with exponential backoff
+///
+/// the body goes to `<path>.part` first and is only renamed once complete, so an
+/// existing `path` is always a whole file; a retry resumes the `.part` file with a
+/// range request when the server supports it
+pub async fn download_file(url: &str, path: &Path) -> Result<u64> {
+    let mut attempts = 0;
+    let mut backoff = *BACKOFF;
+    loop {
+        attempts += 1;
+        let error = match fetch(url, path).await {
+            Ok(size) => return Ok(size),
+            Err(e) => e,
+        };
+        let status = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<reqwest::Error>())
+            .and_then(reqwest::Error::status);
+        // 除了限流和超时，4xx 重试也不会成功
+        let retryable = status.is_none_or(|status| {
+            status.is_server_error()
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT
+        });
+        if !retryable || attempts > *RETRIES {
+            return Err(DownloadError {
+                url: url.to_owned(),
+                attempts,
+                status: status.map(|status| status.as_u16()),
+                last_error: error,
+            }
+            .into());
+        }
+        warn!(
+            "下载 {} 失败，{} 毫秒后第 {} 次重试: {:#}",
+            url,
+            backoff.as_millis(),
+            attempts,
+            error
+        );
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn fetch(url: &str, path: &Path) -> Result<u64> {
+    let part_path = part_path(path);
+    // 上一次尝试留下的部分内容
+    let resume_from = tokio_fs::metadata(&part_path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    LIMITS.throttle(url).await;
+    let mut request = DOWNLOAD_CLIENT.get(url);
+    // 私有仓库需要认证，令牌不能发给镜像和 CDN
+    if let (Some(token), true) = (&REGISTRY.token, REGISTRY.is_registry_url(url)) {
+        request = request.header(reqwest::header::AUTHORIZATION, token);
+    }
+    if let (Some((name, value)), true) = (&*MIRROR_AUTH, REGISTRY.is_mirror_url(url)) {
+        request = request.header(name, value);
+    }
+    if resume_from > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+    }
+    let response = request.send().await.context("请求失败")?;
+    // 部分内容已经不能续传（例如文件已完整或服务器上的文件变了），删除后从头下载
+    if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        let _ = tokio_fs::remove_file(&part_path).await;
+        bail!("无法从第 {} 字节续传", resume_from);
+    }
+    // 4xx/5xx 的响应体（例如 403 的 HTML 页面）不能当作 crate 文件保存
+    let mut response = response.error_for_status().context("服务器返回错误")?;
+
+    // 服务器不支持范围请求时返回 200 和完整内容，从头写
+    let resumed = resume_from > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+    let (mut file, mut size) = if resumed {
+        info!("从第 {} 字节继续下载 {}", resume_from, url);
+        let file = tokio_fs::OpenOptions::new()
+            .append(true)
+            .open(&part_path)
+            .await
+            .context(format!("打开 {} 失败", part_path.display()))?;
+        (file, resume_from)
+    } else {
+        let file = tokio_fs::File::create(&part_path)
+            .await
+            .context(format!("创建 {} 失败", part_path.display()))?;
+        (file, 0)
+    };
+    let total = response.content_length().map(|length| length + size);
+    let mut reported = size;
+    while let Some(chunk) = response.chunk().await.context("读取响应失败")? {
+        file.write_all(&chunk)
+            .await
+            .context(format!("写入 {} 失败", part_path.display()))?;
+        size += chunk.len() as u64;
+        if size - reported >= PROGRESS_STEP {
+            reported = size;
+            match total {
+                Some(total) => info!(
+                    "{}: {}/{} 字节 ({}%)",
+                    url,
+                    size,
+                    total,
+                    size * 100 / total.max(1)
+                ),
+                None => info!("{}: {} 字节", url, size),
+            }
+        }
+    }
+    file.flush().await?;
+    drop(file);
+
+    if size == 0 {
+        bail!("下载的文件为空");
+    }
+    if let Some(total) = total {
+        if size != total {
+            bail!("下载不完整: {}/{} 字节", size, total);
+        }
+    }
+    tokio_fs::rename(&part_path, path)
+        .await
+        .context(format!("重命名 {} 失败", part_path.display()))?;
+    Ok(size)
+}
+
+/// sha256 of a crate file as recorded in the registry index, None when the index
+/// doesn't list the version
+pub async fn expected_checksum(name: &str, version: &str) -> Result<Option<String>> {
+    Ok(INDEX
+        .versions(name)
+        .await?
+        .iter()
+        .find(|entry| entry.vers == version)
+        .map(|entry| entry.cksum.clone()))
+}
+
+/// sha256 of a file as lowercase hex
+pub async fn file_checksum(path: &Path) -> Result<String> {
+    let content = tokio_fs::read(path)
+        .await
+        .context(format!("读取 {} 失败", path.display()))?;
+    Ok(hex::encode(Sha256::digest(&content)))
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// `foo-1.0.0.crate` -> `foo-1.0.0.crate.part`
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
@@ -0,0 +1,162 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::graph::{CrateNode, FunctionNode, Graph};
+use crate::store;
+
+// run once per database, MERGE needs them to stay fast on large graphs
+const CONSTRAINTS: &str = "\
+CREATE CONSTRAINT crate_version IF NOT EXISTS FOR (c:Crate) REQUIRE (c.name, c.version) IS UNIQUE;
+CREATE CONSTRAINT function_path IF NOT EXISTS FOR (f:Function) REQUIRE (f.crate, f.version, f.path) IS UNIQUE;
+";
+
+/// the impact graph as a Cypher script for Neo4j, e.g. `cypher-shell -f impact.cypher`
+///
+/// crate versions become `Crate` nodes linked by `(dependent)-[:DEPENDS_ON]->(parent)`,
+/// functions become `Function` nodes linked by `CALLS` from caller to callee and to their
+/// crate by `DEFINES`. Everything is written with MERGE, so the scripts of several
+/// advisories can be loaded into the same database and queried together
+pub fn impact_cypher(graph: &Graph) -> String {
+    let mut cypher = String::from(CONSTRAINTS);
+    // ids are positions in the node lists
+    let crates = graph.crates().collect::<Vec<_>>();
+    for node in &crates {
+        let mut properties = vec![
+            format!("c.depth = {}", node.depth),
+            format!("c.yanked = {}", node.yanked),
+        ];
+        if let Some(repository) = &node.repository {
+            properties.push(format!("c.repository = {}", quote(repository)));
+        }
+        if let Some(downloads) = node.downloads {
+            properties.push(format!("c.downloads = {}", downloads));
+        }
+        if let Some(range) = &node.range {
+            properties.push(format!("c.vulnerable_range = {}", quote(range)));
+        }
+        let _ = writeln!(
+            cypher,
+            "MERGE (c:Crate {}) SET {};",
+            crate_key(node),
+            properties.join(", ")
+        );
+    }
+    for (parent, dependent, edge) in graph.dependencies_with_id() {
+        let mut properties = vec![
+            format!("r.req = {}", quote(&edge.req)),
+            format!("r.source = {}", quote(edge.source.as_str())),
+            format!(
+                "r.kinds = {}",
+                list(edge.kinds.iter().map(|kind| kind.as_str()))
+            ),
+            format!("r.targets = {}", list(edge.functions.keys())),
+        ];
+        if let Some(resolved) = &edge.resolved_version {
+            properties.push(format!("r.resolved_version = {}", quote(resolved)));
+        }
+        if !edge.required_features.is_empty() {
+            properties.push(format!(
+                "r.required_features = {}",
+                list(&edge.required_features)
+            ));
+        }
+        let _ = writeln!(
+            cypher,
+            "MATCH (p:Crate {}), (d:Crate {}) MERGE (d)-[r:DEPENDS_ON]->(p) SET {};",
+            crate_key(crates[parent]),
+            crate_key(crates[dependent]),
+            properties.join(", ")
+        );
+    }
+
+    let functions = graph
+        .functions_with_id()
+        .map(|(_, function)| function)
+        .collect::<Vec<_>>();
+    for function in &functions {
+        let mut properties = vec![
+            format!("f.exposure = {}", quote(function.exposure.as_str())),
+            format!("f.call_site = {}", quote(function.call_site.as_str())),
+        ];
+        if let (Some(file), Some(line)) = (&function.file, function.line) {
+            properties.push(format!("f.file = {}", quote(file)));
+            properties.push(format!("f.line = {}", line));
+        }
+        let _ = writeln!(
+            cypher,
+            "MERGE (f:Function {}) SET {} MERGE (c:Crate {{name: {}, version: {}}}) \
+             MERGE (c)-[:DEFINES]->(f);",
+            function_key(function),
+            properties.join(", "),
+            quote(&function.crate_name),
+            quote(&function.version)
+        );
+    }
+    for (caller, callee, edge) in graph.calls_with_id() {
+        let _ = writeln!(
+            cypher,
+            "MATCH (a:Function {}), (b:Function {}) MERGE (a)-[r:CALLS]->(b) \
+             SET r.kind = {}, r.constraint_depth = {};",
+            function_key(functions[caller]),
+            function_key(functions[callee]),
+            quote(edge.kind.as_str()),
+            edge.constraint_depth
+        );
+    }
+    cypher
+}
+
+pub async fn export_to_cypher(graph: &Graph, path: &Path) -> Result<()> {
+    store::write_atomic(path, impact_cypher(graph).as_bytes()).await?;
+    info!(
+        "已导出Cypher到 {}: {} 个 crate, {} 个函数",
+        path.display(),
+        graph.crate_count(),
+        graph.function_count()
+    );
+    Ok(())
+}
+
+fn crate_key(node: &CrateNode) -> String {
+    format!(
+        "{{name: {}, version: {}}}",
+        quote(&node.name),
+        quote(&node.version)
+    )
+}
+
+fn function_key(function: &FunctionNode) -> String {
+    format!(
+        "{{crate: {}, version: {}, path: {}}}",
+        quote(&function.crate_name),
+        quote(&function.version),
+        quote(&function.path)
+    )
+}
+
+/// a Cypher string literal
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('\'');
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+fn list<S: AsRef<str>>(values: impl IntoIterator<Item = S>) -> String {
+    let values = values
+        .into_iter()
+        .map(|value| quote(value.as_ref()))
+        .collect::<Vec<_>>();
+    format!("[{}]", values.join(", "))
+}
@@ -0,0 +1,136 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::graph::{format_kinds, CallKind, Graph};
+use crate::store;
+
+/// the crate-level graph as Graphviz DOT: one node per crate version labelled
+/// `crate@version`, one edge per dependency labelled with the requirement and the
+/// dependency kinds
+pub fn crate_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph crates {\n    rankdir=LR;\n    node [shape=box];\n");
+    for (id, node) in graph.crates_with_id() {
+        let mut attributes = vec![
+            format!(
+                "label={}",
+                quote(&format!("{}@{}", node.name, node.version))
+            ),
+            format!("depth={}", node.depth),
+        ];
+        if node.depth == 0 {
+            attributes.push("style=filled".to_string());
+            attributes.push("fillcolor=lightcoral".to_string());
+        }
+        if let Some(range) = &node.range {
+            attributes.push(format!("range={}", quote(range)));
+        }
+        if node.yanked {
+            attributes.push("yanked=true".to_string());
+        }
+        let _ = writeln!(dot, "    c{} [{}];", id, attributes.join(", "));
+    }
+    for (parent, dependent, edge) in graph.dependencies_with_id() {
+        let mut attributes = vec![
+            format!("label={}", quote(&edge.req)),
+            format!("req={}", quote(&edge.req)),
+            format!("source={}", quote(edge.source.as_str())),
+        ];
+        if !edge.kinds.is_empty() {
+            attributes.push(format!("kind={}", quote(&format_kinds(&edge.kinds))));
+        }
+        if !edge.required_features.is_empty() {
+            attributes.push("style=dashed".to_string());
+            attributes.push(format!(
+                "required_features={}",
+                quote(&edge.required_features.join(","))
+            ));
+        }
+        let _ = writeln!(
+            dot,
+            "    c{} -> c{} [{}];",
+            parent,
+            dependent,
+            attributes.join(", ")
+        );
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// the function-level graph as Graphviz DOT: one node per function labelled
+/// `crate@version::fn`, edges from caller to callee with the constraint depth and
+/// whether it is a call or a re-export
+pub fn function_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph functions {\n    rankdir=LR;\n    node [shape=ellipse];\n");
+    for (id, function) in graph.functions_with_id() {
+        // 路径以 crate 名开头时不重复
+        let lib_name = function.crate_name.replace('-', "_");
+        let path = function
+            .path
+            .strip_prefix(&lib_name)
+            .and_then(|rest| rest.strip_prefix("::"))
+            .unwrap_or(&function.path);
+        let label = format!("{}@{}::{}", function.crate_name, function.version, path);
+        let _ = writeln!(
+            dot,
+            "    f{} [label={}, exposure={}, call_site={}];",
+            id,
+            quote(&label),
+            quote(function.exposure.as_str()),
+            quote(function.call_site.as_str())
+        );
+    }
+    for (caller, callee, edge) in graph.calls_with_id() {
+        let style = match edge.kind {
+            CallKind::Call => "solid",
+            CallKind::ReExport => "dashed",
+        };
+        let _ = writeln!(
+            dot,
+            "    f{} -> f{} [label={}, constraint_depth={}, kind={}, style={}];",
+            caller,
+            callee,
+            quote(&format!("depth {}", edge.constraint_depth)),
+            edge.constraint_depth,
+            quote(edge.kind.as_str()),
+            style
+        );
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// write the crate-level and function-level DOT files that are configured
+pub async fn export_to_dot(
+    graph: &Graph,
+    crate_path: Option<&Path>,
+    function_path: Option<&Path>,
+) -> Result<()> {
+    if let Some(path) = crate_path {
+        store::write_atomic(path, crate_dot(graph).as_bytes()).await?;
+        info!(
+            "已导出依赖图到 {}: {} 个节点, {} 条边",
+            path.display(),
+            graph.crate_count(),
+            graph.dependency_count()
+        );
+    }
+    if let Some(path) = function_path {
+        store::write_atomic(path, function_dot(graph).as_bytes()).await?;
+        info!(
+            "已导出调用图到 {}: {} 个函数, {} 条调用",
+            path.display(),
+            graph.function_count(),
+            graph.call_count()
+        );
+    }
+    Ok(())
+}
+
+/// quote a string as DOT id
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,185 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::export::escape_xml;
+use crate::graph::{format_kinds, Graph};
+use crate::store;
+
+/// a GraphML attribute: (id, for, name, type)
+type Key = (&'static str, &'static str, &'static str, &'static str);
+
+const CRATE_KEYS: &[Key] = &[
+    ("name", "node", "name", "string"),
+    ("version", "node", "version", "string"),
+    ("depth", "node", "depth", "int"),
+    ("repository", "node", "repository", "string"),
+    ("range", "node", "range", "string"),
+    ("yanked", "node", "yanked", "boolean"),
+    ("req", "edge", "req", "string"),
+    ("source", "edge", "source", "string"),
+    ("resolved_version", "edge", "resolved_version", "string"),
+    ("kind", "edge", "kind", "string"),
+    ("required_features", "edge", "required_features", "string"),
+    ("platforms", "edge", "platforms", "string"),
+    ("callers", "edge", "callers", "int"),
+];
+
+const FUNCTION_KEYS: &[Key] = &[
+    ("crate_name", "node", "crate_name", "string"),
+    ("version", "node", "version", "string"),
+    ("path", "node", "path", "string"),
+    ("exposure", "node", "exposure", "string"),
+    ("call_site", "node", "call_site", "string"),
+    ("constraint_depth", "edge", "constraint_depth", "int"),
+    ("kind", "edge", "kind", "string"),
+];
+
+/// the crate-level graph as GraphML, one node per crate version and one edge per
+/// dependency; optional attributes are left out when unknown
+pub fn crate_graphml(graph: &Graph) -> String {
+    let mut xml = header("crates", CRATE_KEYS);
+    for (id, node) in graph.crates_with_id() {
+        let mut data = vec![
+            ("name", node.name.clone()),
+            ("version", node.version.clone()),
+            ("depth", node.depth.to_string()),
+            ("yanked", node.yanked.to_string()),
+        ];
+        if let Some(repository) = &node.repository {
+            data.push(("repository", repository.clone()));
+        }
+        if let Some(range) = &node.range {
+            data.push(("range", range.clone()));
+        }
+        element(
+            &mut xml,
+            &format!("<node id=\"c{}\">", id),
+            "</node>",
+            &data,
+        );
+    }
+    for (edge_id, (parent, dependent, edge)) in graph.dependencies_with_id().enumerate() {
+        let mut data = vec![
+            ("req", edge.req.clone()),
+            ("source", edge.source.as_str().to_string()),
+            ("kind", format_kinds(&edge.kinds)),
+            ("required_features", edge.required_features.join(",")),
+            ("platforms", edge.platforms.join(",")),
+            (
+                "callers",
+                edge.functions.values().sum::<usize>().to_string(),
+            ),
+        ];
+        if let Some(resolved_version) = &edge.resolved_version {
+            data.push(("resolved_version", resolved_version.clone()));
+        }
+        let open = format!(
+            "<edge id=\"d{}\" source=\"c{}\" target=\"c{}\">",
+            edge_id, parent, dependent
+        );
+        element(&mut xml, &open, "</edge>", &data);
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+/// the function-level graph as GraphML, edges go from caller to callee
+pub fn function_graphml(graph: &Graph) -> String {
+    let mut xml = header("functions", FUNCTION_KEYS);
+    for (id, function) in graph.functions_with_id() {
+        let data = vec![
+            ("crate_name", function.crate_name.clone()),
+            ("version", function.version.clone()),
+            ("path", function.path.clone()),
+            ("exposure", function.exposure.as_str().to_string()),
+            ("call_site", function.call_site.as_str().to_string()),
+        ];
+        element(
+            &mut xml,
+            &format!("<node id=\"f{}\">", id),
+            "</node>",
+            &data,
+        );
+    }
+    for (edge_id, (caller, callee, edge)) in graph.calls_with_id().enumerate() {
+        let data = vec![
+            ("constraint_depth", edge.constraint_depth.to_string()),
+            ("kind", edge.kind.as_str().to_string()),
+        ];
+        let open = format!(
+            "<edge id=\"e{}\" source=\"f{}\" target=\"f{}\">",
+            edge_id, caller, callee
+        );
+        element(&mut xml, &open, "</edge>", &data);
+    }
+    xml.push_str("  </graph>\n</graphml>\n");
+    xml
+}
+
+/// write the crate-level and function-level GraphML files that are configured
+pub async fn export_to_graphml(
+    graph: &Graph,
+    crate_path: Option<&Path>,
+    function_path: Option<&Path>,
+) -> Result<()> {
+    if let Some(path) = crate_path {
+        store::write_atomic(path, crate_graphml(graph).as_bytes()).await?;
+        info!(
+            "已导出依赖图到 {}: {} 个节点, {} 条边",
+            path.display(),
+            graph.crate_count(),
+            graph.dependency_count()
+        );
+    }
+    if let Some(path) = function_path {
+        store::write_atomic(path, function_graphml(graph).as_bytes()).await?;
+        info!(
+            "已导出调用图到 {}: {} 个函数, {} 条调用",
+            path.display(),
+            graph.function_count(),
+            graph.call_count()
+        );
+    }
+    Ok(())
+}
+
+/// the document up to the opening `<graph>`, with the attribute schema
+fn header(graph_id: &str, keys: &[Key]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\" \
+         xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xsi:schemaLocation=\"http://graphml.graphdrawing.org/xmlns \
+         http://graphml.graphdrawing.org/xmlns/1.0/graphml.xsd\">\n",
+    );
+    for (id, domain, name, kind) in keys {
+        let _ = writeln!(
+            xml,
+            "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>",
+            id, domain, name, kind
+        );
+    }
+    let _ = writeln!(
+        xml,
+        "  <graph id=\"{}\" edgedefault=\"directed\">",
+        graph_id
+    );
+    xml
+}
+
+/// a node or edge with its `<data>` children
+fn element(xml: &mut String, open: &str, close: &str, data: &[(&str, String)]) {
+    let _ = writeln!(xml, "    {}", open);
+    for (key, value) in data {
+        let _ = writeln!(
+            xml,
+            "      <data key=\"{}\">{}</data>",
+            key,
+            escape_xml(value)
+        );
+    }
+    let _ = writeln!(xml, "    {}", close);
+}
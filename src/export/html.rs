@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::export::{calls_by_crate, escape_xml, result_rows, CallsByCrate};
+use crate::graph::{format_kinds, CallKind, ChainFilter, CrateNode, DependencyEdge, Graph};
+use crate::model::normalize_crate_name;
+use crate::store;
+
+// stderr shown per failure, the end is where the errors are
+const STDERR_TAIL: usize = 4000;
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h2 { margin-top: 2em; border-bottom: 1px solid #ccc; }
+#search { width: 40em; padding: 0.4em; font-size: 1em; }
+ul.tree, ul.tree ul { list-style: none; padding-left: 1.4em; }
+summary { cursor: pointer; }
+.root { color: #b30000; font-weight: bold; }
+.meta { color: #666; font-size: 0.9em; }
+.calls { font-family: monospace; font-size: 0.85em; color: #444; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ddd; padding: 0.2em 0.6em; text-align: left; }
+td.affected { color: #b30000; }
+td.cleared { color: #1a7f37; }
+td.failed { color: #9a6700; }
+pre { max-height: 20em; overflow: auto; background: #f6f8fa; padding: 0.5em; }
+";
+
+// search over the tree, the tables and the chains; matching tree nodes are shown with
+// their ancestors expanded
+const SCRIPT: &str = "
+function search(query) {
+  query = query.trim().toLowerCase();
+  document.querySelectorAll('[data-search]').forEach(function (el) {
+    el.hidden = query !== '' && !el.dataset.search.includes(query);
+  });
+  if (query === '') return;
+  document.querySelectorAll('#tree li[data-search]').forEach(function (node) {
+    if (!node.dataset.search.includes(query)) return;
+    for (var el = node; el && el.id !== 'tree'; el = el.parentElement) {
+      if (el.tagName === 'LI') el.hidden = false;
+      if (el.tagName === 'DETAILS') el.open = true;
+    }
+  });
+}
+";
+
+/// the impact graph as a single self-contained HTML page for people who don't read the
+/// logs: a collapsible impact tree, the verdict of every analyzed dependent, the
+/// propagation chains and the failed invocations, with client-side search
+pub fn impact_html(graph: &Graph, chain_filter: &ChainFilter) -> String {
+    let crates = graph.crates().collect::<Vec<_>>();
+    let rows = result_rows(graph);
+    let failures = graph
+        .invocations()
+        .iter()
+        .filter(|invocation| invocation.failure.is_some())
+        .collect::<Vec<_>>();
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>impact report</title>\n",
+    );
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    html.push_str("<h1>Impact report</h1>\n");
+    let _ = writeln!(
+        html,
+        "<p>{} crates, {} dependencies, {} functions, {} calls; {} dependents cleared, {} \
+         failed invocations</p>",
+        graph.crate_count(),
+        graph.dependency_count(),
+        graph.function_count(),
+        graph.call_count(),
+        rows.iter().filter(|row| row.verdict == "cleared").count(),
+        failures.len()
+    );
+    html.push_str(
+        "<input id=\"search\" type=\"search\" placeholder=\"search crates, functions, \
+         reasons\" oninput=\"search(this.value)\">\n",
+    );
+
+    html.push_str("<h2>Impact tree</h2>\n<ul class=\"tree\" id=\"tree\">\n");
+    let mut tree = TreeContext {
+        crates: crates.clone(),
+        children: BTreeMap::new(),
+        calls: calls_by_crate(graph),
+    };
+    for (parent, dependent, edge) in graph.dependencies_with_id() {
+        tree.children
+            .entry(parent)
+            .or_default()
+            .push((dependent, edge));
+    }
+    let mut expanded = HashSet::new();
+    for (id, _) in crates
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| node.depth == 0)
+    {
+        tree.render(&mut html, id, None, &mut expanded);
+    }
+    html.push_str("</ul>\n");
+
+    html.push_str(
+        "<h2>Verdicts</h2>\n<table>\n<tr><th>crate</th><th>version</th><th>parent</th>\
+         <th>verdict</th><th>reason</th><th>callers</th><th>depth</th></tr>\n",
+    );
+    for row in &rows {
+        let parent = match (&row.parent_name, &row.parent_version) {
+            (Some(name), Some(version)) => format!("{}@{}", name, version),
+            _ => String::new(),
+        };
+        let reason = row.reason.clone().unwrap_or_default();
+        let search = format!(
+            "{} {} {} {} {}",
+            row.name, row.version, parent, row.verdict, reason
+        );
+        let _ = writeln!(
+            html,
+            "<tr data-search=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td class=\"{}\">{}</td>\
+             <td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape_xml(&search.to_lowercase()),
+            escape_xml(&row.name),
+            escape_xml(&row.version),
+            escape_xml(&parent),
+            row.verdict,
+            row.verdict,
+            escape_xml(&reason),
+            row.callers,
+            row.depth.map(|depth| depth.to_string()).unwrap_or_default()
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Propagation chains</h2>\n<ol>\n");
+    for chain in graph.chains(chain_filter) {
+        let chain = chain
+            .iter()
+            .map(|id| crate_label(crates[*id]))
+            .collect::<Vec<_>>()
+            .join(" → ");
+        let _ = writeln!(
+            html,
+            "<li data-search=\"{}\">{}</li>",
+            escape_xml(&chain.to_lowercase()),
+            escape_xml(&chain)
+        );
+    }
+    html.push_str("</ol>\n");
+
+    html.push_str(
+        "<h2>Failures</h2>\n<table>\n<tr><th>crate</th><th>version</th><th>kind</th>\
+         <th>exit code</th><th>command</th></tr>\n",
+    );
+    for invocation in &failures {
+        let kind = invocation
+            .failure
+            .map(|kind| kind.as_str())
+            .unwrap_or_default();
+        let search = format!("{} {} {}", invocation.crate_name, invocation.version, kind);
+        let _ = write!(
+            html,
+            "<tr data-search=\"{}\"><td>{}</td><td>{}</td><td class=\"failed\">{}</td><td>{}</td>",
+            escape_xml(&search.to_lowercase()),
+            escape_xml(&invocation.crate_name),
+            escape_xml(&invocation.version),
+            kind,
+            invocation
+                .exit_code
+                .map(|code| code.to_string())
+                .unwrap_or_default()
+        );
+        // 敏感 crate 的命令行和输出中有源码路径，报告中只给出失败类别
+        if invocation.sensitive {
+            html.push_str("<td></td></tr>\n");
+            continue;
+        }
+        let stderr = &invocation.stderr;
+        let mut start = stderr.len().saturating_sub(STDERR_TAIL);
+        while !stderr.is_char_boundary(start) {
+            start += 1;
+        }
+        let _ = writeln!(
+            html,
+            "<td><details><summary><code>{}</code></summary><pre>{}</pre></details></td></tr>",
+            escape_xml(&invocation.command),
+            escape_xml(&stderr[start..])
+        );
+    }
+    html.push_str("</table>\n");
+
+    let _ = writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+    html
+}
+
+pub async fn export_to_html(graph: &Graph, path: &Path, chain_filter: &ChainFilter) -> Result<()> {
+    store::write_atomic(path, impact_html(graph, chain_filter).as_bytes()).await?;
+    info!(
+        "已导出HTML报告到 {}: {} 个 crate",
+        path.display(),
+        graph.crate_count()
+    );
+    Ok(())
+}
+
+struct TreeContext<'a> {
+    crates: Vec<&'a CrateNode>,
+    children: BTreeMap<usize, Vec<(usize, &'a DependencyEdge)>>,
+    calls: CallsByCrate<'a>,
+}
+
+impl TreeContext<'_> {
+    /// one node of the impact tree with its dependents, `via` is the parent it is shown
+    /// under; a crate reached again through another parent is only expanded the first
+    /// time
+    fn render(
+        &self,
+        html: &mut String,
+        id: usize,
+        via: Option<(usize, &DependencyEdge)>,
+        expanded: &mut HashSet<usize>,
+    ) {
+        let node = self.crates[id];
+        let mut search = crate_label(node);
+        let mut label = escape_xml(&crate_label(node));
+        let mut meta = Vec::new();
+        let mut details = String::new();
+        match via {
+            None => {
+                label = format!("<span class=\"root\">{}</span>", label);
+                if let Some(range) = &node.range {
+                    meta.push(format!("range {}", range));
+                }
+            }
+            Some((parent, edge)) => {
+                meta.push(format!("req {}", edge.req));
+                if !edge.kinds.is_empty() {
+                    meta.push(format_kinds(&edge.kinds));
+                }
+                if !edge.required_features.is_empty() {
+                    meta.push(format!("features {}", edge.required_features.join(",")));
+                }
+                let parent = self.crates[parent];
+                let key = (
+                    normalize_crate_name(&node.name),
+                    node.version.as_str(),
+                    normalize_crate_name(&parent.name),
+                    parent.version.as_str(),
+                );
+                if let Some(calls) = self.calls.get(&key) {
+                    details.push_str("<ul class=\"calls\">");
+                    for (caller, callee, kind) in calls {
+                        let arrow = match kind {
+                            CallKind::Call => "→",
+                            CallKind::ReExport => "re-exports",
+                        };
+                        let _ = write!(
+                            details,
+                            "<li>{} {} {}</li>",
+                            escape_xml(caller),
+                            arrow,
+                            escape_xml(callee)
+                        );
+                        let _ = write!(search, " {} {}", caller, callee);
+                    }
+                    details.push_str("</ul>");
+                }
+            }
+        }
+        if node.yanked {
+            meta.push("yanked".to_string());
+        }
+        if !meta.is_empty() {
+            let _ = write!(
+                label,
+                " <span class=\"meta\">{}</span>",
+                escape_xml(&meta.join(", "))
+            );
+        }
+
+        let _ = write!(
+            html,
+            "<li data-search=\"{}\">",
+            escape_xml(&search.to_lowercase())
+        );
+        let children = self
+            .children
+            .get(&id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if children.is_empty() || !expanded.insert(id) {
+            let repeated = if children.is_empty() {
+                ""
+            } else {
+                " <span class=\"meta\">(dependents listed above)</span>"
+            };
+            let _ = writeln!(html, "{}{}{}</li>", label, repeated, details);
+            return;
+        }
+        let _ = writeln!(
+            html,
+            "<details open><summary>{}</summary>{}<ul>",
+            label, details
+        );
+        for (child, edge) in children {
+            self.render(html, *child, Some((id, edge)), expanded);
+        }
+        html.push_str("</ul></details></li>\n");
+    }
+}
+
+fn crate_label(node: &CrateNode) -> String {
+    format!("{}@{}", node.name, node.version)
+}
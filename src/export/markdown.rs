@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::info;
+
+use crate::export::{calls_by_crate, result_rows};
+use crate::graph::{CallKind, ChainFilter, CrateNode, Graph};
+use crate::model::normalize_crate_name;
+use crate::store;
+
+// chains listed in the report, the ones ending at the most downloaded crates
+const MAX_CHAINS: usize = 10;
+// calls shown per hop of a chain
+const CALLS_PER_HOP: usize = 3;
+
+/// a Markdown summary of the impact for an advisory or an issue: the number of
+/// dependents per verdict, the affected crates by downloads and a few representative
+/// propagation chains with the calls behind each hop
+pub fn impact_markdown(graph: &Graph, chain_filter: &ChainFilter) -> String {
+    let crates = graph.crates().collect::<Vec<_>>();
+    let roots = crates
+        .iter()
+        .filter(|node| node.depth == 0)
+        .collect::<Vec<_>>();
+    let mut md = String::from("# Impact summary\n\n");
+    for root in &roots {
+        let _ = write!(md, "- `{}@{}`", root.name, root.version);
+        if let Some(range) = &root.range {
+            let _ = write!(md, " (vulnerable range `{}`)", range);
+        }
+        md.push('\n');
+    }
+
+    let mut verdicts: BTreeMap<&str, usize> = BTreeMap::new();
+    for row in result_rows(graph) {
+        *verdicts.entry(row.verdict).or_default() += 1;
+    }
+    let affected = crates
+        .iter()
+        .filter(|node| node.depth > 0)
+        .collect::<Vec<_>>();
+    let _ = writeln!(
+        md,
+        "\n{} crate versions reach a vulnerable function, {} of them directly.\n",
+        affected.len(),
+        affected.iter().filter(|node| node.depth == 1).count()
+    );
+    md.push_str("| verdict | dependents |\n|---|---|\n");
+    for verdict in ["affected", "cleared", "failed"] {
+        let _ = writeln!(
+            md,
+            "| {} | {} |",
+            verdict,
+            verdicts.get(verdict).copied().unwrap_or_default()
+        );
+    }
+
+    // 已知下载次数的排在前面
+    let mut by_downloads = affected.clone();
+    by_downloads.sort_by_key(|node| {
+        (
+            std::cmp::Reverse(node.downloads),
+            node.depth,
+            node.name.clone(),
+        )
+    });
+    let mut parents: BTreeMap<(String, &str), Vec<String>> = BTreeMap::new();
+    for (parent, dependent, _) in graph.dependencies() {
+        parents
+            .entry((
+                normalize_crate_name(&dependent.name),
+                dependent.version.as_str(),
+            ))
+            .or_default()
+            .push(format!("{}@{}", parent.name, parent.version));
+    }
+    md.push_str(
+        "\n## Affected crates\n\n| crate | version | depth | downloads | via |\n\
+         |---|---|---|---|---|\n",
+    );
+    for node in &by_downloads {
+        let via = parents
+            .get(&(normalize_crate_name(&node.name), node.version.as_str()))
+            .map(|parents| parents.join(", "))
+            .unwrap_or_default();
+        let _ = writeln!(
+            md,
+            "| {} | {}{} | {} | {} | {} |",
+            cell(&node.name),
+            cell(&node.version),
+            if node.yanked { " (yanked)" } else { "" },
+            node.depth,
+            node.downloads
+                .map(|downloads| downloads.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            cell(&via)
+        );
+    }
+
+    // 每个末端 crate 只取最轻的一条路径
+    let filter = ChainFilter {
+        top_k: 1,
+        max_length: chain_filter.max_length,
+    };
+    let mut chains = graph.chains(&filter);
+    chains.sort_by_key(|chain| {
+        let leaf = crates[*chain.last().unwrap_or(&0)];
+        (std::cmp::Reverse(leaf.downloads), chain.len())
+    });
+    let calls = calls_by_crate(graph);
+    md.push_str("\n## Representative call chains\n\n");
+    if chains.is_empty() {
+        md.push_str("No dependent reaches a vulnerable function.\n");
+    }
+    for (index, chain) in chains.iter().take(MAX_CHAINS).enumerate() {
+        let path = chain
+            .iter()
+            .map(|id| format!("`{}`", crate_label(crates[*id])))
+            .collect::<Vec<_>>()
+            .join(" → ");
+        let _ = writeln!(md, "{}. {}", index + 1, path);
+        for hop in chain.windows(2) {
+            let (parent, dependent) = (crates[hop[0]], crates[hop[1]]);
+            let key = (
+                normalize_crate_name(&dependent.name),
+                dependent.version.as_str(),
+                normalize_crate_name(&parent.name),
+                parent.version.as_str(),
+            );
+            let Some(hop_calls) = calls.get(&key) else {
+                continue;
+            };
+            for (caller, callee, kind) in hop_calls.iter().take(CALLS_PER_HOP) {
+                let verb = match kind {
+                    CallKind::Call => "calls",
+                    CallKind::ReExport => "re-exports",
+                };
+                let _ = writeln!(md, "   - `{}` {} `{}`", caller, verb, callee);
+            }
+            if hop_calls.len() > CALLS_PER_HOP {
+                let _ = writeln!(
+                    md,
+                    "   - … {} more in `{}`",
+                    hop_calls.len() - CALLS_PER_HOP,
+                    crate_label(dependent)
+                );
+            }
+        }
+    }
+    if chains.len() > MAX_CHAINS {
+        let _ = writeln!(md, "\n{} more chains not shown.", chains.len() - MAX_CHAINS);
+    }
+    md
+}
+
+pub async fn export_to_markdown(
+    graph: &Graph,
+    path: &Path,
+    chain_filter: &ChainFilter,
+) -> Result<()> {
+    store::write_atomic(path, impact_markdown(graph, chain_filter).as_bytes()).await?;
+    info!("已导出Markdown报告到 {}", path.display());
+    Ok(())
+}
+
+fn crate_label(node: &CrateNode) -> String {
+    format!("{}@{}", node.name, node.version)
+}
+
+/// text for a table cell, a `|` would end the cell
+fn cell(value: &str) -> String {
+    value.replace('|', "\\|")
+}
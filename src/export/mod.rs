@@ -0,0 +1,163 @@
+//! exporters turning the impact graph into formats used outside this tool
+
+pub mod cypher;
+pub mod dot;
+pub mod gexf;
+pub mod graphml;
+pub mod html;
+pub mod markdown;
+pub mod osv;
+pub mod parquet;
+pub mod sarif;
+pub mod sql;
+pub mod vex;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::graph::{CallKind, Graph};
+use crate::model::normalize_crate_name;
+
+/// escape text for XML content and attribute values
+pub fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// one analyzed crate version against one parent
+pub struct ResultRow {
+    pub name: String,
+    pub version: String,
+    pub parent_name: Option<String>,
+    pub parent_version: Option<String>,
+    // affected, cleared or failed
+    pub verdict: &'static str,
+    // the clearance reason or the failure kind
+    pub reason: Option<String>,
+    pub depth: Option<u32>,
+    pub callers: u64,
+    pub invocations: u32,
+    // wall time of its cargo / call-cg4rs invocations
+    pub duration_secs: Option<f64>,
+}
+
+/// one row per analyzed dependent: the affected edges, the cleared dependents and the
+/// dependents whose invocations failed without a verdict
+pub fn result_rows(graph: &Graph) -> Vec<ResultRow> {
+    // (crate, version) -> (invocations, total seconds, last failure)
+    let mut runs: BTreeMap<(String, String), (u32, f64, Option<&'static str>)> = BTreeMap::new();
+    for invocation in graph.invocations() {
+        let entry = runs
+            .entry((
+                normalize_crate_name(&invocation.crate_name),
+                invocation.version.clone(),
+            ))
+            .or_default();
+        entry.0 += 1;
+        entry.1 += invocation.duration.as_secs_f64();
+        if let Some(failure) = invocation.failure {
+            entry.2 = Some(failure.as_str());
+        }
+    }
+    let run_of = |name: &str, version: &str| {
+        runs.get(&(normalize_crate_name(name), version.to_owned()))
+            .map_or((0, None), |(count, secs, _)| (*count, Some(*secs)))
+    };
+
+    let mut rows = Vec::new();
+    let mut analyzed = BTreeSet::new();
+    for (parent, dependent, edge) in graph.dependencies() {
+        let (invocations, duration_secs) = run_of(&dependent.name, &dependent.version);
+        analyzed.insert((
+            normalize_crate_name(&dependent.name),
+            dependent.version.clone(),
+        ));
+        rows.push(ResultRow {
+            name: dependent.name.clone(),
+            version: dependent.version.clone(),
+            parent_name: Some(parent.name.clone()),
+            parent_version: Some(parent.version.clone()),
+            verdict: "affected",
+            reason: None,
+            depth: Some(dependent.depth as u32),
+            callers: edge.functions.values().sum::<usize>() as u64,
+            invocations,
+            duration_secs,
+        });
+    }
+    for cleared in graph.cleared() {
+        let (invocations, duration_secs) = run_of(&cleared.name, &cleared.version);
+        analyzed.insert((normalize_crate_name(&cleared.name), cleared.version.clone()));
+        rows.push(ResultRow {
+            name: cleared.name.clone(),
+            version: cleared.version.clone(),
+            parent_name: Some(cleared.parent_name.clone()),
+            parent_version: Some(cleared.parent_version.clone()),
+            verdict: "cleared",
+            reason: Some(cleared.clearance.reason.as_str().to_string()),
+            depth: graph
+                .crate_node(&cleared.parent_name, &cleared.parent_version)
+                .map(|parent| parent.depth as u32 + 1),
+            callers: 0,
+            invocations,
+            duration_secs,
+        });
+    }
+    // 调用记录不含父节点，失败的依赖者只能按 crate 版本列出
+    for ((name, version), (invocations, secs, failure)) in &runs {
+        let Some(failure) = failure else {
+            continue;
+        };
+        if analyzed.contains(&(name.clone(), version.clone())) {
+            continue;
+        }
+        rows.push(ResultRow {
+            name: name.clone(),
+            version: version.clone(),
+            parent_name: None,
+            parent_version: None,
+            verdict: "failed",
+            reason: Some(failure.to_string()),
+            depth: None,
+            callers: 0,
+            invocations: *invocations,
+            duration_secs: Some(*secs),
+        });
+    }
+    rows
+}
+
+// (dependent, dependent version, parent, parent version) -> (caller, callee, kind)
+pub type CallsByCrate<'a> =
+    HashMap<(String, &'a str, String, &'a str), Vec<(&'a str, &'a str, CallKind)>>;
+
+/// the calls between each pair of crate versions
+pub fn calls_by_crate(graph: &Graph) -> CallsByCrate<'_> {
+    let functions = graph
+        .functions_with_id()
+        .map(|(_, function)| function)
+        .collect::<Vec<_>>();
+    let mut calls: CallsByCrate = HashMap::new();
+    for (caller, callee, edge) in graph.calls_with_id() {
+        let (caller, callee) = (functions[caller], functions[callee]);
+        calls
+            .entry((
+                normalize_crate_name(&caller.crate_name),
+                caller.version.as_str(),
+                normalize_crate_name(&callee.crate_name),
+                callee.version.as_str(),
+            ))
+            .or_default()
+            .push((caller.path.as_str(), callee.path.as_str(), edge.kind));
+    }
+    calls
+}
@@ -0,0 +1,182 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::export::calls_by_crate;
+use crate::graph::{CrateNode, DependencyEdge, Graph, Remedy};
+use crate::model::normalize_crate_name;
+use crate::store;
+
+const OSV_SCHEMA_VERSION: &str = "1.6.0";
+
+/// the affected versions of one dependent through one parent
+struct DraftGroup<'a> {
+    dependent: &'a str,
+    parent: &'a str,
+    // (dependent, parent, edge), one per affected version pair
+    edges: Vec<(&'a CrateNode, &'a CrateNode, &'a DependencyEdge)>,
+}
+
+/// draft OSV records for the transitively affected crates, one per dependent and parent
+/// it calls into
+///
+/// the versions are the analyzed ones found affected; a range is added from the
+/// bisected introduction and the fix advice when they are known. The calls found are
+/// kept in `ecosystem_specific` as evidence, `affects.functions` as in RustSec
+pub fn draft_records(graph: &Graph) -> Vec<(String, Value)> {
+    let modified = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_default();
+    let roots = graph
+        .crates()
+        .filter(|node| node.depth == 0)
+        .map(|node| match &node.range {
+            Some(range) => format!(
+                "{} {} (vulnerable range {})",
+                node.name, node.version, range
+            ),
+            None => format!("{} {}", node.name, node.version),
+        })
+        .collect::<Vec<_>>();
+    let calls = calls_by_crate(graph);
+
+    let mut groups: BTreeMap<(String, String), DraftGroup> = BTreeMap::new();
+    for (parent, dependent, edge) in graph.dependencies() {
+        groups
+            .entry((
+                normalize_crate_name(&dependent.name),
+                normalize_crate_name(&parent.name),
+            ))
+            .or_insert_with(|| DraftGroup {
+                dependent: &dependent.name,
+                parent: &parent.name,
+                edges: Vec::new(),
+            })
+            .edges
+            .push((dependent, parent, edge));
+    }
+
+    let mut records = Vec::new();
+    for ((dependent_key, parent_key), group) in groups {
+        let mut versions = group
+            .edges
+            .iter()
+            .map(|(dependent, _, _)| dependent.version.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        versions.sort_by_key(|version| semver::Version::parse(version).ok());
+
+        let mut functions = BTreeSet::new();
+        let mut evidence = Vec::new();
+        for (dependent, parent, edge) in &group.edges {
+            let key = (
+                dependent_key.clone(),
+                dependent.version.as_str(),
+                parent_key.clone(),
+                parent.version.as_str(),
+            );
+            let edge_calls = calls
+                .get(&key)
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .map(|(caller, callee, kind)| {
+                    functions.insert(caller.to_string());
+                    json!({ "caller": caller, "callee": callee, "kind": kind.as_str() })
+                })
+                .collect::<Vec<_>>();
+            evidence.push(json!({
+                "version": dependent.version,
+                "parent_version": parent.version,
+                "req": edge.req,
+                "depth": dependent.depth,
+                "callers": edge.functions,
+                "calls": edge_calls,
+            }));
+        }
+
+        let mut affected = json!({
+            "package": {
+                "ecosystem": "crates.io",
+                "name": group.dependent,
+                "purl": format!("pkg:cargo/{}", group.dependent),
+            },
+            "versions": versions,
+            "ecosystem_specific": {
+                "affects": { "functions": functions },
+                "evidence": evidence,
+            },
+        });
+        let introduced = graph
+            .introductions()
+            .iter()
+            .filter(|introduction| {
+                normalize_crate_name(&introduction.name) == dependent_key
+                    && normalize_crate_name(&introduction.parent_name) == parent_key
+            })
+            .map(|introduction| introduction.first_affected.clone())
+            .min_by_key(|version| semver::Version::parse(version).ok());
+        let fixed = graph
+            .fixes()
+            .iter()
+            .filter(|fix| {
+                normalize_crate_name(&fix.name) == dependent_key
+                    && normalize_crate_name(&fix.parent_name) == parent_key
+            })
+            .find_map(|fix| match &fix.remedy {
+                Remedy::AdmitsFixedParent { version, .. }
+                | Remedy::DropsDependency { version }
+                | Remedy::CallRemoved { version, .. } => Some(version.clone()),
+                _ => None,
+            });
+        if introduced.is_some() || fixed.is_some() {
+            let mut events = vec![json!({
+                "introduced": introduced.or_else(|| versions.first().cloned())
+            })];
+            if let Some(fixed) = fixed {
+                events.push(json!({ "fixed": fixed }));
+            }
+            affected["ranges"] = json!([{ "type": "SEMVER", "events": events }]);
+        }
+
+        let id = format!("DRAFT-{}-via-{}", dependent_key, parent_key);
+        let record = json!({
+            "schema_version": OSV_SCHEMA_VERSION,
+            "id": id,
+            "modified": modified,
+            "summary": format!(
+                "{} uses a vulnerable function of {}",
+                group.dependent, group.parent
+            ),
+            "details": format!(
+                "{} calls functions of {} that reach the vulnerable code of {}. \
+                 Affected versions found by call graph analysis: {}. \
+                 This is a draft, review the evidence before publishing.",
+                group.dependent,
+                group.parent,
+                roots.join(", "),
+                versions.join(", ")
+            ),
+            "affected": [affected],
+            "database_specific": { "source": env!("CARGO_PKG_NAME"), "draft": true },
+        });
+        records.push((id, record));
+    }
+    records
+}
+
+pub async fn export_to_osv(graph: &Graph, dir: &Path) -> Result<()> {
+    let records = draft_records(graph);
+    for (id, record) in &records {
+        let content = serde_json::to_vec_pretty(record).context("序列化OSV记录失败")?;
+        store::write_atomic(&dir.join(format!("{}.json", id)), &content).await?;
+    }
+    info!("已导出 {} 条OSV草稿到 {}", records.len(), dir.display());
+    Ok(())
+}
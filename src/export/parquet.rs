@@ -0,0 +1,80 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt32Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use tracing::info;
+
+use crate::export::{result_rows, ResultRow};
+use crate::graph::Graph;
+use crate::store;
+
+/// the per-dependent results as a Parquet file, for slicing large runs with pandas or
+/// duckdb
+fn results_parquet(rows: &[ResultRow]) -> Result<Vec<u8>> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("name", DataType::Utf8, false),
+        Field::new("version", DataType::Utf8, false),
+        Field::new("parent_name", DataType::Utf8, true),
+        Field::new("parent_version", DataType::Utf8, true),
+        Field::new("verdict", DataType::Utf8, false),
+        Field::new("reason", DataType::Utf8, true),
+        Field::new("depth", DataType::UInt32, true),
+        Field::new("callers", DataType::UInt64, false),
+        Field::new("invocations", DataType::UInt32, false),
+        Field::new("duration_secs", DataType::Float64, true),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.name.as_str()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.version.as_str()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|row| row.parent_name.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|row| row.parent_version.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.verdict),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|row| row.reason.as_deref()),
+        )),
+        Arc::new(UInt32Array::from_iter(rows.iter().map(|row| row.depth))),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|row| row.callers),
+        )),
+        Arc::new(UInt32Array::from_iter_values(
+            rows.iter().map(|row| row.invocations),
+        )),
+        Arc::new(Float64Array::from_iter(
+            rows.iter().map(|row| row.duration_secs),
+        )),
+    ];
+    let batch =
+        RecordBatch::try_new(Arc::clone(&schema), columns).context("构建 Arrow 记录批失败")?;
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut content = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut content, schema, Some(properties))
+        .context("创建 Parquet 写入器失败")?;
+    writer.write(&batch).context("写入 Parquet 失败")?;
+    writer.close().context("写入 Parquet 失败")?;
+    Ok(content)
+}
+
+pub async fn export_to_parquet(graph: &Graph, path: &Path) -> Result<()> {
+    let rows = result_rows(graph);
+    store::write_atomic(path, &results_parquet(&rows)?).await?;
+    info!("已导出Parquet到 {}: {} 行", path.display(), rows.len());
+    Ok(())
+}
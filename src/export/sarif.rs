@@ -0,0 +1,200 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use tracing::info;
+
+use crate::export::{calls_by_crate, CallsByCrate};
+use crate::graph::{CrateNode, FunctionNode, Graph};
+use crate::model::normalize_crate_name;
+use crate::store;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// the affected dependents as a SARIF 2.1.0 log for code scanning platforms
+///
+/// every affected crate version is one result of the rule of the root it reaches; the
+/// location is its call into the parent and the code flow follows the calls from it
+/// down to the vulnerable function, one hop per crate
+pub fn impact_sarif(graph: &Graph) -> Value {
+    let crates = graph.crates().collect::<Vec<_>>();
+    let functions = graph
+        .functions_with_id()
+        .map(|(_, function)| {
+            (
+                (
+                    normalize_crate_name(&function.crate_name),
+                    function.version.as_str(),
+                    function.path.as_str(),
+                ),
+                function,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let calls = calls_by_crate(graph);
+
+    let mut rules = BTreeMap::new();
+    for root in crates.iter().filter(|node| node.depth == 0) {
+        let id = rule_id(root);
+        let description = match &root.range {
+            Some(range) => format!(
+                "{} {} is in the vulnerable range {}",
+                root.name, root.version, range
+            ),
+            None => format!("{} {} is vulnerable", root.name, root.version),
+        };
+        rules.entry(id.clone()).or_insert_with(|| {
+            json!({
+                "id": id,
+                "name": "ReachesVulnerableFunction",
+                "shortDescription": {
+                    "text": format!("reaches a vulnerable function of {}", root.name)
+                },
+                "fullDescription": { "text": description },
+                "defaultConfiguration": { "level": "warning" },
+            })
+        });
+    }
+
+    let mut results = Vec::new();
+    for (id, node) in crates.iter().enumerate().filter(|(_, node)| node.depth > 0) {
+        let chain = graph.shortest_chain(id);
+        let root = crates[chain[0]];
+        let flow = call_flow(&chain, &crates, &calls);
+        let via = chain
+            .get(1..chain.len().saturating_sub(1))
+            .unwrap_or_default()
+            .iter()
+            .map(|id| crate_label(crates[*id]))
+            .collect::<Vec<_>>();
+        let mut message = format!(
+            "{} reaches a vulnerable function of {}",
+            crate_label(node),
+            crate_label(root)
+        );
+        if !via.is_empty() {
+            message.push_str(&format!(" through {}", via.join(" → ")));
+        }
+
+        let mut result = json!({
+            "ruleId": rule_id(root),
+            "level": "warning",
+            "message": { "text": message },
+            "partialFingerprints": {
+                "crateVersion/v1": format!("{}@{}", normalize_crate_name(&node.name), node.version)
+            },
+            "properties": {
+                "crate": node.name,
+                "version": node.version,
+                "depth": node.depth,
+                "downloads": node.downloads,
+                "yanked": node.yanked,
+            },
+        });
+        if let Some((crate_name, version, path)) = flow.first() {
+            let function = functions.get(&(normalize_crate_name(crate_name), *version, *path));
+            result["locations"] = json!([location(crate_name, version, path, function.copied())]);
+            let steps = flow
+                .iter()
+                .map(|(crate_name, version, path)| {
+                    let function =
+                        functions.get(&(normalize_crate_name(crate_name), *version, *path));
+                    json!({
+                        "location": location(crate_name, version, path, function.copied())
+                    })
+                })
+                .collect::<Vec<_>>();
+            result["codeFlows"] = json!([{ "threadFlows": [{ "locations": steps }] }]);
+        }
+        results.push(result);
+    }
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.into_values().collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+pub async fn export_to_sarif(graph: &Graph, path: &Path) -> Result<()> {
+    let content = serde_json::to_vec_pretty(&impact_sarif(graph)).context("序列化SARIF失败")?;
+    store::write_atomic(path, &content).await?;
+    info!("已导出SARIF到 {}", path.display());
+    Ok(())
+}
+
+/// the functions from the dependent at the end of `chain` down to the vulnerable
+/// function as (crate, version, path), each hop continuing from the function the
+/// previous one called when possible
+fn call_flow<'a>(
+    chain: &[usize],
+    crates: &[&'a CrateNode],
+    calls: &CallsByCrate<'a>,
+) -> Vec<(&'a str, &'a str, &'a str)> {
+    let mut flow = Vec::new();
+    let mut previous: Option<&str> = None;
+    for hop in chain.windows(2).rev() {
+        let (parent, dependent) = (crates[hop[0]], crates[hop[1]]);
+        let key = (
+            normalize_crate_name(&dependent.name),
+            dependent.version.as_str(),
+            normalize_crate_name(&parent.name),
+            parent.version.as_str(),
+        );
+        let Some(hop_calls) = calls.get(&key) else {
+            break;
+        };
+        let Some((caller, callee, _)) = hop_calls
+            .iter()
+            .find(|(caller, _, _)| Some(*caller) == previous)
+            .or_else(|| hop_calls.first())
+        else {
+            break;
+        };
+        if previous != Some(*caller) {
+            flow.push((dependent.name.as_str(), dependent.version.as_str(), *caller));
+        }
+        flow.push((parent.name.as_str(), parent.version.as_str(), *callee));
+        previous = Some(*callee);
+    }
+    flow
+}
+
+fn location(crate_name: &str, version: &str, path: &str, function: Option<&FunctionNode>) -> Value {
+    let mut location = json!({
+        "message": { "text": format!("{}@{}: {}", crate_name, version, path) },
+        "logicalLocations": [{ "fullyQualifiedName": path, "kind": "function" }],
+    });
+    if let Some((file, line)) =
+        function.and_then(|function| Some((function.file.as_ref()?, function.line?)))
+    {
+        let artifact = if Path::new(file).is_absolute() {
+            json!({ "uri": format!("file://{}", file) })
+        } else {
+            json!({ "uri": file, "uriBaseId": "%SRCROOT%" })
+        };
+        location["physicalLocation"] = json!({
+            "artifactLocation": artifact,
+            "region": { "startLine": line.max(1) },
+        });
+    }
+    location
+}
+
+fn rule_id(root: &CrateNode) -> String {
+    format!("vulnerable-call/{}", normalize_crate_name(&root.name))
+}
+
+fn crate_label(node: &CrateNode) -> String {
+    format!("{}@{}", node.name, node.version)
+}
@@ -0,0 +1,569 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use sqlx::any::AnyPoolOptions;
+use sqlx::{Any, AnyPool, Transaction};
+use tracing::info;
+
+use crate::database::{byte_chunks, placeholders, INSERT_CHUNK_BYTES};
+use crate::graph::{ChainFilter, Graph};
+
+// rows per multi-row INSERT statement
+const INSERT_CHUNK_SIZE: usize = 500;
+
+// bind parameters per statement, below SQLite's limit of 32766
+const MAX_PARAMETERS: usize = 32000;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS cg_nodes (
+        run_id TEXT NOT NULL,
+        node_id INTEGER NOT NULL,
+        crate_name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        depth INTEGER NOT NULL,
+        repository TEXT
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_edges (
+        run_id TEXT NOT NULL,
+        edge_id INTEGER NOT NULL,
+        parent_id INTEGER NOT NULL,
+        dependent_id INTEGER NOT NULL,
+        req TEXT NOT NULL,
+        source TEXT NOT NULL,
+        resolved_version TEXT,
+        kinds TEXT NOT NULL,
+        required_features TEXT NOT NULL,
+        platforms TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_edge_functions (
+        run_id TEXT NOT NULL,
+        edge_id INTEGER NOT NULL,
+        function_path TEXT NOT NULL,
+        callers INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_edge_features (
+        run_id TEXT NOT NULL,
+        edge_id INTEGER NOT NULL,
+        feature_set TEXT NOT NULL,
+        affected BOOLEAN NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_functions (
+        run_id TEXT NOT NULL,
+        function_id INTEGER NOT NULL,
+        crate_name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        path TEXT NOT NULL,
+        exposure TEXT NOT NULL,
+        call_site TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_calls (
+        run_id TEXT NOT NULL,
+        caller_id INTEGER NOT NULL,
+        callee_id INTEGER NOT NULL,
+        constraint_depth INTEGER NOT NULL,
+        kind TEXT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_chains (
+        run_id TEXT NOT NULL,
+        chain_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        node_id INTEGER NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_cleared (
+        run_id TEXT NOT NULL,
+        crate_name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        parent_name TEXT NOT NULL,
+        parent_version TEXT NOT NULL,
+        req TEXT NOT NULL,
+        source TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        precheck TEXT NOT NULL,
+        confidence TEXT NOT NULL,
+        resolved_version TEXT,
+        evidence_path TEXT
+    )",
+    "CREATE TABLE IF NOT EXISTS cg_invocations (
+        run_id TEXT NOT NULL,
+        crate_name TEXT NOT NULL,
+        version TEXT NOT NULL,
+        command TEXT NOT NULL,
+        exit_code INTEGER,
+        duration_ms INTEGER NOT NULL,
+        failure TEXT,
+        stdout TEXT NOT NULL,
+        stderr TEXT NOT NULL
+    )",
+];
+
+/// export the graph into the relational tables `cg_nodes`, `cg_edges`,
+/// `cg_edge_functions`, `cg_edge_features`, `cg_functions`, `cg_calls`, `cg_chains`,
+/// `cg_cleared` and `cg_invocations`
+///
+/// `url` is either a database url (`postgres://...`, `sqlite://...`) or a plain
+/// file path, which is created as a standalone SQLite database.
+/// Every export gets its own `run_id`, so several runs can share the tables, and is
+/// written in one transaction, so a failed export leaves no partial run behind.
+/// `cg_edge_features` holds whether the dependent calls a target under each analyzed
+/// feature configuration.
+/// `cg_chains` only holds the chains left after `chain_filter`.
+/// `cg_cleared` lists the dependents analyzed and confirmed not affected, `precheck`
+/// names the precheck strategy and `confidence` holds its confidence per target
+/// function as JSON.
+/// `cg_invocations` holds every cargo / call-cg4rs run on a dependent with its exit
+/// code, duration, failure kind and (truncated) output.
+pub async fn export_to_sql(graph: &Graph, url: &str, chain_filter: &ChainFilter) -> Result<String> {
+    sqlx::any::install_default_drivers();
+    let url = if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("sqlite://{}?mode=rwc", url)
+    };
+
+    let pool = AnyPoolOptions::new()
+        .max_connections(1)
+        .connect(&url)
+        .await
+        .context("无法连接到导出数据库")?;
+
+    for statement in SCHEMA {
+        execute(&pool, statement).await?;
+    }
+
+    let run_id = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis().to_string())
+        .unwrap_or_default();
+    let run = || text(&run_id);
+    let mut tx = pool.begin().await.context("开始导出事务失败")?;
+
+    let nodes = graph
+        .crates_with_id()
+        .map(|(id, node)| {
+            vec![
+                run(),
+                int(id),
+                text(&node.name),
+                text(&node.version),
+                int(node.depth),
+                Value::Text(node.repository.clone()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    insert_rows(
+        &mut tx,
+        "cg_nodes",
+        &[
+            "run_id",
+            "node_id",
+            "crate_name",
+            "version",
+            "depth",
+            "repository",
+        ],
+        &nodes,
+    )
+    .await?;
+
+    let mut edges = Vec::new();
+    let mut edge_functions = Vec::new();
+    let mut edge_features = Vec::new();
+    for (edge_id, (parent_id, dependent_id, edge)) in graph.dependencies_with_id().enumerate() {
+        edges.push(vec![
+            run(),
+            int(edge_id),
+            int(parent_id),
+            int(dependent_id),
+            text(&edge.req),
+            text(edge.source.as_str()),
+            Value::Text(edge.resolved_version.clone()),
+            text(
+                &edge
+                    .kinds
+                    .iter()
+                    .map(|kind| kind.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            text(&edge.required_features.join(",")),
+            text(&edge.platforms.join(",")),
+        ]);
+        for (function_path, callers) in &edge.functions {
+            edge_functions.push(vec![
+                run(),
+                int(edge_id),
+                text(function_path),
+                int(*callers),
+            ]);
+        }
+        for (feature_set, affected) in &edge.feature_verdicts {
+            edge_features.push(vec![
+                run(),
+                int(edge_id),
+                text(feature_set),
+                Value::Bool(*affected),
+            ]);
+        }
+    }
+    insert_rows(
+        &mut tx,
+        "cg_edges",
+        &[
+            "run_id",
+            "edge_id",
+            "parent_id",
+            "dependent_id",
+            "req",
+            "source",
+            "resolved_version",
+            "kinds",
+            "required_features",
+            "platforms",
+        ],
+        &edges,
+    )
+    .await?;
+    insert_rows(
+        &mut tx,
+        "cg_edge_functions",
+        &["run_id", "edge_id", "function_path", "callers"],
+        &edge_functions,
+    )
+    .await?;
+    insert_rows(
+        &mut tx,
+        "cg_edge_features",
+        &["run_id", "edge_id", "feature_set", "affected"],
+        &edge_features,
+    )
+    .await?;
+
+    let functions = graph
+        .functions_with_id()
+        .map(|(id, function)| {
+            vec![
+                run(),
+                int(id),
+                text(&function.crate_name),
+                text(&function.version),
+                text(&function.path),
+                text(function.exposure.as_str()),
+                text(function.call_site.as_str()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    insert_rows(
+        &mut tx,
+        "cg_functions",
+        &[
+            "run_id",
+            "function_id",
+            "crate_name",
+            "version",
+            "path",
+            "exposure",
+            "call_site",
+        ],
+        &functions,
+    )
+    .await?;
+
+    let calls = graph
+        .calls_with_id()
+        .map(|(caller_id, callee_id, edge)| {
+            vec![
+                run(),
+                int(caller_id),
+                int(callee_id),
+                int(edge.constraint_depth),
+                text(edge.kind.as_str()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    insert_rows(
+        &mut tx,
+        "cg_calls",
+        &[
+            "run_id",
+            "caller_id",
+            "callee_id",
+            "constraint_depth",
+            "kind",
+        ],
+        &calls,
+    )
+    .await?;
+
+    let chains = graph
+        .chains(chain_filter)
+        .into_iter()
+        .enumerate()
+        .flat_map(|(chain_id, chain)| {
+            chain
+                .into_iter()
+                .enumerate()
+                .map(move |(position, node_id)| {
+                    vec![run(), int(chain_id), int(position), int(node_id)]
+                })
+        })
+        .collect::<Vec<_>>();
+    insert_rows(
+        &mut tx,
+        "cg_chains",
+        &["run_id", "chain_id", "position", "node_id"],
+        &chains,
+    )
+    .await?;
+
+    let cleared = graph
+        .cleared()
+        .map(|cleared| {
+            let clearance = &cleared.clearance;
+            vec![
+                run(),
+                text(&cleared.name),
+                text(&cleared.version),
+                text(&cleared.parent_name),
+                text(&cleared.parent_version),
+                text(&cleared.req),
+                text(cleared.source.as_str()),
+                text(clearance.reason.as_str()),
+                text(clearance.precheck.as_str()),
+                text(&serde_json::json!(clearance.confidence).to_string()),
+                Value::Text(clearance.resolved_version.clone()),
+                Value::Text(clearance.evidence_path.clone()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    insert_rows(
+        &mut tx,
+        "cg_cleared",
+        &[
+            "run_id",
+            "crate_name",
+            "version",
+            "parent_name",
+            "parent_version",
+            "req",
+            "source",
+            "reason",
+            "precheck",
+            "confidence",
+            "resolved_version",
+            "evidence_path",
+        ],
+        &cleared,
+    )
+    .await?;
+
+    let invocations = graph
+        .invocations()
+        .iter()
+        .map(|invocation| {
+            vec![
+                run(),
+                text(&invocation.crate_name),
+                text(&invocation.version),
+                text(&invocation.command),
+                Value::Int(invocation.exit_code.map(i64::from)),
+                int(invocation.duration.as_millis()),
+                Value::Text(invocation.failure.map(|kind| kind.as_str().to_owned())),
+                text(&invocation.stdout),
+                text(&invocation.stderr),
+            ]
+        })
+        .collect::<Vec<_>>();
+    insert_rows(
+        &mut tx,
+        "cg_invocations",
+        &[
+            "run_id",
+            "crate_name",
+            "version",
+            "command",
+            "exit_code",
+            "duration_ms",
+            "failure",
+            "stdout",
+            "stderr",
+        ],
+        &invocations,
+    )
+    .await?;
+
+    tx.commit().await.context("提交导出事务失败")?;
+    info!(
+        "已导出图到SQL数据库 run_id={}: {} 个节点, {} 条边, {} 个函数, {} 条调用, {} 行链路, {} 个未受影响的依赖者",
+        run_id,
+        nodes.len(),
+        edges.len(),
+        functions.len(),
+        calls.len(),
+        chains.len(),
+        cleared.len()
+    );
+    Ok(run_id)
+}
+
+/// a bound value of an exported row, None is NULL
+#[derive(Debug, Clone)]
+enum Value {
+    Text(Option<String>),
+    Int(Option<i64>),
+    Bool(bool),
+}
+
+impl Value {
+    /// bytes sent for the value, to keep statements with large output rows small
+    fn size(&self) -> usize {
+        match self {
+            Value::Text(value) => value.as_ref().map_or(0, String::len),
+            Value::Int(_) | Value::Bool(_) => 8,
+        }
+    }
+}
+
+fn text(value: &str) -> Value {
+    Value::Text(Some(value.to_owned()))
+}
+
+fn int(value: impl TryInto<i64>) -> Value {
+    Value::Int(value.try_into().ok())
+}
+
+/// insert `rows` in multi-row statements with bound values, limited by row count,
+/// parameter count and bytes
+async fn insert_rows(
+    tx: &mut Transaction<'static, Any>,
+    table: &str,
+    columns: &[&str],
+    rows: &[Vec<Value>],
+) -> Result<()> {
+    let max_rows = INSERT_CHUNK_SIZE.min(MAX_PARAMETERS / columns.len());
+    let chunks = byte_chunks(rows, max_rows, INSERT_CHUNK_BYTES, |row| {
+        row.iter().map(Value::size).sum()
+    });
+    for chunk in chunks {
+        let values = (0..chunk.len())
+            .map(|i| format!("({})", placeholders(i * columns.len() + 1, columns.len())))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let statement = format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            table,
+            columns.join(", "),
+            values
+        );
+        let mut query = sqlx::query(&statement);
+        for value in chunk.iter().flatten() {
+            query = match value {
+                Value::Text(value) => query.bind(value.as_deref()),
+                Value::Int(value) => query.bind(*value),
+                Value::Bool(value) => query.bind(*value),
+            };
+        }
+        query.execute(&mut **tx).await.context(format!(
+            "写入 {} 失败（{} 行）",
+            table,
+            chunk.len()
+        ))?;
+    }
+    Ok(())
+}
+
+async fn execute(pool: &AnyPool, statement: &str) -> Result<()> {
+    sqlx::query(statement)
+        .execute(pool)
+        .await
+        .context(format!("执行SQL失败: {}", truncate(statement)))?;
+    Ok(())
+}
+
+fn truncate(statement: &str) -> &str {
+    match statement.char_indices().nth(200) {
+        Some((idx, _)) => &statement[..idx],
+        None => statement,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use sqlx::Row;
+
+    use super::*;
+    use crate::diagnostics::Invocation;
+    use crate::model::{FunctionCalls, ReverseDependency};
+
+    #[tokio::test]
+    async fn rows_are_exported_with_bound_values() {
+        let mut graph = Graph::new();
+        graph.add_crate("vuln", "1.0.0", 0);
+        let mut calls = FunctionCalls::default();
+        calls.insert("vuln::decode", r#"[{"path": "app::run"}]"#);
+        calls.set_feature_verdict("default", true);
+        graph.add_dependency(
+            ("vuln", "1.0.0"),
+            ("app", "0.1.0"),
+            1,
+            &ReverseDependency::new("app".into(), "0.1.0".into(), "^1".into()),
+            &calls,
+        );
+        let stderr = format!("error: can't find `it's`\n{}", "x".repeat(64 * 1024));
+        graph.set_invocations(vec![Invocation {
+            crate_name: "app".into(),
+            version: "0.1.0".into(),
+            parent_name: "vuln".into(),
+            parent_version: "1.0.0".into(),
+            command: "cargo build".into(),
+            exit_code: None,
+            duration: Duration::from_millis(1500),
+            stdout: String::new(),
+            stderr: stderr.clone(),
+            failure: None,
+            sensitive: false,
+        }]);
+
+        let path = std::env::temp_dir()
+            .join(format!("cross_pro_cg-sql-{}", std::process::id()))
+            .join("export.db");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let filter = ChainFilter {
+            top_k: 3,
+            max_length: None,
+        };
+        let run_id = export_to_sql(&graph, path.to_str().unwrap(), &filter)
+            .await
+            .unwrap();
+
+        let pool = AnyPoolOptions::new()
+            .max_connections(1)
+            .connect(&format!("sqlite://{}", path.display()))
+            .await
+            .unwrap();
+        let row = sqlx::query(
+            "SELECT stderr, duration_ms FROM cg_invocations \
+             WHERE run_id = $1 AND exit_code IS NULL",
+        )
+        .bind(run_id.as_str())
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(row.get::<String, _>("stderr"), stderr);
+        assert_eq!(row.get::<i64, _>("duration_ms"), 1500);
+        let features = sqlx::query("SELECT feature_set FROM cg_edge_features")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(features.len(), 1);
+        let nodes = sqlx::query("SELECT crate_name FROM cg_nodes")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(nodes.len(), 2);
+        pool.close().await;
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::info;
+
+use crate::export::result_rows;
+use crate::graph::Graph;
+use crate::model::{normalize_crate_name, ClearanceReason};
+use crate::store;
+
+/// CycloneDX impact analysis state of one analyzed dependent, ordered so that the
+/// strongest evidence wins when a crate version has several parents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum VexState {
+    NotAffected,
+    InTriage,
+    Exploitable,
+}
+
+impl VexState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VexState::NotAffected => "not_affected",
+            VexState::InTriage => "in_triage",
+            VexState::Exploitable => "exploitable",
+        }
+    }
+}
+
+struct Statement {
+    name: String,
+    version: String,
+    state: VexState,
+    justification: Option<&'static str>,
+    details: Vec<String>,
+}
+
+/// the analyzed dependents as a CycloneDX 1.5 VEX document
+///
+/// affected dependents are `exploitable`, dependents shown not to call a target are
+/// `not_affected` with `code_not_reachable` (OpenVEX's
+/// `vulnerable_code_not_in_execute_path`), those whose parent is not shipped or not
+/// resolved `code_not_present`; unverified and failed ones are `in_triage`
+pub fn vex_document(graph: &Graph, vulnerability_id: Option<&str>) -> Value {
+    let mut statements: BTreeMap<(String, String), Statement> = BTreeMap::new();
+    let mut add = |name: &str,
+                   version: &str,
+                   state: VexState,
+                   justification: Option<&'static str>,
+                   detail: String| {
+        let statement = statements
+            .entry((normalize_crate_name(name), version.to_owned()))
+            .or_insert_with(|| Statement {
+                name: name.to_owned(),
+                version: version.to_owned(),
+                state,
+                justification,
+                details: Vec::new(),
+            });
+        if state > statement.state {
+            statement.state = state;
+            statement.justification = justification;
+            statement.details.clear();
+        }
+        if state == statement.state {
+            statement.details.push(detail);
+        }
+    };
+
+    for (parent, dependent, edge) in graph.dependencies() {
+        let functions = edge
+            .functions
+            .iter()
+            .map(|(function, callers)| format!("{} ({} callers)", function, callers))
+            .collect::<Vec<_>>();
+        add(
+            &dependent.name,
+            &dependent.version,
+            VexState::Exploitable,
+            None,
+            format!(
+                "calls {} of {}@{}",
+                functions.join(", "),
+                parent.name,
+                parent.version
+            ),
+        );
+    }
+    for cleared in graph.cleared() {
+        let reason = cleared.clearance.reason;
+        let (state, justification, evidence) = match reason {
+            ClearanceReason::NotReferenced
+            | ClearanceReason::NoCallers
+            | ClearanceReason::ExcludedCallSites => (
+                VexState::NotAffected,
+                Some("code_not_reachable"),
+                "vulnerable_code_not_in_execute_path",
+            ),
+            ClearanceReason::NotResolved | ClearanceReason::ExcludedDependencyKind => (
+                VexState::NotAffected,
+                Some("code_not_present"),
+                "vulnerable_code_not_present",
+            ),
+            ClearanceReason::UntrustedBuild => (VexState::InTriage, None, "not verified"),
+        };
+        add(
+            &cleared.name,
+            &cleared.version,
+            state,
+            justification,
+            format!(
+                "{}: {} against {}@{}",
+                evidence,
+                reason.as_str(),
+                cleared.parent_name,
+                cleared.parent_version
+            ),
+        );
+    }
+    for row in result_rows(graph)
+        .into_iter()
+        .filter(|row| row.verdict == "failed")
+    {
+        add(
+            &row.name,
+            &row.version,
+            VexState::InTriage,
+            None,
+            format!(
+                "analysis failed: {}",
+                row.reason.unwrap_or_else(|| "unknown".to_string())
+            ),
+        );
+    }
+
+    let roots = graph
+        .crates()
+        .filter(|node| node.depth == 0)
+        .map(|node| format!("{}@{}", node.name, node.version))
+        .collect::<Vec<_>>();
+    let id = vulnerability_id.map_or_else(
+        || format!("vulnerable-call/{}", roots.join(",")),
+        str::to_owned,
+    );
+    let source = id.starts_with("RUSTSEC-").then(
+        || json!({ "name": "RustSec", "url": format!("https://rustsec.org/advisories/{}", id) }),
+    );
+
+    let mut components = Vec::new();
+    let mut vulnerabilities = Vec::new();
+    for statement in statements.into_values() {
+        let purl = format!("pkg:cargo/{}@{}", statement.name, statement.version);
+        components.push(json!({
+            "type": "library",
+            "bom-ref": purl,
+            "name": statement.name,
+            "version": statement.version,
+            "purl": purl,
+        }));
+        let mut analysis = json!({
+            "state": statement.state.as_str(),
+            "detail": statement.details.join("; "),
+        });
+        if let Some(justification) = statement.justification {
+            analysis["justification"] = json!(justification);
+        }
+        let mut vulnerability = json!({
+            "id": id,
+            "description": format!("reaches a vulnerable function of {}", roots.join(", ")),
+            "affects": [{ "ref": purl }],
+            "analysis": analysis,
+        });
+        if let Some(source) = &source {
+            vulnerability["source"] = source.clone();
+        }
+        vulnerabilities.push(vulnerability);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }]
+            },
+        },
+        "components": components,
+        "vulnerabilities": vulnerabilities,
+    })
+}
+
+pub async fn export_to_vex(
+    graph: &Graph,
+    path: &Path,
+    vulnerability_id: Option<&str>,
+) -> Result<()> {
+    let document = vex_document(graph, vulnerability_id);
+    let content = serde_json::to_vec_pretty(&document).context("序列化VEX失败")?;
+    store::write_atomic(path, &content).await?;
+    info!(
+        "已导出VEX到 {}: {} 条声明",
+        path.display(),
+        document["vulnerabilities"].as_array().map_or(0, Vec::len)
+    );
+    Ok(())
+}
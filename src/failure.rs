@@ -0,0 +1,220 @@
+use std::fmt;
+use std::future::Future;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// why a cargo / call-cg4rs invocation failed, read from its stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    // a `-sys` crate didn't find a system library or header
+    MissingSystemLibrary,
+    // the toolchain is too old or too new for the dependent (MSRV, edition, nightly features)
+    Toolchain,
+    // killed by the OOM killer or a failed allocation
+    OutOfMemory,
+    // downloading dependencies failed
+    Network,
+    // no output and no cpu use for too long, killed by the watchdog
+    Stalled,
+    // a phase ran past its configured timeout
+    TimedOut,
+    // the dependent (or one of its dependencies) doesn't compile
+    CompileError,
+    Unknown,
+}
+
+/// what to change before running a failed invocation again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    // retrying can't help
+    Never,
+    // the same command after a pause, up to the given number of times
+    After(Duration, usize),
+    // a single job at a time, a build with all cpus may use several times the memory
+    SingleJob,
+    // another toolchain
+    OtherToolchain,
+}
+
+impl FailureKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::MissingSystemLibrary => "missing_system_library",
+            FailureKind::Toolchain => "toolchain",
+            FailureKind::OutOfMemory => "out_of_memory",
+            FailureKind::Network => "network",
+            FailureKind::Stalled => "stalled",
+            FailureKind::TimedOut => "timed_out",
+            FailureKind::CompileError => "compile_error",
+            FailureKind::Unknown => "unknown",
+        }
+    }
+
+    pub fn retry(&self) -> Retry {
+        match self {
+            FailureKind::Network => Retry::After(Duration::from_secs(10), 2),
+            FailureKind::OutOfMemory => Retry::SingleJob,
+            FailureKind::Toolchain => Retry::OtherToolchain,
+            FailureKind::MissingSystemLibrary
+            | FailureKind::CompileError
+            | FailureKind::Stalled
+            | FailureKind::TimedOut
+            | FailureKind::Unknown => Retry::Never,
+        }
+    }
+
+    /// whether the dependent can't be built at all, as opposed to a failure of the tool
+    /// for one target path
+    pub fn is_build_failure(&self) -> bool {
+        *self != FailureKind::Unknown
+    }
+
+    /// classify a failed invocation, the more specific causes first since a missing
+    /// library or an old compiler also end in "could not compile"
+    pub fn classify(stderr: &str, status: &ExitStatus) -> Self {
+        let stderr_lower = stderr.to_lowercase();
+        let contains = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .any(|pattern| stderr_lower.contains(pattern))
+        };
+        if killed_by_sigkill(status)
+            || contains(&[
+                "memory allocation of",
+                "out of memory",
+                "cannot allocate memory",
+                "signal: 9, sigkill",
+            ])
+        {
+            return FailureKind::OutOfMemory;
+        }
+        if contains(&[
+            "failed to download",
+            "could not resolve host",
+            "couldn't resolve host",
+            "spurious network error",
+            "failed to query replaced source registry",
+            "failed to fetch",
+            "network failure",
+            "ssl connect error",
+        ]) {
+            return FailureKind::Network;
+        }
+        if contains(&[
+            "could not find system library",
+            "was not found in the pkg-config search path",
+            "unable to find library -l",
+            "cannot find -l",
+            "pkg-config exited with status",
+            "could not find directory of openssl installation",
+        ]) || (contains(&["fatal error:"]) && contains(&["no such file or directory"]))
+        {
+            return FailureKind::MissingSystemLibrary;
+        }
+        if contains(&[
+            "is not supported by the following package",
+            "requires rustc",
+            "feature `edition20",
+            "may not be used on the stable release channel",
+            "error[e0554]",
+            "this version of cargo is older than",
+            "lock file version",
+            "unknown feature",
+            "is incompatible with the version of",
+        ]) {
+            return FailureKind::Toolchain;
+        }
+        if contains(&[
+            "error[e",
+            "error: could not compile",
+            "failed to run custom build command",
+        ]) {
+            return FailureKind::CompileError;
+        }
+        FailureKind::Unknown
+    }
+}
+
+#[cfg(unix)]
+fn killed_by_sigkill(status: &ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal() == Some(9)
+}
+
+#[cfg(not(unix))]
+fn killed_by_sigkill(_status: &ExitStatus) -> bool {
+    false
+}
+
+/// a dependent that couldn't be built, after the retries its failure kind allows
+#[derive(Debug, Clone)]
+pub struct BuildFailure {
+    pub kind: FailureKind,
+    pub tool: String,
+    pub attempts: usize,
+    // last lines of stderr, enough to see the error
+    pub stderr_tail: String,
+}
+
+impl BuildFailure {
+    pub fn new(kind: FailureKind, tool: &str, attempts: usize, stderr: &str) -> Self {
+        let lines = stderr.lines().collect::<Vec<_>>();
+        let tail = lines[lines.len().saturating_sub(20)..].join("\n");
+        Self {
+            kind,
+            tool: tool.to_owned(),
+            attempts,
+            stderr_tail: tail,
+        }
+    }
+}
+
+impl fmt::Display for BuildFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} 构建失败 ({}，共尝试 {} 次): {}",
+            self.tool,
+            self.kind.as_str(),
+            self.attempts,
+            self.stderr_tail
+        )
+    }
+}
+
+impl std::error::Error for BuildFailure {}
+
+/// a phase of analyzing a crate that didn't finish within its timeout
+#[derive(Debug, Clone)]
+pub struct PhaseTimeout {
+    pub phase: &'static str,
+    pub limit: Duration,
+}
+
+impl fmt::Display for PhaseTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 阶段超时（{} 秒）", self.phase, self.limit.as_secs())
+    }
+}
+
+impl std::error::Error for PhaseTimeout {}
+
+/// run a phase with an optional timeout, on expiry the phase future is dropped, which
+/// kills the process groups of the commands it was running
+pub async fn with_timeout<T>(
+    phase: &'static str,
+    limit: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(limit) = limit else {
+        return future.await;
+    };
+    match tokio::time::timeout(limit, future).await {
+        Ok(result) => result,
+        Err(_) => Err(PhaseTimeout { phase, limit }.into()),
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+
+use crate::checkpoint::NodeCheckpoint;
+use crate::store::{self, STORE};
+
+/// options of the `gc` subcommand
+#[derive(Debug, Args)]
+pub struct GcArgs {
+    /// delete extracted crates (with their archive and build artifacts) not used for
+    /// this many days
+    #[arg(long, value_name = "DAYS")]
+    pub older_than: Option<u64>,
+    /// delete every extracted crate not found affected in a checkpoint, the roots and
+    /// affected dependents are kept
+    #[arg(long)]
+    pub keep_positives: bool,
+    /// delete the whole download directory
+    #[arg(long, conflicts_with_all = ["older_than", "keep_positives"])]
+    pub all: bool,
+    /// only report what would be deleted
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// something that can be deleted on its own: a crate of the store with its archive, a
+/// git checkout or the copy of a local project
+#[derive(Debug, Default)]
+struct CacheUnit {
+    // `name version` of every crate version using it
+    labels: BTreeSet<String>,
+    paths: Vec<PathBuf>,
+    archive_size: u64,
+    source_size: u64,
+    target_size: u64,
+    last_used: Option<SystemTime>,
+}
+
+impl CacheUnit {
+    fn label(&self) -> String {
+        match self.labels.iter().next() {
+            Some(label) if self.labels.len() > 1 => {
+                format!("{} (+{})", label, self.labels.len() - 1)
+            }
+            Some(label) => label.clone(),
+            None => self.paths[0].display().to_string(),
+        }
+    }
+
+    /// whether an analysis holds the source lock of one of its directories
+    fn in_use(&self) -> bool {
+        self.paths
+            .iter()
+            .any(|path| path.is_dir() && STORE.source_in_use(path))
+    }
+
+    fn size(&self) -> u64 {
+        self.archive_size + self.source_size + self.target_size
+    }
+}
+
+/// report the disk usage of DOWNLOAD_DIR and delete what the options select
+pub fn run(args: &GcArgs, checkpoint_dir: &Path) -> Result<()> {
+    let base_dir = STORE.base_dir().to_path_buf();
+    if !base_dir.exists() {
+        println!("下载目录 {} 不存在", base_dir.display());
+        return Ok(());
+    }
+    let units = collect_units(&base_dir);
+    print_usage(&base_dir, &units);
+
+    if args.all {
+        if let Some(unit) = units.iter().find(|unit| unit.in_use()) {
+            bail!("{} 正在被分析使用，不能删除整个下载目录", unit.label());
+        }
+        println!("删除整个下载目录 {}", base_dir.display());
+        if !args.dry_run {
+            std::fs::remove_dir_all(&base_dir)
+                .context(format!("删除 {} 失败", base_dir.display()))?;
+        }
+        return Ok(());
+    }
+    if args.older_than.is_none() && !args.keep_positives {
+        return Ok(());
+    }
+
+    let cutoff = match args.older_than {
+        Some(days) => Some(
+            days.checked_mul(24 * 60 * 60)
+                .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)))
+                .context("--older-than 超出范围")?,
+        ),
+        None => None,
+    };
+    let positives = if args.keep_positives {
+        let positives = read_positives(checkpoint_dir)?;
+        if positives.is_empty() {
+            bail!(
+                "{} 中没有检查点，无法确定哪些 crate 受影响",
+                checkpoint_dir.display()
+            );
+        }
+        Some(positives)
+    } else {
+        None
+    };
+
+    let mut deleted = 0;
+    let mut freed = 0;
+    for unit in &units {
+        let stale = match (cutoff, unit.last_used) {
+            (Some(cutoff), Some(last_used)) => last_used < cutoff,
+            (Some(_), None) => true,
+            (None, _) => true,
+        };
+        let negative = positives
+            .as_ref()
+            .is_none_or(|positives| unit.labels.is_disjoint(positives));
+        if !stale || !negative {
+            continue;
+        }
+        // 正在运行的分析持有源码锁，删除会让它的构建失败
+        if unit.in_use() {
+            println!("跳过 {}: 正在被分析使用", unit.label());
+            continue;
+        }
+        println!("删除 {} ({})", unit.label(), format_size(unit.size()));
+        if !args.dry_run {
+            for path in &unit.paths {
+                let removed = if path.is_dir() {
+                    std::fs::remove_dir_all(path)
+                } else {
+                    std::fs::remove_file(path)
+                };
+                if let Err(e) = removed {
+                    tracing::warn!("删除 {} 失败: {}", path.display(), e);
+                }
+            }
+        }
+        deleted += 1;
+        freed += unit.size();
+    }
+    if !args.dry_run {
+        prune_links(&base_dir.join("dl"));
+    }
+    println!(
+        "{}{} 项，共 {}",
+        if args.dry_run {
+            "可以删除 "
+        } else {
+            "已删除 "
+        },
+        deleted,
+        format_size(freed)
+    );
+    Ok(())
+}
+
+/// group the contents of DOWNLOAD_DIR into deletable units
+fn collect_units(base_dir: &Path) -> Vec<CacheUnit> {
+    let root = STORE.root();
+    let mut units: HashMap<PathBuf, CacheUnit> = HashMap::new();
+
+    // 内容寻址存储中的 crate，以解压目录为键
+    let labels = store::indexed_versions(root).into_iter().fold(
+        HashMap::<String, BTreeSet<String>>::new(),
+        |mut labels, (name, version, hash)| {
+            labels
+                .entry(hash)
+                .or_default()
+                .insert(format!("{} {}", name, version));
+            labels
+        },
+    );
+    for entry in store::store_entries(root) {
+        let source_dir = STORE.source_dir(&entry.hash);
+        let mut unit = CacheUnit {
+            labels: labels.get(&entry.hash).cloned().unwrap_or_default(),
+            last_used: entry.last_used,
+            ..CacheUnit::default()
+        };
+        for path in entry.paths {
+            if path == source_dir {
+                add_source(&mut unit, &path);
+            } else {
+                unit.archive_size += store::dir_size(&path);
+            }
+            unit.paths.push(path);
+        }
+        units.insert(canonical(&source_dir), unit);
+    }
+
+    // git 仓库的检出
+    for clone_dir in store::read_dir_paths(&base_dir.join("git")) {
+        let mut unit = CacheUnit {
+            last_used: modified(&clone_dir),
+            ..CacheUnit::default()
+        };
+        add_source(&mut unit, &clone_dir);
+        unit.paths.push(clone_dir.clone());
+        units.insert(canonical(&clone_dir), unit);
+    }
+
+    // dl/ab/cd/<name>/<name>-<version>: 指向上面两类的链接，或本地项目的副本
+    for shard in store::read_dir_paths(&base_dir.join("dl")) {
+        for shard in store::read_dir_paths(&shard) {
+            for crate_dir in store::read_dir_paths(&shard) {
+                let Some(name) = crate_dir.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let prefix = format!("{}-", name);
+                for extract_dir in store::read_dir_paths(&crate_dir) {
+                    let Some(version) = extract_dir
+                        .file_name()
+                        .and_then(|dir| dir.to_str())
+                        .and_then(|dir| dir.strip_prefix(&prefix))
+                    else {
+                        continue;
+                    };
+                    let label = format!("{} {}", name, version);
+                    if extract_dir.is_symlink() {
+                        let target = std::fs::read_link(&extract_dir).unwrap_or_default();
+                        if let Some(unit) = units.get_mut(&canonical(&target)) {
+                            unit.labels.insert(label);
+                        }
+                    } else if extract_dir.is_dir() {
+                        let mut unit = CacheUnit {
+                            last_used: modified(&extract_dir),
+                            ..CacheUnit::default()
+                        };
+                        unit.labels.insert(label);
+                        add_source(&mut unit, &extract_dir);
+                        unit.paths.push(extract_dir.clone());
+                        units.insert(extract_dir, unit);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut units = units.into_values().collect::<Vec<_>>();
+    units.sort_by_key(|unit| std::cmp::Reverse(unit.size()));
+    units
+}
+
+/// count a source tree, its `target/` separately
+fn add_source(unit: &mut CacheUnit, dir: &Path) {
+    let total = store::dir_size(dir);
+    let target = store::dir_size(&dir.join("target"));
+    unit.source_size += total - target;
+    unit.target_size += target;
+}
+
+fn print_usage(base_dir: &Path, units: &[CacheUnit]) {
+    let archives = units.iter().map(|unit| unit.archive_size).sum::<u64>();
+    let sources = units.iter().map(|unit| unit.source_size).sum::<u64>();
+    let targets = units.iter().map(|unit| unit.target_size).sum::<u64>();
+    println!("下载目录: {}", base_dir.display());
+    println!("  crate 压缩包: {}", format_size(archives));
+    println!("  解压的源码:   {}", format_size(sources));
+    println!("  target 目录:  {}", format_size(targets));
+    println!(
+        "  合计:         {} ({} 项)",
+        format_size(archives + sources + targets),
+        units.len()
+    );
+
+    let mut by_target = units
+        .iter()
+        .filter(|unit| unit.target_size > 0)
+        .collect::<Vec<_>>();
+    by_target.sort_by_key(|unit| std::cmp::Reverse(unit.target_size));
+    if !by_target.is_empty() {
+        println!("最大的 target 目录:");
+        for unit in by_target.iter().take(10) {
+            println!("  {:>10}  {}", format_size(unit.target_size), unit.label());
+        }
+    }
+}
+
+/// `name version` of the checkpointed nodes and their affected dependents
+fn read_positives(checkpoint_dir: &Path) -> Result<BTreeSet<String>> {
+    let mut positives = BTreeSet::new();
+    let indexed = store::indexed_versions(STORE.root());
+    for path in store::read_dir_paths(checkpoint_dir) {
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let content =
+            std::fs::read_to_string(&path).context(format!("读取 {} 失败", path.display()))?;
+        let checkpoint: NodeCheckpoint = match serde_json::from_str(&content) {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                tracing::warn!("跳过无法解析的检查点 {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        for entry in &checkpoint.affected {
+            positives.insert(format!("{} {}", entry.name, entry.version));
+        }
+        // 检查点文件名为 `name-version-digest.json`，节点本身也受影响
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        for (name, version, _) in &indexed {
+            if file_name.starts_with(&format!("{}-{}-", name, version)) {
+                positives.insert(format!("{} {}", name, version));
+            }
+        }
+    }
+    Ok(positives)
+}
+
+/// remove links into deleted sources and the directories left empty
+fn prune_links(dir: &Path) {
+    for path in store::read_dir_paths(dir) {
+        if path.is_symlink() && !path.exists() {
+            let _ = std::fs::remove_file(&path);
+        } else if path.is_dir() && !path.is_symlink() {
+            prune_links(&path);
+            // 只有空目录才能删除成功
+            let _ = std::fs::remove_dir(&path);
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs as tokio_fs;
+use tokio::process::Command;
+use tracing::info;
+
+use crate::model::{DependentSource, Krate, ReverseDependency};
+
+/// a dependent hosted in a git repository instead of crates.io, e.g. an unpublished
+/// internal project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitKrate {
+    url: String,
+    // branch, tag or commit, None for the default branch
+    rev: Option<String>,
+}
+
+impl GitKrate {
+    /// `URL` or `URL#REV`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (url, rev) = match spec.trim().rsplit_once('#') {
+            Some((url, rev)) => (url, Some(rev.trim())),
+            None => (spec.trim(), None),
+        };
+        if url.is_empty() {
+            bail!("git 仓库地址为空: {}", spec);
+        }
+        Ok(Self {
+            url: url.to_owned(),
+            rev: rev.filter(|rev| !rev.is_empty()).map(str::to_owned),
+        })
+    }
+
+    /// $DOWNLOAD_DIR/git/<hash of the url>-<rev>/
+    fn get_clone_dir(&self) -> PathBuf {
+        let base_dir = std::env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "./downloads".to_string());
+        let digest = hex::encode(Sha256::digest(self.url.as_bytes()));
+        let rev = self.rev.as_deref().unwrap_or("HEAD").replace(
+            |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-',
+            "_",
+        );
+        Path::new(&base_dir)
+            .join("git")
+            .join(format!("{}-{}", &digest[..16], rev))
+    }
+
+    /// clone the repository at `rev` and link it where `Krate` keeps extracted crates,
+    /// so the patch and analysis pipeline treats it like a downloaded crate
+    ///
+    /// the version is the package version with the commit as build metadata, so it
+    /// never collides with a release of the same name on crates.io
+    pub async fn checkout(&self) -> Result<ReverseDependency> {
+        let clone_dir = self.get_clone_dir();
+        if !clone_dir.join(".git").exists() {
+            info!("克隆 {} 到 {}", self.url, clone_dir.display());
+            if let Some(parent) = clone_dir.parent() {
+                tokio_fs::create_dir_all(parent)
+                    .await
+                    .context(format!("创建目录 {} 失败", parent.display()))?;
+            }
+            git(
+                None,
+                &["clone", "--quiet", &self.url, &clone_dir.to_string_lossy()],
+            )
+            .await?;
+            if let Some(rev) = &self.rev {
+                git(Some(&clone_dir), &["checkout", "--quiet", rev]).await?;
+            }
+        }
+        let commit = git(Some(&clone_dir), &["rev-parse", "--short=12", "HEAD"]).await?;
+        let (name, version) = root_package(&clone_dir).await?;
+        let version = format!("{}+git.{}", version, commit.trim());
+
+        let krate = Krate::new(&name, &version);
+        let extract_dir = krate.get_extract_dir_path();
+        if !extract_dir.exists() {
+            if let Some(parent) = extract_dir.parent() {
+                tokio_fs::create_dir_all(parent)
+                    .await
+                    .context(format!("创建目录 {} 失败", parent.display()))?;
+            }
+            let target = clone_dir
+                .canonicalize()
+                .context(format!("无法定位 {}", clone_dir.display()))?;
+            tokio_fs::symlink(&target, &extract_dir)
+                .await
+                .context(format!("链接 {} 失败", extract_dir.display()))?;
+        }
+        info!(
+            "git 依赖者 {} {} 已就绪: {}",
+            name,
+            version,
+            extract_dir.display()
+        );
+        // 依赖者的版本要求未知，由 patch 阶段的 cargo update 验证是否兼容
+        Ok(
+            ReverseDependency::new(name, version, "*".to_string())
+                .with_source(DependentSource::Git),
+        )
+    }
+}
+
+/// where a published crate comes from in its repository, from `.cargo_vcs_info.json`
+#[derive(Debug, Clone)]
+pub struct VcsInfo {
+    pub commit: String,
+    // the package directory inside the repository, empty for the repository root
+    pub path_in_vcs: String,
+}
+
+impl VcsInfo {
+    /// read the file `cargo package` writes into the archive, None for crates published
+    /// without a git checkout
+    pub async fn read(crate_dir: &Path) -> Option<Self> {
+        let content = tokio_fs::read_to_string(crate_dir.join(".cargo_vcs_info.json"))
+            .await
+            .ok()?;
+        let info: serde_json::Value = serde_json::from_str(&content).ok()?;
+        Some(Self {
+            commit: info["git"]["sha1"].as_str()?.to_owned(),
+            path_in_vcs: info["path_in_vcs"].as_str().unwrap_or_default().to_owned(),
+        })
+    }
+}
+
+/// the commit a line was last changed in, from `git blame`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlamedLine {
+    pub commit: String,
+    pub author: String,
+    // author date, `YYYY-MM-DD`
+    pub date: String,
+    pub summary: String,
+    // `#123` from the summary of squash and merge commits
+    pub pull_request: Option<String>,
+}
+
+/// a full clone of a repository for blaming, $DOWNLOAD_DIR/git/<hash of the url>/
+///
+/// the clone is reused across runs and fetched again when `commit` isn't in it yet
+pub async fn history_checkout(url: &str, commit: &str) -> Result<PathBuf> {
+    let base_dir = std::env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "./downloads".to_string());
+    let digest = hex::encode(Sha256::digest(url.as_bytes()));
+    let clone_dir = Path::new(&base_dir).join("git").join(&digest[..16]);
+    if !clone_dir.join(".git").exists() {
+        info!("克隆 {} 到 {}", url, clone_dir.display());
+        if let Some(parent) = clone_dir.parent() {
+            tokio_fs::create_dir_all(parent)
+                .await
+                .context(format!("创建目录 {} 失败", parent.display()))?;
+        }
+        git(
+            None,
+            &[
+                "clone",
+                "--quiet",
+                "--no-checkout",
+                url,
+                &clone_dir.to_string_lossy(),
+            ],
+        )
+        .await?;
+    }
+    let object = format!("{}^{{commit}}", commit);
+    if git(Some(&clone_dir), &["cat-file", "-e", &object])
+        .await
+        .is_err()
+    {
+        git(Some(&clone_dir), &["fetch", "--quiet", "origin"]).await?;
+    }
+    Ok(clone_dir)
+}
+
+/// blame one line of `path` as of `commit`
+pub async fn blame_line(
+    clone_dir: &Path,
+    commit: &str,
+    path: &str,
+    line: u64,
+) -> Result<BlamedLine> {
+    let range = format!("{},{}", line, line);
+    let porcelain = git(
+        Some(clone_dir),
+        &["blame", "--porcelain", "-L", &range, commit, "--", path],
+    )
+    .await?;
+    let mut lines = porcelain.lines();
+    let blamed = lines
+        .next()
+        .and_then(|header| header.split_whitespace().next())
+        .context(format!("git blame 没有输出: {}:{}", path, line))?
+        .to_owned();
+    let mut author = String::new();
+    let mut summary = String::new();
+    for line in lines {
+        if let Some(value) = line.strip_prefix("author ") {
+            author = value.to_owned();
+        } else if let Some(value) = line.strip_prefix("summary ") {
+            summary = value.to_owned();
+        }
+    }
+    let date = git(Some(clone_dir), &["show", "-s", "--format=%as", &blamed]).await?;
+    let pull_request = pull_request(&summary);
+    Ok(BlamedLine {
+        commit: blamed,
+        author,
+        date: date.trim().to_owned(),
+        summary,
+        pull_request,
+    })
+}
+
+/// the last `#123` in a commit summary, e.g. `Fix parser (#123)` or
+/// `Merge pull request #123 from user/branch`
+fn pull_request(summary: &str) -> Option<String> {
+    summary.match_indices('#').rev().find_map(|(idx, _)| {
+        let digits = summary[idx + 1..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>();
+        (!digits.is_empty()).then(|| format!("#{}", digits))
+    })
+}
+
+/// run git and return its stdout
+async fn git(dir: Option<&Path>, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    command.args(args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let output = command
+        .output()
+        .await
+        .context(format!("执行 git {} 失败", args.join(" ")))?;
+    if !output.status.success() {
+        bail!(
+            "git {} 执行失败: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// name and version of the package at the root of a checkout, or of its only member
+/// when the root is a virtual manifest
+pub async fn root_package(dir: &Path) -> Result<(String, String)> {
+    let manifest_path = dir.join("Cargo.toml");
+    let metadata = Krate::cargo_metadata(dir, true).await?;
+    let packages = metadata.packages;
+    let root = manifest_path.canonicalize().ok();
+    let package = packages
+        .iter()
+        .find(|package| package.manifest_path.as_std_path().canonicalize().ok() == root)
+        .or(match packages.as_slice() {
+            [only] => Some(only),
+            _ => None,
+        })
+        .context(format!(
+            "{} 是包含 {} 个成员的虚拟工作区，无法确定要分析的包",
+            dir.display(),
+            packages.len()
+        ))?;
+    Ok((package.name.clone(), package.version.to_string()))
+}
@@ -0,0 +1,1579 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::callers::{ApiExposure, CallSite};
+use crate::diagnostics::Invocation;
+use crate::git::BlamedLine;
+use crate::model::{
+    matches_with_prerelease, normalize_crate_name, Clearance, DependencyKind, DependentSource,
+    FunctionCalls, ReverseDependency,
+};
+use crate::resolution::ResolutionCheck;
+use crate::store;
+
+// version of the saved graph format, bumped on incompatible changes
+const GRAPH_FORMAT_VERSION: u32 = 1;
+
+/// crate-level node of the impact graph, i.e. one crate version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateNode {
+    pub name: String,
+    pub version: String,
+    // BFS depth, the roots are at depth 0
+    pub depth: usize,
+    // source repository from the crate metadata, crates published from the same
+    // workspace share it
+    pub repository: Option<String>,
+    // the version was yanked from the registry, only kept when yanked versions are flagged
+    pub yanked: bool,
+    // for roots, the vulnerable range the version was selected from
+    pub range: Option<String>,
+    // downloads of the version from the metadata database, None without one
+    pub downloads: Option<u64>,
+}
+
+/// edge from a parent crate version to a dependent which reaches the target function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DependencyEdge {
+    // the version requirement the dependent declares on the parent
+    pub req: String,
+    pub source: DependentSource,
+    // the parent version confirmed by `cargo tree`, None when it couldn't be checked
+    pub resolved_version: Option<String>,
+    // the tables the dependent declares the parent in, empty when unknown
+    pub kinds: Vec<DependencyKind>,
+    // the edge only exists with one of these features of the dependent enabled
+    pub required_features: Vec<String>,
+    // target function path -> number of callers found in the dependent
+    pub functions: BTreeMap<String, usize>,
+    // target function path -> paths the dependent re-exports it under
+    pub reexports: BTreeMap<String, BTreeSet<String>>,
+    // callers left out because of their call site (tests, examples, ...)
+    pub excluded: BTreeMap<CallSite, usize>,
+    // feature configuration -> whether a target is called under it, empty when only the
+    // default features were analyzed
+    pub feature_verdicts: BTreeMap<String, bool>,
+    // target triples a target is called on, empty when no platforms were given
+    pub platforms: Vec<String>,
+    // whether a resolver picks the parent version for `req`, None when not simulated
+    pub resolution: Option<ResolutionCheck>,
+}
+
+/// dependent analyzed against a parent version and found not to reach any target
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClearedDependent {
+    pub parent_name: String,
+    pub parent_version: String,
+    pub name: String,
+    pub version: String,
+    pub req: String,
+    pub source: DependentSource,
+    pub clearance: Clearance,
+}
+
+/// the version of a dependent that introduced its call, see `IntroducedEntry`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Introduction {
+    pub parent_name: String,
+    pub parent_version: String,
+    pub name: String,
+    pub first_affected: String,
+    pub last_clear: Option<String>,
+    pub checked: usize,
+}
+
+/// what upgrading an affected dependent of a root does to its exposure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Remedy {
+    // from `version` on, the requirement admits `parent_version`, which is outside the
+    // vulnerable ranges
+    AdmitsFixedParent {
+        version: String,
+        parent_version: String,
+    },
+    // from `version` on, the dependent no longer depends on the root
+    DropsDependency {
+        version: String,
+    },
+    // the newest version still requires a vulnerable root version but no longer calls a
+    // target, checked against `parent_version`
+    CallRemoved {
+        version: String,
+        parent_version: String,
+    },
+    // the newest version still requires a vulnerable root version and calls a target
+    StillAffected {
+        version: String,
+    },
+    // no version newer than the affected one is published
+    NoNewerVersion,
+    // the newest version couldn't be analyzed
+    Unknown {
+        reason: String,
+    },
+}
+
+/// upgrade advice for an affected direct dependent of a root
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixAdvice {
+    pub parent_name: String,
+    pub name: String,
+    // the newest version found affected
+    pub affected_version: String,
+    pub remedy: Remedy,
+}
+
+/// the commit a caller in an affected dependent was introduced in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blame {
+    pub name: String,
+    pub version: String,
+    pub function: String,
+    // the file in the repository, not in the published archive
+    pub file: String,
+    pub line: u64,
+    pub blamed: BlamedLine,
+}
+
+/// the versions of one dependent analyzed against the versions of one parent
+#[derive(Debug, Clone)]
+pub struct VersionMatrix {
+    pub parent: String,
+    pub dependent: String,
+    // the parent versions compared against, oldest first: the vulnerable range for
+    // roots, the affected versions in the graph otherwise
+    pub parent_versions: Vec<String>,
+    // one row per analyzed version of the dependent, oldest first
+    pub rows: Vec<MatrixRow>,
+}
+
+/// one analyzed version of a dependent in a `VersionMatrix`
+#[derive(Debug, Clone)]
+pub struct MatrixRow {
+    pub version: String,
+    pub req: String,
+    // the parent versions the requirement admits, empty when it doesn't parse
+    pub admitted: Vec<String>,
+    // parent version -> whether a target is reached with it, only the analyzed ones
+    pub analyzed: BTreeMap<String, bool>,
+}
+
+impl MatrixRow {
+    pub fn is_affected(&self) -> bool {
+        self.analyzed.values().any(|affected| *affected)
+    }
+}
+
+/// function-level node of the impact graph, a function of one crate version
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FunctionNode {
+    pub crate_name: String,
+    pub version: String,
+    pub path: String,
+    // whether the function is part of its crate's public API
+    pub exposure: ApiExposure,
+    pub call_site: CallSite,
+    // where the function is defined, from callers.json, None when it isn't recorded
+    pub file: Option<String>,
+    pub line: Option<u64>,
+}
+
+/// how a function of a dependent reaches the function of its parent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    Call,
+    // the dependent doesn't call the function but exposes it through `pub use`
+    ReExport,
+}
+
+impl CallKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CallKind::Call => "call",
+            CallKind::ReExport => "reexport",
+        }
+    }
+}
+
+/// edge from a caller function to the function it calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallEdge {
+    // BFS level the call was found at, i.e. how many crate boundaries lie between the
+    // caller and the vulnerable function
+    pub constraint_depth: usize,
+    pub kind: CallKind,
+}
+
+/// limits applied to the propagation chains handed to exporters
+#[derive(Debug, Clone)]
+pub struct ChainFilter {
+    // keep at most this many chains per dependent, the lightest first
+    pub top_k: usize,
+    // drop chains with more crates than this, roots included
+    pub max_length: Option<usize>,
+}
+
+/// merged impact graph of one analysis run
+///
+/// every root passed to `DependencyAnalyzer::analyze` ends up in the same graph,
+/// so dependents reachable from several roots appear only once
+#[derive(Debug, Clone, Default)]
+pub struct Graph {
+    crates: DiGraph<CrateNode, DependencyEdge>,
+    crate_index: HashMap<(String, String), NodeIndex>,
+    // the advisories the run was about, from `ADVISORY_ID`
+    advisories: BTreeSet<String>,
+    // the target functions after expansion
+    targets: BTreeSet<String>,
+    // module/type target spec -> the functions it was expanded to
+    target_groups: BTreeMap<String, BTreeSet<String>>,
+    // caller -> callee edges across crate boundaries
+    functions: DiGraph<FunctionNode, CallEdge>,
+    function_index: HashMap<(String, String, String), NodeIndex>,
+    cleared: Vec<ClearedDependent>,
+    introductions: Vec<Introduction>,
+    fixes: Vec<FixAdvice>,
+    blames: Vec<Blame>,
+    // root -> its published versions inside the vulnerable ranges
+    range_versions: HashMap<String, Vec<String>>,
+    // cargo / call-cg4rs invocations of the run, for debugging skipped dependents
+    invocations: Vec<Invocation>,
+}
+
+/// a `Graph` as saved to disk, nodes are referenced by their position in the lists
+#[derive(Debug, Serialize, Deserialize)]
+struct GraphFile {
+    format_version: u32,
+    crates: Vec<CrateNode>,
+    // (parent, dependent, edge)
+    dependencies: Vec<(usize, usize, DependencyEdge)>,
+    #[serde(default)]
+    advisories: BTreeSet<String>,
+    #[serde(default)]
+    targets: BTreeSet<String>,
+    #[serde(default)]
+    target_groups: BTreeMap<String, BTreeSet<String>>,
+    functions: Vec<FunctionNode>,
+    // (caller, callee, edge)
+    calls: Vec<(usize, usize, CallEdge)>,
+    #[serde(default)]
+    cleared: Vec<ClearedDependent>,
+    #[serde(default)]
+    introductions: Vec<Introduction>,
+    #[serde(default)]
+    fixes: Vec<FixAdvice>,
+    #[serde(default)]
+    blames: Vec<Blame>,
+    #[serde(default)]
+    range_versions: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    invocations: Vec<Invocation>,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a crate version to the graph, return the existing node if already present
+    pub fn add_crate(&mut self, name: &str, version: &str, depth: usize) -> NodeIndex {
+        let key = (normalize_crate_name(name), version.to_owned());
+        if let Some(idx) = self.crate_index.get(&key) {
+            // 流水线遍历中较深的路径可能先完成，深度取最短路径
+            let node = &mut self.crates[*idx];
+            node.depth = node.depth.min(depth);
+            return *idx;
+        }
+        let idx = self.crates.add_node(CrateNode {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            depth,
+            repository: None,
+            yanked: false,
+            range: None,
+            downloads: None,
+        });
+        self.crate_index.insert(key, idx);
+        idx
+    }
+
+    /// record that `dependent` depends on `parent` and reaches the target function
+    pub fn add_dependency(
+        &mut self,
+        parent: (&str, &str),
+        dependent: (&str, &str),
+        depth: usize,
+        dependency: &ReverseDependency,
+        calls: &FunctionCalls,
+    ) {
+        let parent_idx = self.add_crate(parent.0, parent.1, depth.saturating_sub(1));
+        let dependent_idx = self.add_crate(dependent.0, dependent.1, depth);
+        self.crates[dependent_idx].yanked |= dependency.yanked;
+        // 同一节点的后续运行（例如合并后到达的目标）与已有的边合并
+        self.merge_dependency(
+            parent_idx,
+            dependent_idx,
+            DependencyEdge {
+                req: dependency.req.clone(),
+                source: dependency.source,
+                resolved_version: dependency.resolved_version.clone(),
+                kinds: dependency.kinds.clone(),
+                required_features: dependency.required_features.clone(),
+                functions: calls.breakdown(),
+                reexports: calls.reexports().clone(),
+                excluded: calls.excluded().clone(),
+                feature_verdicts: calls.feature_verdicts().clone(),
+                platforms: calls.affected_platforms(),
+                resolution: dependency.resolution,
+            },
+        );
+    }
+
+    /// record the vulnerable range a root version was selected from
+    pub fn set_root_range(&mut self, name: &str, version: &str, range: &str) {
+        let key = (normalize_crate_name(name), version.to_owned());
+        if let Some(idx) = self.crate_index.get(&key) {
+            self.crates[*idx].range = Some(range.to_owned());
+        }
+    }
+
+    /// the vulnerable ranges of the roots a crate version is reached from
+    pub fn ranges_of(&self, name: &str, version: &str) -> BTreeSet<String> {
+        let mut ranges = BTreeSet::new();
+        let key = (normalize_crate_name(name), version.to_owned());
+        let Some(&idx) = self.crate_index.get(&key) else {
+            return ranges;
+        };
+        let mut seen = BTreeSet::from([idx]);
+        let mut stack = vec![idx];
+        while let Some(current) = stack.pop() {
+            if let Some(range) = &self.crates[current].range {
+                ranges.insert(range.clone());
+            }
+            for parent in self
+                .crates
+                .neighbors_directed(current, petgraph::Direction::Incoming)
+            {
+                if seen.insert(parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+        ranges
+    }
+
+    /// record every version of a root inside its vulnerable ranges, not only the
+    /// selected ones, for the version matrix
+    pub fn set_range_versions(&mut self, name: &str, mut versions: Vec<String>) {
+        sort_versions(&mut versions);
+        self.range_versions
+            .insert(normalize_crate_name(name), versions);
+    }
+
+    /// dependent × parent version matrices, one per pair of crates connected by an edge
+    ///
+    /// rows are the dependent versions found affected or cleared against the parent;
+    /// the admitted parent versions come from each row's requirement
+    pub fn version_matrices(&self) -> Vec<VersionMatrix> {
+        // (父节点, 依赖者) -> 依赖者版本 -> (要求, 父节点版本 -> 是否受影响)
+        type Rows = BTreeMap<String, (String, BTreeMap<String, bool>)>;
+        let mut pairs: BTreeMap<(String, String), Rows> = BTreeMap::new();
+        for (parent, dependent, edge) in self.dependencies() {
+            let (_, analyzed) = pairs
+                .entry((parent.name.clone(), dependent.name.clone()))
+                .or_default()
+                .entry(dependent.version.clone())
+                .or_insert_with(|| (edge.req.clone(), BTreeMap::new()));
+            analyzed.insert(parent.version.clone(), true);
+        }
+        for cleared in &self.cleared {
+            let Some(rows) = pairs.get_mut(&(cleared.parent_name.clone(), cleared.name.clone()))
+            else {
+                // 依赖者的所有版本都未受影响，不构成矩阵
+                continue;
+            };
+            let (_, analyzed) = rows
+                .entry(cleared.version.clone())
+                .or_insert_with(|| (cleared.req.clone(), BTreeMap::new()));
+            analyzed
+                .entry(cleared.parent_version.clone())
+                .or_insert(false);
+        }
+
+        pairs
+            .into_iter()
+            .map(|((parent, dependent), rows)| {
+                let parent_versions = self.parent_versions(&parent);
+                let mut rows = rows
+                    .into_iter()
+                    .map(|(version, (req, analyzed))| {
+                        let admitted = match semver::VersionReq::parse(&req) {
+                            Ok(version_req) => parent_versions
+                                .iter()
+                                .filter(|version| {
+                                    semver::Version::parse(version).is_ok_and(|version| {
+                                        matches_with_prerelease(&version_req, &version)
+                                    })
+                                })
+                                .cloned()
+                                .collect(),
+                            Err(_) => Vec::new(),
+                        };
+                        MatrixRow {
+                            version,
+                            req,
+                            admitted,
+                            analyzed,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                rows.sort_by_key(|row| semver::Version::parse(&row.version).ok());
+                VersionMatrix {
+                    parent,
+                    dependent,
+                    parent_versions,
+                    rows,
+                }
+            })
+            .collect()
+    }
+
+    /// the versions of a parent a matrix compares against
+    fn parent_versions(&self, name: &str) -> Vec<String> {
+        if let Some(versions) = self.range_versions.get(&normalize_crate_name(name)) {
+            return versions.clone();
+        }
+        let name = normalize_crate_name(name);
+        let mut versions = self
+            .crates()
+            .filter(|node| normalize_crate_name(&node.name) == name)
+            .map(|node| node.version.clone())
+            .collect::<Vec<_>>();
+        sort_versions(&mut versions);
+        versions
+    }
+
+    /// mark a crate version as yanked
+    pub fn mark_yanked(&mut self, name: &str, version: &str) {
+        let key = (normalize_crate_name(name), version.to_owned());
+        if let Some(idx) = self.crate_index.get(&key) {
+            self.crates[*idx].yanked = true;
+        }
+    }
+
+    /// set the download count of a crate version, ignored when it isn't in the graph
+    pub fn set_downloads(&mut self, name: &str, version: &str, downloads: u64) {
+        let key = (normalize_crate_name(name), version.to_owned());
+        if let Some(idx) = self.crate_index.get(&key) {
+            self.crates[*idx].downloads = Some(downloads);
+        }
+    }
+
+    /// set the repository of every version of a crate
+    pub fn set_repository(&mut self, name: &str, repository: &str) {
+        let name = normalize_crate_name(name);
+        let repository = normalize_repository(repository);
+        for node in self.crates.node_weights_mut() {
+            if normalize_crate_name(&node.name) == name {
+                node.repository = Some(repository.clone());
+            }
+        }
+    }
+
+    /// affected crates (roots excluded) grouped by repository, crates without a known
+    /// repository are left out
+    pub fn repository_rollup(&self) -> BTreeMap<String, BTreeSet<(String, String)>> {
+        let mut rollup: BTreeMap<String, BTreeSet<(String, String)>> = BTreeMap::new();
+        for node in self.crates().filter(|node| node.depth > 0) {
+            if let Some(repository) = &node.repository {
+                rollup
+                    .entry(repository.clone())
+                    .or_default()
+                    .insert((node.name.clone(), node.version.clone()));
+            }
+        }
+        rollup
+    }
+
+    pub fn set_invocations(&mut self, invocations: Vec<Invocation>) {
+        self.invocations = invocations;
+    }
+
+    pub fn invocations(&self) -> &[Invocation] {
+        &self.invocations
+    }
+
+    /// record a dependent that was analyzed against `parent` and cleared
+    pub fn add_cleared(&mut self, cleared: ClearedDependent) {
+        self.cleared.push(cleared);
+    }
+
+    pub fn add_introduction(&mut self, introduction: Introduction) {
+        self.introductions.push(introduction);
+    }
+
+    pub fn introductions(&self) -> &[Introduction] {
+        &self.introductions
+    }
+
+    pub fn add_blame(&mut self, blame: Blame) {
+        self.blames.push(blame);
+    }
+
+    pub fn blames(&self) -> &[Blame] {
+        &self.blames
+    }
+
+    /// the node of a crate version
+    pub fn crate_node(&self, name: &str, version: &str) -> Option<&CrateNode> {
+        let key = (normalize_crate_name(name), version.to_owned());
+        self.crate_index.get(&key).map(|idx| &self.crates[*idx])
+    }
+
+    pub fn add_fix(&mut self, fix: FixAdvice) {
+        self.fixes.push(fix);
+    }
+
+    pub fn fixes(&self) -> &[FixAdvice] {
+        &self.fixes
+    }
+
+    /// dependents confirmed not affected, a dependent cleared against one parent but
+    /// affected through another one is left out
+    pub fn cleared(&self) -> impl Iterator<Item = &ClearedDependent> {
+        self.cleared.iter().filter(|cleared| {
+            let key = (normalize_crate_name(&cleared.name), cleared.version.clone());
+            !self.crate_index.contains_key(&key)
+        })
+    }
+
+    /// add a function to the graph, return the existing node if already present
+    pub fn add_function(&mut self, crate_name: &str, version: &str, path: &str) -> NodeIndex {
+        let key = (
+            normalize_crate_name(crate_name),
+            version.to_owned(),
+            path.to_owned(),
+        );
+        if let Some(idx) = self.function_index.get(&key) {
+            return *idx;
+        }
+        let idx = self.functions.add_node(FunctionNode {
+            crate_name: crate_name.to_owned(),
+            version: version.to_owned(),
+            path: path.to_owned(),
+            exposure: ApiExposure::Unknown,
+            call_site: CallSite::Lib,
+            file: None,
+            line: None,
+        });
+        self.function_index.insert(key, idx);
+        idx
+    }
+
+    /// record that `caller` calls `callee`, both given as (crate, version, function path)
+    pub fn add_call(
+        &mut self,
+        caller: (&str, &str, &str),
+        callee: (&str, &str, &str),
+        constraint_depth: usize,
+    ) {
+        self.add_function_edge(caller, callee, constraint_depth, CallKind::Call);
+    }
+
+    /// annotate a function with its public API classification and call site
+    pub fn annotate_function(
+        &mut self,
+        function: (&str, &str, &str),
+        exposure: ApiExposure,
+        call_site: CallSite,
+    ) {
+        let idx = self.add_function(function.0, function.1, function.2);
+        self.functions[idx].exposure = exposure;
+        self.functions[idx].call_site = call_site;
+    }
+
+    /// record where a function is defined
+    pub fn locate_function(&mut self, function: (&str, &str, &str), file: &str, line: u64) {
+        let idx = self.add_function(function.0, function.1, function.2);
+        self.functions[idx].file = Some(file.to_owned());
+        self.functions[idx].line = Some(line);
+    }
+
+    /// the functions of `dependent` calling into `parent`, counted by public API
+    /// classification and by call site
+    pub fn caller_breakdown(
+        &self,
+        parent: &CrateNode,
+        dependent: &CrateNode,
+    ) -> (BTreeMap<&'static str, usize>, BTreeMap<&'static str, usize>) {
+        let mut exposure = BTreeMap::new();
+        let mut sites = BTreeMap::new();
+        for edge in self.functions.edge_references() {
+            let (caller, callee) = (
+                &self.functions[edge.source()],
+                &self.functions[edge.target()],
+            );
+            if edge.weight().kind == CallKind::Call
+                && caller.version == dependent.version
+                && callee.version == parent.version
+                && normalize_crate_name(&caller.crate_name) == normalize_crate_name(&dependent.name)
+                && normalize_crate_name(&callee.crate_name) == normalize_crate_name(&parent.name)
+            {
+                *exposure.entry(caller.exposure.as_str()).or_default() += 1;
+                *sites.entry(caller.call_site.as_str()).or_default() += 1;
+            }
+        }
+        (exposure, sites)
+    }
+
+    /// record that the dependent function path `exposed` is a `pub use` of `target`
+    pub fn add_reexport(
+        &mut self,
+        exposed: (&str, &str, &str),
+        target: (&str, &str, &str),
+        constraint_depth: usize,
+    ) {
+        self.add_function_edge(exposed, target, constraint_depth, CallKind::ReExport);
+    }
+
+    fn add_function_edge(
+        &mut self,
+        from: (&str, &str, &str),
+        to: (&str, &str, &str),
+        constraint_depth: usize,
+        kind: CallKind,
+    ) {
+        let from_idx = self.add_function(from.0, from.1, from.2);
+        let to_idx = self.add_function(to.0, to.1, to.2);
+        if self.functions.find_edge(from_idx, to_idx).is_none() {
+            self.functions.add_edge(
+                from_idx,
+                to_idx,
+                CallEdge {
+                    constraint_depth,
+                    kind,
+                },
+            );
+        }
+    }
+
+    pub fn function_count(&self) -> usize {
+        self.functions.node_count()
+    }
+
+    pub fn call_count(&self) -> usize {
+        self.functions.edge_count()
+    }
+
+    /// iterate over all function nodes together with their stable id
+    pub fn functions_with_id(&self) -> impl Iterator<Item = (usize, &FunctionNode)> {
+        self.functions
+            .node_indices()
+            .map(|idx| (idx.index(), &self.functions[idx]))
+    }
+
+    /// iterate over all call edges as (caller id, callee id, edge)
+    pub fn calls_with_id(&self) -> impl Iterator<Item = (usize, usize, &CallEdge)> {
+        self.functions
+            .edge_references()
+            .map(|edge| (edge.source().index(), edge.target().index(), edge.weight()))
+    }
+
+    pub fn crate_count(&self) -> usize {
+        self.crates.node_count()
+    }
+
+    pub fn dependency_count(&self) -> usize {
+        self.crates.edge_count()
+    }
+
+    /// iterate over all crate nodes
+    pub fn crates(&self) -> impl Iterator<Item = &CrateNode> {
+        self.crates.node_weights()
+    }
+
+    /// iterate over all edges as (parent, dependent, edge)
+    pub fn dependencies(&self) -> impl Iterator<Item = (&CrateNode, &CrateNode, &DependencyEdge)> {
+        self.crates.edge_references().map(|edge| {
+            (
+                &self.crates[edge.source()],
+                &self.crates[edge.target()],
+                edge.weight(),
+            )
+        })
+    }
+
+    /// iterate over all crate nodes together with their stable id
+    pub fn crates_with_id(&self) -> impl Iterator<Item = (usize, &CrateNode)> {
+        self.crates
+            .node_indices()
+            .map(|idx| (idx.index(), &self.crates[idx]))
+    }
+
+    /// iterate over all edges as (parent id, dependent id, edge)
+    pub fn dependencies_with_id(&self) -> impl Iterator<Item = (usize, usize, &DependencyEdge)> {
+        self.crates
+            .edge_references()
+            .map(|edge| (edge.source().index(), edge.target().index(), edge.weight()))
+    }
+
+    /// propagation chains, i.e. paths from a root to a crate without further dependents,
+    /// as lists of node ids
+    ///
+    /// the `filter.top_k` lightest chains of each leaf, by the sum of their hop weights
+    /// (see `hop_weights`), grouped by leaf. Partial chains are expanded lightest first
+    /// and every crate is expanded at most `top_k` times, so the work stays bounded
+    /// however many paths the graph has; chains longer than `filter.max_length` are
+    /// pruned while expanding
+    pub fn chains(&self, filter: &ChainFilter) -> Vec<Vec<usize>> {
+        let hop_weights = self.hop_weights();
+        let hop_weight = |parent: NodeIndex, dependent: NodeIndex| {
+            hop_weights
+                .get(&(parent.index(), dependent.index()))
+                .copied()
+                .unwrap_or(self.crates[dependent].depth)
+        };
+
+        // (weight, crates, path)，最轻的先出队
+        let mut heap = self
+            .crates
+            .node_indices()
+            .filter(|idx| self.crates[*idx].depth == 0)
+            .map(|root| Reverse((0, 1, vec![root])))
+            .collect::<BinaryHeap<_>>();
+        let mut expanded: HashMap<NodeIndex, usize> = HashMap::new();
+        let mut by_leaf: BTreeMap<usize, Vec<Vec<usize>>> = BTreeMap::new();
+        while let Some(Reverse((weight, length, path))) = heap.pop() {
+            let current = *path.last().unwrap_or(&NodeIndex::new(0));
+            let count = expanded.entry(current).or_default();
+            if *count >= filter.top_k {
+                continue;
+            }
+            *count += 1;
+
+            let next = self
+                .crates
+                .neighbors(current)
+                // a dependent may show up again through dev-dependency cycles
+                .filter(|idx| !path.contains(idx))
+                .collect::<Vec<_>>();
+            if next.is_empty() {
+                if path.len() > 1 {
+                    by_leaf
+                        .entry(current.index())
+                        .or_default()
+                        .push(path.iter().map(|idx| idx.index()).collect());
+                }
+                continue;
+            }
+            if filter
+                .max_length
+                .is_some_and(|max_length| length >= max_length)
+            {
+                continue;
+            }
+            for idx in next {
+                let mut extended = path.clone();
+                extended.push(idx);
+                heap.push(Reverse((
+                    weight + hop_weight(current, idx),
+                    length + 1,
+                    extended,
+                )));
+            }
+        }
+        by_leaf.into_values().flatten().collect()
+    }
+
+    /// crate ids from a root to the crate `id`, following the shallowest parent at each
+    /// step; only `id` itself when no parent leads to a root
+    pub fn shortest_chain(&self, id: usize) -> Vec<usize> {
+        let mut chain = vec![id];
+        let mut current = NodeIndex::new(id);
+        // 每一步深度减一，步数不会超过 crate 数
+        while self.crates[current].depth > 0 && chain.len() <= self.crates.node_count() {
+            let Some(parent) = self
+                .crates
+                .neighbors_directed(current, petgraph::Direction::Incoming)
+                .min_by_key(|parent| (self.crates[*parent].depth, parent.index()))
+            else {
+                break;
+            };
+            current = parent;
+            chain.push(current.index());
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// the lowest constraint_depth of the function edges between each pair of crate
+    /// nodes, keyed by (parent id, dependent id); hops without function edges weigh the
+    /// depth of the dependent
+    fn hop_weights(&self) -> HashMap<(usize, usize), usize> {
+        let crate_id = |function: &FunctionNode| {
+            self.crate_index
+                .get(&(
+                    normalize_crate_name(&function.crate_name),
+                    function.version.clone(),
+                ))
+                .map(|idx| idx.index())
+        };
+        let mut weights: HashMap<(usize, usize), usize> = HashMap::new();
+        for edge in self.functions.edge_references() {
+            let (Some(dependent), Some(parent)) = (
+                crate_id(&self.functions[edge.source()]),
+                crate_id(&self.functions[edge.target()]),
+            ) else {
+                continue;
+            };
+            let weight = weights.entry((parent, dependent)).or_insert(usize::MAX);
+            *weight = (*weight).min(edge.weight().constraint_depth);
+        }
+        weights
+    }
+
+    /// record the advisory the graph is about, so that saved graphs can be told apart
+    pub fn add_advisory(&mut self, id: &str) {
+        self.advisories.insert(id.to_owned());
+    }
+
+    pub fn advisories(&self) -> &BTreeSet<String> {
+        &self.advisories
+    }
+
+    /// record a target function the dependents were analyzed for
+    pub fn add_target(&mut self, function_path: &str) {
+        self.targets.insert(function_path.to_owned());
+    }
+
+    pub fn targets(&self) -> &BTreeSet<String> {
+        &self.targets
+    }
+
+    /// remember which functions a module or type target was expanded to, so that the
+    /// results can be aggregated per method
+    pub fn add_target_group(&mut self, group: &str, function_path: &str) {
+        self.target_groups
+            .entry(group.to_owned())
+            .or_default()
+            .insert(function_path.to_owned());
+    }
+
+    /// per target function: (number of dependents calling it, total number of callers)
+    pub fn function_summary(&self) -> BTreeMap<String, (usize, usize)> {
+        let mut summary: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        for edge in self.crates.edge_weights() {
+            for (function_path, callers) in &edge.functions {
+                let entry = summary.entry(function_path.clone()).or_default();
+                entry.0 += 1;
+                entry.1 += callers;
+            }
+        }
+        summary
+    }
+
+    /// write the graph to `path` as versioned JSON, to be read back with `Graph::load`
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let file = GraphFile {
+            format_version: GRAPH_FORMAT_VERSION,
+            crates: self.crates.node_weights().cloned().collect(),
+            dependencies: self
+                .dependencies_with_id()
+                .map(|(parent, dependent, edge)| (parent, dependent, edge.clone()))
+                .collect(),
+            advisories: self.advisories.clone(),
+            targets: self.targets.clone(),
+            target_groups: self.target_groups.clone(),
+            functions: self.functions.node_weights().cloned().collect(),
+            calls: self
+                .calls_with_id()
+                .map(|(caller, callee, edge)| (caller, callee, edge.clone()))
+                .collect(),
+            cleared: self.cleared.clone(),
+            introductions: self.introductions.clone(),
+            fixes: self.fixes.clone(),
+            blames: self.blames.clone(),
+            range_versions: self.range_versions.clone(),
+            invocations: self.invocations.clone(),
+        };
+        let content = serde_json::to_vec(&file).context("序列化影响图失败")?;
+        store::write_atomic(path, &content).await?;
+        info!(
+            "影响图已保存到 {}: {} 个 crate, {} 个函数",
+            path.display(),
+            self.crate_count(),
+            self.function_count()
+        );
+        Ok(())
+    }
+
+    /// read a graph written by `Graph::save`
+    pub async fn load(path: &Path) -> Result<Graph> {
+        let content = tokio::fs::read(path)
+            .await
+            .context(format!("读取影响图失败: {}", path.display()))?;
+        let file: GraphFile = serde_json::from_slice(&content)
+            .context(format!("解析影响图失败: {}", path.display()))?;
+        if file.format_version != GRAPH_FORMAT_VERSION {
+            bail!(
+                "{} 的格式版本 {} 不受支持，应为 {}",
+                path.display(),
+                file.format_version,
+                GRAPH_FORMAT_VERSION
+            );
+        }
+
+        let mut graph = Graph::new();
+        let crate_ids = file
+            .crates
+            .iter()
+            .map(|node| graph.merge_crate(node))
+            .collect::<Vec<_>>();
+        for (parent, dependent, edge) in file.dependencies {
+            let (Some(parent), Some(dependent)) = (crate_ids.get(parent), crate_ids.get(dependent))
+            else {
+                bail!("{} 中的依赖边指向不存在的 crate", path.display());
+            };
+            graph.merge_dependency(*parent, *dependent, edge);
+        }
+        let function_ids = file
+            .functions
+            .iter()
+            .map(|node| graph.merge_function(node))
+            .collect::<Vec<_>>();
+        for (caller, callee, edge) in file.calls {
+            let (Some(caller), Some(callee)) = (function_ids.get(caller), function_ids.get(callee))
+            else {
+                bail!("{} 中的调用边指向不存在的函数", path.display());
+            };
+            graph.merge_call(*caller, *callee, edge);
+        }
+        graph.advisories = file.advisories;
+        graph.targets = file.targets;
+        graph.target_groups = file.target_groups;
+        graph.cleared = file.cleared;
+        graph.introductions = file.introductions;
+        graph.fixes = file.fixes;
+        graph.blames = file.blames;
+        graph.range_versions = file.range_versions;
+        graph.invocations = file.invocations;
+        Ok(graph)
+    }
+
+    /// add the nodes, edges and findings of `other`, e.g. the partial results of
+    /// another run or machine
+    ///
+    /// crate versions and functions present in both are kept once, with the shallowest
+    /// depth and the attributes known to either side; findings already present are not
+    /// duplicated
+    pub fn merge(&mut self, other: Graph) {
+        let crate_ids = other
+            .crates
+            .node_weights()
+            .map(|node| self.merge_crate(node))
+            .collect::<Vec<_>>();
+        for edge in other.crates.edge_references() {
+            let (parent, dependent) = (
+                crate_ids[edge.source().index()],
+                crate_ids[edge.target().index()],
+            );
+            self.merge_dependency(parent, dependent, edge.weight().clone());
+        }
+        let function_ids = other
+            .functions
+            .node_weights()
+            .map(|node| self.merge_function(node))
+            .collect::<Vec<_>>();
+        for edge in other.functions.edge_references() {
+            let (caller, callee) = (
+                function_ids[edge.source().index()],
+                function_ids[edge.target().index()],
+            );
+            self.merge_call(caller, callee, edge.weight().clone());
+        }
+
+        self.advisories.extend(other.advisories);
+        self.targets.extend(other.targets);
+        for (group, functions) in other.target_groups {
+            self.target_groups
+                .entry(group)
+                .or_default()
+                .extend(functions);
+        }
+        for (name, versions) in other.range_versions {
+            let mut merged = self.range_versions.remove(&name).unwrap_or_default();
+            merged.extend(versions);
+            merged.sort_unstable();
+            merged.dedup();
+            self.set_range_versions(&name, merged);
+        }
+        let mut seen = self.cleared.iter().map(cleared_key).collect::<HashSet<_>>();
+        self.cleared.extend(
+            other
+                .cleared
+                .into_iter()
+                .filter(|cleared| seen.insert(cleared_key(cleared))),
+        );
+        let mut seen = self
+            .introductions
+            .iter()
+            .map(introduction_key)
+            .collect::<HashSet<_>>();
+        self.introductions.extend(
+            other
+                .introductions
+                .into_iter()
+                .filter(|introduction| seen.insert(introduction_key(introduction))),
+        );
+        let mut seen = self
+            .fixes
+            .iter()
+            .map(|fix| {
+                (
+                    normalize_crate_name(&fix.parent_name),
+                    normalize_crate_name(&fix.name),
+                )
+            })
+            .collect::<HashSet<_>>();
+        self.fixes.extend(other.fixes.into_iter().filter(|fix| {
+            seen.insert((
+                normalize_crate_name(&fix.parent_name),
+                normalize_crate_name(&fix.name),
+            ))
+        }));
+        let mut seen = self.blames.iter().map(blame_key).collect::<HashSet<_>>();
+        self.blames.extend(
+            other
+                .blames
+                .into_iter()
+                .filter(|blame| seen.insert(blame_key(blame))),
+        );
+        self.invocations.extend(other.invocations);
+    }
+
+    /// add a crate node of another graph, filling in what this graph doesn't know yet
+    fn merge_crate(&mut self, node: &CrateNode) -> NodeIndex {
+        let idx = self.add_crate(&node.name, &node.version, node.depth);
+        let merged = &mut self.crates[idx];
+        merged.yanked |= node.yanked;
+        if merged.repository.is_none() {
+            merged.repository = node.repository.clone();
+        }
+        if merged.range.is_none() {
+            merged.range = node.range.clone();
+        }
+        merged.downloads = merged.downloads.or(node.downloads);
+        idx
+    }
+
+    /// add a dependency of another graph or of another run of the same node; when the
+    /// edge exists already, the per-function results are united since partial runs and
+    /// follow-up runs may have analyzed different targets
+    ///
+    /// a count both graphs have for the same key keeps the larger one, the same target
+    /// analyzed twice finds the same callers
+    fn merge_dependency(&mut self, parent: NodeIndex, dependent: NodeIndex, edge: DependencyEdge) {
+        let Some(idx) = self.crates.find_edge(parent, dependent) else {
+            self.crates.add_edge(parent, dependent, edge);
+            return;
+        };
+        let merged = &mut self.crates[idx];
+        for (function_path, callers) in edge.functions {
+            let count = merged.functions.entry(function_path).or_default();
+            *count = (*count).max(callers);
+        }
+        for (target, paths) in edge.reexports {
+            merged.reexports.entry(target).or_default().extend(paths);
+        }
+        for (call_site, callers) in edge.excluded {
+            let count = merged.excluded.entry(call_site).or_default();
+            *count = (*count).max(callers);
+        }
+        // 任一次分析在某个配置下调用了目标，这个配置就受影响
+        for (feature_set, affected) in edge.feature_verdicts {
+            *merged.feature_verdicts.entry(feature_set).or_default() |= affected;
+        }
+        merged.platforms.extend(edge.platforms);
+        merged.platforms.sort_unstable();
+        merged.platforms.dedup();
+    }
+
+    /// add a function node of another graph, a classification or location this graph
+    /// lacks is taken over
+    fn merge_function(&mut self, node: &FunctionNode) -> NodeIndex {
+        let idx = self.add_function(&node.crate_name, &node.version, &node.path);
+        let merged = &mut self.functions[idx];
+        if merged.exposure == ApiExposure::Unknown {
+            merged.exposure = node.exposure;
+            merged.call_site = node.call_site;
+        }
+        if merged.file.is_none() {
+            merged.file = node.file.clone();
+            merged.line = node.line;
+        }
+        idx
+    }
+
+    fn merge_call(&mut self, caller: NodeIndex, callee: NodeIndex, edge: CallEdge) {
+        if self.functions.find_edge(caller, callee).is_none() {
+            self.functions.add_edge(caller, callee, edge);
+        }
+    }
+
+    /// print the graph to stdout, one edge per line
+    pub fn print_graph(&self) {
+        println!(
+            "impact graph: {} crates, {} dependencies, {} functions, {} calls",
+            self.crate_count(),
+            self.dependency_count(),
+            self.function_count(),
+            self.call_count()
+        );
+        for node in self.crates().filter(|node| node.depth == 0) {
+            print!("[root] {} {}{}", node.name, node.version, yanked_mark(node));
+            match &node.range {
+                Some(range) => println!(" (range: {})", range),
+                None => println!(),
+            }
+        }
+        // 只有一个版本区间时每条路径都来自它，不必逐条标注
+        let multiple_ranges = self
+            .crates()
+            .filter_map(|node| node.range.as_ref())
+            .collect::<BTreeSet<_>>()
+            .len()
+            > 1;
+        for (parent, dependent, edge) in self.dependencies() {
+            println!(
+                "{} {} -> {} {}{} (req: {}, depth: {})",
+                parent.name,
+                parent.version,
+                dependent.name,
+                dependent.version,
+                yanked_mark(dependent),
+                edge.req,
+                dependent.depth
+            );
+            if let Some(resolved_version) = &edge.resolved_version {
+                println!("    resolves to {} {}", parent.name, resolved_version);
+            }
+            if let Some(resolution) = &edge.resolution {
+                let policies = resolution.policies();
+                if policies.is_empty() {
+                    println!(
+                        "    no resolution policy picks {} {}, only reached by pinning",
+                        parent.name, parent.version
+                    );
+                } else {
+                    println!("    picked under: {}", policies.join(", "));
+                }
+            }
+            if !edge.kinds.is_empty() {
+                println!("    declared in: {}", format_kinds(&edge.kinds));
+            }
+            if !edge.required_features.is_empty() {
+                println!(
+                    "    only with feature: {}",
+                    edge.required_features.join(" | ")
+                );
+            }
+            if edge.source != DependentSource::Dump {
+                println!("    found via {}", edge.source.as_str());
+            }
+            if multiple_ranges {
+                let ranges = self.ranges_of(&dependent.name, &dependent.version);
+                println!(
+                    "    from range: {}",
+                    ranges.into_iter().collect::<Vec<_>>().join(" | ")
+                );
+            }
+            for (function_path, count) in &edge.functions {
+                println!("    calls {} ({} callers)", function_path, count);
+            }
+            let (exposure, sites) = self.caller_breakdown(parent, dependent);
+            if !exposure.is_empty() {
+                println!("    callers by API exposure: {}", format_counts(&exposure));
+                println!("    callers by call site: {}", format_counts(&sites));
+            }
+            if !edge.excluded.is_empty() {
+                let excluded = edge
+                    .excluded
+                    .iter()
+                    .map(|(site, count)| (site.as_str(), *count))
+                    .collect();
+                println!("    excluded callers: {}", format_counts(&excluded));
+            }
+            if !edge.feature_verdicts.is_empty() {
+                let verdicts = edge
+                    .feature_verdicts
+                    .iter()
+                    .map(|(feature_set, affected)| {
+                        let verdict = if *affected {
+                            "affected"
+                        } else {
+                            "not affected"
+                        };
+                        format!("{} {}", feature_set, verdict)
+                    })
+                    .collect::<Vec<_>>();
+                println!("    by feature set: {}", verdicts.join(", "));
+            }
+            if !edge.platforms.is_empty() {
+                println!("    on platforms: {}", edge.platforms.join(", "));
+            }
+            for (function_path, exposed_paths) in &edge.reexports {
+                for exposed_path in exposed_paths {
+                    println!("    re-exports {} as {}", function_path, exposed_path);
+                }
+            }
+        }
+
+        for matrix in self.version_matrices() {
+            println!(
+                "[matrix] {} -> {}: {} of {} analyzed versions affected, {} {} versions compared",
+                matrix.parent,
+                matrix.dependent,
+                matrix.rows.iter().filter(|row| row.is_affected()).count(),
+                matrix.rows.len(),
+                matrix.parent_versions.len(),
+                matrix.parent
+            );
+            for row in &matrix.rows {
+                let (affected, cleared): (Vec<_>, Vec<_>) =
+                    row.analyzed.iter().partition(|(_, affected)| **affected);
+                let affected = affected
+                    .into_iter()
+                    .map(|(version, _)| version.as_str())
+                    .collect::<Vec<_>>();
+                let cleared = cleared
+                    .into_iter()
+                    .map(|(version, _)| version.as_str())
+                    .collect::<Vec<_>>();
+                print!(
+                    "    {} {} (req: {}) admits {}",
+                    matrix.dependent,
+                    row.version,
+                    row.req,
+                    summarize_versions(&matrix.parent_versions, &row.admitted)
+                );
+                if !affected.is_empty() {
+                    print!("; affected with {}", affected.join(", "));
+                }
+                if !cleared.is_empty() {
+                    print!("; not affected with {}", cleared.join(", "));
+                }
+                println!();
+            }
+        }
+
+        for introduction in self.introductions() {
+            print!(
+                "[introduced] {} {} against {} {}",
+                introduction.name,
+                introduction.first_affected,
+                introduction.parent_name,
+                introduction.parent_version
+            );
+            match &introduction.last_clear {
+                Some(last_clear) => print!(", {} does not call a target", last_clear),
+                None => print!(", no older version admitting the parent shown clear"),
+            }
+            println!(" ({} versions bisected)", introduction.checked);
+        }
+
+        for fix in self.fixes() {
+            let advice = match &fix.remedy {
+                Remedy::AdmitsFixedParent {
+                    version,
+                    parent_version,
+                } => format!(
+                    "upgrade to >= {}, it admits {} {} outside the vulnerable ranges",
+                    version, fix.parent_name, parent_version
+                ),
+                Remedy::DropsDependency { version } => format!(
+                    "upgrade to >= {}, it no longer depends on {}",
+                    version, fix.parent_name
+                ),
+                Remedy::CallRemoved {
+                    version,
+                    parent_version,
+                } => format!(
+                    "upgrade to {}, it no longer calls a target (checked against {} {})",
+                    version, fix.parent_name, parent_version
+                ),
+                Remedy::StillAffected { version } => {
+                    format!("no fix, the newest version {} is still affected", version)
+                }
+                Remedy::NoNewerVersion => "no fix, no newer version published".to_string(),
+                Remedy::Unknown { reason } => format!("unknown, {}", reason),
+            };
+            println!(
+                "[fix] {} {} via {}: {}",
+                fix.name, fix.affected_version, fix.parent_name, advice
+            );
+        }
+
+        for blame in self.blames() {
+            println!(
+                "[blame] {} {}: {} at {}:{} introduced in {} ({}, {}) \"{}\"{}",
+                blame.name,
+                blame.version,
+                blame.function,
+                blame.file,
+                blame.line,
+                &blame.blamed.commit[..blame.blamed.commit.len().min(12)],
+                blame.blamed.author,
+                blame.blamed.date,
+                blame.blamed.summary,
+                blame
+                    .blamed
+                    .pull_request
+                    .as_ref()
+                    .map(|pull_request| format!(" {}", pull_request))
+                    .unwrap_or_default()
+            );
+        }
+
+        for (repository, crates) in self.repository_rollup() {
+            let names = crates
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<BTreeSet<_>>();
+            println!(
+                "[repository] {}: {} affected crate versions ({})",
+                repository,
+                crates.len(),
+                names.into_iter().collect::<Vec<_>>().join(", ")
+            );
+        }
+
+        for cleared in self.cleared() {
+            println!(
+                "[cleared] {} {} against {} {}: {} (precheck: {})",
+                cleared.name,
+                cleared.version,
+                cleared.parent_name,
+                cleared.parent_version,
+                cleared.clearance.reason.as_str(),
+                cleared.clearance.precheck.as_str()
+            );
+            if let Some(resolved_version) = &cleared.clearance.resolved_version {
+                println!(
+                    "    resolves to {} {}",
+                    cleared.parent_name, resolved_version
+                );
+            }
+            if !cleared.clearance.untrusted_build.is_empty() {
+                println!(
+                    "    not built, would run: {}",
+                    cleared.clearance.untrusted_build.join(", ")
+                );
+            }
+            if let Some(evidence_path) = &cleared.clearance.evidence_path {
+                println!("    evidence: {}", evidence_path);
+            }
+        }
+
+        let summary = self.function_summary();
+        for (group, functions) in &self.target_groups {
+            println!("[target] {}", group);
+            for function_path in functions {
+                let (dependents, callers) = summary.get(function_path).copied().unwrap_or_default();
+                if dependents > 0 {
+                    println!(
+                        "    {}: {} dependents, {} callers",
+                        function_path, dependents, callers
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// `2 public, 1 private`
+fn format_counts(counts: &BTreeMap<&str, usize>) -> String {
+    counts
+        .iter()
+        .map(|(category, count)| format!("{} {}", count, category))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `normal, dev`
+pub fn format_kinds(kinds: &[DependencyKind]) -> String {
+    kinds
+        .iter()
+        .map(DependencyKind::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// sort version numbers by semver, unparsable ones first
+fn sort_versions(versions: &mut [String]) {
+    versions.sort_by_key(|version| semver::Version::parse(version).ok());
+}
+
+/// `picked` as runs of consecutive versions of `all`, e.g. `1.0.0..=1.2.3, 1.4.0`
+pub fn summarize_versions(all: &[String], picked: &[String]) -> String {
+    if picked.is_empty() {
+        return "none".to_string();
+    }
+    if picked.len() == all.len() {
+        return format!("all {}", all.len());
+    }
+    let mut runs: Vec<(&str, &str)> = Vec::new();
+    let mut previous_picked = false;
+    for version in all {
+        let is_picked = picked.contains(version);
+        if is_picked {
+            match runs.last_mut() {
+                Some((_, end)) if previous_picked => *end = version,
+                _ => runs.push((version, version)),
+            }
+        }
+        previous_picked = is_picked;
+    }
+    runs.iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}..={}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn cleared_key(cleared: &ClearedDependent) -> (String, String, String, String) {
+    (
+        normalize_crate_name(&cleared.parent_name),
+        cleared.parent_version.clone(),
+        normalize_crate_name(&cleared.name),
+        cleared.version.clone(),
+    )
+}
+
+fn introduction_key(introduction: &Introduction) -> (String, String, String) {
+    (
+        normalize_crate_name(&introduction.parent_name),
+        introduction.parent_version.clone(),
+        normalize_crate_name(&introduction.name),
+    )
+}
+
+fn blame_key(blame: &Blame) -> (String, String, String, String, u64) {
+    (
+        normalize_crate_name(&blame.name),
+        blame.version.clone(),
+        blame.function.clone(),
+        blame.file.clone(),
+        blame.line,
+    )
+}
+
+fn yanked_mark(node: &CrateNode) -> &'static str {
+    if node.yanked {
+        " [yanked]"
+    } else {
+        ""
+    }
+}
+
+/// canonical form of a repository url, so that `https://github.com/tokio-rs/tokio.git`
+/// and `https://github.com/tokio-rs/tokio/tree/master/tokio-util` are the same repository
+fn normalize_repository(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/').to_ascii_lowercase();
+    let url = url.strip_suffix(".git").unwrap_or(&url);
+    // links into a subdirectory of a GitHub/GitLab repository
+    let url = ["/tree/", "/blob/", "/-/tree/"]
+        .iter()
+        .find_map(|marker| url.find(marker).map(|idx| &url[..idx]))
+        .unwrap_or(url);
+    url.replacen("http://", "https://", 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{FunctionCalls, ReverseDependency};
+
+    /// a run over one target of `vuln` finding `app` calling it
+    fn partial_run(target: &str, features: &[(&str, bool)], platform: &str) -> Graph {
+        let mut graph = Graph::new();
+        graph.add_crate("vuln", "1.0.0", 0);
+        graph.add_target(target);
+        let mut calls = FunctionCalls::default();
+        calls.insert(target, r#"[{"path": "app::run"}, {"path": "app::start"}]"#);
+        for (feature_set, affected) in features {
+            calls.set_feature_verdict(feature_set, *affected);
+        }
+        calls.set_platform_verdict(platform, true);
+        graph.add_dependency(
+            ("vuln", "1.0.0"),
+            ("app", "0.1.0"),
+            1,
+            &ReverseDependency::new("app".into(), "0.1.0".into(), "^1".into()),
+            &calls,
+        );
+        graph.add_call(("app", "0.1.0", "app::run"), ("vuln", "1.0.0", target), 1);
+        graph
+    }
+
+    #[tokio::test]
+    async fn partial_runs_merge_after_a_round_trip() {
+        let dir = std::env::temp_dir().join(format!("cross_pro_cg-merge-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let runs = [
+            partial_run(
+                "vuln::decode",
+                &[("default", false), ("tls", true)],
+                "x86_64-unknown-linux-gnu",
+            ),
+            partial_run(
+                "vuln::encode",
+                &[("default", true)],
+                "x86_64-pc-windows-msvc",
+            ),
+        ];
+        let mut loaded = Vec::new();
+        for (i, run) in runs.iter().enumerate() {
+            let path = dir.join(format!("run{}.json", i));
+            run.save(&path).await.unwrap();
+            loaded.push(Graph::load(&path).await.unwrap());
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut merged = loaded.remove(0);
+        merged.merge(loaded.remove(0));
+        assert_eq!(merged.crate_count(), 2);
+        assert_eq!(merged.dependency_count(), 1);
+        assert_eq!(merged.call_count(), 2);
+        let (_, _, edge) = merged.dependencies().next().unwrap();
+        assert_eq!(
+            edge.functions,
+            BTreeMap::from([
+                ("vuln::decode".to_owned(), 2),
+                ("vuln::encode".to_owned(), 2)
+            ])
+        );
+        assert_eq!(
+            edge.feature_verdicts,
+            BTreeMap::from([("default".to_owned(), true), ("tls".to_owned(), true)])
+        );
+        assert_eq!(
+            edge.platforms,
+            ["x86_64-pc-windows-msvc", "x86_64-unknown-linux-gnu"]
+        );
+    }
+}
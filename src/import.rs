@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use flate2::read::GzDecoder;
+use sqlx::{Connection, SqliteConnection};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::store;
+
+// rows per multi-row INSERT, below SQLite's limit of bind parameters per statement
+const INSERT_BATCH: usize = 1000;
+// log the progress of a table every this many rows
+const PROGRESS_INTERVAL: usize = 1_000_000;
+
+/// options of the `import-dump` subcommand
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// the crates.io database dump, `db-dump.tar.gz` from https://static.crates.io/db-dump.tar.gz
+    pub dump: PathBuf,
+    /// the SQLite file to create, defaults to `DATABASE_URL` when it names an SQLite
+    /// database, else `crates_meta.db`
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// a table of the metadata database filled from one CSV file of the dump
+struct Table {
+    file: &'static str,
+    name: &'static str,
+    // the columns kept, named as in the CSV header
+    columns: &'static [&'static str],
+    schema: &'static str,
+}
+
+// only what `Database` queries, the dump has many more tables and columns
+const TABLES: &[Table] = &[
+    Table {
+        file: "crates.csv",
+        name: "crates",
+        columns: &["id", "name", "repository"],
+        schema: "CREATE TABLE crates (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            repository TEXT
+        )",
+    },
+    Table {
+        file: "versions.csv",
+        name: "versions",
+        columns: &["id", "crate_id", "num", "created_at", "yanked", "downloads"],
+        schema: "CREATE TABLE versions (
+            id INTEGER PRIMARY KEY,
+            crate_id INTEGER NOT NULL,
+            num TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            yanked INTEGER NOT NULL,
+            downloads INTEGER NOT NULL
+        )",
+    },
+    Table {
+        file: "dependencies.csv",
+        name: "dependencies",
+        columns: &["version_id", "crate_id", "req"],
+        schema: "CREATE TABLE dependencies (
+            version_id INTEGER NOT NULL,
+            crate_id INTEGER NOT NULL,
+            req TEXT NOT NULL
+        )",
+    },
+];
+
+// created after loading, building them row by row would be much slower
+const INDEXES: &[&str] = &[
+    "CREATE INDEX crates_name ON crates (name)",
+    "CREATE INDEX versions_crate_id ON versions (crate_id)",
+    "CREATE INDEX dependencies_crate_id ON dependencies (crate_id)",
+    "CREATE INDEX dependencies_version_id ON dependencies (version_id)",
+];
+
+/// rows of one table read from the archive
+struct Batch {
+    table: usize,
+    rows: Vec<Vec<String>>,
+}
+
+/// build an SQLite metadata database from the crates.io dump, usable as `DATABASE_URL`
+///
+/// the archive is read in a blocking task and streamed into the database in batches,
+/// the file only replaces `output` once it is complete
+pub async fn run(args: &ImportArgs) -> Result<()> {
+    let output = args.output.clone().unwrap_or_else(default_output);
+    let staging = store::temp_path(&output);
+    store::create_parent(&output).await?;
+    info!(
+        "从 {} 导入元数据到 {}",
+        args.dump.display(),
+        output.display()
+    );
+
+    let result = import(&args.dump, &staging).await;
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&staging).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&staging, &output)
+        .await
+        .context(format!("移动 {} 失败", output.display()))?;
+    info!("元数据库已生成: {}", output.display());
+    Ok(())
+}
+
+async fn import(dump: &Path, staging: &Path) -> Result<()> {
+    let url = format!("sqlite://{}?mode=rwc", staging.display());
+    let mut connection = SqliteConnection::connect(&url)
+        .await
+        .context(format!("无法创建 {}", staging.display()))?;
+    // 导入失败时整个文件都会丢弃，不需要日志和同步
+    for pragma in ["PRAGMA journal_mode = OFF", "PRAGMA synchronous = OFF"] {
+        sqlx::query(pragma).execute(&mut connection).await?;
+    }
+    let mut transaction = connection.begin().await?;
+    for table in TABLES {
+        sqlx::query(table.schema).execute(&mut *transaction).await?;
+    }
+
+    let (sender, mut receiver) = mpsc::channel(4);
+    let dump = dump.to_path_buf();
+    let reader = tokio::task::spawn_blocking(move || read_dump(&dump, sender));
+
+    let mut counts = vec![0usize; TABLES.len()];
+    let mut statements = HashMap::new();
+    while let Some(batch) = receiver.recv().await {
+        let table = &TABLES[batch.table];
+        let statement = statements
+            .entry((batch.table, batch.rows.len()))
+            .or_insert_with(|| insert_statement(table, batch.rows.len()));
+        let mut query = sqlx::query(statement.as_str());
+        for value in batch.rows.iter().flatten() {
+            query = query.bind(value.as_str());
+        }
+        query
+            .execute(&mut *transaction)
+            .await
+            .context(format!("写入 {} 失败", table.name))?;
+        let before = counts[batch.table];
+        counts[batch.table] += batch.rows.len();
+        if before / PROGRESS_INTERVAL != counts[batch.table] / PROGRESS_INTERVAL {
+            info!("{}: 已导入 {} 行", table.name, counts[batch.table]);
+        }
+    }
+    reader.await.context("读取数据库快照的任务异常退出")??;
+
+    info!("创建索引");
+    for index in INDEXES {
+        sqlx::query(index).execute(&mut *transaction).await?;
+    }
+    transaction.commit().await?;
+    connection.close().await?;
+    for (table, count) in TABLES.iter().zip(counts) {
+        info!("{}: 共 {} 行", table.name, count);
+    }
+    Ok(())
+}
+
+/// stream the CSV files the tables come from out of the archive
+fn read_dump(dump: &Path, sender: mpsc::Sender<Batch>) -> Result<()> {
+    let file = File::open(dump).context(format!("无法打开 {}", dump.display()))?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+    let mut found = vec![false; TABLES.len()];
+    for entry in archive.entries().context("读取数据库快照失败")? {
+        let entry = entry.context("读取数据库快照失败")?;
+        // 文件位于 `<日期>/data/` 下
+        let path = entry.path()?.into_owned();
+        let Some(table) = TABLES.iter().position(|table| {
+            path.parent().and_then(Path::file_name) == Some("data".as_ref())
+                && path.file_name() == Some(table.file.as_ref())
+        }) else {
+            continue;
+        };
+        info!("读取 {}", path.display());
+        found[table] = true;
+        read_table(table, entry, &sender).context(format!("解析 {} 失败", path.display()))?;
+    }
+    let missing = TABLES
+        .iter()
+        .zip(&found)
+        .filter(|(_, found)| !**found)
+        .map(|(table, _)| table.file)
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        bail!("{} 中缺少 {}", dump.display(), missing.join(", "));
+    }
+    Ok(())
+}
+
+fn read_table(table: usize, csv: impl std::io::Read, sender: &mpsc::Sender<Batch>) -> Result<()> {
+    let mut reader = csv::Reader::from_reader(csv);
+    let headers = reader.headers()?.clone();
+    let positions = TABLES[table]
+        .columns
+        .iter()
+        .map(|column| {
+            headers
+                .iter()
+                .position(|header| header == *column)
+                .context(format!("缺少列 {}", column))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut rows = Vec::with_capacity(INSERT_BATCH);
+    for record in reader.records() {
+        let record = record?;
+        rows.push(
+            positions
+                .iter()
+                .zip(TABLES[table].columns)
+                .map(|(&position, &column)| clean(column, &record[position]))
+                .collect(),
+        );
+        if rows.len() == INSERT_BATCH {
+            let batch = Batch {
+                table,
+                rows: std::mem::replace(&mut rows, Vec::with_capacity(INSERT_BATCH)),
+            };
+            if sender.blocking_send(batch).is_err() {
+                // 写入端已经出错退出，错误由它报告
+                return Ok(());
+            }
+        }
+    }
+    if !rows.is_empty() {
+        let _ = sender.blocking_send(Batch { table, rows });
+    }
+    Ok(())
+}
+
+/// timestamps of the dump look like `2015-05-14 19:35:26.153539+00`, SQLite's date
+/// functions only understand them without the time zone; booleans are `t`/`f`
+fn clean(column: &str, value: &str) -> String {
+    match column {
+        "created_at" => value.get(..19).unwrap_or(value).to_string(),
+        "yanked" => if value == "t" { "1" } else { "0" }.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+fn insert_statement(table: &Table, rows: usize) -> String {
+    let width = table.columns.len();
+    let values = (0..rows)
+        .map(|row| {
+            let placeholders = (1..=width)
+                .map(|column| format!("${}", row * width + column))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("({})", placeholders)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        table.name,
+        table.columns.join(", "),
+        values
+    )
+}
+
+fn default_output() -> PathBuf {
+    match std::env::var("DATABASE_URL") {
+        Ok(url) if url.starts_with("sqlite://") => {
+            let path = url.trim_start_matches("sqlite://");
+            PathBuf::from(path.split('?').next().unwrap_or(path))
+        }
+        Ok(url) if !url.contains("://") => PathBuf::from(url),
+        _ => PathBuf::from("crates_meta.db"),
+    }
+}
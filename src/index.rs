@@ -0,0 +1,197 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::cache::MetadataCache;
+use crate::download::CLIENT;
+use crate::limits::LIMITS;
+use crate::model::{normalize_crate_name, DependencyKind};
+use crate::registry::REGISTRY;
+
+// 索引文件按 crate 缓存，校验和、版本列表和依赖要求都从同一份读取
+pub static INDEX: Lazy<SparseIndex> = Lazy::new(SparseIndex::new);
+
+/// where version lists and dependency requirements come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetadataSource {
+    // the metadata database, see `Database`
+    #[default]
+    Database,
+    // the registry's sparse index, no database needed for forward metadata
+    Index,
+}
+
+impl FromStr for MetadataSource {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "database" | "db" => Ok(MetadataSource::Database),
+            "index" | "sparse" => Ok(MetadataSource::Index),
+            _ => bail!("未知的元数据来源: {}", name),
+        }
+    }
+}
+
+/// one line of a registry index file: a published version
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexVersion {
+    pub vers: String,
+    pub cksum: String,
+    #[serde(default)]
+    pub yanked: bool,
+    #[serde(default)]
+    pub deps: Vec<IndexDependency>,
+}
+
+/// a dependency of a published version as recorded in the index
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexDependency {
+    // the name the dependency is declared under, the crate itself when `package` is None
+    pub name: String,
+    pub req: String,
+    // the real crate name of a renamed dependency
+    #[serde(default)]
+    pub package: Option<String>,
+    // `normal`, `build` or `dev`, missing in old entries
+    #[serde(default)]
+    pub kind: Option<String>,
+}
+
+impl IndexDependency {
+    /// the crate depended on, with renames resolved
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+
+    /// the dependency table, entries without a kind are normal dependencies
+    pub fn kind(&self) -> DependencyKind {
+        self.kind
+            .as_deref()
+            .and_then(DependencyKind::parse)
+            .unwrap_or(DependencyKind::Normal)
+    }
+}
+
+/// the registry's sparse HTTP index, the metadata source that needs no database:
+/// version lists, checksums and the dependency requirements of every version
+#[derive(Debug)]
+pub struct SparseIndex {
+    cache: MetadataCache<String, Arc<Vec<IndexVersion>>>,
+}
+
+impl SparseIndex {
+    fn new() -> Self {
+        Self {
+            cache: MetadataCache::new("sparse_index"),
+        }
+    }
+
+    /// every published version of a crate in publishing order, empty when the index
+    /// doesn't know the crate
+    pub async fn versions(&self, name: &str) -> Result<Arc<Vec<IndexVersion>>> {
+        let key = normalize_crate_name(name);
+        if let Some(versions) = self.cache.get(&key) {
+            return Ok(versions);
+        }
+        let url = REGISTRY.index_url(name);
+        LIMITS.throttle(&url).await;
+        let mut request = CLIENT.get(&url);
+        if let Some(token) = &REGISTRY.token {
+            request = request.header(reqwest::header::AUTHORIZATION, token);
+        }
+        let response = request
+            .send()
+            .await
+            .context(format!("请求索引失败: {}", url))?;
+        let versions = if response.status() == reqwest::StatusCode::NOT_FOUND {
+            Vec::new()
+        } else {
+            response
+                .error_for_status()
+                .context(format!("请求索引失败: {}", url))?
+                .text()
+                .await
+                .context(format!("读取索引失败: {}", url))?
+                .lines()
+                .filter_map(|line| serde_json::from_str::<IndexVersion>(line).ok())
+                .collect()
+        };
+        info!("索引中 {} 有 {} 个版本", name, versions.len());
+        let versions = Arc::new(versions);
+        self.cache.put(key, Arc::clone(&versions));
+        Ok(versions)
+    }
+
+    /// version numbers of a crate, newest published first like the database query
+    pub async fn version_list(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .versions(name)
+            .await?
+            .iter()
+            .rev()
+            .map(|version| version.vers.clone())
+            .collect())
+    }
+
+    /// the requirement `name version` declares on `dependency`, None when it doesn't
+    /// depend on it or the index doesn't list the version
+    pub async fn dependency_req(
+        &self,
+        name: &str,
+        version: &str,
+        dependency: &str,
+    ) -> Result<Option<String>> {
+        let dependency = normalize_crate_name(dependency);
+        Ok(self
+            .versions(name)
+            .await?
+            .iter()
+            .find(|entry| entry.vers == version)
+            .and_then(|entry| {
+                entry
+                    .deps
+                    .iter()
+                    .find(|dep| normalize_crate_name(dep.crate_name()) == dependency)
+            })
+            .map(|dep| dep.req.clone()))
+    }
+
+    pub fn log_stats(&self) {
+        self.cache.log_stats();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependency_kinds_default_to_normal() {
+        let line = r#"{"name": "app", "vers": "1.0.0", "cksum": "00", "deps": [
+            {"name": "vuln", "req": "^1", "kind": "dev"},
+            {"name": "vuln", "req": "^1", "kind": "build"},
+            {"name": "vuln", "req": "^1", "kind": null},
+            {"name": "old", "req": "^1"}
+        ]}"#;
+        let version = serde_json::from_str::<IndexVersion>(line).unwrap();
+        let kinds = version
+            .deps
+            .iter()
+            .map(IndexDependency::kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                DependencyKind::Dev,
+                DependencyKind::Build,
+                DependencyKind::Normal,
+                DependencyKind::Normal
+            ]
+        );
+    }
+}
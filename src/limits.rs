@@ -0,0 +1,104 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::config::env_parse;
+
+pub static LIMITS: Lazy<PhaseLimits> = Lazy::new(PhaseLimits::from_env);
+
+/// concurrency limits of the pipeline phases, and request rates towards crates.io
+///
+/// downloads are network bound and builds CPU bound, so each phase gets its own
+/// semaphore and a dependent waiting for a build slot doesn't hold up downloads
+#[derive(Debug)]
+pub struct PhaseLimits {
+    // dependents in flight, from `MAX_CONCURRENT_TASKS` (default 16)
+    pub tasks: usize,
+    // downloading and unpacking crates, from `MAX_CONCURRENT_DOWNLOADS` (default 8)
+    pub downloads: Semaphore,
+    // cargo runs that resolve or compile a dependent (patching, fetching, expanding),
+    // from `MAX_CONCURRENT_BUILDS` (default 4)
+    pub builds: Semaphore,
+    // call-cg4rs runs, from `MAX_CONCURRENT_CALL_CG` (default: half of the cores)
+    pub call_cg: Semaphore,
+    // database queries, also the size of the connection pool, from
+    // `MAX_CONCURRENT_DB_QUERIES` (default 5)
+    pub db_queries: Semaphore,
+    db_connections: u32,
+    // requests to the crates.io web API, from `API_REQUESTS_PER_SEC` (default 1, what the
+    // crawler policy asks for)
+    pub api_requests: RateLimiter,
+    // requests to the static.crates.io CDN and the sparse index, from
+    // `CDN_REQUESTS_PER_SEC` (default 20)
+    pub cdn_requests: RateLimiter,
+}
+
+impl PhaseLimits {
+    fn from_env() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let limit = |key: &str, default: usize| env_parse(key, default).max(1);
+        let db_queries = limit("MAX_CONCURRENT_DB_QUERIES", 5);
+        Self {
+            tasks: limit("MAX_CONCURRENT_TASKS", 16),
+            downloads: Semaphore::new(limit("MAX_CONCURRENT_DOWNLOADS", 8)),
+            builds: Semaphore::new(limit("MAX_CONCURRENT_BUILDS", 4)),
+            call_cg: Semaphore::new(limit("MAX_CONCURRENT_CALL_CG", (cores / 2).max(1))),
+            db_queries: Semaphore::new(db_queries),
+            db_connections: db_queries as u32,
+            api_requests: RateLimiter::per_sec(env_parse("API_REQUESTS_PER_SEC", 1.0)),
+            cdn_requests: RateLimiter::per_sec(env_parse("CDN_REQUESTS_PER_SEC", 20.0)),
+        }
+    }
+
+    pub fn db_connections(&self) -> u32 {
+        self.db_connections
+    }
+
+    /// wait for a request slot if `url` goes to crates.io, mirrors and private registries
+    /// aren't limited
+    pub async fn throttle(&self, url: &str) {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned));
+        match host.as_deref() {
+            Some("crates.io") => self.api_requests.wait().await,
+            Some(host) if host.ends_with(".crates.io") => self.cdn_requests.wait().await,
+            _ => {}
+        }
+    }
+}
+
+/// spaces requests out evenly so all tasks together stay below a rate
+#[derive(Debug)]
+pub struct RateLimiter {
+    // None when unlimited (a rate of 0)
+    interval: Option<Duration>,
+    // the earliest time the next request may start
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn per_sec(rate: f64) -> Self {
+        Self {
+            interval: (rate > 0.0).then(|| Duration::from_secs_f64(1.0 / rate)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// reserve the next free slot and sleep until it
+    pub async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
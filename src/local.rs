@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ignore::WalkBuilder;
+use tokio::fs as tokio_fs;
+use tracing::info;
+
+use crate::git::root_package;
+use crate::model::{DependentSource, Krate, ReverseDependency};
+
+/// copy a local (unpublished) project to where `Krate` keeps extracted crates so it is
+/// analyzed like a downloaded dependent
+///
+/// the analysis rewrites Cargo.lock, builds and runs `cargo clean`, so it works on a
+/// copy (without `.git`, `target` and ignored files) refreshed on every run
+pub async fn prepare_local_project(project_dir: &Path) -> Result<ReverseDependency> {
+    let project_dir = project_dir
+        .canonicalize()
+        .context(format!("无法定位本地项目 {}", project_dir.display()))?;
+    let (name, version) = root_package(&project_dir).await?;
+    let version = format!("{}+local", version);
+
+    let copy_dir = Krate::new(&name, &version).get_extract_dir_path();
+    if copy_dir.exists() {
+        tokio_fs::remove_dir_all(&copy_dir)
+            .await
+            .context(format!("删除旧的副本 {} 失败", copy_dir.display()))?;
+    }
+    let source = project_dir.clone();
+    let destination = copy_dir.clone();
+    let copied = tokio::task::spawn_blocking(move || copy_project(&source, &destination)).await??;
+    info!(
+        "本地项目 {} {} 已复制到 {}（{} 个文件）",
+        name,
+        version,
+        copy_dir.display(),
+        copied
+    );
+    Ok(ReverseDependency::new(name, version, "*".to_string()).with_source(DependentSource::Local))
+}
+
+/// copy the files of a project that git would track, returns the number of files
+fn copy_project(source: &Path, destination: &Path) -> Result<usize> {
+    let mut copied = 0;
+    let walker = WalkBuilder::new(source)
+        .hidden(false)
+        .filter_entry(|entry| {
+            let name = entry.file_name();
+            name != ".git" && name != "target"
+        })
+        .build();
+    for entry in walker {
+        let entry = entry?;
+        let relative = entry.path().strip_prefix(source)?;
+        let target = destination.join(relative);
+        if entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir())
+        {
+            std::fs::create_dir_all(&target)
+                .context(format!("创建目录 {} 失败", target.display()))?;
+        } else {
+            std::fs::copy(entry.path(), &target)
+                .context(format!("复制 {} 失败", entry.path().display()))?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
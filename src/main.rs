@@ -1,17 +1,62 @@
+mod audit;
+mod build_env;
+mod cache;
+mod callers;
+mod checkpoint;
+mod cli;
+mod config;
+mod crates_io;
 mod database;
 mod dependency_analyzer;
+mod determinism;
+mod diagnostics;
+mod download;
+mod export;
+mod failure;
+mod gc;
+mod git;
+mod limits;
+mod graph;
+mod import;
+mod index;
+mod local;
 mod logger;
+mod merge;
 mod model;
+mod osv;
+mod precheck;
+mod reexport;
+mod resolution;
+mod registry;
+mod report;
+mod sbom;
+mod scheduler;
+mod store;
+mod surface;
+mod target;
+mod toolchain;
+mod watchdog;
 
+use clap::Parser;
+use cli::{Cli, Command};
+use config::AnalyzerConfig;
 use dependency_analyzer::DependencyAnalyzer;
+use graph::Graph;
+use model::AnalysisRoot;
 use std::fs;
 use std::path::Path;
 
 #[tokio::main]
 async fn main() {
-    let crate_name = "crossbeam-channel";
-    let version_range = ">0.5.11, <0.5.15";
-    let target_function_path = "crossbeam_channel::flavors::list::Channel::drop";
+    let cli = Cli::parse();
+    // 同一漏洞可能涉及多个 crate，所有根节点共享一次 BFS
+    let roots = vec![AnalysisRoot::new("crossbeam-channel", ">0.5.11, <0.5.15")];
+    // 公告中可能列出多个漏洞函数，调用其中任意一个即视为受影响
+    // 以 `::*` 结尾的路径表示该模块下的所有函数
+    // 以 `::Type::*` 结尾（类型名大写开头）的路径表示该类型的所有方法
+    // trait 方法可以写成 `<crossbeam_channel::flavors::list::Channel<T> as Drop>::drop`
+    let target_function_paths =
+        vec!["crossbeam_channel::flavors::list::Channel::drop".to_string()];
     let log_file_path = Path::new("logs/cross_pro_cg.log");
 
     dotenv::dotenv().ok();
@@ -20,12 +65,189 @@ async fn main() {
         fs::remove_file(log_file_path).expect("无法删除旧日志文件");
     }
 
+    if let Some(Command::Gc(args)) = &cli.command {
+        let config = AnalyzerConfig::from_env();
+        if let Err(e) = gc::run(args, &config.checkpoint_dir) {
+            tracing::error!("清理下载目录失败: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::ImportDump(args)) = &cli.command {
+        if let Err(e) = import::run(args).await {
+            tracing::error!("导入数据库快照失败: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::MergeGraphs(args)) = &cli.command {
+        let graph = match merge::run(args).await {
+            Ok(graph) => graph,
+            Err(e) => {
+                tracing::error!("合并影响图失败: {:#}", e);
+                std::process::exit(1);
+            }
+        };
+        graph.print_graph();
+        export_graph(&graph, &AnalyzerConfig::from_env()).await;
+        return;
+    }
+
+    if let Some(Command::Report(args)) = &cli.command {
+        let config = AnalyzerConfig::from_env();
+        if let Err(e) = report::run(args, &config.chain_filter).await {
+            tracing::error!("生成报告失败: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Audit(args)) = &cli.command {
+        match audit::run(args).await {
+            Ok(0) => {}
+            Ok(_) => std::process::exit(1),
+            Err(e) => {
+                tracing::error!("标注 cargo audit 报告失败: {:#}", e);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Sbom(args)) = &cli.command {
+        if let Err(e) = sbom::run(args).await {
+            tracing::error!("SBOM 比对失败: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     tracing::info!("开始分析依赖关系");
-    let analyzer = DependencyAnalyzer::new().await.unwrap();
-    analyzer
-        .analyze(crate_name, version_range, target_function_path)
-        .await
-        .unwrap();
+    if let Err(e) = model::migrate_download_layout().await {
+        tracing::warn!("迁移下载目录布局失败: {:#}", e);
+    }
+    let mut config = AnalyzerConfig::from_env();
+    config.platforms = cli.targets.clone();
+    if let Some(sandbox) = &cli.sandbox {
+        config.build_env.sandbox = sandbox.clone();
+    }
+    let mut analyzer = DependencyAnalyzer::new(config.clone()).await.unwrap();
+    if let Some(path) = &cli.dependents_file {
+        let dependents = match cli::read_dependents_file(path) {
+            Ok(dependents) => dependents,
+            Err(e) => {
+                tracing::error!("{:#}", e);
+                std::process::exit(1);
+            }
+        };
+        tracing::info!("只分析 {} 中列出的 {} 个依赖者", path.display(), dependents.len());
+        analyzer = analyzer.with_dependents(dependents);
+    }
+    let mut extra_dependents = Vec::new();
+    for spec in &cli.git_dependents {
+        let checkout = async { git::GitKrate::parse(spec)?.checkout().await };
+        match checkout.await {
+            Ok(dependent) => extra_dependents.push(dependent),
+            Err(e) => tracing::warn!("跳过 git 依赖者 {}: {:#}", spec, e),
+        }
+    }
+    for path in &cli.local_projects {
+        match local::prepare_local_project(path).await {
+            Ok(dependent) => extra_dependents.push(dependent),
+            Err(e) => tracing::warn!("跳过本地项目 {}: {:#}", path.display(), e),
+        }
+    }
+    if !extra_dependents.is_empty() {
+        analyzer = analyzer.with_extra_dependents(extra_dependents);
+    }
+    let graph = match analyzer.analyze(&roots, &target_function_paths).await {
+        Ok(graph) => graph,
+        Err(e) => {
+            tracing::error!("分析失败: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    graph.print_graph();
+    if let Some(path) = &config.save_graph {
+        if let Err(e) = graph.save(path).await {
+            tracing::warn!("保存影响图失败: {:#}", e);
+        }
+    }
+    export_graph(&graph, &config).await;
 
     tracing::info!("分析完成");
 }
+
+/// run the exports configured in the environment
+async fn export_graph(graph: &Graph, config: &AnalyzerConfig) {
+    if let Some(url) = &config.export_sql_url {
+        if let Err(e) = export::sql::export_to_sql(graph, url, &config.chain_filter).await {
+            tracing::warn!("导出SQL失败: {:#}", e);
+        }
+    }
+    if config.export_crate_dot.is_some() || config.export_dot.is_some() {
+        let exported = export::dot::export_to_dot(
+            graph,
+            config.export_crate_dot.as_deref(),
+            config.export_dot.as_deref(),
+        )
+        .await;
+        if let Err(e) = exported {
+            tracing::warn!("导出DOT失败: {:#}", e);
+        }
+    }
+    if config.export_crate_graphml.is_some() || config.export_graphml.is_some() {
+        let exported = export::graphml::export_to_graphml(
+            graph,
+            config.export_crate_graphml.as_deref(),
+            config.export_graphml.as_deref(),
+        )
+        .await;
+        if let Err(e) = exported {
+            tracing::warn!("导出GraphML失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_gexf {
+        if let Err(e) = export::gexf::export_to_gexf(graph, path).await {
+            tracing::warn!("导出GEXF失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_cypher {
+        if let Err(e) = export::cypher::export_to_cypher(graph, path).await {
+            tracing::warn!("导出Cypher失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_parquet {
+        if let Err(e) = export::parquet::export_to_parquet(graph, path).await {
+            tracing::warn!("导出Parquet失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_html {
+        if let Err(e) = export::html::export_to_html(graph, path, &config.chain_filter).await {
+            tracing::warn!("导出HTML报告失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_markdown {
+        let exported =
+            export::markdown::export_to_markdown(graph, path, &config.chain_filter).await;
+        if let Err(e) = exported {
+            tracing::warn!("导出Markdown报告失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_sarif {
+        if let Err(e) = export::sarif::export_to_sarif(graph, path).await {
+            tracing::warn!("导出SARIF失败: {:#}", e);
+        }
+    }
+    if let Some(dir) = &config.export_osv_dir {
+        if let Err(e) = export::osv::export_to_osv(graph, dir).await {
+            tracing::warn!("导出OSV草稿失败: {:#}", e);
+        }
+    }
+    if let Some(path) = &config.export_vex {
+        let id = config.vex_vulnerability_id.as_deref();
+        if let Err(e) = export::vex::export_to_vex(graph, path, id).await {
+            tracing::warn!("导出VEX失败: {:#}", e);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use tracing::info;
+
+use crate::graph::Graph;
+
+/// options of the `merge-graphs` subcommand
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /// graphs saved with `SAVE_GRAPH`, e.g. by runs on several machines
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// where to save the merged graph
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// combine the graphs of several runs into one, saved to `output` when given
+pub async fn run(args: &MergeArgs) -> Result<Graph> {
+    let mut merged = Graph::new();
+    for path in &args.inputs {
+        let graph = Graph::load(path)
+            .await
+            .context(format!("无法合并 {}", path.display()))?;
+        info!(
+            "合并 {}: {} 个 crate, {} 条依赖",
+            path.display(),
+            graph.crate_count(),
+            graph.dependency_count()
+        );
+        merged.merge(graph);
+    }
+    if let Some(output) = &args.output {
+        merged.save(output).await?;
+    }
+    Ok(merged)
+}
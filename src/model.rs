@@ -1,19 +1,46 @@
 use anyhow::{Context, Result};
+use cargo_metadata::{Metadata, MetadataCommand, Package, TargetKind};
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::fs as tokio_fs;
 use tokio::process::Command;
-use tokio::sync::Semaphore;
 use tracing::info;
 
-const MAX_DOWNLOAD_CONCURRENT: usize = 4; // 与 DependencyAnalyzer 保持一致
-                                          // static CARGO_UPDATE_MUTEX: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+use crate::cache::MetadataCache;
+use crate::callers::{parse_caller, parse_callers, ApiExposure, CallSite, CallerInfo};
+use crate::config::PhaseTimeouts;
+use crate::diagnostics::RecordedOutput;
+use crate::download;
+use crate::failure::with_timeout;
+use crate::limits::LIMITS;
+use crate::precheck::{Confidence, PrecheckKind};
+use crate::registry::REGISTRY;
+use crate::resolution::ResolutionCheck;
+use crate::store::{self, STORE};
 
-// 下载/解压限流
-static DOWNLOAD_SEMAPHORE: Lazy<Arc<Semaphore>> =
-    Lazy::new(|| Arc::new(Semaphore::new(MAX_DOWNLOAD_CONCURRENT)));
+// Cargo.toml 内容缓存，同一个 crate 在各阶段会被反复读取
+pub static MANIFEST_CACHE: Lazy<MetadataCache<PathBuf, Arc<String>>> =
+    Lazy::new(|| MetadataCache::new("manifest"));
+
+/// canonical form of a crate name
+///
+/// crates.io treats `-` and `_` (and letter case) as equivalent, and Rust paths always
+/// use `_`, so both spellings map to the same key
+pub fn normalize_crate_name(name: &str) -> String {
+    name.to_ascii_lowercase().replace('-', "_")
+}
+
+/// the stand-in for a function path of a sensitive crate, stable across runs so that
+/// the same caller is still counted once
+pub fn hashed_path(path: &str) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(path.as_bytes())))
+}
 
 #[derive(Debug, Clone)]
 pub struct Krate {
@@ -49,112 +76,122 @@ impl Krate {
         &mut self.dependents
     }
 
-    /// obtain the download directory
-    /// $DOWNLOAD_DIR/crate_name/ ,such as /home/rust/xinshi/download/crossbeam-channel/
+    /// obtain the download directory, sharded by the hash of the name so no directory
+    /// holds more than a few hundred entries
+    /// $DOWNLOAD_DIR/dl/ab/cd/crate_name/ ,such as /home/rust/download/dl/ff/91/crossbeam-channel/
     fn get_download_dir(&self) -> PathBuf {
         let base_dir = std::env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "./downloads".to_string());
-        Path::new(&base_dir).join(&self.name)
-    }
-
-    /// obtain the crate file path
-    /// $DOWNLOAD_DIR/crate_name/crate_name-crate_version.crate
-    fn get_crate_file_path(&self) -> PathBuf {
-        let crate_file = format!("{}-{}.crate", self.name, self.version);
-        self.get_download_dir().join(crate_file)
+        shard_dir(Path::new(&base_dir), &self.name)
     }
 
     /// obtain the extract directory path
-    /// $DOWNLOAD_DIR/crate_name/crate_name-crate_version/
-    fn get_extract_dir_path(&self) -> PathBuf {
+    /// $DOWNLOAD_DIR/dl/ab/cd/crate_name/crate_name-crate_version/
+    ///
+    /// for crates from the registry this is a link into the content-addressed store
+    pub fn get_extract_dir_path(&self) -> PathBuf {
         let extract_dir = format!("{}-{}", self.name, self.version);
         self.get_download_dir().join(extract_dir)
     }
 
-    /// download the crate file
-    async fn download(&self) -> Result<()> {
+    /// download the crate file into the store, return its sha256
+    async fn download(&self) -> Result<String> {
         info!("download crate: {} {}", self.name, self.version);
 
-        let download_dir = self.get_download_dir();
-        let crate_file_path = self.get_crate_file_path();
-        let extract_dir_path = self.get_extract_dir_path();
-
-        tracing::info!("crate_file_path: {}", crate_file_path.display());
-        tracing::info!("extract_dir_path: {}", extract_dir_path.display());
-        tracing::info!("download_dir: {}", download_dir.display());
-
-        // check if the crate-version.crate file already exists
-        // we don't need to download the crate file again
-        if crate_file_path.exists() {
-            info!(
-                "directory {} already exists, skip the download",
-                extract_dir_path.display()
-            );
-            return Ok(());
+        // 之前的运行已经下载过同样的内容
+        if let Some(hash) = STORE.lookup(&self.name, &self.version).await {
+            let crate_file_path = STORE.crate_file(&hash);
+            if crate_file_path.exists() && verify_crate_file(&crate_file_path, Some(&hash)).await? {
+                info!(
+                    "crate file {} already exists, skip the download",
+                    crate_file_path.display()
+                );
+                return Ok(hash);
+            }
         }
 
-        tokio_fs::create_dir_all(&download_dir)
-            .await
-            .context(format!(
-                "Failed to create the download directory: {}",
-                download_dir.display()
-            ))?;
-
-        // download the crate file
-        info!("downloading the crate file: {}", crate_file_path.display());
-        let download_url = format!(
-            "https://crates.io/api/v1/crates/{}/{}/download",
-            self.name, self.version
-        );
-
-        let download_result = Command::new("curl")
-            .args(&[
-                "-L",
-                &download_url,
-                "-o",
-                &crate_file_path.to_string_lossy(),
-            ])
-            .output()
-            .await;
-
-        if let Err(e) = download_result {
-            return Err(anyhow::anyhow!("Failed to download the crate: {}", e));
+        // 截断或损坏的压缩包解压后会污染缓存，下载后与索引中的 sha256 比对
+        let expected = match download::expected_checksum(&self.name, &self.version).await {
+            Ok(Some(checksum)) => Some(checksum.to_lowercase()),
+            Ok(None) => {
+                tracing::warn!("索引中没有 {} {}，跳过校验", self.name, self.version);
+                None
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "无法获取 {} {} 的校验和，跳过校验: {:#}",
+                    self.name,
+                    self.version,
+                    e
+                );
+                None
+            }
+        };
+        if let Some(hash) = &expected {
+            let crate_file_path = STORE.crate_file(hash);
+            if crate_file_path.exists() && verify_crate_file(&crate_file_path, Some(hash)).await? {
+                STORE.record(&self.name, &self.version, hash).await?;
+                return Ok(hash.clone());
+            }
         }
 
-        // check the file size
-        let metadata = tokio_fs::metadata(&crate_file_path).await.context(format!(
-            "Failed to get the file metadata: {}",
-            crate_file_path.display()
-        ))?;
-
-        let size = metadata.len();
-        info!("the size of the downloaded file is {} bytes", size);
+        // download the crate file
+        let temp_file = STORE.temp_file(&format!("{}-{}.crate", self.name, self.version));
+        store::create_parent(&temp_file).await?;
+        info!("downloading the crate file: {}", temp_file.display());
 
-        if size == 0 {
-            return Err(anyhow::anyhow!(
-                "the size of the downloaded file is 0, maybe the download failed"
-            ));
+        // 依次尝试各个下载地址（API 被限流或出错时改用 CDN），校验失败时重新下载一次
+        let mut last_error = None;
+        for download_url in REGISTRY.download_urls(&self.name, &self.version) {
+            for attempt in 0..2 {
+                let size = match download::download_file(&download_url, &temp_file).await {
+                    Ok(size) => size,
+                    Err(e) => {
+                        tracing::warn!("{:#}，尝试下一个下载地址", e);
+                        last_error = Some(e);
+                        break;
+                    }
+                };
+                info!("the size of the downloaded file is {} bytes", size);
+                if verify_crate_file(&temp_file, expected.as_deref()).await? {
+                    let hash = match &expected {
+                        Some(hash) => hash.clone(),
+                        None => download::file_checksum(&temp_file).await?,
+                    };
+                    STORE.insert_crate_file(&temp_file, &hash).await?;
+                    STORE.note_added(size).await;
+                    STORE.record(&self.name, &self.version, &hash).await?;
+                    return Ok(hash);
+                }
+                tracing::warn!(
+                    "{} 与索引中的校验和不一致（第 {} 次下载）",
+                    temp_file.display(),
+                    attempt + 1
+                );
+                last_error = Some(anyhow::anyhow!(
+                    "{} {} 的下载内容与索引中的校验和不一致",
+                    self.name,
+                    self.version
+                ));
+            }
         }
-
-        Ok(())
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("没有可用的下载地址")))
     }
 
-    /// unzip the crate file
-    async fn unzip(&self) -> Result<PathBuf> {
-        let crate_file_path = self.get_crate_file_path();
-        let extract_dir_path = self.get_extract_dir_path();
-        let download_dir = self.get_download_dir();
+    /// extract the crate file with the given sha256 into the store, return the source dir
+    async fn unzip(&self, hash: &str) -> Result<PathBuf> {
+        let crate_file_path = STORE.crate_file(hash);
+        let source_dir = STORE.source_dir(hash);
 
         tracing::info!("crate_file_path: {}", crate_file_path.display());
-        tracing::info!("extract_dir_path: {}", extract_dir_path.display());
-        tracing::info!("download_dir: {}", download_dir.display());
+        tracing::info!("source_dir: {}", source_dir.display());
 
         // if the target directory already exists, return directly
-        if extract_dir_path.exists() {
+        if source_dir.exists() {
             info!(
                 "directory {} already exists, no need to extract",
-                extract_dir_path.display()
+                source_dir.display()
             );
-            return Ok(extract_dir_path);
+            return Ok(source_dir);
         }
 
         // ensure the crate file exists
@@ -169,66 +206,35 @@ impl Krate {
         info!(
             "extracting crate: {} to {}",
             crate_file_path.display(),
-            download_dir.display()
+            source_dir.display()
         );
-
-        let unzip_result = Command::new("tar")
-            .args(&["-xf", &crate_file_path.to_string_lossy()])
-            .current_dir(&download_dir)
-            .output()
+        store::create_parent(&source_dir).await?;
+        let archive = crate_file_path.clone();
+        let destination = source_dir.clone();
+        let entries = tokio::task::spawn_blocking(move || extract_crate(&archive, &destination))
             .await
-            .context("Failed to execute tar command")?;
-
-        if !unzip_result.status.success() {
-            let stderr = String::from_utf8_lossy(&unzip_result.stderr);
-            return Err(anyhow::anyhow!("Extract command failed: {}", stderr));
-        }
-
-        // check if the directory exists
-        if !extract_dir_path.exists() {
-            // try to list the current directory contents
-            let entries = tokio_fs::read_dir(&download_dir)
-                .await
-                .context("Failed to read directory")?;
-
-            let mut files = String::new();
-            let mut entry_count = 0;
-
-            tokio::pin!(entries);
-            while let Some(entry) = entries
-                .next_entry()
-                .await
-                .context("Failed to read directory entry")?
-            {
-                files.push_str(&format!("\n  - {}", entry.path().display()));
-                entry_count += 1;
-
-                if entry_count > 10 {
-                    files.push_str("\n  ... (more files)");
-                    break;
-                }
-            }
+            .context("extract task panicked")??;
+        info!("extracted {} entries", entries);
 
+        if !source_dir.is_dir() {
             return Err(anyhow::anyhow!(
-                "Extracted directory does not exist: {}. Directory contents: {}",
-                extract_dir_path.display(),
-                files
+                "Extracted directory does not exist: {}",
+                source_dir.display()
             ));
         }
 
-        info!(
-            "Successfully extracted crate to: {}",
-            extract_dir_path.display()
-        );
-        Ok(extract_dir_path)
+        info!("Successfully extracted crate to: {}", source_dir.display());
+        Ok(source_dir)
     }
 
     /// download and unzip the crate, return the path to the extracted directory
-    pub async fn get_crate_dir_path(&self) -> Result<PathBuf> {
-        let _download_permit = DOWNLOAD_SEMAPHORE.acquire().await.unwrap();
+    ///
+    /// an unpacking cut off by its timeout keeps running in the background into its own
+    /// staging directory, which never becomes visible
+    pub async fn get_crate_dir_path(&self, timeouts: &PhaseTimeouts) -> Result<PathBuf> {
+        let _download_permit = LIMITS.downloads.acquire().await.unwrap();
 
         let extract_dir_path = self.get_extract_dir_path();
-        let _key = format!("{}-{}", self.name, self.version);
 
         tracing::info!(
             "get_crate_dir_path: extract_dir_path={}",
@@ -241,62 +247,64 @@ impl Krate {
                 "get_crate_dir_path: 解压目录已存在: {}",
                 extract_dir_path.display()
             );
+            STORE.touch(&extract_dir_path);
             return Ok(extract_dir_path);
         }
 
-        // 下面的代码只有第一个任务能执行
-        let result = async {
-            tracing::info!("get_crate_dir_path: 解压目录不存在，准备下载和解压");
+        // 存储中的源码已被清理，留下了失效的链接
+        if extract_dir_path.is_symlink() {
+            tokio_fs::remove_file(&extract_dir_path)
+                .await
+                .context(format!(
+                    "删除失效的链接 {} 失败",
+                    extract_dir_path.display()
+                ))?;
+        }
 
-            if let Err(e) = self.download().await {
-                tracing::warn!(
-                    "get_crate_dir_path: download()失败: {}，crate_file_path={}",
-                    e,
-                    self.get_crate_file_path().display()
-                );
-                return Err(anyhow::anyhow!("download()失败: {}", e));
-            } else {
-                tracing::info!("get_crate_dir_path: download()成功");
-            }
+        STORE.enforce_quota().await?;
 
-            let unzip_path = match self.unzip().await {
-                Ok(path) => {
-                    tracing::info!("get_crate_dir_path: unzip() 成功，解压到: {}", path.display());
-                    path
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "get_crate_dir_path: unzip() 失败: {}，crate_file_path={}, extract_dir_path={}",
-                        e,
-                        self.get_crate_file_path().display(),
-                        extract_dir_path.display()
-                    );
-                    return Err(anyhow::anyhow!("unzip() 失败: {}", e));
-                }
-            };
+        tracing::info!("get_crate_dir_path: 解压目录不存在，准备下载和解压");
+        let download = with_timeout("download", timeouts.download, self.download());
+        let hash = download.await.map_err(|e| {
+            tracing::warn!("get_crate_dir_path: download()失败: {:#}", e);
+            anyhow::anyhow!("download()失败: {:#}", e)
+        })?;
+        let unzip = with_timeout("extract", timeouts.extract, self.unzip(&hash));
+        let source_dir = unzip.await.map_err(|e| {
+            tracing::warn!("get_crate_dir_path: unzip() 失败: {:#}，sha256={}", e, hash);
+            anyhow::anyhow!("unzip() 失败: {:#}", e)
+        })?;
+        STORE.touch(&source_dir);
 
-            // 检查解压目录
-            if !unzip_path.is_dir() || unzip_path.read_dir().is_err() {
-                tracing::warn!(
-                    "get_crate_dir_path: 解压目录不是有效目录: {}",
-                    unzip_path.display()
-                );
+        // 按名字和版本访问的路径链接到存储中的源码
+        store::create_parent(&extract_dir_path).await?;
+        let target = source_dir
+            .canonicalize()
+            .context(format!("无法定位 {}", source_dir.display()))?;
+        if let Err(e) = tokio_fs::symlink(&target, &extract_dir_path).await {
+            // 另一个运行刚刚创建了同样的链接
+            if !extract_dir_path.is_dir() {
                 return Err(anyhow::anyhow!(
-                    "the unzip path is not a directory: {}",
-                    unzip_path.display()
+                    "链接 {} 失败: {}",
+                    extract_dir_path.display(),
+                    e
                 ));
             }
+        }
 
-            tracing::info!("get_crate_dir_path: 返回解压目录: {}", unzip_path.display());
-            Ok(unzip_path)
-        }.await;
-
-        result
+        tracing::info!(
+            "get_crate_dir_path: 返回解压目录: {}",
+            extract_dir_path.display()
+        );
+        Ok(extract_dir_path)
     }
 
     /// cleanup the downloaded crate file, keep the extracted directory
     pub async fn cleanup_crate_file(&self) -> Result<()> {
-        let crate_file_path = self.get_crate_file_path();
+        let Some(hash) = STORE.lookup(&self.name, &self.version).await else {
+            return Ok(());
+        };
+        let crate_file_path = STORE.crate_file(&hash);
 
         if crate_file_path.exists() {
             tokio_fs::remove_file(&crate_file_path)
@@ -311,15 +319,270 @@ impl Krate {
         Ok(())
     }
 
-    /// 修改目标 crate 的 Cargo.toml，将父节点依赖锁定为指定版本
+    /// read the Cargo.toml of an extracted crate, served from `MANIFEST_CACHE` when possible
+    pub async fn read_manifest(crate_dir: &Path) -> Result<Arc<String>> {
+        let cargo_toml_path = crate_dir.join("Cargo.toml");
+        if let Some(content) = MANIFEST_CACHE.get(&cargo_toml_path) {
+            return Ok(content);
+        }
+        let content = Arc::new(
+            tokio_fs::read_to_string(&cargo_toml_path)
+                .await
+                .context(format!("Failed to read {}", cargo_toml_path.display()))?,
+        );
+        MANIFEST_CACHE.put(cargo_toml_path, Arc::clone(&content));
+        Ok(content)
+    }
+
+    /// the name the crate is referred to by in Rust paths: `[lib] name` from the manifest,
+    /// or the crate name with `-` replaced by `_`
+    pub async fn lib_name(crate_dir: &Path, crate_name: &str) -> String {
+        let manifest_lib_name = Self::read_manifest(crate_dir)
+            .await
+            .ok()
+            .and_then(|content| content.parse::<toml_edit::DocumentMut>().ok())
+            .and_then(|manifest| {
+                manifest
+                    .get("lib")
+                    .and_then(|lib| lib.get("name"))
+                    .and_then(|name| name.as_str())
+                    .map(|name| name.to_string())
+            });
+        manifest_lib_name.unwrap_or_else(|| crate_name.replace('-', "_"))
+    }
+
+    /// `cargo metadata` of an extracted crate, parsed with `cargo_metadata`
+    ///
+    /// with `no_deps` only the workspace members are listed and nothing is resolved
+    pub async fn cargo_metadata(crate_dir: &Path, no_deps: bool) -> Result<Metadata> {
+        let mut command = MetadataCommand::new();
+        command
+            .manifest_path(crate_dir.join("Cargo.toml"))
+            .current_dir(crate_dir);
+        if no_deps {
+            command.no_deps();
+        }
+        // 通过 tokio 执行，输出会记录到诊断信息中
+        let output = Command::from(command.cargo_command())
+            .recorded_output()
+            .await
+            .context("执行 cargo metadata 失败")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "cargo metadata 执行失败: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // 输出中可能混有警告，只取 JSON 所在的行
+        let json = stdout
+            .lines()
+            .find(|line| line.starts_with('{'))
+            .context("cargo metadata 没有输出")?;
+        MetadataCommand::parse(json).context("解析 cargo metadata 输出失败")
+    }
+
+    /// `cargo metadata --no-deps` 中名为 `package_name` 的包
+    ///
+    /// 解压目录可能是工作区根目录甚至是虚拟清单，依赖者只是其中一个成员
+    async fn package_metadata(crate_dir: &Path, package_name: &str) -> Result<Package> {
+        let metadata = Self::cargo_metadata(crate_dir, true).await?;
+        let package_count = metadata.packages.len();
+        metadata
+            .packages
+            .into_iter()
+            .find(|package| {
+                normalize_crate_name(&package.name) == normalize_crate_name(package_name)
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} 的 {} 个包中没有 {}",
+                    crate_dir.display(),
+                    package_count,
+                    package_name
+                )
+            })
+    }
+
+    /// 编译 crate 时会执行的第三方代码：依赖图中带构建脚本或过程宏的包
+    ///
+    /// 返回 `(包名, "name version (build script|proc macro)")`
+    pub async fn build_time_code(crate_dir: &Path) -> Result<Vec<(String, String)>> {
+        let metadata = Self::cargo_metadata(crate_dir, false).await?;
+        // 只看实际解析到的包，packages 中还有未启用的可选依赖
+        let resolved = metadata
+            .resolve
+            .iter()
+            .flat_map(|resolve| &resolve.nodes)
+            .map(|node| &node.id)
+            .collect::<BTreeSet<_>>();
+        let mut found = Vec::new();
+        for package in &metadata.packages {
+            if !resolved.contains(&package.id) {
+                continue;
+            }
+            let kinds = package
+                .targets
+                .iter()
+                .flat_map(|target| &target.kind)
+                .filter_map(|kind| match kind {
+                    TargetKind::CustomBuild => Some("build script"),
+                    TargetKind::ProcMacro => Some("proc macro"),
+                    _ => None,
+                })
+                .collect::<BTreeSet<_>>();
+            for kind in kinds {
+                found.push((
+                    package.name.clone(),
+                    format!("{} {} ({})", package.name, package.version, kind),
+                ));
+            }
+        }
+        Ok(found)
+    }
+
+    /// 依赖者自己的包所在的目录，解压目录是工作区时为对应成员的目录
+    pub async fn package_dir(crate_dir: &Path, package_name: &str) -> Result<PathBuf> {
+        let package = Self::package_metadata(crate_dir, package_name).await?;
+        package
+            .manifest_path
+            .parent()
+            .map(|dir| dir.as_std_path().to_path_buf())
+            .context(format!(
+                "cargo metadata 中 {} 的 manifest_path 没有上级目录",
+                package_name
+            ))
+    }
+
+    /// 用 `cargo metadata` 找出依赖者在 Cargo.toml 中对父节点的所有声明
+    ///
+    /// 按包名匹配，重命名、可选和平台相关的依赖都能找到，`[workspace.dependencies]` 中
+    /// 继承的依赖也已由 cargo 展开；只通过传递依赖使用父节点时为空
+    pub async fn declared_dependencies(
+        crate_dir: &Path,
+        package_name: &str,
+        parent_name: &str,
+    ) -> Result<Vec<DeclaredDependency>> {
+        let package = Self::package_metadata(crate_dir, package_name).await?;
+        Ok(package
+            .dependencies
+            .into_iter()
+            .filter(|dep| normalize_crate_name(&dep.name) == normalize_crate_name(parent_name))
+            .map(DeclaredDependency::from)
+            .collect())
+    }
+
+    /// 包的 feature 表（包括可选依赖的隐式 feature），来自 `cargo metadata`
+    pub async fn features(
+        crate_dir: &Path,
+        package_name: &str,
+    ) -> Result<BTreeMap<String, Vec<String>>> {
+        let package = Self::package_metadata(crate_dir, package_name).await?;
+        Ok(package.features)
+    }
+
+    /// 依赖者中能启用可选依赖 `dep_key`（重命名时为重命名后的名字）的 feature
+    pub async fn optional_dependency_features(
+        crate_dir: &Path,
+        package_name: &str,
+        dep_key: &str,
+    ) -> Result<OptionalFeatures> {
+        let features = Self::features(crate_dir, package_name).await?;
+        Ok(OptionalFeatures::resolve(&features, dep_key))
+    }
+
+    /// 把 `features` 加入依赖者的默认 feature，使依赖可选父节点的代码参与编译
+    pub async fn enable_default_features(package_dir: &Path, features: &[String]) -> Result<()> {
+        let manifest_path = package_dir.join("Cargo.toml");
+        let content = tokio_fs::read_to_string(&manifest_path)
+            .await
+            .context(format!("读取 {} 失败", manifest_path.display()))?;
+        let content = with_default_features(&content, features, true)
+            .context(format!("修改 {} 失败", manifest_path.display()))?;
+        tokio_fs::write(&manifest_path, content)
+            .await
+            .context(format!("写入 {} 失败", manifest_path.display()))?;
+        tracing::info!(
+            "已在 {} 中启用 features: {}",
+            manifest_path.display(),
+            features.join(", ")
+        );
+        Ok(())
+    }
+
+    /// 将依赖者的依赖图中的父节点锁定为指定版本，返回依赖者对父节点的声明
+    ///
+    /// 先用 `cargo metadata` 确认依赖方式，再用 `cargo update --precise` 锁定，不改动 Cargo.toml
     pub async fn patch_cargo_toml_with_parent(
         crate_dir: &Path,
+        dependent_name: &str,
         parent_name: &str,
         parent_version: &str,
-    ) -> Result<Option<String>> {
-        let cargo_toml_path = crate_dir.join("Cargo.toml");
-        let original_content = tokio_fs::read_to_string(&cargo_toml_path).await.ok();
+        strategy: PinStrategy,
+    ) -> Result<Vec<DeclaredDependency>> {
+        let declared = Self::declared_dependencies(crate_dir, dependent_name, parent_name).await?;
+        if declared.is_empty() {
+            tracing::info!(
+                "{} 没有直接声明对 {} 的依赖",
+                crate_dir.display(),
+                parent_name
+            );
+        }
+        for dep in &declared {
+            tracing::info!(
+                "{} 声明了依赖 {} {} ({}){}{}{}{}",
+                crate_dir.display(),
+                dep.name,
+                dep.req,
+                dep.origin(),
+                dep.rename
+                    .as_deref()
+                    .map(|rename| format!(" 重命名为 {}", rename))
+                    .unwrap_or_default(),
+                dep.kind
+                    .as_deref()
+                    .map(|kind| format!(" ({})", kind))
+                    .unwrap_or_default(),
+                if dep.optional { " 可选" } else { "" },
+                dep.target
+                    .as_deref()
+                    .map(|target| format!(" 仅用于 {}", target))
+                    .unwrap_or_default()
+            );
+        }
+        // 通过 git 或 path 依赖父节点时用的不是 crates.io 上发布的版本，锁定版本会失败
+        // 或得到无意义的清单，这样的依赖者结果未知
+        if !declared.is_empty() && !declared.iter().any(DeclaredDependency::is_registry) {
+            let origins = declared
+                .iter()
+                .map(DeclaredDependency::origin)
+                .collect::<BTreeSet<_>>();
+            return Err(anyhow::anyhow!(
+                "{} 通过 {} 依赖 {}，无法锁定为发布的版本",
+                dependent_name,
+                origins.into_iter().collect::<Vec<_>>().join("/"),
+                parent_name
+            ));
+        }
+
+        match strategy {
+            PinStrategy::Lockfile => {
+                Self::pin_in_lockfile(crate_dir, parent_name, parent_version).await?
+            }
+            PinStrategy::Manifest => {
+                Self::pin_in_manifest(crate_dir, dependent_name, &declared, parent_version).await?
+            }
+        }
+        Ok(declared)
+    }
 
+    /// 用 `cargo update --precise` 在 Cargo.lock 中锁定父节点，传递依赖同样生效
+    async fn pin_in_lockfile(
+        crate_dir: &Path,
+        parent_name: &str,
+        parent_version: &str,
+    ) -> Result<()> {
+        let cargo_toml_path = crate_dir.join("Cargo.toml");
         let mut command_str = String::new();
         write!(
             &mut command_str,
@@ -335,20 +598,26 @@ impl Krate {
 
         // let _update_guard = CARGO_UPDATE_MUTEX.lock().await;
         // 使用cargo update --precise
-        let status = Command::new("cargo")
-            .args(&[
-                "update",
-                "--precise",
-                parent_version,
-                "--package",
-                parent_name,
-                "--manifest-path",
-                &cargo_toml_path.to_string_lossy(),
-            ])
-            .current_dir(crate_dir)
-            .output()
-            .await
-            .context("执行 cargo update --precise 失败")?;
+        let mut status = Self::cargo_update_precise(crate_dir, parent_name, parent_version).await?;
+        // 依赖图中有父节点的多个版本时（例如传递依赖用了另一个大版本），
+        // 只锁定与目标版本兼容的那一个
+        if !status.status.success() && String::from_utf8_lossy(&status.stderr).contains("ambiguous")
+        {
+            let target = semver::Version::parse(parent_version)?;
+            let compatible = Self::locked_versions(crate_dir, parent_name)
+                .await?
+                .into_iter()
+                .filter(|locked| {
+                    semver::VersionReq::parse(&format!("^{}", locked))
+                        .is_ok_and(|req| req.matches(&target))
+                })
+                .max();
+            if let Some(locked) = compatible {
+                let spec = format!("{}@{}", parent_name, locked);
+                tracing::info!("依赖图中有多个 {}，锁定 {}", parent_name, spec);
+                status = Self::cargo_update_precise(crate_dir, &spec, parent_version).await?;
+            }
+        }
         if !status.status.success() {
             let stderr = String::from_utf8_lossy(&status.stderr);
             tracing::warn!("cargo update --precise 执行失败: {}", stderr);
@@ -359,7 +628,190 @@ impl Krate {
         } else {
             tracing::info!("cargo update --precise 执行成功");
         }
-        Ok(original_content)
+        Ok(())
+    }
+
+    /// 把依赖者清单中对父节点的每条声明改为 `=version` 并重新生成 Cargo.lock
+    ///
+    /// 用 toml_edit 只改 `version`，features 等其他键和格式保持不变；
+    /// 只能锁定直接声明的依赖
+    async fn pin_in_manifest(
+        crate_dir: &Path,
+        dependent_name: &str,
+        declared: &[DeclaredDependency],
+        parent_version: &str,
+    ) -> Result<()> {
+        if declared.is_empty() {
+            return Err(anyhow::anyhow!(
+                "没有直接声明父节点的依赖者无法在 Cargo.toml 中锁定"
+            ));
+        }
+        let manifest_path = Self::package_dir(crate_dir, dependent_name)
+            .await?
+            .join("Cargo.toml");
+        let content = tokio_fs::read_to_string(&manifest_path)
+            .await
+            .context(format!("读取 {} 失败", manifest_path.display()))?;
+        let mut manifest = content
+            .parse::<toml_edit::DocumentMut>()
+            .context(format!("解析 {} 失败", manifest_path.display()))?;
+        let pinned = format!("={}", parent_version);
+        for dep in declared {
+            // 给 git/path 依赖加上 `version` 会改变它的含义，这些声明保持不变
+            if !dep.is_registry() {
+                tracing::info!("跳过 {} 依赖 {} 的声明", dep.origin(), dep.name);
+                continue;
+            }
+            let table_name = match dep.dependency_kind() {
+                DependencyKind::Normal => "dependencies",
+                DependencyKind::Build => "build-dependencies",
+                DependencyKind::Dev => "dev-dependencies",
+            };
+            // 重命名的依赖以重命名后的名字为键
+            let key = dep.rename.as_deref().unwrap_or(&dep.name);
+            let table = match &dep.target {
+                Some(target) => manifest
+                    .get_mut("target")
+                    .and_then(|targets| targets.get_mut(target))
+                    .and_then(|target| target.get_mut(table_name)),
+                None => manifest.get_mut(table_name),
+            };
+            let item = table.and_then(|table| table.get_mut(key)).context(format!(
+                "{} 中找不到依赖 {}",
+                manifest_path.display(),
+                key
+            ))?;
+            if item
+                .get("workspace")
+                .and_then(|workspace| workspace.as_bool())
+                == Some(true)
+            {
+                return Err(anyhow::anyhow!(
+                    "依赖 {} 继承自工作区，无法在清单中锁定",
+                    key
+                ));
+            }
+            if item.is_str() {
+                *item = toml_edit::value(pinned.clone());
+            } else if let Some(table) = item.as_table_like_mut() {
+                table.insert("version", toml_edit::value(pinned.clone()));
+            } else {
+                return Err(anyhow::anyhow!("无法识别依赖 {} 的写法", key));
+            }
+        }
+        tokio_fs::write(&manifest_path, manifest.to_string())
+            .await
+            .context(format!("写入 {} 失败", manifest_path.display()))?;
+        tracing::info!("已在 {} 中锁定 {}", manifest_path.display(), pinned);
+
+        let output = Command::new("cargo")
+            .args([
+                "generate-lockfile",
+                "--manifest-path",
+                &crate_dir.join("Cargo.toml").to_string_lossy(),
+            ])
+            .current_dir(crate_dir)
+            .recorded_output()
+            .await
+            .context("执行 cargo generate-lockfile 失败")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "cargo generate-lockfile 执行失败: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        Ok(())
+    }
+
+    /// 锁定父节点版本后，确认依赖图确实解析到了该版本
+    ///
+    /// 用 `cargo tree -i <parent>@<version>` 检查，失败时从 Cargo.lock 读出实际解析到的
+    /// 版本（取最高的一个），依赖图中没有父节点时返回 None
+    pub async fn resolved_parent_version(
+        crate_dir: &Path,
+        parent_name: &str,
+        parent_version: &str,
+    ) -> Result<Option<String>> {
+        let cargo_toml_path = crate_dir.join("Cargo.toml");
+        let spec = format!("{}@{}", parent_name, parent_version);
+        let output = Command::new("cargo")
+            .args([
+                "tree",
+                "--invert",
+                &spec,
+                "--depth",
+                "0",
+                "--locked",
+                "--manifest-path",
+                &cargo_toml_path.to_string_lossy(),
+            ])
+            .current_dir(crate_dir)
+            .recorded_output()
+            .await
+            .context("执行 cargo tree 失败")?;
+        if output.status.success() {
+            return Ok(Some(parent_version.to_owned()));
+        }
+        tracing::info!(
+            "cargo tree -i {} 未找到: {}",
+            spec,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+
+        let resolved = Self::locked_versions(crate_dir, parent_name).await?.pop();
+        Ok(resolved.map(|version| version.to_string()))
+    }
+
+    /// `cargo update --precise <version> --package <spec>`，spec 可以带版本 `name@x.y.z`
+    async fn cargo_update_precise(
+        crate_dir: &Path,
+        package_spec: &str,
+        version: &str,
+    ) -> Result<std::process::Output> {
+        let cargo_toml_path = crate_dir.join("Cargo.toml");
+        Command::new("cargo")
+            .args([
+                "update",
+                "--precise",
+                version,
+                "--package",
+                package_spec,
+                "--manifest-path",
+                &cargo_toml_path.to_string_lossy(),
+            ])
+            .current_dir(crate_dir)
+            .recorded_output()
+            .await
+            .context("执行 cargo update --precise 失败")
+    }
+
+    /// Cargo.lock 中某个 crate 的所有版本，从低到高排序
+    pub async fn locked_versions(crate_dir: &Path, name: &str) -> Result<Vec<semver::Version>> {
+        let lock_path = crate_dir.join("Cargo.lock");
+        let lock = tokio_fs::read_to_string(&lock_path)
+            .await
+            .context(format!("读取 {} 失败", lock_path.display()))?;
+        let lock = lock
+            .parse::<toml_edit::DocumentMut>()
+            .context(format!("解析 {} 失败", lock_path.display()))?;
+        let mut versions = lock
+            .get("package")
+            .and_then(|packages| packages.as_array_of_tables())
+            .into_iter()
+            .flatten()
+            .filter(|package| {
+                package
+                    .get("name")
+                    .and_then(|package_name| package_name.as_str())
+                    .is_some_and(|package_name| {
+                        normalize_crate_name(package_name) == normalize_crate_name(name)
+                    })
+            })
+            .filter_map(|package| package.get("version").and_then(|version| version.as_str()))
+            .filter_map(|version| semver::Version::parse(version).ok())
+            .collect::<Vec<_>>();
+        versions.sort();
+        Ok(versions)
     }
 
     /// 在 crate 解压目录下执行 cargo clean，释放 target 空间
@@ -372,9 +824,9 @@ impl Krate {
         }
         tracing::info!("cargo_clean: {}", manifest_path.display());
         let output = Command::new("cargo")
-            .args(&["clean", "--manifest-path", &manifest_path.to_string_lossy()])
+            .args(["clean", "--manifest-path", &manifest_path.to_string_lossy()])
             .current_dir(&extract_dir)
-            .output()
+            .recorded_output()
             .await
             .context(format!(
                 "执行 cargo clean 失败: {}",
@@ -388,19 +840,1389 @@ impl Krate {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct ReverseDependency {
-    // the crate name of the reverse dependency
-    pub name: String,
-    // the version of the reverse dependency
-    pub version: String,
-    // the version requirement of the dependency
-    // i.e. `[dependencies]  "dep_name": "1.0.0"` in `Cargo.toml`
-    pub req: String,
+/// restores `Cargo.toml` and `Cargo.lock` of an extracted crate when dropped
+///
+/// pinning the parent rewrites the lockfile, the extracted directory is reused by later
+/// runs against other parent versions and has to be left as it was published
+pub struct ManifestGuard {
+    // file -> content before the analysis, None when the file didn't exist
+    files: Vec<(PathBuf, Option<Vec<u8>>)>,
 }
 
-impl ReverseDependency {
-    pub fn new(name: String, version: String, req: String) -> Self {
-        Self { name, version, req }
+impl ManifestGuard {
+    /// `crate_dirs` holds the extracted directory and, for workspaces, the member package
+    pub fn new(crate_dirs: &[&Path]) -> Self {
+        let mut files: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+        for crate_dir in crate_dirs {
+            let crate_dir = crate_dir
+                .canonicalize()
+                .unwrap_or_else(|_| crate_dir.to_path_buf());
+            for name in ["Cargo.toml", "Cargo.lock"] {
+                let path = crate_dir.join(name);
+                if files.iter().all(|(known, _)| known != &path) {
+                    let content = std::fs::read(&path).ok();
+                    files.push((path, content));
+                }
+            }
+        }
+        Self { files }
+    }
+}
+
+impl Drop for ManifestGuard {
+    fn drop(&mut self) {
+        for (path, original) in &self.files {
+            let current = std::fs::read(path).ok();
+            if &current == original {
+                continue;
+            }
+            let result = match original {
+                Some(content) => std::fs::write(path, content),
+                None => std::fs::remove_file(path),
+            };
+            match result {
+                Ok(()) => tracing::info!("已恢复 {}", path.display()),
+                Err(e) => tracing::warn!("恢复 {} 失败: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+/// features of a dependent that turn on one of its optional dependencies
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OptionalFeatures {
+    // enabling any one of these features pulls in the dependency
+    pub enabling: Vec<String>,
+    // the default features already pull it in
+    pub default: bool,
+}
+
+impl OptionalFeatures {
+    /// features enabling `dep_key`, directly (`dep:key`, `key/feature`, the implicit
+    /// feature `key`) or through other features; weak `key?/feature` doesn't count
+    pub fn resolve(features: &BTreeMap<String, Vec<String>>, dep_key: &str) -> Self {
+        let enables_directly = |values: &Vec<String>| {
+            values.iter().any(|value| {
+                value.strip_prefix("dep:") == Some(dep_key)
+                    || value.split_once('/').is_some_and(|(dep, _)| dep == dep_key)
+                    || (value == dep_key && !features.contains_key(dep_key))
+            })
+        };
+        let mut enabling = features
+            .iter()
+            .filter(|(_, values)| enables_directly(values))
+            .map(|(name, _)| name.clone())
+            .collect::<BTreeSet<_>>();
+        // the implicit feature cargo creates when no feature uses `dep:key`
+        if enabling.is_empty() && !features.contains_key(dep_key) {
+            enabling.insert(dep_key.to_owned());
+        }
+        loop {
+            let more = features
+                .iter()
+                .filter(|(name, values)| {
+                    !enabling.contains(*name) && values.iter().any(|value| enabling.contains(value))
+                })
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            if more.is_empty() {
+                break;
+            }
+            enabling.extend(more);
+        }
+        let default = enabling.remove("default");
+        Self {
+            enabling: enabling.into_iter().collect(),
+            default,
+        }
+    }
+}
+
+/// `$base_dir/dl/ab/cd/crate_name`, `abcd` being the start of the sha256 of the name
+fn shard_dir(base_dir: &Path, name: &str) -> PathBuf {
+    let digest = hex::encode(Sha256::digest(name.to_lowercase().as_bytes()));
+    base_dir
+        .join("dl")
+        .join(&digest[..2])
+        .join(&digest[2..4])
+        .join(name)
+}
+
+/// move crates downloaded with the old flat layout (`$DOWNLOAD_DIR/crate_name/`) into
+/// their shard, returns the number of crates moved
+///
+/// a directory is taken for a legacy crate directory when it holds `crate_name-*`
+/// entries, which tells it apart from `dl/`, `git/` and `store/`
+pub async fn migrate_download_layout() -> Result<usize> {
+    let base_dir = std::env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "./downloads".to_string());
+    let base_dir = PathBuf::from(base_dir);
+    let mut entries = match tokio_fs::read_dir(&base_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e).context(format!("读取 {} 失败", base_dir.display())),
+    };
+    let mut migrated = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let legacy_dir = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if !entry.file_type().await?.is_dir() || !is_legacy_crate_dir(&legacy_dir, &name).await {
+            continue;
+        }
+        let shard = shard_dir(&base_dir, &name);
+        if let Some(parent) = shard.parent() {
+            tokio_fs::create_dir_all(parent)
+                .await
+                .context(format!("创建目录 {} 失败", parent.display()))?;
+        }
+        if !shard.exists() {
+            tokio_fs::rename(&legacy_dir, &shard)
+                .await
+                .context(format!(
+                    "移动 {} 到 {} 失败",
+                    legacy_dir.display(),
+                    shard.display()
+                ))?;
+        } else {
+            // 两种布局下都有这个 crate，只移动新布局中没有的版本
+            let mut children = tokio_fs::read_dir(&legacy_dir).await?;
+            while let Some(child) = children.next_entry().await? {
+                let destination = shard.join(child.file_name());
+                if tokio_fs::symlink_metadata(&destination).await.is_err() {
+                    tokio_fs::rename(child.path(), &destination).await?;
+                }
+            }
+            if let Err(e) = tokio_fs::remove_dir_all(&legacy_dir).await {
+                tracing::warn!("删除旧目录 {} 失败: {}", legacy_dir.display(), e);
+            }
+        }
+        migrated += 1;
+    }
+    if migrated > 0 {
+        info!("已将 {} 个 crate 迁移到分片的下载目录布局", migrated);
+    }
+    Ok(migrated)
+}
+
+async fn is_legacy_crate_dir(dir: &Path, name: &str) -> bool {
+    let Ok(mut children) = tokio_fs::read_dir(dir).await else {
+        return false;
+    };
+    let prefix = format!("{}-", name);
+    while let Ok(Some(child)) = children.next_entry().await {
+        if child.file_name().to_string_lossy().starts_with(&prefix) {
+            return true;
+        }
+    }
+    false
+}
+
+/// compare a crate file with the expected sha256, a mismatching file is removed
+///
+/// without an expected checksum only the presence of the file is checked
+async fn verify_crate_file(crate_file_path: &Path, expected: Option<&str>) -> Result<bool> {
+    let Some(expected) = expected else {
+        return Ok(crate_file_path.exists());
+    };
+    let actual = download::file_checksum(crate_file_path).await?;
+    if actual.eq_ignore_ascii_case(expected) {
+        return Ok(true);
+    }
+    tracing::warn!(
+        "{} 的 sha256 为 {}，索引中为 {}",
+        crate_file_path.display(),
+        actual,
+        expected
+    );
+    tokio_fs::remove_file(crate_file_path)
+        .await
+        .context(format!("删除 {} 失败", crate_file_path.display()))?;
+    Ok(false)
+}
+
+/// stream a `.crate` (tar.gz) archive into `destination`, returns the number of entries
+///
+/// archives are third-party input: entries escaping the archive (absolute paths, `..`)
+/// reject the whole archive and links are skipped. The contents land in `destination`
+/// whatever the archive's top-level directory is called (`{name}-{version}/` normally)
+/// or if it has none. Extraction goes to a staging directory renamed at the end, so
+/// `destination` only ever appears complete
+fn extract_crate(archive: &Path, destination: &Path) -> Result<usize> {
+    let open = || -> Result<tar::Archive<flate2::read::GzDecoder<std::fs::File>>> {
+        let file = std::fs::File::open(archive).context(format!(
+            "Failed to open the crate file: {}",
+            archive.display()
+        ))?;
+        Ok(tar::Archive::new(flate2::read::GzDecoder::new(file)))
+    };
+
+    // first pass: validate every path and find the common top-level directory
+    let mut top_level = BTreeSet::new();
+    let mut nested = true;
+    for entry in open()?.entries().context("Failed to read the archive")? {
+        let entry = entry.context("Failed to read an archive entry")?;
+        let path = entry.path()?.into_owned();
+        let components = safe_components(&path)?;
+        if entry.header().entry_type().is_dir() && components.len() == 1 {
+            top_level.insert(components[0].clone());
+            continue;
+        }
+        nested &= components.len() > 1;
+        if let Some(first) = components.first() {
+            top_level.insert(first.clone());
+        }
+    }
+    let strip = nested && top_level.len() == 1;
+    if !strip {
+        tracing::warn!(
+            "{} has no single top-level directory, extracting it as is",
+            archive.display()
+        );
+    } else if let Some(top) = top_level.first() {
+        let expected = destination.file_name().unwrap_or_default();
+        if top.as_os_str() != expected {
+            tracing::warn!(
+                "{}: top-level directory {} doesn't match {}",
+                archive.display(),
+                top.display(),
+                expected.to_string_lossy()
+            );
+        }
+    }
+
+    let staging = store::temp_path(destination);
+    std::fs::create_dir_all(&staging).context(format!("Failed to create {}", staging.display()))?;
+
+    // second pass: unpack the validated entries below the staging directory
+    let mut count = 0;
+    let unpacked = (|| -> Result<()> {
+        for entry in open()?.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                tracing::warn!("skip link {} in {}", path.display(), archive.display());
+                continue;
+            }
+            let components = safe_components(&path)?;
+            let relative = components
+                .iter()
+                .skip(usize::from(strip))
+                .collect::<PathBuf>();
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+            let target = staging.join(&relative);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            entry
+                .unpack(&target)
+                .context(format!("Failed to extract {}", path.display()))?;
+            count += 1;
+        }
+        Ok(())
+    })();
+    if let Err(e) = unpacked {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    if let Err(e) = std::fs::rename(&staging, destination) {
+        let _ = std::fs::remove_dir_all(&staging);
+        // another run extracted the same content first
+        if !destination.is_dir() {
+            return Err(e).context(format!("Failed to move {} into place", staging.display()));
+        }
+    }
+    Ok(count)
+}
+
+/// the normal components of an archive path, an error for paths that could leave the
+/// extraction directory
+fn safe_components(path: &Path) -> Result<Vec<PathBuf>> {
+    path.components()
+        .filter(|component| !matches!(component, std::path::Component::CurDir))
+        .map(|component| match component {
+            std::path::Component::Normal(name) => Ok(PathBuf::from(name)),
+            _ => Err(anyhow::anyhow!(
+                "archive entry {} escapes the extraction directory",
+                path.display()
+            )),
+        })
+        .collect()
+}
+
+/// rewrite `[features] default` of a manifest to `features`, after the existing defaults
+/// when `keep` is set
+///
+/// call-cg4rs and `cargo expand` build with the default features, changing them selects
+/// the configuration without passing feature flags through
+fn with_default_features(manifest: &str, features: &[String], keep: bool) -> Result<String> {
+    let mut manifest = manifest.parse::<toml_edit::DocumentMut>()?;
+    let default = manifest["features"]
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+        .context("[features] 不是表")?
+        .entry("default")
+        .or_insert(toml_edit::value(toml_edit::Array::new()))
+        .as_array_mut()
+        .context("features.default 不是数组")?;
+    if !keep {
+        default.clear();
+    }
+    for feature in features {
+        if !default.iter().any(|value| value.as_str() == Some(feature)) {
+            default.push(feature.as_str());
+        }
+    }
+    Ok(manifest.to_string())
+}
+
+/// a feature configuration of the dependent the call graph is extracted under
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FeatureSet {
+    // the features the dependent enables by default
+    Default,
+    // `--no-default-features`
+    NoDefault,
+    // `--all-features`
+    All,
+    // the default features plus these
+    Custom(Vec<String>),
+}
+
+impl FeatureSet {
+    pub fn label(&self) -> String {
+        match self {
+            FeatureSet::Default => "default".to_owned(),
+            FeatureSet::NoDefault => "no-default".to_owned(),
+            FeatureSet::All => "all".to_owned(),
+            FeatureSet::Custom(features) => features.join("+"),
+        }
+    }
+
+    /// the manifest rewritten so that building with default features builds this
+    /// configuration, `features` is the package's feature table
+    pub fn apply(
+        &self,
+        manifest: &str,
+        features: &BTreeMap<String, Vec<String>>,
+    ) -> Result<String> {
+        match self {
+            FeatureSet::Default => Ok(manifest.to_owned()),
+            FeatureSet::NoDefault => with_default_features(manifest, &[], false),
+            FeatureSet::All => {
+                let all = features
+                    .keys()
+                    .filter(|name| *name != "default")
+                    .cloned()
+                    .collect::<Vec<_>>();
+                with_default_features(manifest, &all, false)
+            }
+            FeatureSet::Custom(extra) => with_default_features(manifest, extra, true),
+        }
+    }
+}
+
+impl FromStr for FeatureSet {
+    type Err = anyhow::Error;
+
+    /// `default`, `no-default`, `all` or features joined by `+`
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "default" => Ok(FeatureSet::Default),
+            "no-default" | "no-default-features" => Ok(FeatureSet::NoDefault),
+            "all" | "all-features" => Ok(FeatureSet::All),
+            "" => Err(anyhow::anyhow!("空的 feature 配置")),
+            custom => Ok(FeatureSet::Custom(
+                custom
+                    .split('+')
+                    .map(str::trim)
+                    .filter(|feature| !feature.is_empty())
+                    .map(str::to_owned)
+                    .collect(),
+            )),
+        }
+    }
+}
+
+/// how the parent version is forced onto a dependent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PinStrategy {
+    // `cargo update --precise` on the lockfile, the manifest stays untouched
+    #[default]
+    Lockfile,
+    // rewrite the dependent's requirement on the parent to `=version`
+    Manifest,
+}
+
+impl FromStr for PinStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "lockfile" => Ok(PinStrategy::Lockfile),
+            "manifest" => Ok(PinStrategy::Manifest),
+            _ => Err(anyhow::anyhow!("未知的锁定方式: {}", name)),
+        }
+    }
+}
+
+/// what happens to yanked versions of dependents and of the roots
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum YankedPolicy {
+    // leave them out, a fresh resolution never picks them
+    #[default]
+    Skip,
+    // analyze them like any other version and mark them in the report
+    Flag,
+}
+
+impl FromStr for YankedPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "skip" => Ok(YankedPolicy::Skip),
+            "flag" => Ok(YankedPolicy::Flag),
+            _ => Err(anyhow::anyhow!("未知的撤回版本处理方式: {}", name)),
+        }
+    }
+}
+
+/// which pre-release versions are analyzed
+///
+/// semver only lets a pre-release satisfy a requirement that names a pre-release of the
+/// same version, so `<0.5.16` would silently leave out `0.5.15-rc.1` and a listed
+/// dependent's `*` would never accept it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrereleasePolicy {
+    // pre-releases count whenever they lie inside a range
+    #[default]
+    Include,
+    // pre-releases of the roots and of dependents are never analyzed
+    Exclude,
+    // only the pre-releases inside the vulnerable range are analyzed as roots
+    Only,
+}
+
+impl FromStr for PrereleasePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "include" => Ok(PrereleasePolicy::Include),
+            "exclude" => Ok(PrereleasePolicy::Exclude),
+            "only" => Ok(PrereleasePolicy::Only),
+            _ => Err(anyhow::anyhow!("未知的预发布版本处理方式: {}", name)),
+        }
+    }
+}
+
+impl PrereleasePolicy {
+    /// whether a version of a root is inside the vulnerable range
+    pub fn root_matches(self, range: &semver::VersionReq, version: &semver::Version) -> bool {
+        match self {
+            PrereleasePolicy::Include => matches_with_prerelease(range, version),
+            PrereleasePolicy::Exclude => version.pre.is_empty() && range.matches(version),
+            PrereleasePolicy::Only => {
+                !version.pre.is_empty() && matches_with_prerelease(range, version)
+            }
+        }
+    }
+
+    /// whether a dependent's requirement accepts the parent version
+    pub fn accepts(self, req: &semver::VersionReq, parent: &semver::Version) -> bool {
+        match self {
+            PrereleasePolicy::Exclude => req.matches(parent),
+            PrereleasePolicy::Include | PrereleasePolicy::Only => {
+                matches_with_prerelease(req, parent)
+            }
+        }
+    }
+
+    /// whether a root version is analyzed, for ranges that order pre-releases themselves
+    pub fn keeps_root(self, version: &semver::Version) -> bool {
+        match self {
+            PrereleasePolicy::Include => true,
+            PrereleasePolicy::Exclude => version.pre.is_empty(),
+            PrereleasePolicy::Only => !version.pre.is_empty(),
+        }
+    }
+
+    /// whether a dependent version is analyzed at all
+    pub fn keeps_dependent(self, version: &semver::Version) -> bool {
+        self != PrereleasePolicy::Exclude || version.pre.is_empty()
+    }
+}
+
+/// `req.matches(version)`, except that a pre-release is compared like any other version
+///
+/// semver's extra rule is satisfied by adding `>=x.y.z-0`, which every pre-release of
+/// `x.y.z` meets, so the remaining comparators decide
+pub fn matches_with_prerelease(req: &semver::VersionReq, version: &semver::Version) -> bool {
+    if version.pre.is_empty() {
+        return req.matches(version);
+    }
+    let mut relaxed = req.clone();
+    relaxed.comparators.push(semver::Comparator {
+        op: semver::Op::GreaterEq,
+        major: version.major,
+        minor: Some(version.minor),
+        patch: Some(version.patch),
+        pre: semver::Prerelease::new("0").expect("`0` is a valid pre-release"),
+    });
+    relaxed.matches(version)
+}
+
+/// one declaration of a dependency in a manifest, as reported by `cargo metadata`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeclaredDependency {
+    // the package name, not the key it is declared under
+    pub name: String,
+    pub req: String,
+    // the key the dependency is declared under when it differs from the package name
+    #[serde(default)]
+    pub rename: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+    // `dev` or `build`, None for normal dependencies
+    #[serde(default)]
+    pub kind: Option<String>,
+    // `cfg(...)` expression or target triple of target-specific dependencies
+    #[serde(default)]
+    pub target: Option<String>,
+    // `registry+...`, `sparse+...` or `git+...`, None for path dependencies
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+impl DeclaredDependency {
+    /// whether the dependency comes from a registry, only those can be pinned to a
+    /// published version of the parent
+    pub fn is_registry(&self) -> bool {
+        self.source
+            .as_deref()
+            .is_some_and(|source| source.starts_with("registry+") || source.starts_with("sparse+"))
+    }
+
+    /// `registry`, `git` or `path`, for logs
+    pub fn origin(&self) -> &'static str {
+        match self.source.as_deref() {
+            _ if self.is_registry() => "registry",
+            Some(source) if source.starts_with("git+") => "git",
+            _ if self.path.is_some() => "path",
+            _ => "unknown",
+        }
+    }
+
+    pub fn dependency_kind(&self) -> DependencyKind {
+        self.kind
+            .as_deref()
+            .and_then(DependencyKind::parse)
+            .unwrap_or(DependencyKind::Normal)
+    }
+}
+
+impl From<cargo_metadata::Dependency> for DeclaredDependency {
+    fn from(dep: cargo_metadata::Dependency) -> Self {
+        let kind = match dep.kind {
+            cargo_metadata::DependencyKind::Development => Some("dev".to_string()),
+            cargo_metadata::DependencyKind::Build => Some("build".to_string()),
+            _ => None,
+        };
+        Self {
+            name: dep.name,
+            req: dep.req.to_string(),
+            rename: dep.rename,
+            optional: dep.optional,
+            kind,
+            target: dep.target.map(|target| target.to_string()),
+            source: dep.source,
+            path: dep.path.map(|path| path.into_string()),
+        }
+    }
+}
+
+/// the dependency table a dependency is declared in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    // `[dependencies]` and `[target.'cfg(..)'.dependencies]`
+    Normal,
+    Build,
+    Dev,
+}
+
+impl DependencyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependencyKind::Normal => "normal",
+            DependencyKind::Build => "build",
+            DependencyKind::Dev => "dev",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "normal" => Some(DependencyKind::Normal),
+            "build" => Some(DependencyKind::Build),
+            "dev" => Some(DependencyKind::Dev),
+            _ => None,
+        }
+    }
+}
+
+/// where a dependent was enumerated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependentSource {
+    // the crates.io database dump
+    #[default]
+    Dump,
+    // the live crates.io API, consulted when the dump is stale
+    LiveApi,
+    // an explicit list given with `--dependents-file`
+    File,
+    // a repository given with `--git-dependent`
+    Git,
+    // a directory given with `--local-project`
+    Local,
+}
+
+impl DependentSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DependentSource::Dump => "dump",
+            DependentSource::LiveApi => "live_api",
+            DependentSource::File => "file",
+            DependentSource::Git => "git",
+            DependentSource::Local => "local",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReverseDependency {
+    // the crate name of the reverse dependency
+    pub name: String,
+    // the version of the reverse dependency
+    pub version: String,
+    // the version requirement of the dependency
+    // i.e. `[dependencies]  "dep_name": "1.0.0"` in `Cargo.toml`
+    pub req: String,
+    pub source: DependentSource,
+    // the parent version the dependent's dependency graph resolves to after pinning,
+    // None until checked or when `cargo tree` couldn't be run
+    pub resolved_version: Option<String>,
+    // the tables the dependent declares the parent in, from `cargo metadata`
+    pub kinds: Vec<DependencyKind>,
+    // the parent is an optional dependency not enabled by default, the edge only exists
+    // with one of these features enabled
+    pub required_features: Vec<String>,
+    // the dependent version was yanked from the registry, false when unknown
+    pub yanked: bool,
+    // unix time the dependent version was published, None when unknown
+    pub published_at: Option<f64>,
+    // whether a resolver picks the parent version for `req`, None until simulated
+    pub resolution: Option<ResolutionCheck>,
+}
+
+impl ReverseDependency {
+    pub fn new(name: String, version: String, req: String) -> Self {
+        Self {
+            name,
+            version,
+            req,
+            source: DependentSource::Dump,
+            resolved_version: None,
+            kinds: Vec::new(),
+            required_features: Vec::new(),
+            yanked: false,
+            published_at: None,
+            resolution: None,
+        }
+    }
+
+    pub fn with_source(mut self, source: DependentSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    pub fn with_resolved_version(mut self, resolved_version: Option<String>) -> Self {
+        self.resolved_version = resolved_version;
+        self
+    }
+
+    pub fn with_kinds(mut self, kinds: Vec<DependencyKind>) -> Self {
+        self.kinds = kinds;
+        self
+    }
+
+    pub fn with_required_features(mut self, required_features: Vec<String>) -> Self {
+        self.required_features = required_features;
+        self
+    }
+
+    pub fn with_yanked(mut self, yanked: bool) -> Self {
+        self.yanked = yanked;
+        self
+    }
+
+    pub fn with_published_at(mut self, published_at: Option<f64>) -> Self {
+        self.published_at = published_at;
+        self
+    }
+
+    pub fn with_resolution(mut self, resolution: Option<ResolutionCheck>) -> Self {
+        self.resolution = resolution;
+        self
+    }
+}
+
+/// one root of the analysis: a crate and the vulnerable version ranges
+#[derive(Debug, Clone)]
+pub struct AnalysisRoot {
+    pub crate_name: String,
+    // disjoint requirements, a version is vulnerable when it matches any of them
+    pub version_ranges: Vec<String>,
+}
+
+impl AnalysisRoot {
+    /// `version_range` separates several ranges with `||` like advisories do, e.g.
+    /// `>=0.4.0, <0.4.9 || >=0.5.0, <0.5.15`; a range may also be the events of an
+    /// advisory, `[{ introduced = "0.5.0", fixed = "0.5.15" }]`, see `osv::parse_events`
+    pub fn new(crate_name: &str, version_range: &str) -> Self {
+        Self {
+            crate_name: crate_name.to_owned(),
+            version_ranges: version_range
+                .split("||")
+                .map(str::trim)
+                .filter(|range| !range.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+}
+
+/// result of analyzing one dependent whose sources were analyzed successfully
+#[derive(Debug, Clone)]
+pub enum AnalysisOutcome {
+    Affected(FunctionCalls),
+    Cleared(Clearance),
+}
+
+/// why an analyzed dependent was found not to reach any target function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClearanceReason {
+    // the precheck found none of the target functions referenced in the sources
+    NotReferenced,
+    // referenced, but call-cg4rs found no caller
+    NoCallers,
+    // every caller sits in an excluded call site (tests, examples, ...)
+    ExcludedCallSites,
+    // the dependency graph doesn't resolve to the pinned parent version
+    NotResolved,
+    // the parent is only declared in dependency tables that don't count as impact
+    ExcludedDependencyKind,
+    // not verified: building it would run untrusted build scripts or proc macros, which
+    // safe mode forbids; the confidence shows what the precheck found
+    UntrustedBuild,
+}
+
+impl ClearanceReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClearanceReason::NotReferenced => "not_referenced",
+            ClearanceReason::NoCallers => "no_callers",
+            ClearanceReason::ExcludedCallSites => "excluded_call_sites",
+            ClearanceReason::NotResolved => "not_resolved",
+            ClearanceReason::ExcludedDependencyKind => "excluded_dependency_kind",
+            ClearanceReason::UntrustedBuild => "untrusted_build",
+        }
+    }
+
+    /// whether the dependent was built and shown not to call a target, the other
+    /// reasons say nothing about its sources
+    pub fn proves_no_call(&self) -> bool {
+        matches!(
+            self,
+            ClearanceReason::NotReferenced
+                | ClearanceReason::NoCallers
+                | ClearanceReason::ExcludedCallSites
+        )
+    }
+}
+
+/// evidence that a dependent was analyzed and doesn't reach any target function
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clearance {
+    pub reason: ClearanceReason,
+    // the precheck strategy the confidence comes from
+    #[serde(default)]
+    pub precheck: PrecheckKind,
+    // target function path -> precheck confidence
+    pub confidence: BTreeMap<String, Confidence>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub excluded: BTreeMap<CallSite, usize>,
+    // the parent version the dependency graph actually resolves to, see `NotResolved`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_version: Option<String>,
+    // the tables the parent is declared in, see `ExcludedDependencyKind`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependency_kinds: Vec<DependencyKind>,
+    // build scripts and proc macros that kept the build from running, see `UntrustedBuild`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub untrusted_build: Vec<String>,
+    // file the evidence was saved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evidence_path: Option<String>,
+}
+
+impl Clearance {
+    pub fn new(
+        precheck: PrecheckKind,
+        confidence: BTreeMap<String, Confidence>,
+        calls: &FunctionCalls,
+    ) -> Self {
+        let reason = if !calls.excluded().is_empty() {
+            ClearanceReason::ExcludedCallSites
+        } else if confidence.values().all(|c| *c == Confidence::None) {
+            ClearanceReason::NotReferenced
+        } else {
+            ClearanceReason::NoCallers
+        };
+        Self {
+            reason,
+            precheck,
+            confidence,
+            excluded: calls.excluded().clone(),
+            resolved_version: None,
+            dependency_kinds: Vec::new(),
+            untrusted_build: Vec::new(),
+            evidence_path: None,
+        }
+    }
+
+    /// the targets are referenced but the build wasn't run, `untrusted_build` lists the
+    /// build scripts and proc macros it would have executed
+    pub fn untrusted_build(
+        precheck: PrecheckKind,
+        confidence: BTreeMap<String, Confidence>,
+        untrusted_build: Vec<String>,
+    ) -> Self {
+        Self {
+            precheck,
+            confidence,
+            untrusted_build,
+            ..Self::before_analysis(ClearanceReason::UntrustedBuild)
+        }
+    }
+
+    /// the pinned parent version isn't part of the dependent's dependency graph,
+    /// `resolved_version` is the version it resolves to instead, if any
+    pub fn not_resolved(resolved_version: Option<String>) -> Self {
+        Self {
+            resolved_version,
+            ..Self::before_analysis(ClearanceReason::NotResolved)
+        }
+    }
+
+    /// the parent is only declared in the `dependency_kinds` tables, none of which counts
+    pub fn excluded_dependency_kind(dependency_kinds: Vec<DependencyKind>) -> Self {
+        Self {
+            dependency_kinds,
+            ..Self::before_analysis(ClearanceReason::ExcludedDependencyKind)
+        }
+    }
+
+    /// the same clearance with the target paths hashed, for the dependents of a sensitive
+    /// crate, whose targets are that crate's caller paths
+    pub fn with_hashed_targets(&self) -> Self {
+        Self {
+            confidence: hash_keys(&self.confidence),
+            ..self.clone()
+        }
+    }
+
+    // cleared before the sources were looked at, so there is no precheck evidence
+    fn before_analysis(reason: ClearanceReason) -> Self {
+        Self {
+            reason,
+            precheck: PrecheckKind::default(),
+            confidence: BTreeMap::new(),
+            excluded: BTreeMap::new(),
+            resolved_version: None,
+            dependency_kinds: Vec::new(),
+            untrusted_build: Vec::new(),
+            evidence_path: None,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let verdict = match self.reason {
+            ClearanceReason::UntrustedBuild => "unverified",
+            _ => "not_affected",
+        };
+        serde_json::json!({
+            "verdict": verdict,
+            "reason": self.reason.as_str(),
+            "precheck": self.precheck.as_str(),
+            "confidence": self.confidence,
+            "excluded": self.excluded,
+            "resolved_version": self.resolved_version,
+            "dependency_kinds": self.dependency_kinds,
+            "untrusted_build": self.untrusted_build,
+        })
+    }
+}
+
+/// callers found in one dependent, grouped by the target function they reach
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FunctionCalls {
+    // target function path -> callers.json produced by call-cg4rs for that function
+    callers: BTreeMap<String, serde_json::Value>,
+    // target function path -> paths the dependent re-exports it under (`pub use`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    reexports: BTreeMap<String, BTreeSet<String>>,
+    // caller path -> whether it is part of the dependent's public API
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    exposure: BTreeMap<String, ApiExposure>,
+    // caller path -> where the calling code lives
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    sites: BTreeMap<String, CallSite>,
+    // callers dropped because their call site category is excluded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    excluded: BTreeMap<CallSite, usize>,
+    // feature configuration -> whether a target is called under it, only recorded when
+    // more than the default configuration is analyzed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    feature_verdicts: BTreeMap<String, bool>,
+    // target triple -> whether a target is called when building for it, only recorded
+    // when platforms are given
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    platform_verdicts: BTreeMap<String, bool>,
+    // caller and re-export paths are hashes, see `minimized`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    minimized: bool,
+}
+
+impl FunctionCalls {
+    pub fn insert(&mut self, function_path: &str, callers_content: &str) {
+        let value = serde_json::from_str(callers_content)
+            .unwrap_or_else(|_| serde_json::Value::String(callers_content.to_owned()));
+        self.callers.insert(function_path.to_owned(), value);
+    }
+
+    /// add the callers `other` found for targets that have none here yet
+    pub fn merge_callers(&mut self, other: &FunctionCalls) {
+        for (function_path, value) in &other.callers {
+            self.callers
+                .entry(function_path.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    pub fn set_feature_verdict(&mut self, feature_set: &str, affected: bool) {
+        self.feature_verdicts
+            .insert(feature_set.to_owned(), affected);
+    }
+
+    pub fn feature_verdicts(&self) -> &BTreeMap<String, bool> {
+        &self.feature_verdicts
+    }
+
+    pub fn set_platform_verdict(&mut self, platform: &str, affected: bool) {
+        self.platform_verdicts.insert(platform.to_owned(), affected);
+    }
+
+    /// the platforms the dependent calls a target on, empty when no platforms were given
+    pub fn affected_platforms(&self) -> Vec<String> {
+        self.platform_verdicts
+            .iter()
+            .filter(|(_, affected)| **affected)
+            .map(|(platform, _)| platform.clone())
+            .collect()
+    }
+
+    /// record that the dependent re-exports `function_path` as `exposed_path`
+    pub fn insert_reexport(&mut self, function_path: &str, exposed_path: &str) {
+        self.reexports
+            .entry(function_path.to_owned())
+            .or_default()
+            .insert(exposed_path.to_owned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.callers.is_empty() && self.reexports.is_empty()
+    }
+
+    pub fn reexports(&self) -> &BTreeMap<String, BTreeSet<String>> {
+        &self.reexports
+    }
+
+    pub fn set_exposure(&mut self, caller_path: &str, exposure: ApiExposure) {
+        self.exposure.insert(caller_path.to_owned(), exposure);
+    }
+
+    /// classify every caller by call site and drop the callers of excluded categories,
+    /// targets left without callers are removed
+    pub fn classify_call_sites(
+        &mut self,
+        classify: impl Fn(&CallerInfo) -> CallSite,
+        excluded: &[CallSite],
+    ) {
+        let mut sites = BTreeMap::new();
+        let mut dropped: BTreeMap<CallSite, usize> = BTreeMap::new();
+        let mut retain = |entry: &serde_json::Value| {
+            let Some(caller) = parse_caller(entry) else {
+                return true;
+            };
+            let site = classify(&caller);
+            if excluded.contains(&site) {
+                *dropped.entry(site).or_default() += 1;
+                return false;
+            }
+            sites.insert(caller.path, site);
+            true
+        };
+        for value in self.callers.values_mut() {
+            match value {
+                serde_json::Value::Array(entries) => entries.retain(&mut retain),
+                serde_json::Value::Object(map) => {
+                    if let Some(serde_json::Value::Array(entries)) = map.get_mut("callers") {
+                        entries.retain(&mut retain);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.callers
+            .retain(|_, value| Self::count_callers(value) > 0);
+        self.sites = sites;
+        self.excluded = dropped;
+    }
+
+    pub fn call_site(&self, caller_path: &str) -> CallSite {
+        self.sites
+            .get(caller_path)
+            .copied()
+            .unwrap_or(CallSite::Lib)
+    }
+
+    /// number of callers dropped per excluded call site category
+    pub fn excluded(&self) -> &BTreeMap<CallSite, usize> {
+        &self.excluded
+    }
+
+    /// public API classification of a caller, `Unknown` when it wasn't classified
+    pub fn exposure(&self, caller_path: &str) -> ApiExposure {
+        self.exposure
+            .get(caller_path)
+            .copied()
+            .unwrap_or(ApiExposure::Unknown)
+    }
+
+    /// number of callers found for each target function
+    pub fn breakdown(&self) -> BTreeMap<String, usize> {
+        self.callers
+            .iter()
+            .map(|(function_path, value)| (function_path.clone(), Self::count_callers(value)))
+            .collect()
+    }
+
+    /// the caller functions found for each target function
+    pub fn callers(&self) -> BTreeMap<String, Vec<CallerInfo>> {
+        self.callers
+            .iter()
+            .map(|(function_path, value)| (function_path.clone(), parse_callers(value)))
+            .collect()
+    }
+
+    /// callers.json is either a list of callers or an object holding one
+    fn count_callers(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::Array(callers) => callers.len(),
+            serde_json::Value::Object(map) => match map.get("callers") {
+                Some(serde_json::Value::Array(callers)) => callers.len(),
+                _ => 1,
+            },
+            _ => 1,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut evidence = self
+            .callers
+            .clone()
+            .into_iter()
+            .collect::<serde_json::Map<_, _>>();
+        // target paths always contain `::`, so the key can't clash with a target
+        if !self.reexports.is_empty() {
+            evidence.insert("reexports".to_owned(), serde_json::json!(self.reexports));
+        }
+        if !self.exposure.is_empty() {
+            evidence.insert("exposure".to_owned(), serde_json::json!(self.exposure));
+        }
+        if !self.sites.is_empty() {
+            evidence.insert("call_sites".to_owned(), serde_json::json!(self.sites));
+        }
+        if !self.excluded.is_empty() {
+            evidence.insert("excluded".to_owned(), serde_json::json!(self.excluded));
+        }
+        if !self.feature_verdicts.is_empty() {
+            evidence.insert(
+                "feature_verdicts".to_owned(),
+                serde_json::json!(self.feature_verdicts),
+            );
+        }
+        if !self.platform_verdicts.is_empty() {
+            evidence.insert(
+                "platform_verdicts".to_owned(),
+                serde_json::json!(self.platform_verdicts),
+            );
+        }
+        serde_json::Value::Object(evidence)
+    }
+
+    /// the same verdicts for a sensitive crate: caller and re-export paths replaced by
+    /// their hash, file, line and everything else callers.json says about a caller dropped
+    pub fn minimized(&self) -> FunctionCalls {
+        let callers = self
+            .callers()
+            .into_iter()
+            .map(|(function_path, callers)| {
+                let entries = callers
+                    .iter()
+                    .map(|caller| {
+                        let mut entry = serde_json::json!({ "path": hashed_path(&caller.path) });
+                        if let Some(is_public) = caller.is_public {
+                            entry["is_public"] = serde_json::json!(is_public);
+                        }
+                        entry
+                    })
+                    .collect();
+                (function_path, serde_json::Value::Array(entries))
+            })
+            .collect();
+        FunctionCalls {
+            callers,
+            reexports: self
+                .reexports
+                .iter()
+                .map(|(target, paths)| {
+                    (
+                        target.clone(),
+                        paths.iter().map(|path| hashed_path(path)).collect(),
+                    )
+                })
+                .collect(),
+            exposure: hash_keys(&self.exposure),
+            sites: hash_keys(&self.sites),
+            excluded: self.excluded.clone(),
+            feature_verdicts: self.feature_verdicts.clone(),
+            platform_verdicts: self.platform_verdicts.clone(),
+            minimized: true,
+        }
+    }
+
+    /// the same calls with the target paths hashed, for the dependents of a sensitive
+    /// crate, whose targets are that crate's caller paths
+    pub fn with_hashed_targets(&self) -> FunctionCalls {
+        FunctionCalls {
+            callers: self
+                .callers
+                .iter()
+                .map(|(function_path, value)| (hashed_path(function_path), value.clone()))
+                .collect(),
+            reexports: self
+                .reexports
+                .iter()
+                .map(|(target, paths)| (hashed_path(target), paths.clone()))
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// evidence without any source detail: only caller counts and content hashes
+    pub fn to_minimized_json(&self) -> serde_json::Value {
+        let functions = self
+            .callers
+            .iter()
+            .map(|(function_path, value)| {
+                let digest = Sha256::digest(value.to_string().as_bytes());
+                (
+                    function_path.clone(),
+                    serde_json::json!({
+                        "callers": Self::count_callers(value),
+                        "sha256": hex::encode(digest),
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+        serde_json::json!({
+            "verdict": "affected",
+            "minimized": true,
+            "functions": functions,
+            "reexports": self.reexports.values().map(BTreeSet::len).sum::<usize>(),
+        })
+    }
+}
+
+/// a map keyed by function paths, with the paths hashed
+fn hash_keys<T: Copy>(map: &BTreeMap<String, T>) -> BTreeMap<String, T> {
+    map.iter()
+        .map(|(path, value)| (hashed_path(path), *value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an entry written with its raw path, which `tar::Builder` would refuse for
+    /// `..` and absolute paths
+    enum Entry<'a> {
+        File(&'a str, &'a str),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    /// a `.crate` file in a fresh directory, and the directory to extract it to
+    fn archive(test: &str, entries: &[Entry]) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir()
+            .join(format!("cross_pro_cg-extract-{}", std::process::id()))
+            .join(test);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pkg-1.0.0.crate");
+        let encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for entry in entries {
+            let (name, content, link, entry_type) = match entry {
+                Entry::File(name, content) => (*name, *content, None, tar::EntryType::Regular),
+                Entry::Symlink(name, link) => (*name, "", Some(*link), tar::EntryType::Symlink),
+                Entry::HardLink(name, link) => (*name, "", Some(*link), tar::EntryType::Link),
+            };
+            let mut header = tar::Header::new_gnu();
+            let raw = &mut header.as_gnu_mut().unwrap().name;
+            raw[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_mode(0o644);
+            header.set_size(content.len() as u64);
+            if let Some(link) = link {
+                header.set_link_name(link).unwrap();
+            }
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        (path, dir.join("pkg-1.0.0"))
+    }
+
+    #[test]
+    fn entries_escaping_the_destination_are_rejected() {
+        for (test, name) in [
+            ("parent", "../x"),
+            ("nested_parent", "pkg-1.0.0/../../x"),
+            ("absolute", "/tmp/x"),
+        ] {
+            let (path, destination) = archive(
+                test,
+                &[
+                    Entry::File("pkg-1.0.0/Cargo.toml", "[package]"),
+                    Entry::File(name, "owned"),
+                ],
+            );
+            let error = extract_crate(&path, &destination).unwrap_err();
+            assert!(
+                error.to_string().contains("escapes"),
+                "{}: {:#}",
+                name,
+                error
+            );
+            assert!(!destination.exists());
+            assert!(!path.parent().unwrap().parent().unwrap().join("x").exists());
+        }
+    }
+
+    #[test]
+    fn links_are_skipped() {
+        let (path, destination) = archive(
+            "links",
+            &[
+                Entry::File("pkg-1.0.0/src/lib.rs", "pub fn f() {}"),
+                Entry::Symlink("pkg-1.0.0/passwd", "/etc/passwd"),
+                Entry::HardLink("pkg-1.0.0/hard", "/etc/passwd"),
+            ],
+        );
+        assert_eq!(extract_crate(&path, &destination).unwrap(), 1);
+        assert!(destination.join("src/lib.rs").is_file());
+        assert!(std::fs::symlink_metadata(destination.join("passwd")).is_err());
+        assert!(std::fs::symlink_metadata(destination.join("hard")).is_err());
+    }
+
+    #[test]
+    fn top_level_directory_is_stripped_only_when_shared() {
+        let (path, destination) = archive(
+            "flat",
+            &[
+                Entry::File("Cargo.toml", "[package]"),
+                Entry::File("src/lib.rs", ""),
+            ],
+        );
+        assert_eq!(extract_crate(&path, &destination).unwrap(), 2);
+        assert!(destination.join("Cargo.toml").is_file());
+        assert!(destination.join("src/lib.rs").is_file());
+
+        // 顶层目录名不符时只警告，仍然去掉
+        let (path, destination) = archive(
+            "mismatched",
+            &[
+                Entry::File("other-2.0.0/Cargo.toml", "[package]"),
+                Entry::File("other-2.0.0/src/lib.rs", ""),
+            ],
+        );
+        assert_eq!(extract_crate(&path, &destination).unwrap(), 2);
+        assert!(destination.join("Cargo.toml").is_file());
+        assert!(!destination.join("other-2.0.0").exists());
+
+        let (path, destination) = archive(
+            "two_top_levels",
+            &[
+                Entry::File("a/Cargo.toml", ""),
+                Entry::File("b/Cargo.toml", ""),
+            ],
+        );
+        extract_crate(&path, &destination).unwrap();
+        assert!(destination.join("a/Cargo.toml").is_file());
+        assert!(destination.join("b/Cargo.toml").is_file());
+    }
+
+    #[test]
+    fn clearance_targets_of_sensitive_parents_are_hashed() {
+        let confidence =
+            BTreeMap::from([("secret_app::auth::parse_token".to_owned(), Confidence::Low)]);
+        let clearance = Clearance::new(
+            PrecheckKind::default(),
+            confidence,
+            &FunctionCalls::default(),
+        )
+        .with_hashed_targets();
+        let evidence = clearance.to_json().to_string();
+        assert!(!evidence.contains("parse_token"));
+        assert!(evidence.contains(&hashed_path("secret_app::auth::parse_token")));
+        assert_eq!(clearance.reason, ClearanceReason::NoCallers);
     }
 }
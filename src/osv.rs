@@ -0,0 +1,262 @@
+use anyhow::{bail, Context, Result};
+use semver::Version;
+
+/// one affected interval of an advisory, from an `introduced` event to the next `fixed`
+/// or `last_affected` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AffectedInterval {
+    // None for `introduced = "0"`, i.e. every version before the end
+    introduced: Option<Version>,
+    end: Option<IntervalEnd>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum IntervalEnd {
+    // the first version that is no longer affected
+    Fixed(Version),
+    // the last version that is still affected
+    LastAffected(Version),
+}
+
+impl AffectedInterval {
+    /// whether a published version is inside the interval, pre-releases are ordered like
+    /// any other version, as OSV does
+    pub fn contains(&self, version: &Version) -> bool {
+        if self
+            .introduced
+            .as_ref()
+            .is_some_and(|introduced| version < introduced)
+        {
+            return false;
+        }
+        match &self.end {
+            Some(IntervalEnd::Fixed(fixed)) => version < fixed,
+            Some(IntervalEnd::LastAffected(last)) => version <= last,
+            None => true,
+        }
+    }
+
+    /// how the interval is shown in logs and the report, e.g. `[0.5.0, 0.5.15)`
+    pub fn label(&self) -> String {
+        let start = self
+            .introduced
+            .as_ref()
+            .map_or_else(|| "0".to_string(), Version::to_string);
+        match &self.end {
+            Some(IntervalEnd::Fixed(fixed)) => format!("[{}, {})", start, fixed),
+            Some(IntervalEnd::LastAffected(last)) => format!("[{}, {}]", start, last),
+            None => format!("[{}, *)", start),
+        }
+    }
+}
+
+/// whether a version range is written as advisory events rather than a semver requirement
+pub fn is_events(range: &str) -> bool {
+    range.trim_start().starts_with('[')
+}
+
+/// parse the range events of a RustSec / OSV advisory into affected intervals
+///
+/// both the TOML form `[{ introduced = "0.5.0", fixed = "0.5.15" }]` and the OSV JSON
+/// form `[{"introduced": "0.5.0"}, {"fixed": "0.5.15"}]` are accepted; an object may
+/// hold several events. Like OSV, the events are applied in version order
+pub fn parse_events(range: &str) -> Result<Vec<AffectedInterval>> {
+    let mut events = read_events(range)?;
+    if events.is_empty() {
+        bail!("版本区间中没有事件: {}", range);
+    }
+    // `introduced = "0"` 排在最前面，同一版本上引入排在修复之前
+    events.sort_by(|(kind_a, version_a), (kind_b, version_b)| {
+        version_a
+            .cmp(version_b)
+            .then_with(|| (*kind_a != "introduced").cmp(&(*kind_b != "introduced")))
+    });
+
+    let mut intervals = Vec::new();
+    let mut open: Option<Option<Version>> = None;
+    for (kind, version) in events {
+        match kind {
+            "introduced" => {
+                open.get_or_insert(version);
+            }
+            "fixed" | "last_affected" => {
+                let Some(introduced) = open.take() else {
+                    // 没有对应的引入事件，不构成区间
+                    continue;
+                };
+                let version = version.context(format!("{} 不能是 0", kind))?;
+                let end = if kind == "fixed" {
+                    IntervalEnd::Fixed(version)
+                } else {
+                    IntervalEnd::LastAffected(version)
+                };
+                intervals.push(AffectedInterval {
+                    introduced,
+                    end: Some(end),
+                });
+            }
+            // `limit` 只用于 git 提交区间
+            _ => {}
+        }
+    }
+    if let Some(introduced) = open {
+        intervals.push(AffectedInterval {
+            introduced,
+            end: None,
+        });
+    }
+    Ok(intervals)
+}
+
+/// the events as (kind, version) pairs, version None for `"0"`
+fn read_events(range: &str) -> Result<Vec<(&'static str, Option<Version>)>> {
+    let pairs = match serde_json::from_str::<Vec<serde_json::Map<String, serde_json::Value>>>(range)
+    {
+        Ok(objects) => objects
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key, value.as_str().map(str::to_owned)))
+            .collect::<Vec<_>>(),
+        Err(_) => read_toml_events(range)?,
+    };
+    let mut events = Vec::new();
+    for (key, value) in pairs {
+        let kind = match key.as_str() {
+            "introduced" => "introduced",
+            "fixed" => "fixed",
+            "last_affected" => "last_affected",
+            "limit" => "limit",
+            _ => bail!("未知的版本事件: {}", key),
+        };
+        let value = value.context(format!("{} 的值不是字符串", key))?;
+        let version = match value.trim() {
+            "0" => None,
+            version => Some(
+                Version::parse(version).context(format!("{} 不是合法的版本: {}", key, value))?,
+            ),
+        };
+        events.push((kind, version));
+    }
+    Ok(events)
+}
+
+fn read_toml_events(range: &str) -> Result<Vec<(String, Option<String>)>> {
+    let document = format!("events = {}", range)
+        .parse::<toml_edit::DocumentMut>()
+        .context(format!("无法解析版本事件: {}", range))?;
+    let events = document["events"]
+        .as_array()
+        .context(format!("版本事件应是数组: {}", range))?;
+    let mut pairs = Vec::new();
+    for event in events {
+        let table = event.as_inline_table().context(format!(
+            "版本事件应是 {{ introduced = \"...\" }} 形式: {}",
+            range
+        ))?;
+        for (key, value) in table.iter() {
+            pairs.push((key.to_string(), value.as_str().map(str::to_owned)));
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(range: &str) -> Vec<String> {
+        parse_events(range)
+            .unwrap()
+            .iter()
+            .map(AffectedInterval::label)
+            .collect()
+    }
+
+    #[test]
+    fn events_become_intervals() {
+        let cases: &[(&str, &[&str])] = &[
+            (
+                r#"[{ introduced = "0.5.0", fixed = "0.5.15" }]"#,
+                &["[0.5.0, 0.5.15)"],
+            ),
+            (
+                r#"[{"introduced": "0.5.0"}, {"fixed": "0.5.15"}]"#,
+                &["[0.5.0, 0.5.15)"],
+            ),
+            (
+                r#"[{"introduced": "1.0.0", "fixed": "1.1.0"}]"#,
+                &["[1.0.0, 1.1.0)"],
+            ),
+            (
+                r#"[{ introduced = "0", fixed = "1.2.3" }]"#,
+                &["[0, 1.2.3)"],
+            ),
+            (
+                r#"[{ introduced = "1.0.0", last_affected = "1.4.2" }]"#,
+                &["[1.0.0, 1.4.2]"],
+            ),
+            (r#"[{"introduced": "0"}]"#, &["[0, *)"]),
+            // 事件按版本排序后再配对
+            (
+                r#"[{"fixed": "2.0.0"}, {"introduced": "1.5.0"}, {"fixed": "1.0.0"}, {"introduced": "0"}]"#,
+                &["[0, 1.0.0)", "[1.5.0, 2.0.0)"],
+            ),
+            // 同一版本上先引入后修复
+            (
+                r#"[{"fixed": "1.0.0"}, {"introduced": "1.0.0"}]"#,
+                &["[1.0.0, 1.0.0)"],
+            ),
+            // 没有引入事件的修复被忽略
+            (
+                r#"[{"fixed": "1.0.0"}, {"introduced": "2.0.0"}]"#,
+                &["[2.0.0, *)"],
+            ),
+            (r#"[{ fixed = "1.0.0" }]"#, &[]),
+            // 重复的引入事件不开启新区间
+            (
+                r#"[{"introduced": "1.0.0"}, {"introduced": "1.2.0"}, {"fixed": "1.3.0"}]"#,
+                &["[1.0.0, 1.3.0)"],
+            ),
+            (r#"[{"introduced": "0"}, {"limit": "3.0.0"}]"#, &["[0, *)"]),
+        ];
+        for (range, expected) in cases {
+            assert!(is_events(range));
+            assert_eq!(labels(range), *expected, "{}", range);
+        }
+    }
+
+    #[test]
+    fn interval_boundaries() {
+        let version = |version: &str| Version::parse(version).unwrap();
+        let fixed = &parse_events(r#"[{ introduced = "0.5.0", fixed = "0.5.15" }]"#).unwrap()[0];
+        assert!(!fixed.contains(&version("0.4.9")));
+        assert!(fixed.contains(&version("0.5.0")));
+        assert!(fixed.contains(&version("0.5.15-alpha.1")));
+        assert!(!fixed.contains(&version("0.5.15")));
+
+        let last =
+            &parse_events(r#"[{"introduced": "0"}, {"last_affected": "1.4.2"}]"#).unwrap()[0];
+        assert!(last.contains(&version("0.0.1")));
+        assert!(last.contains(&version("1.4.2")));
+        assert!(!last.contains(&version("1.4.3")));
+
+        let open = &parse_events(r#"[{ introduced = "2.0.0" }]"#).unwrap()[0];
+        assert!(!open.contains(&version("1.9.9")));
+        assert!(open.contains(&version("99.0.0")));
+    }
+
+    #[test]
+    fn malformed_events_are_rejected() {
+        for range in [
+            "[]",
+            r#"[{ introduced = "0", fixed = "0" }]"#,
+            r#"[{"introduced": "0", "patched": "1.0.0"}]"#,
+            r#"[{"introduced": "1.0"}]"#,
+            r#"[{"introduced": 1}]"#,
+            r#"[ "0.5.0" ]"#,
+        ] {
+            assert!(parse_events(range).is_err(), "{}", range);
+        }
+        assert!(!is_events(">= 0.5.0, < 0.5.15"));
+    }
+}
@@ -0,0 +1,673 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::sinks::UTF8;
+use grep_searcher::{BinaryDetection, SearcherBuilder};
+use ignore::{WalkBuilder, WalkState};
+use serde::{Deserialize, Serialize};
+use syn::visit::{self, Visit};
+
+use crate::target;
+
+/// how likely a dependent references a target function, judged from its sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    // the function name never appears, call-cg4rs can be skipped
+    None,
+    // the function name appears, but nothing refers to the crate defining it
+    Low,
+    // the crate is referenced and the function name appears
+    Medium,
+    // a path naming the function (or its type/module and the function) is used
+    High,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confidence::None => "none",
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+/// how a dependent is checked for references to the target functions before call-cg4rs
+/// runs on it, from the cheapest (and most likely to let a false positive through) to
+/// the most precise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrecheckKind {
+    // no precheck, call-cg4rs runs for every target
+    None,
+    // the function name occurs somewhere in the sources
+    Identifier,
+    // the crate defining the function is imported (`use lib` or `lib::`) and the type or
+    // module containing the function is named
+    #[default]
+    Import,
+    // the sources are parsed and the paths they use are compared to the target
+    Ast,
+}
+
+impl PrecheckKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrecheckKind::None => "none",
+            PrecheckKind::Identifier => "identifier",
+            PrecheckKind::Import => "import",
+            PrecheckKind::Ast => "ast",
+        }
+    }
+
+    pub fn strategy(self) -> Box<dyn PrecheckStrategy> {
+        match self {
+            PrecheckKind::None => Box::new(NoPrecheck),
+            PrecheckKind::Identifier => Box::new(IdentifierPrecheck),
+            PrecheckKind::Import => Box::new(ImportPrecheck),
+            PrecheckKind::Ast => Box::new(AstPrecheck),
+        }
+    }
+}
+
+impl FromStr for PrecheckKind {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.trim() {
+            "none" | "off" => Ok(PrecheckKind::None),
+            "identifier" => Ok(PrecheckKind::Identifier),
+            "import" => Ok(PrecheckKind::Import),
+            "ast" => Ok(PrecheckKind::Ast),
+            _ => bail!("未知的预检查策略: {}", name),
+        }
+    }
+}
+
+/// decides which target functions the sources of a dependent plausibly reference,
+/// call-cg4rs is skipped for targets judged `Confidence::None`
+///
+/// runs on a blocking thread, the sources are read synchronously
+pub trait PrecheckStrategy: Send + Sync {
+    fn kind(&self) -> PrecheckKind;
+
+    /// confidence per target, `targets` maps each target to the spellings of its path
+    fn check(
+        &self,
+        src_dir: &Path,
+        targets: &BTreeMap<String, Vec<String>>,
+    ) -> Result<BTreeMap<String, Confidence>>;
+}
+
+/// reports every target as `Medium`, nothing is filtered
+pub struct NoPrecheck;
+
+impl PrecheckStrategy for NoPrecheck {
+    fn kind(&self) -> PrecheckKind {
+        PrecheckKind::None
+    }
+
+    fn check(
+        &self,
+        _src_dir: &Path,
+        targets: &BTreeMap<String, Vec<String>>,
+    ) -> Result<BTreeMap<String, Confidence>> {
+        Ok(targets
+            .keys()
+            .map(|target| (target.clone(), Confidence::Medium))
+            .collect())
+    }
+}
+
+/// `Low` when the function name occurs as a whole word
+pub struct IdentifierPrecheck;
+
+impl PrecheckStrategy for IdentifierPrecheck {
+    fn kind(&self) -> PrecheckKind {
+        PrecheckKind::Identifier
+    }
+
+    fn check(
+        &self,
+        src_dir: &Path,
+        targets: &BTreeMap<String, Vec<String>>,
+    ) -> Result<BTreeMap<String, Confidence>> {
+        let found = find_identifiers(src_dir, &function_names(targets))?;
+        Ok(targets
+            .iter()
+            .map(|(target, candidates)| {
+                let confidence = if candidates
+                    .iter()
+                    .any(|candidate| found.contains(target::function_name(candidate)))
+                {
+                    Confidence::Low
+                } else {
+                    Confidence::None
+                };
+                (target.clone(), confidence)
+            })
+            .collect())
+    }
+}
+
+/// `Medium` when the crate defining the function is imported and the item the function
+/// belongs to is named, the function name itself only counts for functions at the crate
+/// root
+///
+/// names like `drop` or `new` occur in nearly every crate, the type they belong to
+/// rarely does
+pub struct ImportPrecheck;
+
+impl PrecheckStrategy for ImportPrecheck {
+    fn kind(&self) -> PrecheckKind {
+        PrecheckKind::Import
+    }
+
+    fn check(
+        &self,
+        src_dir: &Path,
+        targets: &BTreeMap<String, Vec<String>>,
+    ) -> Result<BTreeMap<String, Confidence>> {
+        let crates = targets
+            .values()
+            .flatten()
+            .map(|candidate| candidate_crate(candidate))
+            .collect::<BTreeSet<_>>();
+        let imported = find_imports(src_dir, &crates.into_iter().collect::<Vec<_>>())?;
+        if imported.is_empty() {
+            return Ok(targets
+                .keys()
+                .map(|target| (target.clone(), Confidence::None))
+                .collect());
+        }
+        let names = targets
+            .values()
+            .flatten()
+            .map(|candidate| {
+                item_name(candidate)
+                    .unwrap_or_else(|| target::function_name(candidate))
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        let found = find_identifiers(src_dir, &names)?;
+        Ok(targets
+            .iter()
+            .map(|(target, candidates)| {
+                let confidence = if candidates.iter().any(|candidate| {
+                    let name =
+                        item_name(candidate).unwrap_or_else(|| target::function_name(candidate));
+                    imported.contains(&candidate_crate(candidate)) && found.contains(name)
+                }) {
+                    Confidence::Medium
+                } else {
+                    Confidence::None
+                };
+                (target.clone(), confidence)
+            })
+            .collect())
+    }
+}
+
+/// searches the function names first and only parses the sources when one occurs,
+/// see `SourceIndex::confidence`
+pub struct AstPrecheck;
+
+impl PrecheckStrategy for AstPrecheck {
+    fn kind(&self) -> PrecheckKind {
+        PrecheckKind::Ast
+    }
+
+    fn check(
+        &self,
+        src_dir: &Path,
+        targets: &BTreeMap<String, Vec<String>>,
+    ) -> Result<BTreeMap<String, Confidence>> {
+        let found = find_identifiers(src_dir, &function_names(targets))?;
+        let index = if found.is_empty() {
+            SourceIndex::default()
+        } else {
+            SourceIndex::build(src_dir)?
+        };
+        Ok(targets
+            .iter()
+            .map(|(target, candidates)| (target.clone(), index.confidence(candidates)))
+            .collect())
+    }
+}
+
+/// last path segment of every spelling of every target
+fn function_names(targets: &BTreeMap<String, Vec<String>>) -> Vec<String> {
+    targets
+        .values()
+        .flatten()
+        .map(|candidate| target::function_name(candidate).to_string())
+        .collect()
+}
+
+/// library name a target path starts with, `<` of qualified paths stripped and a
+/// package name like `foo-bar` spelled the way sources use it
+fn candidate_crate(candidate: &str) -> String {
+    candidate
+        .trim_start_matches('<')
+        .split("::")
+        .next()
+        .unwrap_or_default()
+        .replace('-', "_")
+}
+
+/// the type or module a target function is defined in, None for functions at the
+/// crate root and for `{impl#N}` blocks
+fn item_name(candidate: &str) -> Option<&str> {
+    let path = match candidate.strip_prefix('<') {
+        // `<lib::Type<T> as Trait>::function`: the self type
+        Some(qualified) => qualified.split(" as ").next()?.split('<').next()?,
+        None => candidate.rsplit_once("::")?.0,
+    };
+    // the crate name has to precede the item
+    let (_, item) = path.rsplit_once("::")?;
+    is_identifier(item).then_some(item)
+}
+
+/// identifiers and paths used anywhere in the sources of a crate
+#[derive(Debug, Default)]
+pub struct SourceIndex {
+    idents: HashSet<String>,
+    // every path as its segments, `use` trees flattened
+    paths: HashSet<Vec<String>>,
+}
+
+impl SourceIndex {
+    /// index every `.rs` file below `src_dir`
+    ///
+    /// files syn can't parse only contribute their identifier-like tokens
+    pub fn build(src_dir: &Path) -> Result<Self> {
+        let mut index = Self::default();
+        for file in rust_files(src_dir) {
+            let content = std::fs::read_to_string(&file)
+                .context(format!("读取源文件失败: {}", file.display()))?;
+            index.add_source(&content);
+        }
+        Ok(index)
+    }
+
+    pub fn from_source(content: &str) -> Self {
+        let mut index = Self::default();
+        index.add_source(content);
+        index
+    }
+
+    fn add_source(&mut self, content: &str) {
+        match syn::parse_file(content) {
+            Ok(file) => IndexVisitor { index: self }.visit_file(&file),
+            Err(_) => self.idents.extend(
+                content
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .filter(|word| !word.is_empty())
+                    .map(str::to_owned),
+            ),
+        }
+    }
+
+    /// confidence that the sources reference the function spelled as one of `candidates`
+    pub fn confidence(&self, candidates: &[String]) -> Confidence {
+        candidates
+            .iter()
+            .map(|candidate| self.candidate_confidence(candidate))
+            .max()
+            .unwrap_or(Confidence::None)
+    }
+
+    fn candidate_confidence(&self, candidate: &str) -> Confidence {
+        let function_name = target::function_name(candidate);
+        if !self.idents.contains(function_name) {
+            return Confidence::None;
+        }
+        let lib_name = candidate_crate(candidate);
+        let references_crate = self
+            .paths
+            .iter()
+            .any(|path| path.first().is_some_and(|first| *first == lib_name));
+        if !references_crate {
+            return Confidence::Low;
+        }
+        // `Channel::drop` or `list::Channel::drop`: the function named through its parent
+        let segments = candidate.split("::").collect::<Vec<_>>();
+        let qualified = segments.len() >= 2
+            && !candidate.starts_with('<')
+            && self.paths.iter().any(|path| {
+                path.len() >= 2
+                    && path[path.len() - 2..]
+                        .iter()
+                        .zip(&segments[segments.len() - 2..])
+                        .all(|(used, expected)| used == expected)
+            });
+        if qualified {
+            Confidence::High
+        } else {
+            Confidence::Medium
+        }
+    }
+}
+
+struct IndexVisitor<'a> {
+    index: &'a mut SourceIndex,
+}
+
+impl<'ast> Visit<'ast> for IndexVisitor<'_> {
+    fn visit_ident(&mut self, ident: &'ast syn::Ident) {
+        self.index.idents.insert(ident.to_string());
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        self.index.paths.insert(
+            path.segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect(),
+        );
+        visit::visit_path(self, path);
+    }
+
+    fn visit_item_use(&mut self, item_use: &'ast syn::ItemUse) {
+        let mut prefix = Vec::new();
+        collect_use_paths(&mut prefix, &item_use.tree, &mut self.index.paths);
+        visit::visit_item_use(self, item_use);
+    }
+
+    // macro bodies are plain tokens, their identifiers still count
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        collect_token_idents(mac.tokens.clone(), &mut self.index.idents);
+        visit::visit_macro(self, mac);
+    }
+}
+
+fn collect_use_paths(
+    prefix: &mut Vec<String>,
+    tree: &syn::UseTree,
+    paths: &mut HashSet<Vec<String>>,
+) {
+    match tree {
+        syn::UseTree::Path(use_path) => {
+            prefix.push(use_path.ident.to_string());
+            collect_use_paths(prefix, &use_path.tree, paths);
+            prefix.pop();
+        }
+        syn::UseTree::Name(use_name) => {
+            let mut path = prefix.clone();
+            path.push(use_name.ident.to_string());
+            paths.insert(path);
+        }
+        syn::UseTree::Rename(use_rename) => {
+            let mut path = prefix.clone();
+            path.push(use_rename.ident.to_string());
+            paths.insert(path);
+        }
+        syn::UseTree::Glob(_) => {
+            paths.insert(prefix.clone());
+        }
+        syn::UseTree::Group(group) => {
+            for item in &group.items {
+                collect_use_paths(prefix, item, paths);
+            }
+        }
+    }
+}
+
+fn collect_token_idents(tokens: proc_macro2::TokenStream, idents: &mut HashSet<String>) {
+    for token in tokens {
+        match token {
+            proc_macro2::TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            proc_macro2::TokenTree::Group(group) => collect_token_idents(group.stream(), idents),
+            _ => {}
+        }
+    }
+}
+
+/// the identifiers among `identifiers` occurring as a whole word in the `.rs` files
+/// below `dir`
+///
+/// searched in-process on all cores, `.gitignore`d and binary files are skipped
+pub fn find_identifiers(dir: &Path, identifiers: &[String]) -> Result<HashSet<String>> {
+    let identifiers = identifiers
+        .iter()
+        .filter(|identifier| is_identifier(identifier))
+        .cloned()
+        .collect::<Vec<_>>();
+    if identifiers.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let matcher = RegexMatcherBuilder::new()
+        .word(true)
+        .build(&identifiers.join("|"))
+        .context("构造搜索表达式失败")?;
+    search(dir, &matcher)
+}
+
+/// the crates among `crates` imported in the `.rs` files below `dir`, either with
+/// `use lib`, `extern crate lib` or through a path starting with `lib::`
+pub fn find_imports(dir: &Path, crates: &[String]) -> Result<HashSet<String>> {
+    let crates = crates
+        .iter()
+        .filter(|name| is_identifier(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    if crates.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let alternatives = crates.join("|");
+    let matcher = RegexMatcherBuilder::new()
+        .build(&format!(
+            r"\b(?:use|extern\s+crate)\s+(?:::)?(?:{0})\b|\b(?:{0})\s*::",
+            alternatives
+        ))
+        .context("构造搜索表达式失败")?;
+    // a match is the import statement or path prefix, keep only the crate name
+    Ok(search(dir, &matcher)?
+        .iter()
+        .filter_map(|matched| {
+            matched
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .find(|word| !word.is_empty() && !matches!(*word, "use" | "extern" | "crate"))
+                .map(str::to_owned)
+        })
+        .collect())
+}
+
+fn is_identifier(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// every match of `matcher` in the `.rs` files below `dir`, searched in parallel
+fn search(dir: &Path, matcher: &RegexMatcher) -> Result<HashSet<String>> {
+    let found = Mutex::new(HashSet::new());
+    WalkBuilder::new(dir).build_parallel().run(|| {
+        let matcher = matcher.clone();
+        let found = &found;
+        let mut searcher = SearcherBuilder::new()
+            .binary_detection(BinaryDetection::quit(b'\x00'))
+            .build();
+        Box::new(move |entry| {
+            let Ok(entry) = entry else {
+                return WalkState::Continue;
+            };
+            if !is_rust_file(entry.path()) {
+                return WalkState::Continue;
+            }
+            let mut matched = Vec::new();
+            let result = searcher.search_path(
+                &matcher,
+                entry.path(),
+                UTF8(|_, line| {
+                    matcher.find_iter(line.as_bytes(), |m| {
+                        matched.push(line[m].to_owned());
+                        true
+                    })?;
+                    Ok(true)
+                }),
+            );
+            if let Err(e) = result {
+                tracing::warn!("搜索 {} 失败: {}", entry.path().display(), e);
+            }
+            found.lock().unwrap().extend(matched);
+            WalkState::Continue
+        })
+    });
+    Ok(found.into_inner().unwrap())
+}
+
+fn is_rust_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "rs")
+}
+
+/// all `.rs` files below `dir`, `.gitignore`d files excluded
+fn rust_files(dir: &Path) -> Vec<PathBuf> {
+    WalkBuilder::new(dir)
+        .build()
+        .filter_map(Result::ok)
+        .map(|entry| entry.into_path())
+        .filter(|path| is_rust_file(path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a crate directory with the given `.rs` files
+    fn sources(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("cross_pro_cg-precheck-{}", std::process::id()))
+            .join(test);
+        let _ = std::fs::remove_dir_all(&dir);
+        for (file, content) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn import_confidence(test: &str, source: &str, target: &str) -> Confidence {
+        let dir = sources(test, &[("src/lib.rs", source)]);
+        let targets = BTreeMap::from([(target.to_owned(), vec![target.to_owned()])]);
+        let confidence = ImportPrecheck.check(&dir, &targets).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        confidence[target]
+    }
+
+    #[test]
+    fn item_names() {
+        for (candidate, expected) in [
+            ("foo::Bar::baz", Some("Bar")),
+            ("foo::bar::baz", Some("bar")),
+            ("foo::baz", None),
+            ("<foo::Bar<T> as Drop>::drop", Some("Bar")),
+            ("<foo::Bar as foo::Trait>::run", Some("Bar")),
+            ("foo::{impl#1}::baz", None),
+        ] {
+            assert_eq!(item_name(candidate), expected, "{}", candidate);
+        }
+    }
+
+    #[test]
+    fn imports_are_found_however_they_are_written() {
+        let dir = sources(
+            "find_imports",
+            &[
+                ("src/lib.rs", "extern crate alpha;\nuse ::beta::Item;"),
+                ("src/nested/mod.rs", "fn f() { gamma :: run(); }"),
+                ("src/other.rs", "use delta_extra::Item; // delta"),
+                ("README.md", "use epsilon;"),
+            ],
+        );
+        let crates = ["alpha", "beta", "gamma", "delta", "epsilon"].map(String::from);
+        let imported = find_imports(&dir, &crates).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(
+            imported,
+            HashSet::from(["alpha", "beta", "gamma"].map(String::from))
+        );
+    }
+
+    #[test]
+    fn import_precheck_finds_every_way_of_naming_a_target() {
+        for (test, source, target) in [
+            (
+                "glob",
+                "use foo::*;\nfn f() { Bar::baz(); }",
+                "foo::Bar::baz",
+            ),
+            (
+                "glob_module",
+                "use foo::bar::*;\nfn f() { baz(); }",
+                "foo::bar::baz",
+            ),
+            (
+                "renamed_type",
+                "use foo::Bar as Renamed;\nfn f() { Renamed::baz(); }",
+                "foo::Bar::baz",
+            ),
+            (
+                "renamed_function",
+                "use foo::baz as renamed;\nfn f() { renamed(); }",
+                "foo::baz",
+            ),
+            (
+                "nested_group",
+                "use foo::{a::{b::{self, Other}}, c};\nfn f() { b::run(); }",
+                "foo::a::b::run",
+            ),
+            ("qualified", "fn f() { foo::Bar::baz(); }", "foo::Bar::baz"),
+            (
+                "qualified_trait",
+                "fn f(x: foo::Bar) { <foo::Bar as Drop>::drop(&mut x); }",
+                "<foo::Bar as Drop>::drop",
+            ),
+            (
+                "dashed_target",
+                "use foo_bar::Baz;\nfn f() { Baz::run(); }",
+                "foo-bar::Baz::run",
+            ),
+        ] {
+            assert_eq!(
+                import_confidence(test, source, target),
+                Confidence::Medium,
+                "{}",
+                test
+            );
+        }
+    }
+
+    #[test]
+    fn import_precheck_needs_the_crate_and_the_item() {
+        for (test, source, target) in [
+            ("not_imported", "fn f() { Bar::baz(); }", "foo::Bar::baz"),
+            (
+                "prefix_crate",
+                "use foo_extra::Bar;\nfn f() { Bar::baz(); }",
+                "foo::Bar::baz",
+            ),
+            (
+                "item_missing",
+                "use foo::Other;\nfn f() { baz(); }",
+                "foo::Bar::baz",
+            ),
+        ] {
+            assert_eq!(
+                import_confidence(test, source, target),
+                Confidence::None,
+                "{}",
+                test
+            );
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+use crate::surface::PublicSurface;
+
+/// a `pub use` in a dependent that exposes a target function, either directly or
+/// through a re-exported module or type containing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReExport {
+    // the target as reported, e.g. `crossbeam_channel::flavors::list::Channel::drop`
+    pub target: String,
+    // the path the function is reachable at through the dependent, e.g.
+    // `my_crate::channel::Channel::drop` for `pub use crossbeam_channel::flavors::list::Channel`
+    pub exposed_path: String,
+}
+
+/// find the `pub use` items of a dependent re-exporting one of the targets
+///
+/// `targets` maps every target name to the paths it may be spelled as
+pub fn find_reexports(
+    surface: &PublicSurface,
+    targets: &BTreeMap<String, Vec<String>>,
+) -> Vec<ReExport> {
+    let mut reexports = Vec::new();
+    for (target, candidates) in targets {
+        for candidate in candidates {
+            // `<T as Trait>::method` can't be named by a `use`
+            if candidate.starts_with('<') {
+                continue;
+            }
+            let segments = candidate.split("::").collect::<Vec<_>>();
+            for pub_use in surface.pub_uses() {
+                if let Some(exposed_path) = pub_use.expose(&segments) {
+                    let reexport = ReExport {
+                        target: target.clone(),
+                        exposed_path,
+                    };
+                    if !reexports.contains(&reexport) {
+                        reexports.push(reexport);
+                    }
+                }
+            }
+        }
+    }
+    reexports
+}
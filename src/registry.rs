@@ -0,0 +1,202 @@
+use std::env;
+
+use once_cell::sync::Lazy;
+
+const CRATES_IO_API: &str = "https://crates.io/api/v1";
+const CRATES_IO_DL: &str = "https://crates.io/api/v1/crates";
+const CRATES_IO_INDEX: &str = "https://index.crates.io";
+const CRATES_IO_CDN: &str = "https://static.crates.io/crates/{crate}/{crate}-{version}.crate";
+
+// 进程内只读一次环境变量，下载和依赖者查询共用
+pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::from_env);
+
+/// the registry crates are downloaded from and dependents are looked up in, crates.io
+/// unless a private registry (Kellnr, Artifactory, Cloudsmith, ...) is configured
+#[derive(Debug, Clone)]
+pub struct Registry {
+    // download endpoint, the `dl` key of the registry's index config.json, from
+    // `REGISTRY_DL` (default crates.io)
+    pub dl: String,
+    // sparse index the expected checksums are read from, from `REGISTRY_INDEX`
+    // (default crates.io), the `sparse+` prefix is optional
+    pub index: String,
+    // web API, e.g. `https://crates.io/api/v1`, from `REGISTRY_API` or
+    // `CRATES_IO_API_URL`
+    pub api: Option<String>,
+    // sent as the `Authorization` header to the index and the `dl` host, never to
+    // mirrors or the CDN, from `REGISTRY_TOKEN`
+    pub token: Option<String>,
+    // download from static.crates.io first and use the API endpoint as the fallback,
+    // from `DOWNLOAD_PREFER_CDN` (default false), ignored for other registries
+    pub prefer_cdn: bool,
+    // download endpoints of internal mirrors, tried before the registry itself, from
+    // `DOWNLOAD_MIRRORS`, comma separated, same format as `dl`
+    pub mirrors: Vec<String>,
+}
+
+impl Registry {
+    pub fn from_env() -> Self {
+        Self {
+            dl: env::var("REGISTRY_DL").unwrap_or_else(|_| CRATES_IO_DL.to_string()),
+            index: env::var("REGISTRY_INDEX")
+                .map(|index| index.trim_start_matches("sparse+").to_string())
+                .unwrap_or_else(|_| CRATES_IO_INDEX.to_string()),
+            api: env::var("REGISTRY_API")
+                .or_else(|_| env::var("CRATES_IO_API_URL"))
+                .ok(),
+            token: env::var("REGISTRY_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            prefer_cdn: crate::config::env_flag("DOWNLOAD_PREFER_CDN", false),
+            mirrors: env::var("DOWNLOAD_MIRRORS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|mirror| !mirror.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+
+    /// whether this is crates.io, the only registry the database dump covers
+    pub fn is_crates_io(&self) -> bool {
+        self.dl.trim_end_matches('/') == CRATES_IO_DL
+    }
+
+    /// the web API to ask for dependents when there is no database: the configured one,
+    /// or for crates.io its public API
+    pub fn api_or_default(&self) -> Option<String> {
+        match &self.api {
+            Some(api) => Some(api.clone()),
+            None if self.is_crates_io() => Some(CRATES_IO_API.to_string()),
+            None => None,
+        }
+    }
+
+    /// whether `url` is served by the registry's download host, the only host `token`
+    /// is sent to when downloading
+    pub fn is_registry_url(&self, url: &str) -> bool {
+        let origin = origin(url);
+        origin.is_some() && origin == self::origin(&self.dl)
+    }
+
+    /// whether `url` is served by one of the mirrors, the only hosts the mirror auth
+    /// header is sent to
+    pub fn is_mirror_url(&self, url: &str) -> bool {
+        let origin = origin(url);
+        origin.is_some()
+            && self
+                .mirrors
+                .iter()
+                .any(|mirror| self::origin(mirror) == origin)
+    }
+
+    /// download URLs to try in order: the mirrors, then for crates.io the API endpoint
+    /// (which counts downloads and may rate limit) and the static.crates.io CDN
+    pub fn download_urls(&self, name: &str, version: &str) -> Vec<String> {
+        let mut urls = self
+            .mirrors
+            .iter()
+            .map(|mirror| expand_dl(mirror, name, version))
+            .collect::<Vec<_>>();
+        let primary = expand_dl(&self.dl, name, version);
+        if !self.is_crates_io() {
+            urls.push(primary);
+            return urls;
+        }
+        let cdn = CRATES_IO_CDN
+            .replace("{crate}", name)
+            .replace("{version}", version);
+        if self.prefer_cdn {
+            urls.extend([cdn, primary]);
+        } else {
+            urls.extend([primary, cdn]);
+        }
+        urls
+    }
+}
+
+/// host and port of a URL, `dl` templates included
+fn origin(url: &str) -> Option<(String, u16)> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some((
+        url.host_str()?.to_ascii_lowercase(),
+        url.port_or_known_default()?,
+    ))
+}
+
+/// download URL of a crate file, with the same rules as cargo: `{crate}`, `{version}`,
+/// `{prefix}` and `{lowerprefix}` markers in `dl` are replaced,
+/// `/{crate}/{version}/download` is appended when there are none
+///
+/// `{sha256-checksum}` needs the index entry and isn't supported
+fn expand_dl(dl: &str, name: &str, version: &str) -> String {
+    const MARKERS: &[&str] = &[
+        "{crate}",
+        "{version}",
+        "{prefix}",
+        "{lowerprefix}",
+        "{sha256-checksum}",
+    ];
+    if !MARKERS.iter().any(|marker| dl.contains(marker)) {
+        return format!("{}/{}/{}/download", dl.trim_end_matches('/'), name, version);
+    }
+    let prefix = index_prefix(name);
+    dl.replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{prefix}", &prefix)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+}
+
+impl Registry {
+    /// URL of the index file listing every version of a crate
+    pub fn index_url(&self, name: &str) -> String {
+        let name = name.to_lowercase();
+        format!(
+            "{}/{}/{}",
+            self.index.trim_end_matches('/'),
+            index_prefix(&name),
+            name
+        )
+    }
+}
+
+/// directory of a crate in a registry index: `1`, `2`, `3/a` or `ab/cd`
+fn index_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_only_go_to_their_own_hosts() {
+        let registry = Registry {
+            dl: CRATES_IO_DL.to_string(),
+            index: CRATES_IO_INDEX.to_string(),
+            api: None,
+            token: Some("secret".to_string()),
+            prefer_cdn: false,
+            mirrors: vec!["https://mirror.corp:8443/crates/{crate}/{version}".to_string()],
+        };
+        let urls = registry.download_urls("serde", "1.0.0");
+        assert_eq!(urls.len(), 3);
+        let (mirror, primary, cdn) = (&urls[0], &urls[1], &urls[2]);
+
+        assert!(registry.is_registry_url(primary));
+        assert!(!registry.is_registry_url(mirror));
+        assert!(!registry.is_registry_url(cdn));
+        assert!(registry.is_mirror_url(mirror));
+        assert!(!registry.is_mirror_url(primary));
+        assert!(!registry.is_mirror_url(cdn));
+        // 同一主机的其他端口不是同一个服务
+        assert!(!registry.is_mirror_url("https://mirror.corp/crates/serde/1.0.0"));
+        assert!(!registry.is_registry_url("not a url"));
+    }
+}
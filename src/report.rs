@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+
+use crate::export;
+use crate::graph::{ChainFilter, Graph};
+use crate::store;
+
+/// options of the `report` subcommand
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    /// a graph saved with `SAVE_GRAPH` or `merge-graphs --output`
+    pub graph: PathBuf,
+    #[arg(long, value_enum, default_value = "md")]
+    pub format: ReportFormat,
+    /// write the report to this file instead of stdout
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// a Markdown summary for an advisory or an issue
+    #[value(alias = "markdown")]
+    Md,
+    /// the self-contained HTML report
+    Html,
+}
+
+/// render a report from a saved graph, without analyzing anything again
+pub async fn run(args: &ReportArgs, chain_filter: &ChainFilter) -> Result<()> {
+    let graph = Graph::load(&args.graph)
+        .await
+        .context(format!("无法生成报告: {}", args.graph.display()))?;
+    let report = match args.format {
+        ReportFormat::Md => export::markdown::impact_markdown(&graph, chain_filter),
+        ReportFormat::Html => export::html::impact_html(&graph, chain_filter),
+    };
+    match &args.output {
+        Some(output) => {
+            store::write_atomic(output, report.as_bytes()).await?;
+            tracing::info!("报告已写入 {}", output.display());
+        }
+        None => print!("{}", report),
+    }
+    Ok(())
+}
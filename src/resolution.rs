@@ -0,0 +1,111 @@
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+/// one published version of a parent crate
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub num: String,
+    pub yanked: bool,
+    // unix time of publishing, None when the metadata source doesn't record it
+    pub published_at: Option<f64>,
+}
+
+/// whether the resolver would pick the analyzed parent version for a dependent's
+/// requirement, under the policies cargo knows
+///
+/// only the requirement on the parent is considered, other constraints of the
+/// dependency graph (a sibling capping the parent, a `=` pin elsewhere) can still move
+/// the real resolution; `resolved_version` from `cargo tree` reflects those
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolutionCheck {
+    // the newest compatible release today, what `cargo update` picks
+    pub max_version: bool,
+    // the newest compatible release when the dependent was published, i.e. what its
+    // lockfile most likely holds; None without publish times
+    pub max_at_publish: Option<bool>,
+    // the oldest compatible release, what `-Z minimal-versions` picks
+    pub min_version: bool,
+}
+
+impl ResolutionCheck {
+    /// names of the policies that resolve to the parent version
+    pub fn policies(&self) -> Vec<&'static str> {
+        let mut policies = Vec::new();
+        if self.max_version {
+            policies.push("max-version");
+        }
+        if self.max_at_publish == Some(true) {
+            policies.push("max-version at publish");
+        }
+        if self.min_version {
+            policies.push("min-version");
+        }
+        policies
+    }
+}
+
+/// the published versions of a parent, oldest first
+#[derive(Debug, Clone, Default)]
+pub struct ParentReleases {
+    releases: Vec<(Version, Release)>,
+}
+
+impl ParentReleases {
+    pub fn new(releases: Vec<Release>) -> Self {
+        let mut releases = releases
+            .into_iter()
+            .filter_map(|release| Some((Version::parse(&release.num).ok()?, release)))
+            .collect::<Vec<_>>();
+        releases.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Self { releases }
+    }
+
+    /// simulate the resolution of `req` and compare it with `parent`, None when the
+    /// requirement or the version doesn't parse
+    ///
+    /// like cargo, yanked releases are never picked by a new resolution and
+    /// pre-releases only match requirements naming them; for the resolution at publish
+    /// time yanked releases count, the yank may have happened later
+    pub fn simulate(
+        &self,
+        req: &str,
+        parent: &str,
+        published_at: Option<f64>,
+    ) -> Option<ResolutionCheck> {
+        let req = VersionReq::parse(req).ok()?;
+        let parent = Version::parse(parent).ok()?;
+        let compatible = self
+            .releases
+            .iter()
+            .filter(|(version, _)| req.matches(version))
+            .collect::<Vec<_>>();
+        let current = compatible
+            .iter()
+            .filter(|(_, release)| !release.yanked)
+            .collect::<Vec<_>>();
+        let max_version = current
+            .last()
+            .is_some_and(|(version, _)| *version == parent);
+        let min_version = current
+            .first()
+            .is_some_and(|(version, _)| *version == parent);
+        let max_at_publish = published_at.and_then(|published_at| {
+            let known = compatible
+                .iter()
+                .filter(|(_, release)| release.published_at.is_some())
+                .collect::<Vec<_>>();
+            if known.is_empty() {
+                return None;
+            }
+            let newest = known
+                .iter()
+                .rfind(|(_, release)| release.published_at.is_some_and(|at| at <= published_at));
+            Some(newest.is_some_and(|(version, _)| *version == parent))
+        });
+        Some(ResolutionCheck {
+            max_version,
+            max_at_publish,
+            min_version,
+        })
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde_json::Value;
+use tracing::info;
+
+use crate::graph::Graph;
+use crate::model::normalize_crate_name;
+
+/// options of the `sbom` subcommand
+#[derive(Debug, Args)]
+pub struct SbomArgs {
+    /// a CycloneDX or SPDX SBOM in JSON
+    pub sbom: PathBuf,
+    /// graphs saved with `SAVE_GRAPH`, one per advisory, can be repeated; each verdict
+    /// is printed with the advisory or roots of its graph
+    #[arg(long = "graph", value_name = "PATH", required = true)]
+    pub graphs: Vec<PathBuf>,
+}
+
+/// a crates.io component of an SBOM
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SbomComponent {
+    pub name: String,
+    pub version: String,
+}
+
+/// match the crate components of an SBOM against saved analysis results and print
+/// which of them are vulnerable, on a vulnerable call path or shown not affected
+pub async fn run(args: &SbomArgs) -> Result<()> {
+    let components = read_components(&args.sbom)?;
+    info!(
+        "{} 中有 {} 个 crates.io 组件",
+        args.sbom.display(),
+        components.len()
+    );
+    // 每个图对应一个公告，分开比对才能说明结论属于哪个公告
+    let mut graphs = Vec::new();
+    for path in &args.graphs {
+        let graph = Graph::load(path).await?;
+        graphs.push((advisory_label(&graph), graph));
+    }
+
+    let (mut vulnerable, mut affected, mut cleared) = (0, 0, 0);
+    for component in &components {
+        let key = normalize_crate_name(&component.name);
+        let mut strongest = None;
+        for (label, graph) in &graphs {
+            let Some(verdict) = component_verdict(graph, component, &key) else {
+                continue;
+            };
+            match &verdict {
+                Verdict::Vulnerable(range) => {
+                    print!(
+                        "[vulnerable] {} {} ({})",
+                        component.name, component.version, label
+                    );
+                    match range {
+                        Some(range) => println!(" (range: {})", range),
+                        None => println!(),
+                    }
+                }
+                Verdict::Affected(depth, chain) => println!(
+                    "[affected] {} {} ({}, depth {}): {}",
+                    component.name,
+                    component.version,
+                    label,
+                    depth,
+                    chain.join(" -> ")
+                ),
+                Verdict::Cleared(reasons) => println!(
+                    "[cleared] {} {} ({}): {}",
+                    component.name,
+                    component.version,
+                    label,
+                    reasons.join(", ")
+                ),
+            }
+            strongest = strongest.max(Some(verdict.rank()));
+        }
+        match strongest {
+            Some(2) => vulnerable += 1,
+            Some(1) => affected += 1,
+            Some(_) => cleared += 1,
+            None => {}
+        }
+    }
+    println!(
+        "sbom: {} crates, {} vulnerable, {} on a vulnerable call path, {} not affected, {} not analyzed",
+        components.len(),
+        vulnerable,
+        affected,
+        cleared,
+        components.len() - vulnerable - affected - cleared
+    );
+    Ok(())
+}
+
+/// what one saved graph says about a component
+enum Verdict {
+    // a root, with the vulnerable range it was selected from
+    Vulnerable(Option<String>),
+    // depth and the shortest chain from a root
+    Affected(usize, Vec<String>),
+    // the clearance reasons against each parent
+    Cleared(Vec<String>),
+}
+
+impl Verdict {
+    fn rank(&self) -> u8 {
+        match self {
+            Verdict::Vulnerable(_) => 2,
+            Verdict::Affected(..) => 1,
+            Verdict::Cleared(_) => 0,
+        }
+    }
+}
+
+fn component_verdict(graph: &Graph, component: &SbomComponent, key: &str) -> Option<Verdict> {
+    let crates = graph.crates().collect::<Vec<_>>();
+    let node = crates.iter().enumerate().find(|(_, node)| {
+        normalize_crate_name(&node.name) == key && node.version == component.version
+    });
+    match node {
+        Some((_, node)) if node.depth == 0 => Some(Verdict::Vulnerable(node.range.clone())),
+        Some((id, node)) => {
+            let chain = graph
+                .shortest_chain(id)
+                .iter()
+                .map(|id| format!("{}@{}", crates[*id].name, crates[*id].version))
+                .collect();
+            Some(Verdict::Affected(node.depth, chain))
+        }
+        None => {
+            let reasons = graph
+                .cleared()
+                .filter(|entry| {
+                    normalize_crate_name(&entry.name) == key && entry.version == component.version
+                })
+                .map(|entry| {
+                    format!(
+                        "{} against {}@{}",
+                        entry.clearance.reason.as_str(),
+                        entry.parent_name,
+                        entry.parent_version
+                    )
+                })
+                .collect::<Vec<_>>();
+            (!reasons.is_empty()).then_some(Verdict::Cleared(reasons))
+        }
+    }
+}
+
+/// the advisories of a graph saved with `ADVISORY_ID`, else its roots
+fn advisory_label(graph: &Graph) -> String {
+    if !graph.advisories().is_empty() {
+        return graph
+            .advisories()
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+    }
+    graph
+        .crates()
+        .filter(|node| node.depth == 0)
+        .map(|node| format!("{}@{}", node.name, node.version))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// the crates.io components of a CycloneDX or SPDX JSON document, identified by their
+/// `pkg:cargo` purl
+pub fn read_components(path: &Path) -> Result<BTreeSet<SbomComponent>> {
+    let content =
+        std::fs::read_to_string(path).context(format!("读取 SBOM 失败: {}", path.display()))?;
+    let document: Value = serde_json::from_str(&content).context(format!(
+        "SBOM 不是 JSON，只支持 JSON 格式: {}",
+        path.display()
+    ))?;
+    let mut purls = Vec::new();
+    if document["bomFormat"] == "CycloneDX" {
+        let mut stack = vec![&document["components"]];
+        if !document["metadata"]["component"].is_null() {
+            purls.extend(document["metadata"]["component"]["purl"].as_str());
+        }
+        // 组件可以嵌套
+        while let Some(list) = stack.pop() {
+            for component in list.as_array().into_iter().flatten() {
+                purls.extend(component["purl"].as_str());
+                stack.push(&component["components"]);
+            }
+        }
+    } else if document.get("spdxVersion").is_some() {
+        for package in document["packages"].as_array().into_iter().flatten() {
+            purls.extend(
+                package["externalRefs"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter(|reference| reference["referenceType"] == "purl")
+                    .filter_map(|reference| reference["referenceLocator"].as_str()),
+            );
+        }
+    } else {
+        bail!("无法识别的 SBOM 格式: {}", path.display());
+    }
+    Ok(purls.into_iter().filter_map(parse_cargo_purl).collect())
+}
+
+/// name and version of a `pkg:cargo/name@version` purl, None for other ecosystems
+fn parse_cargo_purl(purl: &str) -> Option<SbomComponent> {
+    let rest = purl.strip_prefix("pkg:cargo/")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or(rest);
+    let (name, version) = rest.rsplit_once('@')?;
+    // 构建元数据中的 `+` 在 purl 中被编码
+    let version = version.replace("%2B", "+").replace("%2b", "+");
+    Some(SbomComponent {
+        name: name.to_string(),
+        version,
+    })
+}
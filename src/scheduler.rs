@@ -0,0 +1,569 @@
+use std::future::Future;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use futures::{stream, StreamExt};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::checkpoint::NodeCheckpoint;
+
+// 第一批的大小，之后根据平均耗时自适应调整
+pub const INITIAL_BATCH_SIZE: usize = 100;
+pub const MIN_BATCH_SIZE: usize = 10;
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// splits the pending dependents of a BFS node into batches, a checkpoint is written
+/// after each batch
+///
+/// priority dependents (sorted to the front) form the leading batches on their own,
+/// afterwards the batch size follows the average dependent duration so that
+/// checkpoints land roughly every `interval`. Dependents that failed are handed out
+/// again after the last batch, for up to `retries` rounds
+#[derive(Debug, Clone)]
+pub struct BatchScheduler {
+    total: usize,
+    priority_count: usize,
+    interval: Duration,
+    batch_size: usize,
+    offset: usize,
+    retries: usize,
+}
+
+impl BatchScheduler {
+    pub fn new(total: usize, priority_count: usize, interval: Duration) -> Self {
+        Self {
+            total,
+            priority_count,
+            interval,
+            batch_size: INITIAL_BATCH_SIZE,
+            offset: 0,
+            retries: 0,
+        }
+    }
+
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// index range of the next batch, None when everything has been handed out
+    pub fn next_batch(&mut self) -> Option<Range<usize>> {
+        if self.offset >= self.total {
+            return None;
+        }
+        let len = if self.offset < self.priority_count {
+            (self.priority_count - self.offset).min(self.batch_size)
+        } else {
+            self.batch_size
+        };
+        let end = (self.offset + len).min(self.total);
+        let batch = self.offset..end;
+        self.offset = end;
+        Some(batch)
+    }
+
+    /// adapt the batch size to a finished batch, returns the average duration of one
+    /// dependent in it
+    pub fn finish_batch(&mut self, len: usize, elapsed: Duration) -> Duration {
+        let per_dependent = elapsed.div_f64(len.max(1) as f64);
+        if !per_dependent.is_zero() {
+            let batch_size = self.interval.as_secs_f64() / per_dependent.as_secs_f64();
+            self.batch_size = (batch_size as usize).clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE);
+        }
+        per_dependent
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.total - self.offset
+    }
+
+    /// analyze the pending dependents the scheduler was created for batch by batch,
+    /// `concurrency` at a time, and write the checkpoint after each batch
+    ///
+    /// each dependent comes with the `name@version` it is checkpointed under; `analyze`
+    /// returns None when the analysis failed and `record` adds a verdict to the
+    /// checkpoint. Failed dependents are queued for the retry rounds; those still failing
+    /// after them are not marked as processed, so resuming from the checkpoint retries
+    /// them. Returns the size of each batch, retry batches included
+    pub async fn run<T, R, Fut>(
+        mut self,
+        mut pending: Vec<(String, T)>,
+        concurrency: usize,
+        checkpoint: &mut NodeCheckpoint,
+        checkpoint_path: &Path,
+        mut analyze: impl FnMut(T) -> Fut,
+        mut record: impl FnMut(&mut NodeCheckpoint, R),
+    ) -> Vec<usize>
+    where
+        T: Clone,
+        Fut: Future<Output = Option<R>>,
+    {
+        let mut batch_sizes = Vec::new();
+        let mut failed = Vec::new();
+        let mut round = 0;
+        loop {
+            let Some(batch_range) = self.next_batch() else {
+                // 所有批次完成后，失败的依赖者在同一次运行中重新排队
+                if failed.is_empty() || round == self.retries {
+                    break;
+                }
+                round += 1;
+                info!("第{}轮重试: {}个失败的依赖者", round, failed.len());
+                pending = std::mem::take(&mut failed);
+                self.total = pending.len();
+                self.priority_count = 0;
+                self.offset = 0;
+                continue;
+            };
+            let batch = &pending[batch_range];
+            batch_sizes.push(batch.len());
+            let batch_idx = batch_sizes.len();
+            let batch_start_time = Instant::now();
+            info!("开始处理第{}批, 本批{}个依赖者", batch_idx, batch.len());
+            let batch_results = stream::iter(batch.iter().cloned().enumerate())
+                .map(|(idx, (key, dependent))| {
+                    let analysis = analyze(dependent);
+                    async move { (idx, analysis.await.map(|result| (key, result))) }
+                })
+                .buffer_unordered(concurrency)
+                .collect::<Vec<_>>()
+                .await;
+
+            let failed_before = failed.len();
+            // 失败的依赖者不记为已处理，留给重试轮次或恢复时重新分析
+            for (idx, result) in batch_results {
+                match result {
+                    Some((key, result)) => {
+                        checkpoint.processed.insert(key);
+                        record(checkpoint, result);
+                    }
+                    None => failed.push(batch[idx].clone()),
+                }
+            }
+            let batch_failed = failed.len() - failed_before;
+            info!(
+                "第{}批处理完成，得出结论{}个，失败{}个",
+                batch_idx,
+                batch.len() - batch_failed,
+                batch_failed
+            );
+            if let Err(e) = checkpoint.save(checkpoint_path).await {
+                warn!("保存检查点失败: {:#}", e);
+            }
+
+            // 根据本批每个依赖者的平均耗时调整批大小，使检查点间隔接近配置值
+            let per_dependent = self
+                .finish_batch(batch.len(), batch_start_time.elapsed())
+                .as_secs_f64();
+            let remaining = self.remaining();
+            info!(
+                "平均每个依赖者耗时 {:.1}s，剩余{}个，预计还需 {:.0}s，下一批大小 {}",
+                per_dependent,
+                remaining,
+                per_dependent * remaining as f64,
+                self.batch_size()
+            );
+        }
+        batch_sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    use tokio::sync::Semaphore;
+    use tokio::time::{sleep, timeout};
+
+    use super::*;
+    use crate::checkpoint::{AffectedEntry, NodeCheckpoint};
+    use crate::model::{DependentSource, FunctionCalls};
+
+    const CONCURRENCY: usize = 6;
+
+    /// one simulated dependent
+    #[derive(Debug, Clone)]
+    struct SimJob {
+        name: String,
+        duration: Duration,
+        // the analysis errors out, like a failed download or build
+        fails: bool,
+        // ... on every attempt, not only the first
+        permanent: bool,
+        affected: bool,
+        priority: bool,
+    }
+
+    /// deterministic workload, the same seed always gives the same jobs
+    struct SimulatedWorkload {
+        jobs: Vec<SimJob>,
+    }
+
+    struct WorkloadSpec {
+        seed: u64,
+        nodes: usize,
+        min_duration: Duration,
+        max_duration: Duration,
+        failure_rate: f64,
+        priority_rate: f64,
+    }
+
+    impl SimulatedWorkload {
+        fn generate(spec: WorkloadSpec) -> Self {
+            let mut state = spec.seed.max(1);
+            // xorshift64, uniform in [0, 1)
+            let mut next = move || {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 11) as f64 / (1u64 << 53) as f64
+            };
+            let spread = spec.max_duration.saturating_sub(spec.min_duration);
+            let jobs = (0..spec.nodes)
+                .map(|i| SimJob {
+                    name: format!("sim-{}", i),
+                    duration: spec.min_duration + spread.mul_f64(next()),
+                    fails: next() < spec.failure_rate,
+                    permanent: false,
+                    affected: next() < 0.3,
+                    priority: next() < spec.priority_rate,
+                })
+                .collect();
+            Self { jobs }
+        }
+    }
+
+    struct SimRun {
+        batch_sizes: Vec<usize>,
+        // names in the order their verdicts were recorded
+        completed: Vec<String>,
+        checkpoint: NodeCheckpoint,
+    }
+
+    /// `BatchScheduler::run` as `process_single_bfs_node` drives it, with the analysis
+    /// replaced by a sleep; a failing job only fails on its first attempt, like a
+    /// transient download or build error. Stops at `crash_at` to simulate a crash
+    async fn run(
+        workload: &SimulatedWorkload,
+        attempted: &mut HashSet<String>,
+        checkpoint_path: &Path,
+        interval: Duration,
+        retries: usize,
+        crash_at: Option<Duration>,
+    ) -> SimRun {
+        let mut checkpoint = NodeCheckpoint::load(checkpoint_path).await;
+        let mut pending = workload
+            .jobs
+            .iter()
+            .filter(|job| !checkpoint.is_processed(&job.name, "1.0.0"))
+            .cloned()
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|job| !job.priority);
+        let priority_count = pending.iter().filter(|job| job.priority).count();
+        let scheduler =
+            BatchScheduler::new(pending.len(), priority_count, interval).with_retries(retries);
+        let pending = pending
+            .into_iter()
+            .map(|job| (NodeCheckpoint::key(&job.name, "1.0.0"), job))
+            .collect();
+
+        let semaphore = Arc::new(Semaphore::new(CONCURRENCY));
+        let mut completed = Vec::new();
+        let analyze = |job: SimJob| {
+            let semaphore = Arc::clone(&semaphore);
+            let fails = job.permanent || (job.fails && attempted.insert(job.name.clone()));
+            async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                sleep(job.duration).await;
+                (!fails).then_some((job.name, job.affected))
+            }
+        };
+        let record = |checkpoint: &mut NodeCheckpoint, (name, affected): (String, bool)| {
+            if affected {
+                checkpoint.affected.push(AffectedEntry {
+                    name: name.clone(),
+                    version: "1.0.0".to_string(),
+                    req: "^1".to_string(),
+                    source: DependentSource::Dump,
+                    resolved_version: Some("1.0.0".to_string()),
+                    kinds: Vec::new(),
+                    required_features: Vec::new(),
+                    yanked: false,
+                    resolution: None,
+                    calls: FunctionCalls::default(),
+                });
+            }
+            completed.push(name);
+        };
+        let batches = scheduler.run(
+            pending,
+            CONCURRENCY,
+            &mut checkpoint,
+            checkpoint_path,
+            analyze,
+            record,
+        );
+        let batch_sizes = match crash_at {
+            Some(crash_at) => timeout(crash_at, batches).await.unwrap_or_default(),
+            None => batches.await,
+        };
+        SimRun {
+            batch_sizes,
+            completed,
+            checkpoint,
+        }
+    }
+
+    fn affected_count(workload: &SimulatedWorkload) -> usize {
+        workload.jobs.iter().filter(|job| job.affected).count()
+    }
+
+    fn checkpoint_path(test: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("cross_pro_cg-sim-{}", std::process::id()))
+            .join(format!("{}.json", test));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn spec(seed: u64, nodes: usize) -> WorkloadSpec {
+        WorkloadSpec {
+            seed,
+            nodes,
+            min_duration: Duration::from_secs(1),
+            max_duration: Duration::from_secs(30),
+            failure_rate: 0.1,
+            priority_rate: 0.0,
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_workload() {
+        let first = SimulatedWorkload::generate(spec(7, 50));
+        let second = SimulatedWorkload::generate(spec(7, 50));
+        let durations = |workload: &SimulatedWorkload| {
+            workload
+                .jobs
+                .iter()
+                .map(|job| (job.duration, job.fails, job.affected))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(durations(&first), durations(&second));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn every_dependent_is_processed_exactly_once() {
+        let workload = SimulatedWorkload::generate(spec(1, 537));
+        let path = checkpoint_path("exactly_once");
+        let mut attempted = HashSet::new();
+        let interval = Duration::from_secs(600);
+        let result = run(&workload, &mut attempted, &path, interval, 0, None).await;
+
+        let failing = workload
+            .jobs
+            .iter()
+            .filter(|job| job.fails)
+            .map(|job| job.name.clone())
+            .collect::<HashSet<_>>();
+        assert!(!failing.is_empty());
+        let unique = result.completed.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), result.completed.len());
+        assert_eq!(result.completed.len(), workload.jobs.len() - failing.len());
+        assert!(result.completed.iter().all(|name| !failing.contains(name)));
+        // failed analyses are not checkpointed and never show up as affected
+        assert_eq!(result.checkpoint.processed.len(), result.completed.len());
+        let expected_affected = workload
+            .jobs
+            .iter()
+            .filter(|job| !job.fails && job.affected)
+            .count();
+        assert_eq!(result.checkpoint.affected.len(), expected_affected);
+
+        // resuming retries exactly the failed dependents
+        let retried = run(&workload, &mut attempted, &path, interval, 0, None).await;
+        let retried_names = retried.completed.iter().cloned().collect::<HashSet<_>>();
+        assert_eq!(retried_names, failing);
+        assert_eq!(retried.completed.len(), failing.len());
+        assert_eq!(retried.checkpoint.processed.len(), workload.jobs.len());
+        assert_eq!(retried.checkpoint.affected.len(), affected_count(&workload));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_dependents_are_retried_within_the_run() {
+        let workload = SimulatedWorkload::generate(spec(6, 537));
+        let failing = workload
+            .jobs
+            .iter()
+            .filter(|job| job.fails)
+            .map(|job| job.name.clone())
+            .collect::<HashSet<_>>();
+        assert!(!failing.is_empty());
+
+        let path = checkpoint_path("retry");
+        let interval = Duration::from_secs(600);
+        let result = run(&workload, &mut HashSet::new(), &path, interval, 1, None).await;
+        // 失败的依赖者在所有批次之后重试，不需要恢复
+        let unique = result.completed.iter().collect::<HashSet<_>>();
+        assert_eq!(unique.len(), workload.jobs.len());
+        assert_eq!(result.completed.len(), workload.jobs.len());
+        let (_, retried) = result
+            .completed
+            .split_at(workload.jobs.len() - failing.len());
+        assert!(retried.iter().all(|name| failing.contains(name)));
+        assert_eq!(
+            result.batch_sizes.iter().sum::<usize>(),
+            workload.jobs.len() + failing.len()
+        );
+        assert_eq!(result.checkpoint.processed.len(), workload.jobs.len());
+        assert_eq!(result.checkpoint.affected.len(), affected_count(&workload));
+        let saved = NodeCheckpoint::load(&path).await;
+        assert_eq!(saved.processed.len(), workload.jobs.len());
+
+        // 重试轮次用完后仍失败的依赖者留给恢复
+        let always_failing = SimulatedWorkload {
+            jobs: workload
+                .jobs
+                .iter()
+                .map(|job| SimJob {
+                    fails: false,
+                    permanent: job.fails,
+                    ..job.clone()
+                })
+                .collect(),
+        };
+        let path = checkpoint_path("retry_exhausted");
+        let result = run(
+            &always_failing,
+            &mut HashSet::new(),
+            &path,
+            interval,
+            2,
+            None,
+        )
+        .await;
+        assert_eq!(result.completed.len(), workload.jobs.len() - failing.len());
+        assert_eq!(
+            result.batch_sizes.iter().sum::<usize>(),
+            workload.jobs.len() + 2 * failing.len()
+        );
+        let saved = NodeCheckpoint::load(&path).await;
+        assert!(failing
+            .iter()
+            .all(|name| !saved.is_processed(name, "1.0.0")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn priority_dependents_are_not_starved_by_the_frontier() {
+        let workload = SimulatedWorkload::generate(WorkloadSpec {
+            priority_rate: 0.05,
+            ..spec(2, 400)
+        });
+        let priority = workload
+            .jobs
+            .iter()
+            .filter(|job| job.priority && !job.fails)
+            .map(|job| job.name.clone())
+            .collect::<HashSet<_>>();
+        assert!(!priority.is_empty());
+
+        let path = checkpoint_path("priority");
+        let result = run(
+            &workload,
+            &mut HashSet::new(),
+            &path,
+            Duration::from_secs(600),
+            0,
+            None,
+        )
+        .await;
+        let (head, tail) = result.completed.split_at(priority.len());
+        assert!(head.iter().all(|name| priority.contains(name)));
+        assert!(tail.iter().all(|name| !priority.contains(name)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_size_follows_dependent_duration() {
+        let fast = SimulatedWorkload::generate(WorkloadSpec {
+            min_duration: Duration::from_millis(100),
+            max_duration: Duration::from_millis(200),
+            ..spec(3, 3000)
+        });
+        let result = run(
+            &fast,
+            &mut HashSet::new(),
+            &checkpoint_path("fast"),
+            Duration::from_secs(600),
+            0,
+            None,
+        )
+        .await;
+        assert_eq!(result.batch_sizes[0], INITIAL_BATCH_SIZE);
+        assert_eq!(result.batch_sizes[1], MAX_BATCH_SIZE);
+
+        let slow = SimulatedWorkload::generate(WorkloadSpec {
+            min_duration: Duration::from_secs(300),
+            max_duration: Duration::from_secs(600),
+            ..spec(4, 200)
+        });
+        let result = run(
+            &slow,
+            &mut HashSet::new(),
+            &checkpoint_path("slow"),
+            Duration::from_secs(60),
+            0,
+            None,
+        )
+        .await;
+        assert_eq!(result.batch_sizes[0], INITIAL_BATCH_SIZE);
+        assert!(result.batch_sizes[1..]
+            .iter()
+            .all(|size| *size == MIN_BATCH_SIZE));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resuming_from_a_checkpoint_skips_finished_batches() {
+        let workload = SimulatedWorkload::generate(spec(5, 450));
+        let path = checkpoint_path("resume");
+        let mut attempted = HashSet::new();
+        let interval = Duration::from_secs(600);
+
+        // 处理到中途时崩溃
+        let crash_at = Some(Duration::from_secs(900));
+        run(&workload, &mut attempted, &path, interval, 0, crash_at).await;
+        let saved = NodeCheckpoint::load(&path).await;
+        assert!(!saved.processed.is_empty());
+        assert!(saved.processed.len() < workload.jobs.len());
+        // 崩溃前没有开始的失败依赖者在恢复后第一次失败
+        let first_failures = workload
+            .jobs
+            .iter()
+            .filter(|job| job.fails && !attempted.contains(&job.name))
+            .count();
+
+        let resumed = run(&workload, &mut attempted, &path, interval, 0, None).await;
+        assert!(resumed
+            .completed
+            .iter()
+            .all(|name| !saved.is_processed(name, "1.0.0")));
+        assert_eq!(
+            saved.processed.len() + resumed.completed.len(),
+            workload.jobs.len() - first_failures
+        );
+
+        let finished = run(&workload, &mut attempted, &path, interval, 0, None).await;
+        assert_eq!(finished.completed.len(), first_failures);
+        assert_eq!(finished.checkpoint.processed.len(), workload.jobs.len());
+        assert_eq!(
+            finished.checkpoint.affected.len(),
+            affected_count(&workload)
+        );
+    }
+}
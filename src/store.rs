@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use fs4::fs_std::FileExt;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use tokio::fs as tokio_fs;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+// 下载目录中所有 crate 共用的内容寻址存储
+pub static STORE: Lazy<CrateStore> = Lazy::new(CrateStore::from_env);
+
+// 同一进程内临时文件名的序号
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// 两次统计下载目录大小之间的最短间隔，期间按新下载的文件大小估算
+const RESCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// content-addressed store of crate files and their extracted sources, shared by runs
+/// using the same DOWNLOAD_DIR
+///
+/// ```text
+/// $DOWNLOAD_DIR/store/crates/ab/<sha256>.crate
+/// $DOWNLOAD_DIR/store/src/ab/<sha256>/
+/// $DOWNLOAD_DIR/store/index/<name>/<version>    (contains the sha256)
+/// $DOWNLOAD_DIR/store/locks/<sha256 of the source path>.lock
+/// ```
+///
+/// everything is written to a unique temporary path first and renamed into place, so
+/// concurrent runs racing on the same crate never see partial content; analyses patching
+/// extracted sources hold `lock_source` until they are restored
+///
+/// with a quota the least recently used entries are evicted before more is downloaded
+#[derive(Debug)]
+pub struct CrateStore {
+    base_dir: PathBuf,
+    root: PathBuf,
+    quota: Option<Quota>,
+    // extracted source directory -> lock held by the analysis patching it in this process
+    source_locks: std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+}
+
+/// exclusive use of an extracted crate, in this process and across runs sharing the
+/// store; released when dropped
+pub struct SourceLock {
+    _file: std::fs::File,
+    _guard: OwnedMutexGuard<()>,
+}
+
+/// byte limit of DOWNLOAD_DIR, from `DOWNLOAD_QUOTA` (bytes, or with a `K`/`M`/`G`/`T`
+/// suffix); entries used within `DOWNLOAD_QUOTA_GRACE_SECS` (default 600) are never
+/// evicted since an analysis may be about to lock them, and neither are sources whose
+/// `lock_source` is held
+#[derive(Debug)]
+struct Quota {
+    limit: u64,
+    grace: Duration,
+    // estimated usage and when it was last measured
+    usage: Mutex<Option<(Instant, u64)>>,
+    evictions: AtomicU64,
+    evicted_bytes: AtomicU64,
+}
+
+/// an extracted crate and its archive, evicted together
+#[derive(Debug, Default)]
+pub struct StoreEntry {
+    pub hash: String,
+    pub paths: Vec<PathBuf>,
+    pub size: u64,
+    pub last_used: Option<SystemTime>,
+}
+
+impl CrateStore {
+    pub fn from_env() -> Self {
+        let base_dir = std::env::var("DOWNLOAD_DIR").unwrap_or_else(|_| "./downloads".to_string());
+        let quota = std::env::var("DOWNLOAD_QUOTA")
+            .ok()
+            .and_then(|value| match parse_size(&value) {
+                Some(limit) => Some(limit),
+                None => {
+                    tracing::warn!("无法解析 DOWNLOAD_QUOTA={}，不限制下载目录大小", value);
+                    None
+                }
+            })
+            .map(|limit| Quota {
+                limit,
+                grace: Duration::from_secs(
+                    std::env::var("DOWNLOAD_QUOTA_GRACE_SECS")
+                        .ok()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or(600),
+                ),
+                usage: Mutex::new(None),
+                evictions: AtomicU64::new(0),
+                evicted_bytes: AtomicU64::new(0),
+            });
+        Self {
+            root: Path::new(&base_dir).join("store"),
+            base_dir: PathBuf::from(base_dir),
+            quota,
+            source_locks: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn crate_file(&self, hash: &str) -> PathBuf {
+        self.root
+            .join("crates")
+            .join(&hash[..2])
+            .join(format!("{}.crate", hash))
+    }
+
+    pub fn source_dir(&self, hash: &str) -> PathBuf {
+        self.root.join("src").join(&hash[..2]).join(hash)
+    }
+
+    fn index_file(&self, name: &str, version: &str) -> PathBuf {
+        self.root.join("index").join(name).join(version)
+    }
+
+    /// the content hash recorded for a crate version
+    pub async fn lookup(&self, name: &str, version: &str) -> Option<String> {
+        let hash = tokio_fs::read_to_string(self.index_file(name, version))
+            .await
+            .ok()?;
+        let hash = hash.trim();
+        (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())).then(|| hash.to_owned())
+    }
+
+    pub async fn record(&self, name: &str, version: &str, hash: &str) -> Result<()> {
+        let index_file = self.index_file(name, version);
+        write_atomic(&index_file, hash.as_bytes()).await
+    }
+
+    /// move a verified crate file into the store, a file already there wins
+    pub async fn insert_crate_file(&self, file: &Path, hash: &str) -> Result<PathBuf> {
+        let destination = self.crate_file(hash);
+        if destination.exists() {
+            let _ = tokio_fs::remove_file(file).await;
+            return Ok(destination);
+        }
+        create_parent(&destination).await?;
+        tokio_fs::rename(file, &destination).await.context(format!(
+            "移动 {} 到 {} 失败",
+            file.display(),
+            destination.display()
+        ))?;
+        Ok(destination)
+    }
+
+    /// wait until no other analysis, in this or another run, uses the extracted sources
+    /// behind `dir`; hold the lock from patching the manifest until it is restored
+    pub async fn lock_source(&self, dir: &Path) -> Result<SourceLock> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let lock = Arc::clone(
+            self.source_locks
+                .lock()
+                .unwrap()
+                .entry(dir.clone())
+                .or_default(),
+        );
+        // 先在进程内排队，文件锁只在进程之间竞争
+        let guard = lock.lock_owned().await;
+        let path = self.lock_file(&dir);
+        create_parent(&path).await?;
+        let file = tokio::task::spawn_blocking(move || -> Result<std::fs::File> {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .open(&path)
+                .context(format!("打开锁文件 {} 失败", path.display()))?;
+            file.lock_exclusive()
+                .context(format!("锁定 {} 失败", path.display()))?;
+            Ok(file)
+        })
+        .await
+        .context("等待源码锁的任务异常退出")??;
+        Ok(SourceLock {
+            _file: file,
+            _guard: guard,
+        })
+    }
+
+    /// whether an analysis in this or another run holds `lock_source` for the extracted
+    /// sources at `dir`, which must not be removed then
+    pub fn source_in_use(&self, dir: &Path) -> bool {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let held_here = self
+            .source_locks
+            .lock()
+            .unwrap()
+            .get(&dir)
+            .is_some_and(|lock| lock.try_lock().is_err());
+        if held_here {
+            return true;
+        }
+        let Ok(file) = std::fs::OpenOptions::new()
+            .write(true)
+            .open(self.lock_file(&dir))
+        else {
+            // 从未被锁定过
+            return false;
+        };
+        // 拿不到锁说明另一个运行正在使用，出错时也当作在用
+        !matches!(file.try_lock_exclusive(), Ok(true))
+    }
+
+    fn lock_file(&self, canonical_dir: &Path) -> PathBuf {
+        let digest = hex::encode(Sha256::digest(canonical_dir.to_string_lossy().as_bytes()));
+        self.root.join("locks").join(format!("{}.lock", digest))
+    }
+
+    /// a unique temporary path for downloads in progress
+    pub fn temp_file(&self, name: &str) -> PathBuf {
+        temp_path(&self.root.join("tmp").join(name))
+    }
+
+    /// mark the extracted sources behind `dir` (or a link to them) as used just now
+    pub fn touch(&self, dir: &Path) {
+        let touched =
+            std::fs::File::open(dir).and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = touched {
+            tracing::debug!("更新 {} 的访问时间失败: {}", dir.display(), e);
+        }
+    }
+
+    /// count newly stored bytes towards the quota until the next measurement
+    pub async fn note_added(&self, bytes: u64) {
+        if let Some(quota) = &self.quota {
+            if let Some((_, usage)) = quota.usage.lock().await.as_mut() {
+                *usage += bytes;
+            }
+        }
+    }
+
+    /// evict least recently used entries until DOWNLOAD_DIR fits the quota again,
+    /// called before anything new is downloaded
+    pub async fn enforce_quota(&self) -> Result<()> {
+        let Some(quota) = &self.quota else {
+            return Ok(());
+        };
+        let mut usage = quota.usage.lock().await;
+        let current = match *usage {
+            Some((measured, bytes)) if measured.elapsed() < RESCAN_INTERVAL => bytes,
+            _ => {
+                let base_dir = self.base_dir.clone();
+                let bytes = tokio::task::spawn_blocking(move || dir_size(&base_dir))
+                    .await
+                    .context("统计下载目录大小的任务异常退出")?;
+                *usage = Some((Instant::now(), bytes));
+                bytes
+            }
+        };
+        if current <= quota.limit {
+            return Ok(());
+        }
+
+        let root = self.root.clone();
+        let mut entries = tokio::task::spawn_blocking(move || store_entries(&root))
+            .await
+            .context("列出存储内容的任务异常退出")?;
+        entries.sort_by_key(|entry| entry.last_used);
+
+        let cutoff = SystemTime::now() - quota.grace;
+        let mut remaining = current;
+        for entry in entries {
+            if remaining <= quota.limit {
+                break;
+            }
+            if entry.last_used.is_some_and(|used| used > cutoff) {
+                continue;
+            }
+            // 构建可能超过宽限期，正在被分析的源码无论多久没更新都不清理
+            if entry
+                .paths
+                .iter()
+                .any(|path| path.is_dir() && self.source_in_use(path))
+            {
+                tracing::debug!("{} 正在被分析使用，不清理", entry.paths[0].display());
+                continue;
+            }
+            for path in &entry.paths {
+                let removed = if path.is_dir() {
+                    tokio_fs::remove_dir_all(path).await
+                } else {
+                    tokio_fs::remove_file(path).await
+                };
+                if let Err(e) = removed {
+                    tracing::warn!("清理 {} 失败: {}", path.display(), e);
+                }
+            }
+            remaining = remaining.saturating_sub(entry.size);
+            quota.evictions.fetch_add(1, Ordering::Relaxed);
+            quota.evicted_bytes.fetch_add(entry.size, Ordering::Relaxed);
+            tracing::info!(
+                "下载目录超出配额，已清理 {} ({} 字节)",
+                entry.paths[0].display(),
+                entry.size
+            );
+        }
+        if remaining > quota.limit {
+            tracing::warn!(
+                "下载目录占用 {} 字节，超出配额 {} 字节，但没有可以清理的内容",
+                remaining,
+                quota.limit
+            );
+        }
+        *usage = Some((Instant::now(), remaining));
+        Ok(())
+    }
+
+    /// log how much the quota evicted
+    pub fn log_stats(&self) {
+        if let Some(quota) = &self.quota {
+            tracing::info!(
+                "下载目录配额 {} 字节: 清理 {} 项，共 {} 字节",
+                quota.limit,
+                quota.evictions.load(Ordering::Relaxed),
+                quota.evicted_bytes.load(Ordering::Relaxed)
+            );
+        }
+    }
+}
+
+/// `(name, version, hash)` of every crate version recorded in the index of the store
+pub fn indexed_versions(root: &Path) -> Vec<(String, String, String)> {
+    let mut versions = Vec::new();
+    for name_dir in read_dir_paths(&root.join("index")) {
+        for version_file in read_dir_paths(&name_dir) {
+            let (Some(name), Some(version)) = (
+                name_dir.file_name().and_then(|name| name.to_str()),
+                version_file
+                    .file_name()
+                    .and_then(|version| version.to_str()),
+            ) else {
+                continue;
+            };
+            if let Ok(hash) = std::fs::read_to_string(&version_file) {
+                versions.push((name.to_owned(), version.to_owned(), hash.trim().to_owned()));
+            }
+        }
+    }
+    versions
+}
+
+/// extracted crates and archives of the store by hash, with their size and last use
+pub fn store_entries(root: &Path) -> Vec<StoreEntry> {
+    let mut entries: HashMap<String, StoreEntry> = HashMap::new();
+    for (kind, is_archive) in [("src", false), ("crates", true)] {
+        for shard in read_dir_paths(&root.join(kind)) {
+            for path in read_dir_paths(&shard) {
+                let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let hash = match file_name.strip_suffix(".crate") {
+                    Some(hash) if is_archive => hash,
+                    None if !is_archive => file_name,
+                    _ => continue,
+                };
+                // 临时文件和解压中的目录属于正在进行的操作
+                if hash.ends_with(".tmp") {
+                    continue;
+                }
+                let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+                let entry = entries
+                    .entry(hash.to_owned())
+                    .or_insert_with(|| StoreEntry {
+                        hash: hash.to_owned(),
+                        ..StoreEntry::default()
+                    });
+                entry.size += dir_size(&path);
+                entry.last_used = entry.last_used.max(modified);
+                entry.paths.push(path);
+            }
+        }
+    }
+    entries.into_values().collect()
+}
+
+pub fn read_dir_paths(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+        .unwrap_or_default()
+}
+
+/// bytes used by a file or a directory tree, links are not followed
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    read_dir_paths(path).iter().map(|path| dir_size(path)).sum()
+}
+
+/// `1048576`, `512M`, `20G`, ...
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, ""),
+    };
+    let multiplier: u64 = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches(['B', 'I'])
+    {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// `path` with a suffix unique to this process and call, next to `path`
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+pub async fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio_fs::create_dir_all(parent)
+            .await
+            .context(format!("创建目录 {} 失败", parent.display()))?;
+    }
+    Ok(())
+}
+
+/// write a file through a temporary file next to it, readers never see it half written
+pub async fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    create_parent(path).await?;
+    let temp = temp_path(path);
+    tokio_fs::write(&temp, content)
+        .await
+        .context(format!("写入 {} 失败", temp.display()))?;
+    tokio_fs::rename(&temp, path)
+        .await
+        .context(format!("写入 {} 失败", path.display()))
+}
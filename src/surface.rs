@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::callers::{ApiExposure, CallSite, CallerInfo};
+use crate::target;
+
+/// one path imported by a `pub use`, `use` trees already flattened and `crate::`,
+/// `self::` and `super::` resolved to absolute paths
+#[derive(Debug, Clone)]
+pub struct PubUse {
+    // the imported path, e.g. [`crossbeam_channel`, `flavors`, `list`]
+    pub path: Vec<String>,
+    // the name it is visible under, None for a glob import
+    pub name: Option<String>,
+    // the module holding the `pub use`
+    pub module_path: String,
+}
+
+impl PubUse {
+    /// the path `item` is reachable at through this import, if the import covers it
+    pub fn expose(&self, item: &[&str]) -> Option<String> {
+        let covered = self.path.len() <= item.len()
+            && self
+                .path
+                .iter()
+                .zip(item)
+                .all(|(imported, segment)| imported == segment);
+        if !covered {
+            return None;
+        }
+        let rest = &item[self.path.len()..];
+        let mut exposed = vec![self.module_path.as_str()];
+        match &self.name {
+            Some(name) => exposed.push(name),
+            // a glob only imports the items below the path
+            None if rest.is_empty() => return None,
+            None => {}
+        }
+        exposed.extend(rest);
+        Some(exposed.join("::"))
+    }
+}
+
+/// the public surface of a crate: its module tree and the `pub use` items of the
+/// modules reachable from outside
+#[derive(Debug, Clone, Default)]
+pub struct PublicSurface {
+    // module path -> whether it is reachable from the crate root through `pub mod`
+    modules: BTreeMap<String, bool>,
+    // modules only compiled for tests (`#[cfg(test)]`), including their submodules
+    test_modules: BTreeSet<String>,
+    pub_uses: Vec<PubUse>,
+}
+
+impl PublicSurface {
+    /// walk the module tree from `src/lib.rs`
+    pub async fn load(crate_dir: &Path, lib_name: &str) -> Result<Self> {
+        let (crate_dir, lib_name) = (crate_dir.to_path_buf(), lib_name.to_owned());
+        // syn 的语法树不能跨线程持有，整棵模块树在阻塞线程上一次解析完
+        tokio::task::spawn_blocking(move || Self::walk(&crate_dir, &lib_name))
+            .await
+            .context("解析公开接口的任务异常退出")?
+    }
+
+    fn walk(crate_dir: &Path, lib_name: &str) -> Result<Self> {
+        let src_dir = crate_dir.join("src");
+        let root_items = target::parse_file_blocking(&src_dir.join("lib.rs"))?;
+        let mut surface = Self::default();
+        // (module path, exported, test only, directory holding its submodule files, items)
+        let mut modules = vec![(lib_name.to_owned(), true, false, src_dir, root_items)];
+
+        while let Some((module_path, exported, test_only, module_dir, items)) = modules.pop() {
+            surface.modules.insert(module_path.clone(), exported);
+            if test_only {
+                surface.test_modules.insert(module_path.clone());
+            }
+            for item in items {
+                match item {
+                    syn::Item::Use(item_use) if exported && is_public(&item_use.vis) => {
+                        let mut imports = Vec::new();
+                        flatten_use_tree(&mut Vec::new(), &item_use.tree, &mut imports);
+                        for (path, name) in imports {
+                            let Some(path) = resolve_use_path(lib_name, &module_path, path) else {
+                                continue;
+                            };
+                            surface.pub_uses.push(PubUse {
+                                path,
+                                name,
+                                module_path: module_path.clone(),
+                            });
+                        }
+                    }
+                    syn::Item::Mod(item_mod) => {
+                        let name = item_mod.ident.to_string();
+                        let child_path = format!("{}::{}", module_path, name);
+                        let child_test_only = test_only || is_cfg_test(&item_mod.attrs);
+                        let child_exported =
+                            exported && !child_test_only && is_public(&item_mod.vis);
+                        let child_dir = module_dir.join(&name);
+                        match item_mod.content {
+                            Some((_, child_items)) => modules.push((
+                                child_path,
+                                child_exported,
+                                child_test_only,
+                                child_dir,
+                                child_items,
+                            )),
+                            None => {
+                                let Some(file) = target::find_module_file(&module_dir, &name)
+                                else {
+                                    continue;
+                                };
+                                match target::parse_file_blocking(&file) {
+                                    Ok(child_items) => modules.push((
+                                        child_path,
+                                        child_exported,
+                                        child_test_only,
+                                        child_dir,
+                                        child_items,
+                                    )),
+                                    Err(e) => tracing::warn!("解析模块失败，跳过: {:#}", e),
+                                }
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(surface)
+    }
+
+    pub fn pub_uses(&self) -> &[PubUse] {
+        &self.pub_uses
+    }
+
+    /// where the caller lives: the file decides for `tests/`, `examples/`, `benches/` and
+    /// `build.rs`, otherwise callers in `#[cfg(test)]` modules are tests
+    pub fn call_site(&self, caller: &CallerInfo, crate_dir: &Path) -> CallSite {
+        if let Some(site) = caller
+            .file
+            .as_deref()
+            .and_then(|file| CallSite::from_file(file, crate_dir))
+        {
+            return site;
+        }
+        let segments = caller.path.split("::").collect::<Vec<_>>();
+        let module = (1..segments.len())
+            .rev()
+            .map(|len| segments[..len].join("::"))
+            .find(|module| self.modules.contains_key(module));
+        let in_test_module = match module {
+            Some(module) => self.test_modules.contains(&module),
+            // unknown module, fall back to the usual `mod tests` convention
+            None => segments.contains(&"tests"),
+        };
+        if in_test_module {
+            CallSite::Test
+        } else {
+            CallSite::Lib
+        }
+    }
+
+    /// whether the caller is part of the crate's public API
+    ///
+    /// a `pub` caller is public when its module is reachable through `pub mod` or when a
+    /// `pub use` of a reachable module re-exports it
+    pub fn classify(&self, caller: &CallerInfo) -> ApiExposure {
+        match caller.is_public {
+            None => return ApiExposure::Unknown,
+            Some(false) => return ApiExposure::Private,
+            Some(true) => {}
+        }
+        let segments = caller.path.split("::").collect::<Vec<_>>();
+        // the longest prefix that is a module, the rest is type and function name
+        let module_exported = (1..segments.len())
+            .rev()
+            .find_map(|len| self.modules.get(&segments[..len].join("::")));
+        match module_exported {
+            None => ApiExposure::Unknown,
+            Some(true) => ApiExposure::Public,
+            Some(false)
+                if self
+                    .pub_uses
+                    .iter()
+                    .any(|pub_use| pub_use.expose(&segments).is_some()) =>
+            {
+                ApiExposure::Public
+            }
+            Some(false) => ApiExposure::Internal,
+        }
+    }
+}
+
+/// `#[cfg(test)]`, also inside `all(..)`/`any(..)`, but not `not(test)`
+fn is_cfg_test(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("cfg")
+            && attr.meta.require_list().is_ok_and(|list| {
+                let tokens = list.tokens.to_string();
+                tokens
+                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                    .any(|token| token == "test")
+                    && !tokens.contains("not")
+            })
+    })
+}
+
+fn is_public(vis: &syn::Visibility) -> bool {
+    matches!(vis, syn::Visibility::Public(_))
+}
+
+/// turn a `use` path into an absolute one, `crate`/`self`/`super` resolved against the
+/// module holding the `use`
+fn resolve_use_path(lib_name: &str, module_path: &str, path: Vec<String>) -> Option<Vec<String>> {
+    let mut module = module_path
+        .split("::")
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let mut rest = path.into_iter().peekable();
+    match rest.peek().map(String::as_str) {
+        Some("crate") => {
+            rest.next();
+            module = vec![lib_name.to_owned()];
+        }
+        Some("self") => {
+            rest.next();
+        }
+        Some("super") => {
+            while rest.peek().map(String::as_str) == Some("super") {
+                rest.next();
+                module.pop();
+            }
+            if module.is_empty() {
+                return None;
+            }
+        }
+        Some(_) => return Some(rest.collect()),
+        None => return None,
+    }
+    module.extend(rest);
+    Some(module)
+}
+
+/// flatten a use tree into (imported path, visible name) pairs, the name is None for
+/// glob imports
+fn flatten_use_tree(
+    prefix: &mut Vec<String>,
+    tree: &syn::UseTree,
+    imports: &mut Vec<(Vec<String>, Option<String>)>,
+) {
+    match tree {
+        syn::UseTree::Path(use_path) => {
+            prefix.push(use_path.ident.to_string());
+            flatten_use_tree(prefix, &use_path.tree, imports);
+            prefix.pop();
+        }
+        syn::UseTree::Name(use_name) if use_name.ident == "self" => {
+            if let Some(last) = prefix.last() {
+                imports.push((prefix.clone(), Some(last.clone())));
+            }
+        }
+        syn::UseTree::Name(use_name) => {
+            let mut path = prefix.clone();
+            path.push(use_name.ident.to_string());
+            imports.push((path, Some(use_name.ident.to_string())));
+        }
+        syn::UseTree::Rename(use_rename) => {
+            let mut path = prefix.clone();
+            if use_rename.ident != "self" {
+                path.push(use_rename.ident.to_string());
+            }
+            imports.push((path, Some(use_rename.rename.to_string())));
+        }
+        syn::UseTree::Glob(_) => imports.push((prefix.clone(), None)),
+        syn::UseTree::Group(group) => {
+            for item in &group.items {
+                flatten_use_tree(prefix, item, imports);
+            }
+        }
+    }
+}
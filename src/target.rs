@@ -0,0 +1,429 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use semver::{Version, VersionReq};
+use tokio::fs as tokio_fs;
+
+use crate::model::normalize_crate_name;
+
+/// one target given by the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetSpec {
+    // an exact function path, e.g. `crossbeam_channel::flavors::list::Channel::drop`
+    Function(String),
+    // every function under a module, e.g. `crossbeam_channel::flavors::list::*`
+    Module(String),
+    // a trait method of an implementing type, e.g. `<crossbeam_channel::flavors::list::Channel<T> as Drop>::drop`
+    TraitMethod(TraitMethodSpec),
+    // every inherent and trait method of a type, e.g. `smallvec::SmallVec::*`
+    Type(String),
+}
+
+/// `<self_type as trait_path>::method`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraitMethodSpec {
+    // full path of the implementing type, generics included, e.g. `krate::module::Channel<T>`
+    pub self_type: String,
+    // the trait as written, e.g. `Drop` or `core::ops::Drop`
+    pub trait_path: String,
+    pub method: String,
+}
+
+impl TraitMethodSpec {
+    fn parse(path: &str) -> Option<Self> {
+        let rest = path.strip_prefix('<')?;
+        let (qualified, method) = rest.rsplit_once(">::")?;
+        let (self_type, trait_path) = qualified.rsplit_once(" as ")?;
+        Some(Self {
+            self_type: self_type.trim().to_owned(),
+            trait_path: trait_path.trim().to_owned(),
+            method: method.trim().to_owned(),
+        })
+    }
+
+    /// the implementing type without generics, e.g. `krate::module::Channel`
+    fn self_type_path(&self) -> &str {
+        match self.self_type.find('<') {
+            Some(idx) => &self.self_type[..idx],
+            None => &self.self_type,
+        }
+    }
+
+    /// the module the type is defined in, e.g. `krate::module`
+    fn module_path(&self) -> &str {
+        self.self_type_path()
+            .rsplit_once("::")
+            .map(|(module, _)| module)
+            .unwrap_or_default()
+    }
+
+    fn type_name(&self) -> &str {
+        self.self_type_path()
+            .rsplit("::")
+            .next()
+            .unwrap_or_default()
+    }
+
+    fn trait_name(&self) -> &str {
+        let trait_path = match self.trait_path.find('<') {
+            Some(idx) => &self.trait_path[..idx],
+            None => &self.trait_path,
+        };
+        trait_path.rsplit("::").next().unwrap_or_default()
+    }
+
+    /// the paths that can be derived without looking at the sources
+    fn syntactic_paths(&self) -> Vec<String> {
+        let mut paths = vec![
+            format!("{}::{}", self.self_type_path(), self.method),
+            format!(
+                "<{} as {}>::{}",
+                self.self_type_path(),
+                self.trait_path,
+                self.method
+            ),
+        ];
+        if self.self_type != self.self_type_path() {
+            paths.push(format!(
+                "<{} as {}>::{}",
+                self.self_type, self.trait_path, self.method
+            ));
+        }
+        paths
+    }
+}
+
+impl TargetSpec {
+    pub fn parse(path: &str) -> Self {
+        if let Some(spec) = TraitMethodSpec::parse(path) {
+            return TargetSpec::TraitMethod(spec);
+        }
+        match path.strip_suffix("::*") {
+            // types are CamelCase and modules snake_case by convention
+            Some(type_path) if is_type_name(type_path.rsplit("::").next().unwrap_or_default()) => {
+                TargetSpec::Type(type_path.to_owned())
+            }
+            Some(module_path) => TargetSpec::Module(module_path.to_owned()),
+            None => TargetSpec::Function(path.to_owned()),
+        }
+    }
+
+    /// lib name of the crate the target lives in, i.e. the first path segment
+    pub fn lib_name(&self) -> &str {
+        let path = match self {
+            TargetSpec::Function(path) | TargetSpec::Module(path) | TargetSpec::Type(path) => {
+                path.as_str()
+            }
+            TargetSpec::TraitMethod(spec) => spec.self_type.as_str(),
+        };
+        path.split("::").next().unwrap_or_default()
+    }
+}
+
+/// a concrete target function handed to call-cg4rs
+///
+/// `name` is what results are reported under, `candidates` are the spellings of the
+/// same function tried in order until call-cg4rs finds callers
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FunctionTarget {
+    pub name: String,
+    pub candidates: Vec<String>,
+    // the user given spec this target was expanded from, e.g. `smallvec::SmallVec::*`
+    pub group: Option<String>,
+    // (version requirement, path): the function lives at `path` in those versions
+    pub aliases: Vec<(String, String)>,
+}
+
+impl FunctionTarget {
+    pub fn exact(path: &str) -> Self {
+        Self {
+            name: path.to_owned(),
+            candidates: vec![path.to_owned()],
+            group: None,
+            aliases: Vec::new(),
+        }
+    }
+
+    /// a target expanded from a module or type spec
+    pub fn in_group(path: &str, group: &str) -> Self {
+        Self {
+            group: Some(group.to_owned()),
+            ..Self::exact(path)
+        }
+    }
+
+    /// lib name of the crate defining the function
+    pub fn lib_name(&self) -> &str {
+        self.name
+            .trim_start_matches('<')
+            .split("::")
+            .next()
+            .unwrap_or_default()
+    }
+
+    /// the paths to try when the crate defining the function is pinned to
+    /// `parent_name@parent_version`
+    ///
+    /// aliases whose version requirement matches replace the default candidates, so a
+    /// function moved between versions is looked up where it actually lives
+    pub fn candidates_for(&self, parent_name: &str, parent_version: &str) -> Vec<String> {
+        if normalize_crate_name(parent_name) == normalize_crate_name(self.lib_name()) {
+            if let Ok(version) = Version::parse(parent_version) {
+                let aliased = self
+                    .aliases
+                    .iter()
+                    .filter(|(req, _)| {
+                        VersionReq::parse(req).is_ok_and(|req| req.matches(&version))
+                    })
+                    .map(|(_, path)| path.clone())
+                    .collect::<Vec<_>>();
+                if !aliased.is_empty() {
+                    return aliased;
+                }
+            }
+        }
+        self.candidates.clone()
+    }
+}
+
+/// the path of a target function within a version range, e.g. `drop` lived at
+/// `crossbeam_channel::list::Channel::drop` in `<0.5.0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathAlias {
+    // the target as given by the user
+    pub target: String,
+    pub version_req: String,
+    pub path: String,
+}
+
+impl PathAlias {
+    /// parse `target|version_req|path`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut fields = spec.split('|').map(str::trim);
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(target), Some(version_req), Some(path), None) => {
+                VersionReq::parse(version_req)
+                    .context(format!("别名的版本范围无效: {}", version_req))?;
+                Ok(Self {
+                    target: target.to_owned(),
+                    version_req: version_req.to_owned(),
+                    path: path.to_owned(),
+                })
+            }
+            _ => Err(anyhow::anyhow!(
+                "别名格式应为 target|version_req|path: {}",
+                spec
+            )),
+        }
+    }
+}
+
+/// function name used by the source precheck: the last segment of the path
+pub fn function_name(path: &str) -> &str {
+    path.rsplit("::").next().unwrap_or_default()
+}
+
+/// `path` with its crate segment replaced by `crate_alias`, for dependents declaring the
+/// crate under another name (`cb = { package = "crossbeam-channel" }`)
+pub fn with_crate_alias(path: &str, crate_alias: &str) -> String {
+    let (prefix, rest) = match path.strip_prefix('<') {
+        Some(rest) => ("<", rest),
+        None => ("", path),
+    };
+    match rest.split_once("::") {
+        Some((_, tail)) => format!("{}{}::{}", prefix, crate_alias, tail),
+        None => path.to_owned(),
+    }
+}
+
+/// resolve a trait method target into all canonical paths, including the
+/// `{impl#N}` form rustc uses for the matching impl block of the module when the
+/// sources at `crate_dir` are available
+pub async fn resolve_trait_method(
+    spec: &TraitMethodSpec,
+    crate_dir: Option<&Path>,
+) -> FunctionTarget {
+    let mut candidates = spec.syntactic_paths();
+    if let Some(crate_dir) = crate_dir {
+        match module_items(crate_dir, spec.module_path()).await {
+            Ok(items) => {
+                if let Some(impl_index) = impl_index(&items, spec.type_name(), spec.trait_name()) {
+                    candidates.push(format!(
+                        "{}::{{impl#{}}}::{}",
+                        spec.module_path(),
+                        impl_index,
+                        spec.method
+                    ));
+                }
+            }
+            Err(e) => tracing::warn!("解析 {} 的impl块失败: {:#}", spec.self_type, e),
+        }
+    }
+    FunctionTarget {
+        name: format!(
+            "<{} as {}>::{}",
+            spec.self_type, spec.trait_path, spec.method
+        ),
+        candidates,
+        group: None,
+        aliases: Vec::new(),
+    }
+}
+
+/// position of `impl trait_name for type_name` among all impl blocks of the module
+fn impl_index(items: &[syn::Item], type_name: &str, trait_name: &str) -> Option<usize> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Impl(item_impl) => Some(item_impl),
+            _ => None,
+        })
+        .position(|item_impl| {
+            let implemented_trait = item_impl
+                .trait_
+                .as_ref()
+                .and_then(|(_, path, _)| path.segments.last())
+                .map(|segment| segment.ident.to_string());
+            self_type_name(&item_impl.self_ty).as_deref() == Some(type_name)
+                && implemented_trait.as_deref() == Some(trait_name)
+        })
+}
+
+/// all free functions and impl methods defined directly in the given module of the
+/// crate extracted at `crate_dir`
+pub async fn expand_module(crate_dir: &Path, module_path: &str) -> Result<Vec<String>> {
+    let items = module_items(crate_dir, module_path).await?;
+    Ok(functions_in_items(module_path, &items))
+}
+
+/// all inherent and trait methods of the type `type_path`, looked up in the module the
+/// type is defined in
+pub async fn expand_type(crate_dir: &Path, type_path: &str) -> Result<Vec<String>> {
+    let (module_path, type_name) = type_path
+        .rsplit_once("::")
+        .context(format!("类型路径缺少模块: {}", type_path))?;
+    let items = module_items(crate_dir, module_path).await?;
+    let methods = functions_in_items(module_path, &items)
+        .into_iter()
+        .filter(|function| {
+            function
+                .strip_prefix(type_path)
+                .is_some_and(|method| method.starts_with("::"))
+        })
+        .collect::<Vec<_>>();
+    if methods.is_empty() {
+        tracing::warn!("模块 {} 中没有找到类型 {} 的方法", module_path, type_name);
+    }
+    Ok(methods)
+}
+
+fn is_type_name(segment: &str) -> bool {
+    segment
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_uppercase())
+}
+
+/// locate the items of a module, following both `mod foo;` files and inline `mod foo { .. }`
+pub(crate) async fn module_items(crate_dir: &Path, module_path: &str) -> Result<Vec<syn::Item>> {
+    let mut segments = module_path.split("::");
+    // the first segment is the lib name of the crate
+    segments.next();
+
+    let mut file = crate_dir.join("src").join("lib.rs");
+    let mut items = parse_file(&file).await?;
+    // directory holding the submodule files of the current module
+    let mut module_dir = crate_dir.join("src");
+
+    for segment in segments {
+        let inline = items.iter().find_map(|item| match item {
+            syn::Item::Mod(item_mod) if item_mod.ident == segment => {
+                item_mod.content.as_ref().map(|(_, items)| items.clone())
+            }
+            _ => None,
+        });
+        if let Some(inline_items) = inline {
+            items = inline_items;
+            module_dir = module_dir.join(segment);
+            continue;
+        }
+
+        file = find_module_file(&module_dir, segment).with_context(|| {
+            format!(
+                "找不到模块 {} 的源文件 (在 {} 中)",
+                segment,
+                module_dir.display()
+            )
+        })?;
+        items = parse_file(&file).await?;
+        module_dir = module_dir.join(segment);
+    }
+
+    Ok(items)
+}
+
+pub(crate) fn find_module_file(module_dir: &Path, segment: &str) -> Option<PathBuf> {
+    [
+        module_dir.join(format!("{}.rs", segment)),
+        module_dir.join(segment).join("mod.rs"),
+    ]
+    .into_iter()
+    .find(|candidate| candidate.exists())
+}
+
+pub(crate) async fn parse_file(file: &Path) -> Result<Vec<syn::Item>> {
+    let content = tokio_fs::read_to_string(file)
+        .await
+        .context(format!("读取源文件失败: {}", file.display()))?;
+    parse_content(file, &content)
+}
+
+/// `parse_file` for code that cannot hold syn items across an await
+pub(crate) fn parse_file_blocking(file: &Path) -> Result<Vec<syn::Item>> {
+    let content =
+        std::fs::read_to_string(file).context(format!("读取源文件失败: {}", file.display()))?;
+    parse_content(file, &content)
+}
+
+fn parse_content(file: &Path, content: &str) -> Result<Vec<syn::Item>> {
+    let parsed = syn::parse_file(content).context(format!("解析源文件失败: {}", file.display()))?;
+    Ok(parsed.items)
+}
+
+fn functions_in_items(module_path: &str, items: &[syn::Item]) -> Vec<String> {
+    let mut functions = Vec::new();
+    for item in items {
+        match item {
+            syn::Item::Fn(item_fn) => {
+                functions.push(format!("{}::{}", module_path, item_fn.sig.ident));
+            }
+            syn::Item::Impl(item_impl) => {
+                let Some(type_name) = self_type_name(&item_impl.self_ty) else {
+                    continue;
+                };
+                for impl_item in &item_impl.items {
+                    if let syn::ImplItem::Fn(method) = impl_item {
+                        functions.push(format!(
+                            "{}::{}::{}",
+                            module_path, type_name, method.sig.ident
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    functions
+}
+
+/// last identifier of the implementing type, e.g. `Channel` for `Channel<T>`
+pub(crate) fn self_type_name(self_ty: &syn::Type) -> Option<String> {
+    match self_ty {
+        syn::Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string()),
+        _ => None,
+    }
+}
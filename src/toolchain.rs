@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use semver::Version;
+use tokio::process::Command;
+
+use crate::model::Krate;
+
+/// an installed rustup toolchain dependents can be built with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    pub name: String,
+    // rustc version without the pre-release part, `1.87.0-nightly` counts as 1.87.0
+    pub version: Version,
+}
+
+impl Toolchain {
+    /// ask rustup for the rustc version of a toolchain
+    pub async fn detect(name: &str) -> Result<Self> {
+        let output = Command::new("rustup")
+            .args(["run", name, "rustc", "--version"])
+            .output()
+            .await
+            .context("执行 rustup run 失败")?;
+        if !output.status.success() {
+            bail!(
+                "工具链 {} 不可用: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        // rustc 1.87.0-nightly (a2e63569f 2025-03-13)
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let version = stdout
+            .split_whitespace()
+            .nth(1)
+            .and_then(|version| Version::parse(version).ok())
+            .context(format!("无法解析 {} 的版本: {}", name, stdout.trim()))?;
+        Ok(Self {
+            name: name.to_owned(),
+            version: Version::new(version.major, version.minor, version.patch),
+        })
+    }
+
+    /// detect every configured toolchain, unavailable ones are left out
+    pub async fn detect_all(names: &[String]) -> Vec<Self> {
+        let mut toolchains = Vec::new();
+        for name in names {
+            match Self::detect(name).await {
+                Ok(toolchain) => {
+                    tracing::info!("工具链 {}: rustc {}", toolchain.name, toolchain.version);
+                    toolchains.push(toolchain);
+                }
+                Err(e) => tracing::warn!("跳过工具链: {:#}", e),
+            }
+        }
+        toolchains
+    }
+}
+
+/// the oldest rustc a crate builds with: its `rust-version`, or the release that
+/// stabilized its edition
+pub async fn minimum_rust_version(crate_dir: &Path) -> Result<Option<Version>> {
+    let manifest = Krate::read_manifest(crate_dir).await?;
+    let manifest: toml_edit::DocumentMut = manifest
+        .parse()
+        .context(format!("解析 {} 的 Cargo.toml 失败", crate_dir.display()))?;
+    // 工作区成员可以用 `rust-version.workspace = true` 继承
+    let field = |key: &str| {
+        manifest
+            .get("package")
+            .and_then(|package| package.get(key))
+            .and_then(|value| value.as_str())
+            .or_else(|| {
+                manifest
+                    .get("workspace")
+                    .and_then(|workspace| workspace.get("package"))
+                    .and_then(|package| package.get(key))
+                    .and_then(|value| value.as_str())
+            })
+    };
+    if let Some(rust_version) = field("rust-version") {
+        let mut parts = rust_version
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u64>().ok());
+        if let (Some(Some(major)), Some(Some(minor))) = (parts.next(), parts.next()) {
+            let patch = parts.next().flatten().unwrap_or(0);
+            return Ok(Some(Version::new(major, minor, patch)));
+        }
+        tracing::warn!(
+            "{} 的 rust-version 无效: {}",
+            crate_dir.display(),
+            rust_version
+        );
+    }
+    let edition_release = match field("edition") {
+        Some("2018") => Some((1, 31)),
+        Some("2021") => Some((1, 56)),
+        Some("2024") => Some((1, 85)),
+        _ => None,
+    };
+    Ok(edition_release.map(|(major, minor)| Version::new(major, minor, 0)))
+}
+
+/// the first toolchain of the configured order that is new enough for the crate, None to
+/// keep the default toolchain
+pub fn select<'a>(toolchains: &'a [Toolchain], minimum: Option<&Version>) -> Option<&'a Toolchain> {
+    toolchains
+        .iter()
+        .find(|toolchain| minimum.is_none_or(|minimum| toolchain.version >= *minimum))
+}
@@ -0,0 +1,175 @@
+use std::io;
+use std::process::{Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+// 子进程既没有输出也没有占用 CPU 超过这么久就被视为卡死，从 `STALL_TIMEOUT_SECS`
+// 读取（默认 900，0 表示不检测）
+static STALL_TIMEOUT: Lazy<Option<Duration>> = Lazy::new(|| {
+    let secs = std::env::var("STALL_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(900);
+    (secs > 0).then(|| Duration::from_secs(secs))
+});
+
+// 检查子进程活动的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// like `Command::output`, but the child runs in its own process group which is killed
+/// as a whole when neither the child nor any process it started printed anything or
+/// used cpu for `STALL_TIMEOUT_SECS`; a stall is reported as `io::ErrorKind::TimedOut`
+///
+/// the group is also killed when the returned future is dropped, e.g. by a phase
+/// timeout; builds in a container keep running in the container runtime after the kill
+pub async fn output(command: &mut Command) -> io::Result<Output> {
+    #[cfg(unix)]
+    command.process_group(0);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn()?;
+    let pid = child.id();
+    let mut group = GroupGuard(pid);
+
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+    let stdout = tokio::spawn(read_all(child.stdout.take(), Arc::clone(&last_activity)));
+    let stderr = tokio::spawn(read_all(child.stderr.take(), Arc::clone(&last_activity)));
+
+    let mut cpu_ticks = 0;
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            _ = tokio::time::sleep(CHECK_INTERVAL), if STALL_TIMEOUT.is_some() => {
+                let stall_timeout = STALL_TIMEOUT.unwrap_or_default();
+                let ticks = pid.map(group_cpu_ticks).unwrap_or_default();
+                if ticks != cpu_ticks {
+                    cpu_ticks = ticks;
+                    *last_activity.lock().unwrap() = Instant::now();
+                }
+                let idle = last_activity.lock().unwrap().elapsed();
+                if idle >= stall_timeout {
+                    tracing::warn!(
+                        "子进程 {:?} {} 秒没有输出也没有占用 CPU，终止整个进程组",
+                        command.as_std().get_program(),
+                        idle.as_secs()
+                    );
+                    kill_group(&mut child, pid);
+                    let _ = child.wait().await;
+                    group.0 = None;
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("卡死 {} 秒后被终止", idle.as_secs()),
+                    ));
+                }
+            }
+        }
+    };
+    group.0 = None;
+    Ok(Output {
+        status,
+        stdout: stdout.await.unwrap_or_default(),
+        stderr: stderr.await.unwrap_or_default(),
+    })
+}
+
+async fn read_all<R: AsyncRead + Unpin>(
+    stream: Option<R>,
+    last_activity: Arc<Mutex<Instant>>,
+) -> Vec<u8> {
+    let mut content = Vec::new();
+    let Some(mut stream) = stream else {
+        return content;
+    };
+    let mut buffer = [0u8; 8192];
+    while let Ok(read) = stream.read(&mut buffer).await {
+        if read == 0 {
+            break;
+        }
+        content.extend_from_slice(&buffer[..read]);
+        *last_activity.lock().unwrap() = Instant::now();
+    }
+    content
+}
+
+/// cpu time (in clock ticks) used by the live processes of a process group
+#[cfg(target_os = "linux")]
+fn group_cpu_ticks(group: u32) -> u64 {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return 0;
+    };
+    let mut ticks = 0;
+    for entry in entries.flatten() {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+        {
+            continue;
+        }
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        // `pid (comm) state ppid pgrp ... utime stime`，comm 中可能有空格和括号
+        let Some((_, fields)) = stat.rsplit_once(')') else {
+            continue;
+        };
+        let fields = fields.split_whitespace().collect::<Vec<_>>();
+        if fields.get(2).and_then(|pgrp| pgrp.parse::<u32>().ok()) != Some(group) {
+            continue;
+        }
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        ticks += field(11).unwrap_or(0) + field(12).unwrap_or(0);
+    }
+    ticks
+}
+
+#[cfg(not(target_os = "linux"))]
+fn group_cpu_ticks(_group: u32) -> u64 {
+    0
+}
+
+/// kills the process group of a child still running when dropped
+struct GroupGuard(Option<u32>);
+
+impl Drop for GroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(pid) = self.0 {
+            // SAFETY: killpg only sends a signal, the child leading the group wasn't
+            // reaped yet, so its id can't have been reused
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill_group(child: &mut tokio::process::Child, pid: Option<u32>) {
+    match pid {
+        // SAFETY: killpg only sends a signal, the group was created for this child
+        Some(pid) => unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        },
+        None => {
+            let _ = child.start_kill();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut tokio::process::Child, _pid: Option<u32>) {
+    let _ = child.start_kill();
+}